@@ -178,6 +178,9 @@ function setInterfaceLanguage ( l ) {
 		$('a[tt="manual"]').attr ( { href:'https://meta.wikimedia.org/wiki/PetScan/'+l } ) ;
 		$('#query_length').text ( _t('query_length').replace('$1',$('#query_length').attr('sec')) ) ;
 		$('#num_results').text ( _t('num_results').replace('$1',$('#num_results').attr('num')) ) ;
+		$('span[tt][num]').each ( function () {
+			$(this).text ( _t($(this).attr('tt')).replace('$1',$(this).attr('num')) ) ;
+		} ) ;
 
 		// Permalink
 		var query = decodeURIComponent ( $('#querystring').text() ) ;
@@ -1,7 +1,9 @@
 use crate::datasource::SQLtuple;
+use crate::error::PetScanError;
 use crate::form_parameters::FormParameters;
 use crate::platform::{ContentType, MyResponse};
 use chrono::prelude::*;
+use htmlescape::encode_minimal;
 use mysql as my;
 use rand::seq::SliceRandom;
 use rayon::prelude::*;
@@ -11,15 +13,53 @@ use std::collections::HashMap;
 use std::fs;
 use std::sync::{Arc, Mutex, RwLock};
 use std::{thread, time};
-use wikibase::mediawiki::api::Api;
+use wikibase::mediawiki::api::{Api, NamespaceID};
 
 static MAX_CONCURRENT_DB_CONNECTIONS: u64 = 10;
 static MYSQL_MAX_CONNECTION_ATTEMPTS: u64 = 15;
 static MYSQL_CONNECTION_INITIAL_DELAY_MS: u64 = 100;
 static MYSQL_CONNECTION_MAX_DELAY_MS: u64 = 5000;
+static ENTITYSCHEMA_VALIDATOR_TIMEOUT_SEC: u64 = 30;
+/// Cached EntitySchema results expire, as the entity or the schema may have been edited since
+static ENTITYSCHEMA_CACHE_TTL_SEC: u64 = 3600;
+static ENTITYSCHEMA_CACHE_MAX_ENTRIES: usize = 100000;
+
+/// Entity ID prefixes and the namespace their pages live in.
+/// More can be registered with an "entity_namespaces" object in the config file, e.g. `{"Z": 0}`;
+/// entries there override these defaults.
+/// MediaInfo ("M") entities are not listed here, as their pages live on Commons, not Wikidata;
+/// see `SourceSparql::mediainfo_files`.
+static DEFAULT_ENTITY_NAMESPACES: &[(&str, NamespaceID)] = &[("Q", 0), ("P", 120), ("L", 146)];
+
+/// Tool DB tables needed by features beyond the original schema, as (name, CREATE statement)
+static TOOL_DB_TABLES: &[(&str, &str)] = &[
+    (
+        "result_cache",
+        "CREATE TABLE IF NOT EXISTS `result_cache` (
+            `id` INT UNSIGNED NOT NULL AUTO_INCREMENT,
+            `query_id` INT UNSIGNED NOT NULL,
+            `created` DATETIME NOT NULL,
+            `result` LONGBLOB NOT NULL,
+            PRIMARY KEY (`id`),
+            KEY `query_id` (`query_id`,`created`)
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4",
+    ),
+    (
+        "psid_last_run",
+        "CREATE TABLE IF NOT EXISTS `psid_last_run` (
+            `query_id` INT UNSIGNED NOT NULL,
+            `last_run` DATETIME NOT NULL,
+            `result_count` INT UNSIGNED DEFAULT NULL,
+            PRIMARY KEY (`query_id`)
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4",
+    ),
+];
 
 pub type DbUserPass = (String, String);
 
+/// Reconnection behavior for replica connections: (attempts, initial delay ms, max delay ms)
+pub type DbConnectionRetry = (u64, u64, u64);
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub db_pool: Vec<Arc<Mutex<DbUserPass>>>,
@@ -29,11 +69,32 @@ pub struct AppState {
     shutting_down: Arc<RwLock<bool>>,
     site_matrix: Value,
     main_page: String,
+    db_connection_retry: DbConnectionRetry,
+    entityschema_client: reqwest::blocking::Client,
+    entityschema_cache: Arc<RwLock<HashMap<(String, String), (bool, time::Instant)>>>,
+    api_cache: Arc<RwLock<HashMap<String, Api>>>,
+    entity_namespaces: HashMap<String, NamespaceID>,
 }
 
 impl AppState {
     pub fn new_from_config(config: &Value) -> Self {
         let main_page_path = "./html/index.html";
+        let main_page = String::from_utf8_lossy(
+            &fs::read(main_page_path).expect("Could not read index.html file form disk"),
+        )
+        .parse()
+        .expect("Parsing index.html failed");
+        Self::new_from_parts(config, Some(main_page), None)
+    }
+
+    /// Creates a state without reading web assets from disk.
+    /// Without a main page, HTML output has no page template; without a site matrix,
+    /// it is loaded from the Wikidata API.
+    pub fn new_from_parts(
+        config: &Value,
+        main_page: Option<String>,
+        site_matrix: Option<Value>,
+    ) -> Self {
         let tool_db_access_tuple = (
             config["user"]
                 .as_str()
@@ -49,13 +110,21 @@ impl AppState {
             config: config.to_owned(),
             threads_running: Arc::new(RwLock::new(0)),
             shutting_down: Arc::new(RwLock::new(false)),
-            site_matrix: AppState::load_site_matrix(),
+            site_matrix: site_matrix.unwrap_or_else(AppState::load_site_matrix),
             tool_db_mutex: Arc::new(Mutex::new(tool_db_access_tuple)),
-            main_page: String::from_utf8_lossy(
-                &fs::read(main_page_path).expect("Could not read index.html file form disk"),
-            )
-            .parse()
-            .expect("Parsing index.html failed"),
+            entityschema_client: reqwest::blocking::ClientBuilder::new()
+                .timeout(time::Duration::from_secs(
+                    ENTITYSCHEMA_VALIDATOR_TIMEOUT_SEC,
+                ))
+                .build()
+                .expect("Could not create EntitySchema validator client"),
+            entityschema_cache: Arc::new(RwLock::new(HashMap::new())),
+            api_cache: Arc::new(RwLock::new(HashMap::new())),
+            entity_namespaces: AppState::entity_namespaces_from_config(config)
+                .expect("Invalid entity_namespaces in config"),
+            db_connection_retry: AppState::db_connection_retry_from_config(config)
+                .expect("Invalid database connection retry settings in config"),
+            main_page: main_page.unwrap_or_default(),
         };
 
         match config["mysql"].as_array() {
@@ -207,9 +276,10 @@ impl AppState {
         &self,
         db_user_pass: &DbUserPass,
         wiki: &String,
-    ) -> Result<my::Conn, String> {
-        let mut loops_left = MYSQL_MAX_CONNECTION_ATTEMPTS;
-        let mut milliseconds = MYSQL_CONNECTION_INITIAL_DELAY_MS;
+    ) -> Result<my::Conn, PetScanError> {
+        let (max_attempts, initial_delay_ms, max_delay_ms) = self.db_connection_retry;
+        let mut loops_left = max_attempts;
+        let mut milliseconds = initial_delay_ms;
         let (host, schema) = self.db_host_and_schema_for_wiki(wiki)?;
         let (user, pass) = db_user_pass;
         loop {
@@ -223,7 +293,8 @@ impl AppState {
 
             match my::Conn::new(builder) {
                 Ok(mut con) => {
-                    self.set_group_concat_max_len(wiki, &mut con)?;
+                    self.set_group_concat_max_len(wiki, &mut con)
+                        .map_err(PetScanError::Database)?;
                     return Ok(con);
                 }
                 Err(e) => {
@@ -234,17 +305,79 @@ impl AppState {
                     loops_left -= 1;
                     let sleep_ms = time::Duration::from_millis(milliseconds);
                     milliseconds *= 2;
-                    if milliseconds > MYSQL_CONNECTION_MAX_DELAY_MS {
-                        milliseconds = MYSQL_CONNECTION_MAX_DELAY_MS;
+                    if milliseconds > max_delay_ms {
+                        milliseconds = max_delay_ms;
                     }
                     thread::sleep(sleep_ms);
                 }
             }
         }
-        Err(format!(
+        Err(PetScanError::Database(format!(
             "Could not connect to database replica for '{}' on '{}'/'{}' after {} attempts",
-            &wiki, &host, &schema, MYSQL_MAX_CONNECTION_ATTEMPTS
-        ))
+            &wiki, &host, &schema, max_attempts
+        )))
+    }
+
+    /// Reads the replica reconnection settings from the config, falling back to the defaults
+    fn db_connection_retry_from_config(config: &Value) -> Result<DbConnectionRetry, String> {
+        let max_attempts = config["mysql_max_connection_attempts"]
+            .as_u64()
+            .unwrap_or(MYSQL_MAX_CONNECTION_ATTEMPTS);
+        let initial_delay_ms = config["mysql_connection_initial_delay_ms"]
+            .as_u64()
+            .unwrap_or(MYSQL_CONNECTION_INITIAL_DELAY_MS);
+        let max_delay_ms = config["mysql_connection_max_delay_ms"]
+            .as_u64()
+            .unwrap_or(MYSQL_CONNECTION_MAX_DELAY_MS);
+        if max_attempts == 0 {
+            return Err(format!("mysql_max_connection_attempts must not be 0"));
+        }
+        if max_delay_ms < initial_delay_ms {
+            return Err(format!(
+                "mysql_connection_max_delay_ms ({}) must not be smaller than mysql_connection_initial_delay_ms ({})",
+                max_delay_ms, initial_delay_ms
+            ));
+        }
+        Ok((max_attempts, initial_delay_ms, max_delay_ms))
+    }
+
+    fn entity_namespaces_from_config(
+        config: &Value,
+    ) -> Result<HashMap<String, NamespaceID>, String> {
+        let mut ret: HashMap<String, NamespaceID> = DEFAULT_ENTITY_NAMESPACES
+            .iter()
+            .map(|(prefix, namespace_id)| (prefix.to_string(), *namespace_id))
+            .collect();
+        match &config["entity_namespaces"] {
+            Value::Null => {}
+            Value::Object(o) => {
+                for (prefix, namespace_id) in o {
+                    if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_uppercase()) {
+                        return Err(format!(
+                            "Entity prefix '{}' must be uppercase ASCII letters",
+                            prefix
+                        ));
+                    }
+                    let namespace_id = namespace_id.as_i64().ok_or(format!(
+                        "Namespace for entity prefix '{}' must be a number",
+                        prefix
+                    ))?;
+                    ret.insert(prefix.to_string(), namespace_id as NamespaceID);
+                }
+            }
+            _ => return Err(format!("entity_namespaces must be an object")),
+        }
+        Ok(ret)
+    }
+
+    /// The namespace for an entity ID like "Q42", based on its prefix
+    pub fn get_entity_namespace(&self, entity: &str) -> Option<NamespaceID> {
+        let pos = entity.find(|c: char| !c.is_ascii_uppercase())?;
+        let (prefix, id) = entity.split_at(pos);
+        if !id.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        self.entity_namespaces.get(prefix).cloned()
     }
 
     pub fn render_error(&self, error: String, form_parameters: &FormParameters) -> MyResponse {
@@ -252,7 +385,7 @@ impl AppState {
             Some("") | Some("html") => {
                 let output = format!(
                     "<div class='alert alert-danger' role='alert'>{}</div>",
-                    &error
+                    encode_minimal(&error)
                 );
                 let interface_language = form_parameters
                     .params
@@ -267,6 +400,8 @@ impl AppState {
                 MyResponse {
                     s: html.to_string(),
                     content_type: ContentType::HTML,
+                    binary: None,
+                    filename: None,
                 }
             }
             Some("json") => {
@@ -276,6 +411,8 @@ impl AppState {
             _ => MyResponse {
                 s: error.to_string(),
                 content_type: ContentType::Plain,
+                binary: None,
+                filename: None,
             },
         }
     }
@@ -291,23 +428,42 @@ impl AppState {
                 MyResponse {
                     s: text,
                     content_type: ContentType::JSONP,
+                    binary: None,
+                    filename: None,
                 }
             }
             None => MyResponse {
                 s: ::serde_json::to_string(&value)
                     .expect("app_state::output_json can't stringify JSON [2]"),
                 content_type: ContentType::JSON,
+                binary: None,
+                filename: None,
             },
         }
     }
 
-    pub fn get_api_for_wiki(&self, wiki: String) -> Result<Api, String> {
-        // TODO cache url and/or api object?
+    /// Returns an API object for the wiki. These are cached across queries, so the site info
+    /// is only loaded once per wiki.
+    pub fn get_api_for_wiki(&self, wiki: String) -> Result<Api, PetScanError> {
+        match self.api_cache.read() {
+            Ok(cache) => match cache.get(&wiki) {
+                Some(api) => return Ok(api.clone()),
+                None => {}
+            },
+            _ => {}
+        }
         let url = self.get_server_url_for_wiki(&wiki)? + "/w/api.php";
-        match Api::new(&url) {
-            Ok(api) => Ok(api),
-            Err(e) => Err(format!("{:?}", e)),
+        let api = match Api::new(&url) {
+            Ok(api) => api,
+            Err(e) => return Err(PetScanError::Api(format!("{:?}", e))),
+        };
+        match self.api_cache.write() {
+            Ok(mut cache) => {
+                cache.insert(wiki, api.clone());
+            }
+            _ => {}
         }
+        Ok(api)
     }
 
     fn get_value_from_site_matrix_entry(
@@ -420,7 +576,7 @@ impl AppState {
     pub fn get_tool_db_connection(
         &self,
         tool_db_user_pass: DbUserPass,
-    ) -> Result<my::Conn, String> {
+    ) -> Result<my::Conn, PetScanError> {
         let (host, schema) = self.db_host_and_schema_for_tool_db();
         let (user, pass) = tool_db_user_pass.clone();
         let mut builder = my::OptsBuilder::new();
@@ -438,10 +594,10 @@ impl AppState {
 
         match my::Conn::new(builder) {
             Ok(conn) => Ok(conn),
-            Err(e) => Err(format!(
+            Err(e) => Err(PetScanError::Database(format!(
                 "AppState::get_tool_db_connection can't get DB connection to {}:{} : '{}'",
                 &host, port, &e
-            )),
+            ))),
         }
     }
 
@@ -449,22 +605,27 @@ impl AppState {
         &self.tool_db_mutex
     }
 
-    pub fn get_query_from_psid(&self, psid: &String) -> Result<String, String> {
-        let tool_db_user_pass = self.tool_db_mutex.lock().map_err(|e| format!("{:?}", e))?;
+    pub fn get_query_from_psid(&self, psid: &String) -> Result<String, PetScanError> {
+        let tool_db_user_pass = self.tool_db_mutex.lock()?;
         let mut conn = self.get_tool_db_connection(tool_db_user_pass.clone())?;
 
         let psid = match psid.parse::<usize>() {
             Ok(psid) => psid,
-            Err(e) => return Err(format!("{:?}", e)),
+            Err(e) => {
+                return Err(PetScanError::BadParameter(format!(
+                    "Invalid PSID '{}': {}",
+                    psid, e
+                )))
+            }
         };
         let sql = format!("SELECT querystring FROM query WHERE id={}", psid);
         let result = match conn.prep_exec(sql, ()) {
             Ok(r) => r,
             Err(e) => {
-                return Err(format!(
+                return Err(PetScanError::Database(format!(
                     "AppState::get_query_from_psid query error: {:?}",
                     e
-                ))
+                )))
             }
         };
         let ret = result
@@ -473,12 +634,14 @@ impl AppState {
             .next();
         match ret {
             Some(ret) => Ok(String::from_utf8_lossy(&ret).into_owned()),
-            None => Err("No such PSID in the database".to_string()),
+            None => Err(PetScanError::BadParameter(
+                "No such PSID in the database".to_string(),
+            )),
         }
     }
 
-    pub fn log_query_start(&self, query_string: &String) -> Result<u64, String> {
-        let tool_db_user_pass = self.tool_db_mutex.lock().map_err(|e| format!("{:?}", e))?;
+    pub fn log_query_start(&self, query_string: &String) -> Result<u64, PetScanError> {
+        let tool_db_user_pass = self.tool_db_mutex.lock()?;
         let mut conn = self.get_tool_db_connection(tool_db_user_pass.clone())?;
         let utc: DateTime<Utc> = Utc::now();
         let now = utc.format("%Y-%m-%d %H:%M:%S").to_string();
@@ -493,10 +656,10 @@ impl AppState {
         );
         let ret = match conn.prep_exec(sql.0, sql.1) {
             Ok(r) => Ok(r.last_insert_id()),
-            Err(e) => Err(format!(
+            Err(e) => Err(PetScanError::Database(format!(
                 "AppState::get_new_psid_for_query query error: {:?}",
                 e
-            )),
+            ))),
         };
         ret
     }
@@ -520,8 +683,8 @@ impl AppState {
         };
     }
 
-    pub fn get_or_create_psid_for_query(&self, query_string: &String) -> Result<u64, String> {
-        let tool_db_user_pass = self.tool_db_mutex.lock().map_err(|e| format!("{:?}", e))?;
+    pub fn get_or_create_psid_for_query(&self, query_string: &String) -> Result<u64, PetScanError> {
+        let tool_db_user_pass = self.tool_db_mutex.lock()?;
         let mut conn = self.get_tool_db_connection(tool_db_user_pass.clone())?;
 
         // Check for existing entry
@@ -552,14 +715,98 @@ impl AppState {
         );
         let ret = match conn.prep_exec(sql.0, sql.1) {
             Ok(r) => Ok(r.last_insert_id()),
-            Err(e) => Err(format!(
+            Err(e) => Err(PetScanError::Database(format!(
                 "AppState::get_new_psid_for_query query error: {:?}",
                 e
-            )),
+            ))),
         };
         ret
     }
 
+    /// Creates missing tool DB tables, if "bootstrap_tool_db_schema" is set in the config.
+    /// Safe to run repeatedly; returns the names of the tables that were created.
+    pub fn bootstrap_tool_db_schema(&self) -> Result<Vec<String>, String> {
+        if !self.config["bootstrap_tool_db_schema"]
+            .as_bool()
+            .unwrap_or(false)
+        {
+            return Ok(vec![]);
+        }
+        let tool_db_user_pass = self.tool_db_mutex.lock().map_err(|e| format!("{:?}", e))?;
+        let mut conn = self.get_tool_db_connection(tool_db_user_pass.clone())?;
+        let existing: Vec<String> = conn
+            .prep_exec("SHOW TABLES", ())
+            .map_err(|e| format!("AppState::bootstrap_tool_db_schema: {:?}", e))?
+            .filter_map(|row_result| row_result.ok())
+            .filter_map(|row| my::from_row_opt::<Vec<u8>>(row).ok())
+            .map(|table| String::from_utf8_lossy(&table).into_owned())
+            .collect();
+        let mut created = vec![];
+        for (table, sql) in TOOL_DB_TABLES {
+            if existing.contains(&table.to_string()) {
+                continue;
+            }
+            conn.prep_exec(*sql, ()).map_err(|e| {
+                format!(
+                    "AppState::bootstrap_tool_db_schema: Can't create {}: {:?}",
+                    table, e
+                )
+            })?;
+            println!("Created tool DB table {}", table);
+            created.push(table.to_string());
+        }
+        Ok(created)
+    }
+
+    /// Returns the URL of the EntitySchema validator, if configured
+    pub fn get_entityschema_validator(&self) -> Option<String> {
+        match self.config["entityschema_validator"].as_str() {
+            Some(url) if !url.is_empty() => Some(url.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Checks if a Wikidata entity conforms to an EntitySchema, using the configured validator.
+    /// The validator is called as `<url>?entityschema=E123&entity=Q42` and needs to return
+    /// a JSON object with a boolean "valid" key. It has no batch endpoint, so each entity
+    /// takes one request. Results are cached across queries for ENTITYSCHEMA_CACHE_TTL_SEC,
+    /// for up to ENTITYSCHEMA_CACHE_MAX_ENTRIES (schema, entity) pairs.
+    /// Returns None if the validator is not configured, unavailable, or gives no answer.
+    pub fn get_entityschema_conformance(&self, entityschema: &str, entity: &str) -> Option<bool> {
+        let key = (entityschema.to_string(), entity.to_string());
+        let ttl = time::Duration::from_secs(ENTITYSCHEMA_CACHE_TTL_SEC);
+        match self.entityschema_cache.read() {
+            Ok(cache) => match cache.get(&key) {
+                Some((valid, checked)) if checked.elapsed() < ttl => return Some(*valid),
+                _ => {}
+            },
+            _ => {}
+        }
+        let url = self.get_entityschema_validator()?;
+        let j: Value = self
+            .entityschema_client
+            .get(&url)
+            .query(&[("entityschema", entityschema), ("entity", entity)])
+            .send()
+            .ok()?
+            .json()
+            .ok()?;
+        let valid = j["valid"].as_bool()?;
+        match self.entityschema_cache.write() {
+            Ok(mut cache) => {
+                if cache.len() >= ENTITYSCHEMA_CACHE_MAX_ENTRIES {
+                    cache.retain(|_, (_, checked)| checked.elapsed() < ttl);
+                }
+                if cache.len() >= ENTITYSCHEMA_CACHE_MAX_ENTRIES {
+                    cache.clear();
+                }
+                cache.insert(key, (valid, time::Instant::now()));
+            }
+            _ => {}
+        }
+        Some(valid)
+    }
+
     fn load_site_matrix() -> Value {
         let api =
             Api::new("https://www.wikidata.org/w/api.php").expect("Can't talk to Wikidata API");
@@ -619,6 +866,62 @@ mod tests {
         STATE.clone()
     }
 
+    #[test]
+    fn test_bootstrap_tool_db_schema_disabled() {
+        let mut state = (*get_state()).clone();
+        state.config["bootstrap_tool_db_schema"] = json!(false);
+        assert_eq!(state.bootstrap_tool_db_schema(), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_new_from_parts() {
+        let config = json!({"user": "u", "password": "p"});
+        let site_matrix = json!({"sitematrix": {
+            "count": 1,
+            "0": {"code": "he", "dir": "rtl", "site": [{"url": "https://he.wikipedia.org", "dbname": "hewiki"}]},
+            "specials": []
+        }});
+        let state = AppState::new_from_parts(
+            &config,
+            Some("<html><body></body></html>".to_string()),
+            Some(site_matrix),
+        );
+        assert_eq!(
+            state.get_main_page("he".to_string()),
+            "<html dir='rtl' lang='he'><body></body></html>"
+        );
+        assert_eq!(
+            state.get_server_url_for_wiki(&"hewiki".to_string()),
+            Ok("https://he.wikipedia.org".to_string())
+        );
+        assert!(!state.db_pool.is_empty());
+    }
+
+    #[test]
+    fn test_entity_namespaces() {
+        let state = get_state();
+        assert_eq!(state.get_entity_namespace("Q42"), Some(0));
+        assert_eq!(state.get_entity_namespace("P31"), Some(120));
+        assert_eq!(state.get_entity_namespace("L7"), Some(146));
+        assert_eq!(state.get_entity_namespace("M12345"), None);
+        assert_eq!(state.get_entity_namespace("Z10000"), None);
+        assert_eq!(state.get_entity_namespace("Q"), None);
+        assert_eq!(state.get_entity_namespace("42"), None);
+
+        let mut config = state.config.clone();
+        config["entity_namespaces"] = json!({"Z": 0, "Q": 1});
+        let entity_namespaces = AppState::entity_namespaces_from_config(&config).unwrap();
+        assert_eq!(entity_namespaces.get("Z"), Some(&0));
+        assert_eq!(entity_namespaces.get("Q"), Some(&1));
+        assert_eq!(entity_namespaces.get("P"), Some(&120));
+        let mut custom_state = (*state).clone();
+        custom_state.entity_namespaces = entity_namespaces;
+        assert_eq!(custom_state.get_entity_namespace("Z10000"), Some(0));
+        assert_eq!(custom_state.get_entity_namespace("Q42"), Some(1));
+        config["entity_namespaces"] = json!({"z": 0});
+        assert!(AppState::entity_namespaces_from_config(&config).is_err());
+    }
+
     #[test]
     fn test_get_wiki_for_server_url() {
         let state = get_state();
@@ -659,4 +962,33 @@ mod tests {
         assert!(!state.is_language_rtl("de"));
         assert!(state.is_language_rtl("he"));
     }
+
+    #[test]
+    fn test_db_connection_retry_from_config() {
+        assert_eq!(
+            AppState::db_connection_retry_from_config(&json!({})),
+            Ok((
+                MYSQL_MAX_CONNECTION_ATTEMPTS,
+                MYSQL_CONNECTION_INITIAL_DELAY_MS,
+                MYSQL_CONNECTION_MAX_DELAY_MS
+            ))
+        );
+        assert_eq!(
+            AppState::db_connection_retry_from_config(&json!({
+                "mysql_max_connection_attempts": 3,
+                "mysql_connection_initial_delay_ms": 10,
+                "mysql_connection_max_delay_ms": 20
+            })),
+            Ok((3, 10, 20))
+        );
+        assert!(AppState::db_connection_retry_from_config(
+            &json!({"mysql_max_connection_attempts": 0})
+        )
+        .is_err());
+        assert!(AppState::db_connection_retry_from_config(&json!({
+            "mysql_connection_initial_delay_ms": 1000,
+            "mysql_connection_max_delay_ms": 10
+        }))
+        .is_err());
+    }
 }
@@ -1,13 +1,15 @@
+use crate::form_parameters::FormParameters;
 use crate::pagelist::*;
-use crate::platform::Platform;
+use crate::platform::{Platform, PAGE_BATCH_SIZE};
 use mysql as my;
 use rayon::prelude::*;
+use regex::Regex;
 use serde_json::value::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::time;
-use wikibase::mediawiki::api::Api;
+use wikibase::mediawiki::api::{Api, NamespaceID};
 use wikibase::mediawiki::title::Title;
 
 pub type SQLtuple = (String, Vec<String>);
@@ -16,6 +18,28 @@ pub trait DataSource {
     fn can_run(&self, platform: &Platform) -> bool;
     fn run(&mut self, platform: &Platform) -> Result<PageList, String>;
     fn name(&self) -> String;
+
+    /// Describes parameters that are partially present but insufficient for `can_run`
+    fn unmet_requirements(&self, _platform: &Platform) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Reports a near-miss if some, but not all, of the required parameters are present
+pub fn unmet_required_params(platform: &Platform, source: &str, required: &[&str]) -> Vec<String> {
+    let (present, missing): (Vec<&str>, Vec<&str>) = required
+        .iter()
+        .cloned()
+        .partition(|param| platform.has_param(param));
+    if present.is_empty() || missing.is_empty() {
+        return vec![];
+    }
+    vec![format!(
+        "{}: {} present but {} missing",
+        source,
+        present.join(", "),
+        missing.join(", ")
+    )]
 }
 
 //________________________________________________________________________________________________________________________
@@ -32,13 +56,26 @@ impl DataSource for SourceLabels {
         platform.has_param("labels_yes") || platform.has_param("labels_any")
     }
 
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        if platform.has_param("labels_no") && !self.can_run(platform) {
+            vec![format!(
+                "{}: labels_no present but labels_yes or labels_any missing",
+                self.name()
+            )]
+        } else {
+            vec![]
+        }
+    }
+
     fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
         let state = platform.state();
         let db_user_pass = state
             .get_db_mutex()
             .lock()
             .map_err(|e| format!("{:?}", e))?;
-        let sql = platform.get_label_sql();
+        let sql = platform
+            .get_label_sql(&0)
+            .ok_or(format!("{}: No label filters", self.name()))?;
         let mut conn = platform
             .state()
             .get_wiki_db_connection(&db_user_pass, &"wikidatawiki".to_string())?;
@@ -52,7 +89,7 @@ impl DataSource for SourceLabels {
             .filter_map(|row_result| row_result.ok())
             .filter_map(|row| my::from_row_opt::<Vec<u8>>(row).ok())
             .map(|row| String::from_utf8_lossy(&row).into_owned())
-            .filter_map(|row| Platform::entry_from_entity(&row))
+            .filter_map(|row| platform.entry_from_entity(&row))
             .for_each(|entry| ret.add_entry(entry).unwrap_or(()));
         Ok(ret)
     }
@@ -78,6 +115,14 @@ impl DataSource for SourceWikidata {
         platform.has_param("wpiu_no_statements") && platform.has_param("wikidata_source_sites")
     }
 
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        unmet_required_params(
+            platform,
+            &self.name(),
+            &["wpiu_no_statements", "wikidata_source_sites"],
+        )
+    }
+
     fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
         let no_statements = platform.has_param("wpiu_no_statements");
         let sites = platform
@@ -120,7 +165,7 @@ impl DataSource for SourceWikidata {
             .filter_map(|row_inner| match my::from_row_opt::<usize>(row_inner) {
                 Ok(ips_item_id) => {
                     let term_full_entity_id = format!("Q{}", ips_item_id);
-                    Platform::entry_from_entity(&term_full_entity_id)
+                    platform.entry_from_entity(&term_full_entity_id)
                 }
                 Err(_e) => {
                     // TODO error log for failed usize conversion?
@@ -215,6 +260,14 @@ impl DataSource for SourceSearch {
             && platform.has_param("search_max_results")
     }
 
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        unmet_required_params(
+            platform,
+            &self.name(),
+            &["search_query", "search_wiki", "search_max_results"],
+        )
+    }
+
     fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
         let wiki = platform
             .get_param("search_wiki")
@@ -230,30 +283,207 @@ impl DataSource for SourceSearch {
             Ok(max) => max,
             Err(e) => return Err(format!("{:?}", e)),
         };
+        if max > SEARCH_MAX_RESULTS {
+            platform.note_truncation(format!(
+                "search_max_results is limited to {}",
+                SEARCH_MAX_RESULTS
+            ))?;
+        }
+        let max = std::cmp::min(max, SEARCH_MAX_RESULTS);
         let api = platform.state().get_api_for_wiki(wiki.to_string())?;
         let srlimit = if max > 500 { 500 } else { max };
         let srlimit = format!("{}", srlimit);
-        let namespace_ids = platform
-            .form_parameters()
-            .ns
-            .par_iter()
-            .cloned()
-            .collect::<Vec<usize>>();
-        let namespace_ids = if namespace_ids.is_empty() {
-            "*".to_string()
-        } else {
-            namespace_ids
+        let namespace_ids = match platform.has_param("search_namespaces") {
+            true => {
+                let namespace_ids = namespace_ids_from_param(platform, "search_namespaces")?;
+                match namespace_ids.is_empty() {
+                    true => "*".to_string(),
+                    false => namespace_ids
+                        .iter()
+                        .map(|ns| ns.to_string())
+                        .collect::<Vec<String>>()
+                        .join("|"),
+                }
+            }
+            false => search_namespace_ids(platform),
+        };
+        let sort = platform.get_param("search_sort");
+        let mut params = vec![
+            ("action", "query"),
+            ("list", "search"),
+            ("srlimit", srlimit.as_str()),
+            ("srsearch", query.as_str()),
+            ("srnamespace", namespace_ids.as_str()),
+        ];
+        if let Some(sort) = &sort {
+            if !SEARCH_SORT_ORDERS.contains(&sort.as_str()) {
+                return Err(format!("search_sort: unknown sort order '{}'", sort));
+            }
+            params.push(("srsort", sort.as_str()));
+        }
+        let params = api.params_into(&params);
+        let titles = Self::collect_results(max, &mut |continue_params| {
+            let mut params = params.clone();
+            params.extend(continue_params.clone());
+            api.get_query_api_json(&params)
+                .map_err(|e| format!("{:?}", e))
+        })?;
+        let ret = PageList::new_from_wiki(&wiki);
+        titles
+            .iter()
+            .map(|title| PageListEntry::new(title.to_owned()))
+            .for_each(|entry| ret.add_entry(entry).unwrap_or(()));
+        if ret.is_empty()? {
+            platform.warn(format!("<span tt='warn_search'></span>"))?;
+        }
+        Ok(ret)
+    }
+}
+
+impl SourceSearch {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Fetches result pages, following `continue`, until `max` titles are collected or the
+    /// results are exhausted. Stops early if a continuation repeats, or a page adds no results.
+    pub fn collect_results(
+        max: usize,
+        fetch: &mut dyn FnMut(&HashMap<String, String>) -> Result<Value, String>,
+    ) -> Result<Vec<Title>, String> {
+        let mut titles: Vec<Title> = vec![];
+        let mut continue_params: HashMap<String, String> = HashMap::new();
+        let mut seen_continuations: HashSet<String> = HashSet::new();
+        loop {
+            let result = fetch(&continue_params)?;
+            let new_titles = Api::result_array_to_titles(&result["query"]);
+            if new_titles.is_empty() {
+                break;
+            }
+            titles.extend(new_titles);
+            if titles.len() >= max {
+                break;
+            }
+            let continuation = match result["continue"].as_object() {
+                Some(continuation) => continuation,
+                None => break,
+            };
+            if !seen_continuations.insert(result["continue"].to_string()) {
+                break;
+            }
+            continue_params = continuation
                 .iter()
-                .map(|i| i.to_string())
-                .collect::<Vec<String>>()
-                .join(",")
+                .map(|(k, v)| match v {
+                    Value::String(s) => (k.to_string(), s.to_string()),
+                    other => (k.to_string(), other.to_string()),
+                })
+                .collect();
+        }
+        titles.truncate(max);
+        Ok(titles)
+    }
+}
+
+/// Hard cap for `search_max_results`; CirrusSearch will not page beyond this anyway
+pub static SEARCH_MAX_RESULTS: usize = 10000;
+
+/// Sort orders supported by CirrusSearch, for `search_sort`
+static SEARCH_SORT_ORDERS: &[&str] = &[
+    "relevance",
+    "last_edit_desc",
+    "last_edit_asc",
+    "create_timestamp_desc",
+    "create_timestamp_asc",
+    "incoming_links_desc",
+    "incoming_links_asc",
+    "just_match",
+    "random",
+    "none",
+];
+
+/// Parses a comma- or pipe-separated list of namespace IDs from the parameter `key`
+fn namespace_ids_from_param(platform: &Platform, key: &str) -> Result<Vec<NamespaceID>, String> {
+    platform
+        .get_param_blank(key)
+        .split(|c| c == ',' || c == '|')
+        .map(|ns| ns.trim())
+        .filter(|ns| !ns.is_empty())
+        .map(|ns| {
+            ns.parse::<NamespaceID>()
+                .map_err(|_| format!("{}: '{}' is not a namespace ID", key, ns))
+        })
+        .collect()
+}
+
+/// The `srnamespace` value for the namespaces selected in the form, or all namespaces
+fn search_namespace_ids(platform: &Platform) -> String {
+    let namespace_ids = platform
+        .form_parameters()
+        .ns
+        .par_iter()
+        .cloned()
+        .collect::<Vec<usize>>();
+    if namespace_ids.is_empty() {
+        "*".to_string()
+    } else {
+        namespace_ids
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// CirrusSearch will not page beyond this many results for any query
+pub static DEEPCAT_MAX_RESULTS: usize = 10000;
+
+/// Runs a CirrusSearch query using the `deepcat:` keyword, e.g. `deepcat:Physics incategory:Stubs`.
+/// This avoids replica lag, but CirrusSearch only follows the category tree to a depth of 5,
+/// and gives up on trees with more than 256 categories. Deeper or larger trees need the
+/// database-based category source, which honors `depth` and `max_subcats_per_level`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceDeepcat {}
+
+impl DataSource for SourceDeepcat {
+    fn name(&self) -> String {
+        "deepcat".to_string()
+    }
+
+    fn can_run(&self, platform: &Platform) -> bool {
+        platform.has_param("deepcat_wiki") && platform.has_param("deepcat_query")
+    }
+
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        unmet_required_params(platform, &self.name(), &["deepcat_wiki", "deepcat_query"])
+    }
+
+    fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
+        let wiki = platform
+            .get_param("deepcat_wiki")
+            .ok_or(format!("Missing parameter 'deepcat_wiki'"))?;
+        let query = platform
+            .get_param("deepcat_query")
+            .ok_or(format!("Missing parameter 'deepcat_query'"))?;
+        let max = match platform.get_param("deepcat_max_results") {
+            Some(max) => max
+                .parse::<usize>()
+                .map_err(|e| format!("deepcat_max_results: {:?}", e))?,
+            None => DEEPCAT_MAX_RESULTS,
         };
+        let max = std::cmp::min(max, DEEPCAT_MAX_RESULTS);
+        let api = platform.state().get_api_for_wiki(wiki.to_string())?;
+        let srlimit = format!("{}", std::cmp::min(max, 500));
+        let namespace_ids = search_namespace_ids(platform);
         let params = api.params_into(&vec![
             ("action", "query"),
             ("list", "search"),
             ("srlimit", srlimit.as_str()),
             ("srsearch", query.as_str()),
             ("srnamespace", namespace_ids.as_str()),
+            ("srinfo", "totalhits"),
+            ("srprop", ""),
         ]);
         let result = match api.get_query_api_json_limit(&params, Some(max)) {
             Ok(result) => result,
@@ -263,8 +493,20 @@ impl DataSource for SourceSearch {
         let ret = PageList::new_from_wiki(&wiki);
         titles
             .iter()
+            .take(max)
             .map(|title| PageListEntry::new(title.to_owned()))
             .for_each(|entry| ret.add_entry(entry).unwrap_or(()));
+        let total_hits = result["query"]["searchinfo"]["totalhits"]
+            .as_u64()
+            .unwrap_or(0) as usize;
+        if total_hits > ret.len()? {
+            let note = format!(
+                "deepcat search returned {} of {} results",
+                ret.len()?,
+                total_hits
+            );
+            platform.note_truncation(note)?;
+        }
         if ret.is_empty()? {
             platform.warn(format!("<span tt='warn_search'></span>"))?;
         }
@@ -272,10 +514,429 @@ impl DataSource for SourceSearch {
     }
 }
 
-impl SourceSearch {
+impl SourceDeepcat {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Pages linking to `linkshere_title`, like Special:WhatLinksHere.
+/// With `linkshere_redirects`, pages linking to a redirect to the title are included as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLinksHere {}
+
+impl DataSource for SourceLinksHere {
+    fn name(&self) -> String {
+        "linkshere".to_string()
+    }
+
+    fn can_run(&self, platform: &Platform) -> bool {
+        platform.has_param("linkshere_title") && platform.has_param("linkshere_wiki")
+    }
+
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        unmet_required_params(
+            platform,
+            &self.name(),
+            &["linkshere_title", "linkshere_wiki"],
+        )
+    }
+
+    fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
+        let wiki = platform
+            .get_param("linkshere_wiki")
+            .ok_or(format!("Missing parameter 'linkshere_wiki'"))?;
+        let title = platform
+            .get_param("linkshere_title")
+            .ok_or(format!("Missing parameter 'linkshere_title'"))?;
+        let namespace_ids = namespace_ids_from_param(platform, "linkshere_namespaces")?;
+        let api = platform.state().get_api_for_wiki(wiki.to_string())?;
+        let target = Platform::normalize_title(&title, &api);
+        let target_params = vec![
+            target.namespace_id().to_string(),
+            target.with_underscores().to_string(),
+        ];
+
+        let namespace_sql = namespace_condition("p.page_namespace", &namespace_ids);
+        let mut sql: SQLtuple = (
+            format!("SELECT DISTINCT p.page_title,p.page_namespace FROM page p,pagelinks WHERE pl_from=p.page_id AND pl_namespace=? AND pl_title=?{}",namespace_sql),
+            target_params.to_owned(),
+        );
+        if platform.has_param("linkshere_redirects") {
+            sql.0 += &format!(" UNION SELECT DISTINCT p.page_title,p.page_namespace FROM page p,pagelinks,page r,redirect WHERE pl_from=p.page_id AND pl_namespace=r.page_namespace AND pl_title=r.page_title AND r.page_id=rd_from AND rd_interwiki='' AND rd_namespace=? AND rd_title=?{}",namespace_sql);
+            sql.1.append(&mut target_params.to_owned());
+        }
+        page_list_from_query(platform, &wiki, sql)
+    }
+}
+
+impl SourceLinksHere {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Pages transcluding `transclusions_title`. A title without namespace prefix is a template.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceTransclusions {}
+
+impl DataSource for SourceTransclusions {
+    fn name(&self) -> String {
+        "transclusions".to_string()
+    }
+
+    fn can_run(&self, platform: &Platform) -> bool {
+        platform.has_param("transclusions_title") && platform.has_param("transclusions_wiki")
+    }
+
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        unmet_required_params(
+            platform,
+            &self.name(),
+            &["transclusions_title", "transclusions_wiki"],
+        )
+    }
+
+    fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
+        let wiki = platform
+            .get_param("transclusions_wiki")
+            .ok_or(format!("Missing parameter 'transclusions_wiki'"))?;
+        let title = platform
+            .get_param("transclusions_title")
+            .ok_or(format!("Missing parameter 'transclusions_title'"))?;
+        let namespace_ids = namespace_ids_from_param(platform, "transclusions_namespaces")?;
+        let api = platform.state().get_api_for_wiki(wiki.to_string())?;
+        let template = match Platform::normalize_title(&title, &api) {
+            title if title.namespace_id() == 0 && !title.pretty().is_empty() => {
+                Title::new(title.pretty(), 10)
+            }
+            title => title,
+        };
+        let sql: SQLtuple = (
+            format!("SELECT DISTINCT p.page_title,p.page_namespace FROM page p,templatelinks WHERE tl_from=p.page_id AND tl_namespace=? AND tl_title=?{}",namespace_condition("p.page_namespace", &namespace_ids)),
+            vec![
+                template.namespace_id().to_string(),
+                template.with_underscores().to_string(),
+            ],
+        );
+        page_list_from_query(platform, &wiki, sql)
+    }
+}
+
+impl SourceTransclusions {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Runs a query returning (page_title,page_namespace) rows on `wiki`
+fn page_list_from_query(
+    platform: &Platform,
+    wiki: &String,
+    sql: SQLtuple,
+) -> Result<PageList, String> {
+    let state = platform.state();
+    let db_user_pass = state
+        .get_db_mutex()
+        .lock()
+        .map_err(|e| format!("{:?}", e))?;
+    let mut conn = state.get_wiki_db_connection(&db_user_pass, wiki)?;
+    let result = conn
+        .prep_exec(sql.0, sql.1)
+        .map_err(|e| format!("{:?}", e))?;
+
+    let ret = PageList::new_from_wiki(wiki);
+    result
+        .filter_map(|row| row.ok())
+        .filter_map(|row| my::from_row_opt::<(Vec<u8>, NamespaceID)>(row).ok())
+        .map(|(page_title, namespace_id)| {
+            let page_title = String::from_utf8_lossy(&page_title).into_owned();
+            PageListEntry::new(Title::new(&page_title, namespace_id))
+        })
+        .for_each(|entry| ret.add_entry(entry).unwrap_or(()));
+    Ok(ret)
+}
+
+/// An SQL condition restricting `column` to `namespace_ids`, or nothing if there are none
+fn namespace_condition(column: &str, namespace_ids: &[NamespaceID]) -> String {
+    match namespace_ids.is_empty() {
+        true => "".to_string(),
+        false => format!(
+            " AND {} IN ({})",
+            column,
+            namespace_ids
+                .iter()
+                .map(|ns| ns.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// At most this many pages are loaded for `contribs_user`; prolific users need a time window
+pub static CONTRIBS_MAX_PAGES: usize = 50000;
+
+/// Pages edited by `contribs_user`, each page once. `contribs_after` (inclusive) and
+/// `contribs_before` (exclusive) restrict the edits to a time window, like `created_after`/`created_before`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceContributions {}
+
+impl DataSource for SourceContributions {
+    fn name(&self) -> String {
+        "contributions".to_string()
+    }
+
+    fn can_run(&self, platform: &Platform) -> bool {
+        platform.has_param("contribs_user") && platform.has_param("contribs_wiki")
+    }
+
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        unmet_required_params(platform, &self.name(), &["contribs_user", "contribs_wiki"])
+    }
+
+    fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
+        let wiki = platform
+            .get_param("contribs_wiki")
+            .ok_or(format!("Missing parameter 'contribs_wiki'"))?;
+        let user = platform
+            .get_param("contribs_user")
+            .ok_or(format!("Missing parameter 'contribs_user'"))?;
+        let namespace_ids = namespace_ids_from_param(platform, "contribs_namespaces")?;
+        let mut sql: SQLtuple = (
+            "SELECT DISTINCT page_title,page_namespace FROM page,revision_userindex,actor WHERE rev_page=page_id AND rev_actor=actor_id AND actor_name=?".to_string(),
+            vec![Platform::normalize_user_name(&user)],
+        );
+        if let Some(after) = platform.get_param("contribs_after") {
+            sql.0 += " AND rev_timestamp>=?";
+            sql.1.push(Platform::mediawiki_timestamp_from_param(
+                "contribs_after",
+                &after,
+            )?);
+        }
+        if let Some(before) = platform.get_param("contribs_before") {
+            sql.0 += " AND rev_timestamp<?";
+            sql.1.push(Platform::mediawiki_timestamp_from_param(
+                "contribs_before",
+                &before,
+            )?);
+        }
+        sql.0 += &namespace_condition("page_namespace", &namespace_ids);
+        sql.0 += &format!(" LIMIT {}", CONTRIBS_MAX_PAGES);
+        let ret = page_list_from_query(platform, &wiki, sql)?;
+        if ret.len()? >= CONTRIBS_MAX_PAGES {
+            platform.note_truncation(format!(
+                "contributions are limited to {} pages; use contribs_after/contribs_before for a time window",
+                CONTRIBS_MAX_PAGES
+            ))?;
+        }
+        Ok(ret)
+    }
+}
+
+impl SourceContributions {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// How deeply saved queries may load other saved queries via `petscan_psid`
+pub static MAX_PETSCAN_DEPTH: usize = 3;
+
+/// Runs the saved query `petscan_psid` and uses its result.
+/// If this query names a wiki (`language`), the result is converted to that wiki.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourcePetScan {}
+
+impl DataSource for SourcePetScan {
+    fn name(&self) -> String {
+        "petscan".to_string()
+    }
+
+    fn can_run(&self, platform: &Platform) -> bool {
+        platform.has_param("petscan_psid")
+    }
+
+    fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
+        let psid = platform
+            .get_param("petscan_psid")
+            .ok_or(format!("Missing parameter 'petscan_psid'"))?;
+        let depth = platform
+            .usize_option_from_param("petscan_depth")
+            .unwrap_or(0);
+        if depth >= MAX_PETSCAN_DEPTH {
+            return Err(format!(
+                "PSID {} is nested too deeply (more than {} saved queries)",
+                psid, MAX_PETSCAN_DEPTH
+            ));
+        }
+        let query = platform.state().get_query_from_psid(&psid)?;
+        let mut form_parameters = FormParameters::outcome_from_query(&query)?;
+        form_parameters
+            .params
+            .insert("petscan_depth".to_string(), (depth + 1).to_string());
+        let mut nested = Platform::new_from_parameters(&form_parameters, platform.state());
+        nested.run().map_err(|e| format!("PSID {}: {}", psid, e))?;
+        let ret = match nested.take_result() {
+            Some(result) => result,
+            None => return Err(format!("PSID {} has no result", psid)),
+        };
+        if platform.has_param("language") {
+            if let Some(wiki) = platform.get_main_wiki() {
+                ret.convert_to_wiki(&wiki, platform)?;
+            }
+        }
+        Ok(ret)
+    }
+}
+
+impl SourcePetScan {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Uses the latest result of Quarry query `quarry_id`. The pages are taken from the
+/// `quarry_col_title` (default "page_title") and `quarry_col_ns` (default "page_namespace")
+/// columns; without a namespace column, namespace 0 is assumed.
+/// Only the first QUARRY_MAX_ROWS rows are used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceQuarry {}
+
+pub static QUARRY_MAX_ROWS: usize = 50000;
+
+impl DataSource for SourceQuarry {
+    fn name(&self) -> String {
+        "quarry".to_string()
+    }
+
+    fn can_run(&self, platform: &Platform) -> bool {
+        platform.has_param("quarry_id") && platform.has_param("quarry_wiki")
+    }
+
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        unmet_required_params(platform, &self.name(), &["quarry_id", "quarry_wiki"])
+    }
+
+    fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
+        let quarry_id = platform
+            .get_param("quarry_id")
+            .ok_or(format!("Missing parameter 'quarry_id'"))?
+            .parse::<usize>()
+            .map_err(|_| format!("quarry_id must be a number"))?;
+        let wiki = platform
+            .get_param("quarry_wiki")
+            .ok_or(format!("Missing parameter 'quarry_wiki'"))?;
+        let url = format!(
+            "https://quarry.wmcloud.org/query/{}/result/latest/0/json",
+            quarry_id
+        );
+        let client = reqwest::blocking::ClientBuilder::new()
+            .timeout(Some(time::Duration::from_secs(120)))
+            .build()
+            .map_err(|e| format!("Quarry: {:?}", e))?;
+        let result: Value = client
+            .get(&url)
+            .send()
+            .map_err(|e| format!("Quarry {}: {:?}", quarry_id, e))?
+            .json()
+            .map_err(|e| format!("Quarry {} JSON: {:?}", quarry_id, e))?;
+        let rows = result["rows"]
+            .as_array()
+            .map(|rows| rows.len())
+            .unwrap_or(0);
+        if rows > QUARRY_MAX_ROWS {
+            platform.note_truncation(format!(
+                "Quarry {}: only the first {} of {} rows are used",
+                quarry_id, QUARRY_MAX_ROWS, rows
+            ))?;
+        }
+        let ret = Self::page_list_from_result(
+            &wiki,
+            &result,
+            &platform.get_param_default("quarry_col_title", "page_title"),
+            platform
+                .get_param("quarry_col_ns")
+                .as_ref()
+                .map(|s| s.as_str()),
+            QUARRY_MAX_ROWS,
+        )?;
+        if ret.is_empty()? {
+            platform.warn(format!("Quarry {} returned no pages", quarry_id))?;
+        }
+        Ok(ret)
+    }
+}
+
+impl SourceQuarry {
     pub fn new() -> Self {
         Self {}
     }
+
+    /// Builds a page list from a Quarry result, which has "headers" and "rows" arrays.
+    /// `col_ns` must exist if given; otherwise "page_namespace" is used if it exists.
+    /// Rows beyond `max_rows` are ignored.
+    pub fn page_list_from_result(
+        wiki: &str,
+        result: &Value,
+        col_title: &str,
+        col_ns: Option<&str>,
+        max_rows: usize,
+    ) -> Result<PageList, String> {
+        let headers: Vec<&str> = result["headers"]
+            .as_array()
+            .ok_or(format!("Quarry result has no headers"))?
+            .iter()
+            .filter_map(|header| header.as_str())
+            .collect();
+        let column = |name: &str| headers.iter().position(|header| *header == name);
+        let col_title = column(col_title).ok_or(format!(
+            "Quarry result has no column '{}', only: {}",
+            col_title,
+            headers.join(", ")
+        ))?;
+        let col_ns = match col_ns {
+            Some(col_ns) => Some(column(col_ns).ok_or(format!(
+                "Quarry result has no column '{}', only: {}",
+                col_ns,
+                headers.join(", ")
+            ))?),
+            None => column("page_namespace"),
+        };
+        let ret = PageList::new_from_wiki(wiki);
+        result["rows"]
+            .as_array()
+            .ok_or(format!("Quarry result has no rows"))?
+            .iter()
+            .take(max_rows)
+            .filter_map(|row| {
+                let title = match &row[col_title] {
+                    Value::String(s) => s.to_string(),
+                    Value::Number(n) => n.to_string(),
+                    _ => return None,
+                };
+                let namespace_id = match col_ns {
+                    Some(col_ns) => match &row[col_ns] {
+                        Value::Number(n) => n.as_i64()?,
+                        Value::String(s) => s.parse::<NamespaceID>().ok()?,
+                        _ => return None,
+                    },
+                    None => 0,
+                };
+                Some(PageListEntry::new(Title::new(&title, namespace_id)))
+            })
+            .for_each(|entry| ret.add_entry(entry).unwrap_or(()));
+        Ok(ret)
+    }
 }
 
 //________________________________________________________________________________________________________________________
@@ -292,6 +953,10 @@ impl DataSource for SourceManual {
         platform.has_param("manual_list") && platform.has_param("manual_list_wiki")
     }
 
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        unmet_required_params(platform, &self.name(), &["manual_list", "manual_list_wiki"])
+    }
+
     fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
         let wiki = platform
             .get_param("manual_list_wiki")
@@ -305,7 +970,7 @@ impl DataSource for SourceManual {
             .filter_map(|line| {
                 let line = line.trim().to_string();
                 if !line.is_empty() {
-                    let title = Title::new_from_full(&line, &api);
+                    let title = Platform::normalize_title(&line, &api);
                     let entry = PageListEntry::new(title);
                     Some(entry)
                 } else {
@@ -313,6 +978,9 @@ impl DataSource for SourceManual {
                 }
             })
             .for_each(|entry| ret.add_entry(entry).unwrap_or(()));
+        if ret.is_wikidata() {
+            self.check_wikidata_entities(platform, &ret)?;
+        }
         Ok(ret)
     }
 }
@@ -321,6 +989,85 @@ impl SourceManual {
     pub fn new() -> Self {
         Self {}
     }
+
+    /// Removes entities that do not exist on Wikidata, or were merged into another one.
+    /// With "wd_resolve_redirects", merged entities are replaced by their target instead.
+    fn check_wikidata_entities(
+        &self,
+        platform: &Platform,
+        pagelist: &PageList,
+    ) -> Result<(), String> {
+        let batches: Vec<SQLtuple> = pagelist
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace,rd_title,rd_namespace FROM page LEFT JOIN redirect ON rd_from=page_id WHERE ".to_string() + &sql.0;
+                sql.to_owned()
+            })
+            .collect();
+        let mut existing: HashMap<Title, Option<Title>> = HashMap::new();
+        pagelist
+            .run_batch_queries(&platform.state(), batches)?
+            .into_iter()
+            .filter_map(|row| {
+                my::from_row_opt::<(String, NamespaceID, Option<String>, Option<NamespaceID>)>(row)
+                    .ok()
+            })
+            .for_each(|(page_title, page_namespace, rd_title, rd_namespace)| {
+                let target = match (rd_title, rd_namespace) {
+                    (Some(rd_title), Some(rd_namespace)) => {
+                        Some(Title::new(&rd_title, rd_namespace))
+                    }
+                    _ => None,
+                };
+                existing.insert(Title::new(&page_title, page_namespace), target);
+            });
+
+        let resolve_redirects = platform.has_param("wd_resolve_redirects");
+        let mut missing = 0;
+        let mut redirects = 0;
+        let entries: HashSet<PageListEntry> = pagelist
+            .entries()
+            .write()
+            .map_err(|e| format!("{:?}", e))?
+            .drain()
+            .filter_map(|entry| match existing.get(entry.title()) {
+                Some(Some(target)) => {
+                    redirects += 1;
+                    match resolve_redirects {
+                        true => Some(PageListEntry::new(target.to_owned())),
+                        false => None,
+                    }
+                }
+                Some(None) => Some(entry),
+                None => {
+                    missing += 1;
+                    None
+                }
+            })
+            .collect();
+        pagelist.set_entries(entries)?;
+
+        if missing > 0 {
+            platform.warn(format!(
+                "{} entities in the manual list do not exist",
+                missing
+            ))?;
+        }
+        if redirects > 0 {
+            platform.warn(match resolve_redirects {
+                true => format!(
+                    "{} redirected entities in the manual list were resolved",
+                    redirects
+                ),
+                false => format!(
+                    "{} redirected entities in the manual list were removed",
+                    redirects
+                ),
+            })?;
+        }
+        Ok(())
+    }
 }
 
 //________________________________________________________________________________________________________________________
@@ -369,6 +1116,8 @@ impl DataSource for SourceSparql {
         let mut header = String::new();
         let mut binding = String::new();
         let mut first_var = String::new();
+        let mut extra_vars: Vec<String> = vec![];
+        let mut mediainfo: Vec<(u32, Vec<(String, String)>)> = vec![];
         for line in reader.lines() {
             let line = match line {
                 Ok(line) => line,
@@ -386,6 +1135,17 @@ impl DataSource for SourceSparql {
                         .as_str()
                         .ok_or(format!("No variables found in SPARQL result"))?
                         .to_string();
+                    if platform.has_param("sparql_extra_vars") {
+                        extra_vars = j["head"]["vars"]
+                            .as_array()
+                            .unwrap_or(&vec![])
+                            .iter()
+                            .skip(1)
+                            .filter_map(|v| v.as_str())
+                            .map(|v| v.to_string())
+                            .collect();
+                        platform.set_sparql_extra_vars(extra_vars.to_owned())?;
+                    }
                 }
                 "    }, {" | "    } ]" => match mode {
                     0 => header += &line,
@@ -393,13 +1153,32 @@ impl DataSource for SourceSparql {
                         binding = "{".to_string() + &binding + "}";
                         let j: Value = serde_json::from_str(&binding).unwrap_or(json!({}));
                         binding.clear();
+                        let extra_values = || {
+                            extra_vars
+                                .iter()
+                                .filter_map(|var| {
+                                    j[var]["value"]
+                                        .as_str()
+                                        .map(|value| (var.to_string(), value.to_string()))
+                                })
+                                .collect::<Vec<(String, String)>>()
+                        };
                         match j[&first_var]["value"].as_str() {
-                            Some(entity_url) => match api.extract_entity_from_uri(entity_url) {
-                                Ok(entity) => match Platform::entry_from_entity(&entity) {
-                                    Some(entry) => ret.add_entry(entry).unwrap_or(()),
+                            Some(entity_url) => match Self::mediainfo_page_id(entity_url) {
+                                Some(page_id) => mediainfo.push((page_id, extra_values())),
+                                None => match api
+                                    .extract_entity_from_uri(entity_url)
+                                    .ok()
+                                    .and_then(|entity| platform.entry_from_entity(&entity))
+                                {
+                                    Some(mut entry) => {
+                                        extra_values()
+                                            .iter()
+                                            .for_each(|(var, value)| entry.set_extra(var, value));
+                                        ret.add_entry(entry).unwrap_or(())
+                                    }
                                     None => {}
                                 },
-                                _ => {}
                             },
                             None => {}
                         }
@@ -414,6 +1193,17 @@ impl DataSource for SourceSparql {
             }
         }
 
+        if !mediainfo.is_empty() {
+            // A page list can only hold pages of one wiki
+            if ret.is_empty()? {
+                return Self::mediainfo_files(platform, &mediainfo);
+            }
+            platform.warn(format!(
+                "{} Commons MediaInfo entities were removed from the SPARQL result, as it also contains Wikidata entities",
+                mediainfo.len()
+            ))?;
+        }
+
         Ok(ret)
     }
 
@@ -437,7 +1227,7 @@ impl DataSource for SourceSparql {
         let ret = PageList::new_from_wiki("wikidatawiki");
         api.entities_from_sparql_result(&result, first_var)
             .par_iter()
-            .filter_map(|e| Platform::entry_from_entity(e))
+            .filter_map(|e| platform.entry_from_entity(e))
             .for_each(|entry| ret.add_entry(entry));
         if ret.is_empty() {
             platform.warn(format!("<span tt='warn_sparql'></span>"));
@@ -451,4 +1241,68 @@ impl SourceSparql {
     pub fn new() -> Self {
         Self {}
     }
+
+    /// Returns the page ID for a Commons MediaInfo URI like
+    /// `https://commons.wikimedia.org/entity/M123`; MediaInfo entities live in a slot of the
+    /// file page whose page ID is the numeric part of the entity ID.
+    pub fn mediainfo_page_id(uri: &str) -> Option<u32> {
+        lazy_static! {
+            static ref RE_MEDIAINFO: Regex =
+                Regex::new(r"^https?://commons\.wikimedia\.org/entity/M(\d+)$")
+                    .expect("SourceSparql::mediainfo_page_id: Regex is invalid");
+        }
+        RE_MEDIAINFO
+            .captures(uri)?
+            .get(1)?
+            .as_str()
+            .parse::<u32>()
+            .ok()
+    }
+
+    /// Resolves MediaInfo page IDs (with their extra SPARQL values) to the Commons files
+    fn mediainfo_files(
+        platform: &Platform,
+        mediainfo: &[(u32, Vec<(String, String)>)],
+    ) -> Result<PageList, String> {
+        let wiki = "commonswiki".to_string();
+        let state = platform.state();
+        let db_user_pass = state
+            .get_db_mutex()
+            .lock()
+            .map_err(|e| format!("{:?}", e))?;
+        let mut conn = state.get_wiki_db_connection(&db_user_pass, &wiki)?;
+        let extra_values: HashMap<u32, &Vec<(String, String)>> = mediainfo
+            .iter()
+            .map(|(page_id, values)| (*page_id, values))
+            .collect();
+        let ret = PageList::new_from_wiki(&wiki);
+        for chunk in mediainfo.chunks(PAGE_BATCH_SIZE) {
+            let page_ids: Vec<String> = chunk
+                .iter()
+                .map(|(page_id, _)| page_id.to_string())
+                .collect();
+            let sql = format!(
+                "SELECT page_id,page_title FROM page WHERE page_namespace=6 AND page_id IN ({})",
+                Platform::get_questionmarks(page_ids.len())
+            );
+            let result = conn
+                .prep_exec(sql, page_ids)
+                .map_err(|e| format!("{:?}", e))?;
+            result
+                .filter_map(|row| row.ok())
+                .filter_map(|row| my::from_row_opt::<(u32, Vec<u8>)>(row).ok())
+                .for_each(|(page_id, page_title)| {
+                    let page_title = String::from_utf8_lossy(&page_title).into_owned();
+                    let mut entry = PageListEntry::new(Title::new(&page_title, 6));
+                    entry.page_id = Some(page_id);
+                    if let Some(values) = extra_values.get(&page_id) {
+                        values
+                            .iter()
+                            .for_each(|(var, value)| entry.set_extra(var, value));
+                    }
+                    ret.add_entry(entry).unwrap_or(())
+                });
+        }
+        Ok(ret)
+    }
 }
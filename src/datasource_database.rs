@@ -14,6 +14,7 @@ use wikibase::mediawiki::api::{Api, NamespaceID};
 use wikibase::mediawiki::title::Title;
 
 static MAX_CATEGORY_BATCH_SIZE: usize = 2500;
+static DEFAULT_MAX_SUBCATS_PER_LEVEL: usize = 10000;
 
 #[derive(Debug)]
 struct DsdbParams {
@@ -42,6 +43,7 @@ pub struct SourceDatabaseParameters {
     links_to_any: Vec<String>,
     links_to_none: Vec<String>,
     templates_yes: Vec<String>,
+    templates_yes_any: bool,
     templates_any: Vec<String>,
     templates_no: Vec<String>,
     templates_yes_talk_page: bool,
@@ -56,6 +58,8 @@ pub struct SourceDatabaseParameters {
     last_edit_anon: String,
     last_edit_flagged: String,
     redirects: String,
+    protection: String,
+    content_models: Vec<String>,
     page_wikidata_item: String,
     larger: Option<usize>,
     smaller: Option<usize>,
@@ -66,6 +70,9 @@ pub struct SourceDatabaseParameters {
     cat_pos: Vec<String>,
     cat_neg: Vec<String>,
     depth: u16,
+    max_subcats_per_level: usize,
+    exclude_subcats: HashSet<String>,
+    category_sortkey: bool,
     max_age: Option<i64>,
     only_new_since: bool,
     before: String,
@@ -87,6 +94,7 @@ impl SourceDatabaseParameters {
             links_to_any: vec![],
             links_to_none: vec![],
             templates_yes: vec![],
+            templates_yes_any: false,
             templates_any: vec![],
             templates_no: vec![],
             templates_yes_talk_page: false,
@@ -102,6 +110,8 @@ impl SourceDatabaseParameters {
             last_edit_anon: "both".to_string(),
             last_edit_flagged: "both".to_string(),
             redirects: "".to_string(),
+            protection: "".to_string(),
+            content_models: vec![],
             larger: None,
             smaller: None,
             minlinks: None,
@@ -111,6 +121,9 @@ impl SourceDatabaseParameters {
             cat_pos: vec![],
             cat_neg: vec![],
             depth: 0,
+            max_subcats_per_level: DEFAULT_MAX_SUBCATS_PER_LEVEL,
+            exclude_subcats: HashSet::new(),
+            category_sortkey: false,
             max_age: None,
             only_new_since: false,
             before: "".to_string(),
@@ -142,6 +155,11 @@ impl SourceDatabaseParameters {
             }
             None => "subset".to_string(),
         };
+        // "categories_or" makes a page qualify if it is in any of the positive categories.
+        // Each category is still expanded to its own depth, so this is a union of category trees.
+        if platform.has_param("categories_or") {
+            combine = "union".to_string();
+        }
         let cat_pos = platform.get_param_as_vec("categories", "\n");
         if cat_pos.len() == 1 && combine == "subset" {
             combine = "union".to_string(); // Easier to construct
@@ -155,6 +173,9 @@ impl SourceDatabaseParameters {
             before: platform.get_param_blank("before"),
             after: platform.get_param_blank("after"),
             templates_yes: vec![],
+            // "templates_yes_mode=any" makes a page qualify if it has any of the templates_yes,
+            // instead of all of them
+            templates_yes_any: platform.get_param_blank("templates_yes_mode") == "any",
             templates_any: vec![],
             templates_no: vec![],
             templates_yes_talk_page: platform.has_param("templates_use_talk_yes"),
@@ -175,7 +196,11 @@ impl SourceDatabaseParameters {
             ores_type: platform.get_param_blank("ores_type"),
             ores_prediction: platform.get_param_default("ores_prediction", "any"),
             depth: depth,
+            max_subcats_per_level: platform
+                .usize_option_from_param("max_subcats_per_level")
+                .unwrap_or(DEFAULT_MAX_SUBCATS_PER_LEVEL),
             cat_pos: cat_pos,
+            category_sortkey: platform.has_param("add_category_sortkey"),
             cat_neg: platform.get_param_as_vec("negcats", "\n"),
             ores_prob_from: platform
                 .get_param("ores_prob_from")
@@ -184,6 +209,8 @@ impl SourceDatabaseParameters {
                 .get_param("ores_prob_to")
                 .map(|x| x.parse::<f32>().unwrap_or(1.0)),
             redirects: platform.get_param_blank("show_redirects"),
+            protection: platform.get_param_blank("protection"),
+            content_models: platform.get_param_as_vec("content_model", ","),
             minlinks: platform.usize_option_from_param("minlinks"),
             maxlinks: platform.usize_option_from_param("maxlinks"),
             larger: platform.usize_option_from_param("larger"),
@@ -199,21 +226,79 @@ impl SourceDatabaseParameters {
             category_namespace_is_case_insensitive: !platform.get_namespace_case_sensitivity(14),
             template_namespace_is_case_insensitive: !platform.get_namespace_case_sensitivity(10),
         };
-        ret.templates_yes = Self::vec_to_ucfirst(
+        let api = platform
+            .get_main_wiki()
+            .and_then(|wiki| platform.state().get_api_for_wiki(wiki).ok());
+        let api = api.as_ref();
+        let category_case_insensitive = ret.category_namespace_is_case_insensitive;
+        let template_case_insensitive = ret.template_namespace_is_case_insensitive;
+        ret.cat_pos =
+            Self::normalize_names(ret.cat_pos.to_owned(), 14, category_case_insensitive, api);
+        ret.cat_neg =
+            Self::normalize_names(ret.cat_neg.to_owned(), 14, category_case_insensitive, api);
+        ret.exclude_subcats = Self::normalize_names(
+            platform.get_param_as_vec("exclude_subcats", "\n"),
+            14,
+            category_case_insensitive,
+            api,
+        )
+        .into_iter()
+        .collect();
+        ret.templates_yes = Self::normalize_names(
             platform.get_param_as_vec("templates_yes", "\n"),
-            ret.template_namespace_is_case_insensitive,
+            10,
+            template_case_insensitive,
+            api,
         );
-        ret.templates_any = Self::vec_to_ucfirst(
+        ret.templates_any = Self::normalize_names(
             platform.get_param_as_vec("templates_any", "\n"),
-            ret.template_namespace_is_case_insensitive,
+            10,
+            template_case_insensitive,
+            api,
         );
-        ret.templates_no = Self::vec_to_ucfirst(
+        ret.templates_no = Self::normalize_names(
             platform.get_param_as_vec("templates_no", "\n"),
-            ret.template_namespace_is_case_insensitive,
+            10,
+            template_case_insensitive,
+            api,
         );
         ret
     }
 
+    /// Normalizes category or template names, optionally followed by "|depth", like page titles
+    /// (see `Platform::normalize_title`). A namespace prefix like "category:" is removed.
+    /// Without site information, only the first letter is uppercased, if the namespace is case-insensitive.
+    fn normalize_names(
+        input: Vec<String>,
+        namespace_id: NamespaceID,
+        is_case_insensitive: bool,
+        api: Option<&Api>,
+    ) -> Vec<String> {
+        let api = match api {
+            Some(api) => api,
+            None => return Self::vec_to_ucfirst(input, is_case_insensitive),
+        };
+        input
+            .iter()
+            .map(|s| {
+                let (name, suffix) = match s.find('|') {
+                    Some(pos) => s.split_at(pos),
+                    None => (s.as_str(), ""),
+                };
+                let title = match Platform::normalize_title(name, api) {
+                    title if title.namespace_id() == namespace_id => Some(title),
+                    _ => Title::new(name, namespace_id)
+                        .full_pretty(api)
+                        .map(|full_title| Platform::normalize_title(&full_title, api)),
+                };
+                match title {
+                    Some(title) => format!("{}{}", title.with_underscores(), suffix),
+                    None => Self::s2u_ucfirst(s, is_case_insensitive),
+                }
+            })
+            .collect()
+    }
+
     pub fn s2u_ucfirst(s: &String, is_case_insensitive: bool) -> String {
         match is_case_insensitive {
             true => Title::spaces_to_underscores(&Title::first_letter_uppercase(s)),
@@ -241,6 +326,8 @@ pub struct SourceDatabase {
     has_pos_linked_from: bool,
     params: SourceDatabaseParameters,
     talk_namespace_ids: String,
+    truncated_categories: Vec<String>,
+    pruned_subcats: Vec<String>,
 }
 
 impl DataSource for SourceDatabase {
@@ -258,13 +345,44 @@ impl DataSource for SourceDatabase {
             || platform.has_param("links_to_any")
     }
 
+    fn unmet_requirements(&self, platform: &Platform) -> Vec<String> {
+        if self.can_run(platform) {
+            return vec![];
+        }
+        ["negcats", "templates_no", "outlinks_no", "links_to_no"]
+            .iter()
+            .filter(|param| platform.has_param(param))
+            .map(|param| {
+                format!(
+                    "{}: {} present but no categories, templates_yes/templates_any, outlinks_yes/outlinks_any or links_to_all/links_to_any",
+                    self.name(),
+                    param
+                )
+            })
+            .collect()
+    }
+
     fn run(&mut self, platform: &Platform) -> Result<PageList, String> {
         let ret = self.get_pages(&platform.state(), None);
+        if !self.truncated_categories.is_empty() {
+            let note = format!(
+                "Category trees truncated to {} subcategories per level: {}",
+                self.params.max_subcats_per_level,
+                self.truncated_categories.join(", ")
+            );
+            platform.note_truncation(note)?;
+        }
+        if !self.pruned_subcats.is_empty() {
+            platform.note_pruned_subcats(self.pruned_subcats.to_owned())?;
+        }
         match &ret {
             Ok(pagelist) => {
                 if pagelist.is_empty()? {
                     platform.warn(format!("<span tt='warn_categories'></span>"))?;
                 }
+                if self.params.category_sortkey {
+                    self.add_category_sortkeys(platform, pagelist)?;
+                }
             }
             _ => {}
         }
@@ -281,7 +399,51 @@ impl SourceDatabase {
             has_pos_linked_from: false,
             params,
             talk_namespace_ids: "".to_string(),
+            truncated_categories: vec![],
+            pruned_subcats: vec![],
+        }
+    }
+
+    /// Stores the sort key prefix (`[[Category:X|prefix]]`) of each page in the "category_sortkey" field.
+    /// A page can have a different sort key in each category, so this only works if there is a single
+    /// positive category; with depth > 0, pages found only in subcategories get no sort key.
+    fn add_category_sortkeys(
+        &self,
+        platform: &Platform,
+        pagelist: &PageList,
+    ) -> Result<(), String> {
+        if self.params.cat_pos.len() != 1 {
+            platform.warn(format!(
+                "add_category_sortkey requires exactly one positive category, but {} were given",
+                self.params.cat_pos.len()
+            ))?;
+            return Ok(());
         }
+        let category = match self.parse_category_depth(&self.params.cat_pos, 0).get(0) {
+            Some(cd) => cd.name.trim().replace(" ", "_"),
+            None => return Ok(()),
+        };
+        let batches: Vec<SQLtuple> = pagelist
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql_batch| {
+                let mut params = vec![category.to_owned()];
+                params.append(&mut sql_batch.1);
+                ("SELECT page_title,page_namespace,cl_sortkey_prefix FROM page,categorylinks WHERE cl_from=page_id AND cl_to=? AND ".to_string() + &sql_batch.0, params)
+            })
+            .collect();
+        pagelist.annotate_batch_results(
+            &platform.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| {
+                if let Some(sortkey) = PageList::string_from_row(&row, 2) {
+                    entry.set_extra("category_sortkey", &sortkey);
+                }
+            },
+        )?;
+        Ok(())
     }
 
     fn parse_category_depth(
@@ -322,6 +484,7 @@ impl SourceDatabase {
         categories_batch: &Vec<String>,
         categories_done: &RwLock<HashSet<String>>,
         new_categories: &RwLock<Vec<String>>,
+        pruned: &RwLock<HashSet<String>>,
     ) -> Result<(), String> {
         let db_user_pass = state
             .get_db_mutex()
@@ -353,6 +516,10 @@ impl SourceDatabase {
             .filter_map(|row| my::from_row_opt::<Vec<u8>>(row).ok())
             .map(|row| String::from_utf8_lossy(&row).into_owned())
             .for_each(|page_title| {
+                if self.params.exclude_subcats.contains(&page_title) {
+                    pruned.write().unwrap().insert(page_title);
+                    return;
+                }
                 let do_add = match categories_done.read() {
                     Ok(cd) => !cd.contains(&page_title),
                     _ => false,
@@ -372,9 +539,10 @@ impl SourceDatabase {
         categories_done: &RwLock<HashSet<String>>,
         categories_to_check: &Vec<String>,
         depth: u16,
-    ) -> Result<(), String> {
+        pruned: &RwLock<HashSet<String>>,
+    ) -> Result<bool, String> {
         if depth == 0 || categories_to_check.is_empty() {
-            return Ok(());
+            return Ok(false);
         }
         Platform::profile("DSDB::do_depth begin", Some(categories_to_check.len()));
 
@@ -398,15 +566,27 @@ impl SourceDatabase {
                             &categories_batch,
                             &categories_done,
                             &new_categories,
+                            pruned,
                         )
                     })
                     .collect::<Result<Vec<_>, String>>()
             })?;
 
-        let new_categories = new_categories
+        let mut new_categories = new_categories
             .into_inner()
             .map_err(|e| format!("{:?}", e))?;
 
+        // Limit the breadth of the tree; subcategories beyond the cap are dropped
+        let truncated = new_categories.len() > self.params.max_subcats_per_level;
+        if truncated {
+            new_categories.sort();
+            let dropped = new_categories.split_off(self.params.max_subcats_per_level);
+            let mut cd = categories_done.write().map_err(|e| format!("{:?}", e))?;
+            dropped.iter().for_each(|c| {
+                cd.remove(c);
+            });
+        }
+
         Platform::profile("DSDB::do_depth new categories", Some(new_categories.len()));
 
         Platform::profile(
@@ -419,44 +599,75 @@ impl SourceDatabase {
             ),
         );
 
-        self.go_depth(&state, wiki, categories_done, &new_categories, depth - 1)?;
-        Ok(())
+        let truncated_below = self.go_depth(
+            &state,
+            wiki,
+            categories_done,
+            &new_categories,
+            depth - 1,
+            pruned,
+        )?;
+        Ok(truncated || truncated_below)
     }
 
+    /// Returns the categories in the tree, and whether it was truncated by max_subcats_per_level.
+    /// Subcategories skipped because of exclude_subcats are added to `pruned`.
     fn get_categories_in_tree(
         &self,
         state: &AppState,
         wiki: &String,
         title: &String,
         depth: u16,
-    ) -> Result<Vec<String>, String> {
+        pruned: &RwLock<HashSet<String>>,
+    ) -> Result<(Vec<String>, bool), String> {
         let categories_done = RwLock::new(HashSet::new());
         let title = SourceDatabaseParameters::s2u_ucfirst(
             title,
             self.params.category_namespace_is_case_insensitive,
         );
         (*categories_done.write().map_err(|e| format!("{:?}", e))?).insert(title.to_owned());
-        self.go_depth(&state, wiki, &categories_done, &vec![title], depth)?;
+        let truncated =
+            self.go_depth(&state, wiki, &categories_done, &vec![title], depth, pruned)?;
         let mut tmp = categories_done
             .into_inner()
             .map_err(|e| format!("{:?}", e))?;
-        Ok(tmp.drain().collect())
+        Ok((tmp.drain().collect(), truncated))
     }
 
     pub fn parse_category_list(
-        &self,
+        &mut self,
         state: &AppState,
         wiki: &String,
         input: &Vec<SourceDatabaseCatDepth>,
     ) -> Result<Vec<Vec<String>>, String> {
-        input
+        let pruned = RwLock::new(HashSet::new());
+        let trees = input
             .par_iter()
-            .map(|i| self.get_categories_in_tree(&state, wiki, &i.name, i.depth))
-            .filter(|i| match i {
-                Ok(i) => !i.is_empty(),
-                _ => true,
+            .map(|i| {
+                let (tree, truncated) =
+                    self.get_categories_in_tree(&state, wiki, &i.name, i.depth, &pruned)?;
+                Ok((i.name.to_owned(), tree, truncated))
             })
-            .collect()
+            .collect::<Result<Vec<(String, Vec<String>, bool)>, String>>()?;
+        let mut pruned: Vec<String> = pruned
+            .into_inner()
+            .map_err(|e| format!("{:?}", e))?
+            .into_iter()
+            .collect();
+        pruned.sort();
+        self.pruned_subcats.append(&mut pruned);
+        Ok(trees
+            .into_iter()
+            .filter_map(|(name, tree, truncated)| {
+                if truncated {
+                    self.truncated_categories.push(name);
+                }
+                match tree.is_empty() {
+                    true => None,
+                    false => Some(tree),
+                }
+            })
+            .collect())
     }
 
     fn get_talk_namespace_ids(&self, conn: &mut my::Conn) -> Result<String, String> {
@@ -473,6 +684,80 @@ impl SourceDatabase {
             .join(","))
     }
 
+    /// Conditions for templates_yes and templates_any. If both are given, a page has to match both:
+    /// all (or, with templates_yes_mode=any, any) of templates_yes, and at least one of templates_any.
+    fn positive_templates_sql(&self) -> SQLtuple {
+        let mut sql = Platform::sql_tuple();
+        if self.params.templates_yes_any {
+            if !self.params.templates_yes.is_empty() {
+                let tmp = self.template_subquery(
+                    &self.params.templates_yes,
+                    self.params.templates_yes_talk_page,
+                    false,
+                );
+                Platform::append_sql(&mut sql, tmp);
+            }
+        } else {
+            self.params.templates_yes.iter().for_each(|t| {
+                let tmp = self.template_subquery(
+                    &vec![t.to_string()],
+                    self.params.templates_yes_talk_page,
+                    false,
+                );
+                Platform::append_sql(&mut sql, tmp);
+            });
+        }
+
+        if !self.params.templates_any.is_empty() {
+            let tmp = self.template_subquery(
+                &self.params.templates_any,
+                self.params.templates_any_talk_page,
+                false,
+            );
+            Platform::append_sql(&mut sql, tmp);
+        }
+        sql
+    }
+
+    /// Condition for the "protection" parameter: "any" or "none" for pages with or without any
+    /// current protection, or an action with an optional level, like "move" or "edit=sysop"
+    fn protection_sql(&self) -> Result<Option<SQLtuple>, String> {
+        let protection = self.params.protection.trim();
+        if protection.is_empty() {
+            return Ok(None);
+        }
+        let mut sql: SQLtuple = (
+            "SELECT * FROM page_restrictions WHERE pr_page=p.page_id AND (pr_expiry='infinity' OR pr_expiry>DATE_FORMAT(NOW(),'%Y%m%d%H%i%s'))".to_string(),
+            vec![],
+        );
+        match protection {
+            "any" | "none" => {}
+            _ => {
+                let mut parts = protection.splitn(2, '=');
+                let action = parts.next().unwrap_or("").trim();
+                let level = parts.next().map(|level| level.trim());
+                if !["edit", "move", "upload"].contains(&action) {
+                    return Err(format!("protection: Unknown action '{}'", action));
+                }
+                sql.0 += " AND pr_type=?";
+                sql.1.push(action.to_string());
+                match level {
+                    Some(level) if !level.is_empty() => {
+                        sql.0 += " AND pr_level=?";
+                        sql.1.push(level.to_string());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        sql.0 = if protection == "none" {
+            format!(" AND NOT EXISTS ({})", sql.0)
+        } else {
+            format!(" AND EXISTS ({})", sql.0)
+        };
+        Ok(Some(sql))
+    }
+
     fn template_subquery(
         &self,
         input: &Vec<String>,
@@ -874,7 +1159,7 @@ impl SourceDatabase {
             .filter_map(|mut sql| {
                 let mut conn = match state.get_wiki_db_connection(&db_user_pass, &wiki) {
                     Ok(conn) => conn,
-                    Err(e) => return Some(Err(e)),
+                    Err(e) => return Some(Err(e.to_string())),
                 };
                 let sql_before_after = params.sql_before_after.clone();
                 let mut is_before_after_done = params.is_before_after_done.clone();
@@ -1033,25 +1318,7 @@ impl SourceDatabase {
 
         // Templates as secondary; template namespace only!
         if self.has_pos_templates {
-            // All
-            self.params.templates_yes.iter().for_each(|t| {
-                let tmp = self.template_subquery(
-                    &vec![t.to_string()],
-                    self.params.templates_yes_talk_page,
-                    false,
-                );
-                Platform::append_sql(&mut sql, tmp);
-            });
-
-            // Any
-            if !self.params.templates_any.is_empty() {
-                let tmp = self.template_subquery(
-                    &self.params.templates_any,
-                    self.params.templates_any_talk_page,
-                    false,
-                );
-                Platform::append_sql(&mut sql, tmp);
-            }
+            Platform::append_sql(&mut sql, self.positive_templates_sql());
         }
 
         // Negative templates
@@ -1181,6 +1448,13 @@ impl SourceDatabase {
             "no" => sql.0 += " AND p.page_is_redirect=0",
             _ => {}
         }
+        if let Some(protection_sql) = self.protection_sql()? {
+            Platform::append_sql(sql, protection_sql);
+        }
+        if !self.params.content_models.is_empty() {
+            sql.0 += " AND p.page_content_model";
+            self.sql_in(&self.params.content_models, sql);
+        }
         match self.params.larger {
             Some(i) => {
                 sql.0 += " AND p.page_len>=";
@@ -1311,14 +1585,18 @@ mod tests {
         Arc::new(AppState::new_from_config(&petscan_config))
     }
 
-    fn simulate_category_query(url_params: Vec<(&str, &str)>) -> Result<PageList, String> {
-        let state = get_state();
+    fn platform_for(url_params: Vec<(&str, &str)>) -> Platform {
         let mut fp = FormParameters::new();
         fp.params = url_params
             .iter()
             .map(|pair| (pair.0.to_string(), pair.1.to_string()))
             .collect();
-        let platform = Platform::new_from_parameters(&fp, state.clone());
+        Platform::new_from_parameters(&fp, get_state())
+    }
+
+    fn simulate_category_query(url_params: Vec<(&str, &str)>) -> Result<PageList, String> {
+        let platform = platform_for(url_params);
+        let state = platform.state();
         let params = SourceDatabaseParameters::db_params(&platform);
         let mut dbs = SourceDatabase::new(params);
         dbs.get_pages(&state, None)
@@ -1367,6 +1645,180 @@ mod tests {
         assert!(result.len() > result_size2);
     }
 
+    #[test]
+    fn test_category_or() {
+        let params = vec![
+            ("categories", "1974_births\nBioinformaticians"),
+            ("language", "en"),
+            ("project", "wikipedia"),
+        ];
+        let platform = platform_for(params.clone());
+        assert_eq!(
+            SourceDatabaseParameters::db_params(&platform).combine,
+            "subset"
+        );
+        let platform = platform_for([params, vec![("categories_or", "1")]].concat());
+        assert_eq!(
+            SourceDatabaseParameters::db_params(&platform).combine,
+            "union"
+        );
+    }
+
+    #[test]
+    fn test_templates_yes_mode() {
+        let templates_sql = |mode: &str| {
+            let platform = platform_for(vec![
+                ("templates_yes", "Infobox person\nInfobox scientist"),
+                ("templates_any", "Authority control"),
+                ("templates_yes_mode", mode),
+                ("language", "en"),
+                ("project", "wikipedia"),
+            ]);
+            SourceDatabase::new(SourceDatabaseParameters::db_params(&platform))
+                .positive_templates_sql()
+        };
+
+        // All: one subquery per template in templates_yes, plus one for templates_any
+        let sql = templates_sql("all");
+        assert_eq!(sql.0.matches(" AND p.page_id IN ").count(), 3);
+        assert_eq!(
+            sql.1,
+            vec!["Infobox_person", "Infobox_scientist", "Authority_control"]
+        );
+
+        // Any: one subquery for all of templates_yes, plus one for templates_any
+        let sql = templates_sql("any");
+        assert_eq!(sql.0.matches(" AND p.page_id IN ").count(), 2);
+        assert_eq!(
+            sql.1,
+            vec!["Infobox_person", "Infobox_scientist", "Authority_control"]
+        );
+    }
+
+    #[test]
+    fn test_protection_sql() {
+        let protection_sql = |protection: &str| {
+            let platform = platform_for(vec![
+                ("protection", protection),
+                ("language", "en"),
+                ("project", "wikipedia"),
+            ]);
+            SourceDatabase::new(SourceDatabaseParameters::db_params(&platform)).protection_sql()
+        };
+        assert_eq!(protection_sql(""), Ok(None));
+        let sql = protection_sql("none").unwrap().unwrap();
+        assert!(sql.0.starts_with(" AND NOT EXISTS ("));
+        assert!(sql.1.is_empty());
+        let sql = protection_sql("edit=sysop").unwrap().unwrap();
+        assert!(sql.0.starts_with(" AND EXISTS ("));
+        assert_eq!(sql.1, vec!["edit", "sysop"]);
+        assert_eq!(protection_sql("move").unwrap().unwrap().1, vec!["move"]);
+        assert!(protection_sql("delete=sysop").is_err());
+        // Create protection is not in page_restrictions, and only applies to missing pages
+        assert!(protection_sql("create=sysop").is_err());
+    }
+
+    #[test]
+    fn test_exclude_subcats() {
+        let wiki = "enwiki".to_string();
+        let tree_for = |exclude: &str| {
+            let platform = platform_for(vec![
+                ("categories", "Biology"),
+                ("depth", "1"),
+                ("exclude_subcats", exclude),
+                ("language", "en"),
+                ("project", "wikipedia"),
+            ]);
+            let state = platform.state();
+            let dbs = SourceDatabase::new(SourceDatabaseParameters::db_params(&platform));
+            let pruned = RwLock::new(HashSet::new());
+            let (tree, _) = dbs
+                .get_categories_in_tree(&state, &wiki, &"Biology".to_string(), 1, &pruned)
+                .unwrap();
+            (tree, pruned.into_inner().unwrap())
+        };
+        let (full_tree, pruned) = tree_for("");
+        assert!(pruned.is_empty());
+        let subcat = full_tree
+            .iter()
+            .find(|c| *c != "Biology")
+            .expect("Category:Biology has no subcategories")
+            .to_owned();
+        // Excluded names are normalized like other category names
+        let (tree, pruned) = tree_for(&subcat.replace('_', " "));
+        assert!(!tree.contains(&subcat));
+        assert!(tree.contains(&"Biology".to_string()));
+        assert_eq!(tree.len(), full_tree.len() - 1);
+        assert!(pruned.contains(&subcat));
+    }
+
+    #[test]
+    fn test_max_subcats_per_level() {
+        let platform = platform_for(vec![
+            ("categories", "Biology"),
+            ("depth", "2"),
+            ("max_subcats_per_level", "1"),
+            ("language", "en"),
+            ("project", "wikipedia"),
+        ]);
+        let state = platform.state();
+        let dbs = SourceDatabase::new(SourceDatabaseParameters::db_params(&platform));
+        let pruned = RwLock::new(HashSet::new());
+        let (tree, truncated) = dbs
+            .get_categories_in_tree(
+                &state,
+                &"enwiki".to_string(),
+                &"Biology".to_string(),
+                2,
+                &pruned,
+            )
+            .unwrap();
+        assert!(truncated);
+        assert!(tree.len() <= 3);
+    }
+
+    #[test]
+    fn test_category_ucfirst_in_db_params() {
+        let platform = platform_for(vec![
+            ("categories", "biology|2"),
+            ("negcats", "plant biology"),
+            ("language", "en"),
+            ("project", "wikipedia"),
+        ]);
+        let params = SourceDatabaseParameters::db_params(&platform);
+        assert_eq!(params.cat_pos, vec!["Biology|2".to_string()]);
+        assert_eq!(params.cat_neg, vec!["Plant_biology".to_string()]);
+
+        let platform = platform_for(vec![
+            ("categories", "biology|2"),
+            ("negcats", "plant biology"),
+            ("language", "fr"),
+            ("project", "wiktionary"),
+        ]);
+        let params = SourceDatabaseParameters::db_params(&platform);
+        assert_eq!(params.cat_pos, vec!["biology|2".to_string()]);
+    }
+
+    #[test]
+    fn test_category_and_template_names_normalized() {
+        let platform = platform_for(vec![
+            ("categories", " category:german__bioinformaticians|1"),
+            ("negcats", "1974 births"),
+            ("templates_yes", "template:infobox person"),
+            ("templates_no", "citation needed"),
+            ("language", "en"),
+            ("project", "wikipedia"),
+        ]);
+        let params = SourceDatabaseParameters::db_params(&platform);
+        assert_eq!(
+            params.cat_pos,
+            vec!["German_bioinformaticians|1".to_string()]
+        );
+        assert_eq!(params.cat_neg, vec!["1974_births".to_string()]);
+        assert_eq!(params.templates_yes, vec!["Infobox_person".to_string()]);
+        assert_eq!(params.templates_no, vec!["Citation_needed".to_string()]);
+    }
+
     #[test]
     fn test_category_case_sensitive() {
         let params = vec![
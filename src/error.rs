@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::PoisonError;
+
+/// Errors that callers may want to tell apart. Errors from code that still uses
+/// `Result<_, String>` become `Other`; the message is what users get to see.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PetScanError {
+    /// Database connection or query failure
+    Database(String),
+    /// MediaWiki API failure
+    Api(String),
+    /// A query parameter is invalid
+    BadParameter(String),
+    /// No data source can run with the given parameters
+    NoDataSource(String),
+    /// A data source did not finish in time
+    Timeout(String),
+    /// A lock was poisoned by a panicking thread
+    Mutex(String),
+    Other(String),
+}
+
+impl PetScanError {
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Database(s)
+            | Self::Api(s)
+            | Self::BadParameter(s)
+            | Self::NoDataSource(s)
+            | Self::Timeout(s)
+            | Self::Mutex(s)
+            | Self::Other(s) => s,
+        }
+    }
+}
+
+impl fmt::Display for PetScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl Error for PetScanError {}
+
+impl From<String> for PetScanError {
+    fn from(s: String) -> Self {
+        Self::Other(s)
+    }
+}
+
+impl From<&str> for PetScanError {
+    fn from(s: &str) -> Self {
+        Self::Other(s.to_string())
+    }
+}
+
+impl<T> From<PoisonError<T>> for PetScanError {
+    fn from(e: PoisonError<T>) -> Self {
+        Self::Mutex(format!("{:?}", e))
+    }
+}
+
+/// Lets `?` pass these errors on from functions that return `Result<_, String>`
+impl From<PetScanError> for String {
+    fn from(e: PetScanError) -> Self {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_bridge() {
+        let error: PetScanError = format!("Something went wrong").into();
+        assert_eq!(
+            error,
+            PetScanError::Other("Something went wrong".to_string())
+        );
+        let s: String = PetScanError::Timeout("Source sparql timed out".to_string()).into();
+        assert_eq!(s, "Source sparql timed out");
+    }
+}
@@ -29,6 +29,14 @@ impl FormParameters {
         ret
     }
 
+    /// Creates a new object from decoded parameters, as if they came from a query string
+    pub fn new_from_params(params: HashMap<String, String>) -> Self {
+        let ns = Self::ns_from_params(&params);
+        let mut ret = FormParameters { params, ns };
+        ret.legacy_parameters();
+        ret
+    }
+
     /// Extracts namespaces from parameter list
     fn ns_from_params(params: &HashMap<String, String>) -> HashSet<usize> {
         lazy_static! {
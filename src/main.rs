@@ -9,9 +9,11 @@ extern crate serde_json;
 pub mod app_state;
 pub mod datasource;
 pub mod datasource_database;
+pub mod error;
 pub mod form_parameters;
 pub mod pagelist;
 pub mod platform;
+pub mod platform_builder;
 pub mod render;
 pub mod wdfist;
 
@@ -56,6 +58,8 @@ fn process_form(parameters:&str, state: web::Data<Arc<AppState>>) -> MyResponse
         return MyResponse {
             s: "Temporary maintenance".to_string(),
             content_type: ContentType::Plain,
+            binary: None,
+            filename: None,
         };
     }
 
@@ -71,6 +75,8 @@ fn process_form(parameters:&str, state: web::Data<Arc<AppState>>) -> MyResponse
                 .get_main_page(interface_language.to_string())
                 .to_owned(),
             content_type: ContentType::HTML,
+            binary: None,
+            filename: None,
         };
     }
 
@@ -90,7 +96,7 @@ fn process_form(parameters:&str, state: web::Data<Arc<AppState>>) -> MyResponse
                         };
                         form_parameters.rebase(&psid_params);
                     }
-                    Err(e) => return state.render_error(e, &form_parameters),
+                    Err(e) => return state.render_error(e.to_string(), &form_parameters),
                 }
             }
         }
@@ -117,13 +123,15 @@ fn process_form(parameters:&str, state: web::Data<Arc<AppState>>) -> MyResponse
             return MyResponse {
                 s: html,
                 content_type: ContentType::HTML,
+                binary: None,
+                filename: None,
             };
         }
     }
 
     let started_query_id = match state.log_query_start(&form_parameters.to_string()) {
         Ok(id) => id,
-        Err(e) => return state.render_error(e, &form_parameters),
+        Err(e) => return state.render_error(e.to_string(), &form_parameters),
     };
 
     // Actually do something useful!
@@ -140,7 +148,7 @@ fn process_form(parameters:&str, state: web::Data<Arc<AppState>>) -> MyResponse
         Ok(_) => {}
         Err(error) => {
             drop(platform);
-            return state.render_error(error, &form_parameters);
+            return state.render_error(error.to_string(), &form_parameters);
         }
     }
 
@@ -152,7 +160,7 @@ fn process_form(parameters:&str, state: web::Data<Arc<AppState>>) -> MyResponse
             Ok(psid) => Some(psid),
             Err(e) => {
                 state.log_query_end(started_query_id);
-                return state.render_error(e, &form_parameters);
+                return state.render_error(e.to_string(), &form_parameters);
             }
         },
     };
@@ -167,17 +175,21 @@ fn process_form(parameters:&str, state: web::Data<Arc<AppState>>) -> MyResponse
     response
 }
 
+fn accepts_gzip(req: &HttpRequest) -> bool {
+    MyResponse::accepts_gzip(req.headers().get("Accept-Encoding").and_then(|v| v.to_str().ok()))
+}
+
 async fn query_handler_get(req: HttpRequest,app_state: web::Data<Arc<AppState>>) -> Result<HttpResponse, Error> {
-    process_form ( req.query_string() , app_state ).respond()
+    process_form ( req.query_string() , app_state ).respond(accepts_gzip(&req))
 }
 
-async fn query_handler_post(mut body: web::Payload,app_state: web::Data<Arc<AppState>>) -> Result<HttpResponse, Error> {
+async fn query_handler_post(req: HttpRequest,mut body: web::Payload,app_state: web::Data<Arc<AppState>>) -> Result<HttpResponse, Error> {
     let mut bytes = web::BytesMut::new();
     while let Some(item) = body.next().await {
         bytes.extend_from_slice(&item?);
     }
     let parameters = std::str::from_utf8(&bytes).unwrap_or("") ;
-    process_form ( parameters , app_state ).respond()
+    process_form ( parameters , app_state ).respond(accepts_gzip(&req))
 }
 
 #[actix_rt::main]
@@ -197,6 +209,10 @@ async fn main() -> std::io::Result<()> {
     let port = petscan_config["http_port"].as_u64().unwrap_or(80);
     
     let actual_app_state = Arc::new(AppState::new_from_config(&petscan_config)) ;
+    match actual_app_state.bootstrap_tool_db_schema() {
+        Ok(_created) => {}
+        Err(e) => panic!("Tool DB schema bootstrap failed: {}", e),
+    }
     let app_state = web::Data::new(actual_app_state);
     HttpServer::new(move || {
         App::new()
@@ -1,15 +1,20 @@
 use crate::app_state::AppState;
 use crate::datasource::SQLtuple;
+use crate::error::PetScanError;
 use crate::platform::{Platform, PAGE_BATCH_SIZE};
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
 use mysql as my;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
 use regex::Regex;
 use serde_json::Value;
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::{Hash, Hasher};
-use std::sync::RwLock;
-use wikibase::mediawiki::api::NamespaceID;
+use std::sync::{Mutex, RwLock};
+use wikibase::mediawiki::api::{Api, NamespaceID};
 use wikibase::mediawiki::title::Title;
 
 //________________________________________________________________________________________________________________________
@@ -25,12 +30,96 @@ pub enum PageListSort {
     IncomingLinks(bool),
     FileSize(bool),
     UploadDate(bool),
+    Uploader(bool),
+    Mime(bool),
+    Pageviews(bool),
     Random(bool),
+    LastEditor(bool),
+    WikidataProperty(String, bool),
+    /// Several keys; later ones break ties in earlier ones
+    Multi(Vec<PageListSort>),
 }
 
 impl PageListSort {
+    /// Parses a "sortby" value. This can be a comma-separated list of keys,
+    /// each with an optional ":asc" or ":desc" to override `descending`.
     pub fn new_from_params(s: &String, descending: bool) -> Self {
-        match s.as_str() {
+        let mut sorters: Vec<Self> = Self::sort_keys(s)
+            .iter()
+            .map(|(key, key_descending)| {
+                Self::new_from_key(key, key_descending.unwrap_or(descending))
+            })
+            .collect();
+        match sorters.len() {
+            0 => Self::Default(descending),
+            1 => sorters.remove(0),
+            _ => Self::Multi(sorters),
+        }
+    }
+
+    /// The sort keys in a "sortby" value, with their direction if given
+    fn sort_keys(s: &str) -> Vec<(String, Option<bool>)> {
+        s.split(',')
+            .map(|key| key.trim())
+            .filter(|key| !key.is_empty())
+            .map(|key| match key.rfind(':') {
+                Some(pos) => match key[pos + 1..].to_lowercase().as_str() {
+                    "desc" => (key[..pos].to_string(), Some(true)),
+                    "asc" => (key[..pos].to_string(), Some(false)),
+                    _ => (key.to_string(), None),
+                },
+                None => (key.to_string(), None),
+            })
+            .collect()
+    }
+
+    /// Checks if any of the sort keys needs random keys on the entries
+    pub fn uses_random(&self) -> bool {
+        match self {
+            Self::Random(_) => true,
+            Self::Multi(sorters) => sorters.iter().any(|sorter| sorter.uses_random()),
+            _ => false,
+        }
+    }
+
+    /// The "sortby" value, and whether it is descending, that parse into this sorter
+    pub fn to_params(&self) -> (String, bool) {
+        match self {
+            Self::Multi(sorters) => {
+                let keys: Vec<String> = sorters
+                    .iter()
+                    .map(|sorter| {
+                        let (key, descending) = sorter.to_params();
+                        format!("{}:{}", key, if descending { "desc" } else { "asc" })
+                    })
+                    .collect();
+                (keys.join(","), false)
+            }
+            Self::Default(d) => ("none".to_string(), *d),
+            Self::Title(d) => ("title".to_string(), *d),
+            Self::NsTitle(d) => ("ns_title".to_string(), *d),
+            Self::Size(d) => ("size".to_string(), *d),
+            Self::Date(d) => ("date".to_string(), *d),
+            Self::RedlinksCount(d) => ("redlinks".to_string(), *d),
+            Self::IncomingLinks(d) => ("incoming_links".to_string(), *d),
+            Self::FileSize(d) => ("filesize".to_string(), *d),
+            Self::UploadDate(d) => ("uploaddate".to_string(), *d),
+            Self::Uploader(d) => ("uploader".to_string(), *d),
+            Self::Mime(d) => ("mime".to_string(), *d),
+            Self::Pageviews(d) => ("pageviews".to_string(), *d),
+            Self::Random(d) => ("random".to_string(), *d),
+            Self::LastEditor(d) => ("last_editor".to_string(), *d),
+            Self::WikidataProperty(property, d) => (format!("wd:{}", property), *d),
+        }
+    }
+
+    /// The names of the sort keys in a "sortby" value, without directions
+    pub fn sort_key_names(s: &str) -> Vec<String> {
+        Self::sort_keys(s).into_iter().map(|(key, _)| key).collect()
+    }
+
+    fn new_from_key(s: &str, descending: bool) -> Self {
+        match s {
             "title" => Self::Title(descending),
             "ns_title" => Self::NsTitle(descending),
             "size" => Self::Size(descending),
@@ -39,8 +128,73 @@ impl PageListSort {
             "incoming_links" => Self::IncomingLinks(descending),
             "filesize" => Self::FileSize(descending),
             "uploaddate" => Self::UploadDate(descending),
+            "uploader" => Self::Uploader(descending),
+            "mime" => Self::Mime(descending),
+            "pageviews" => Self::Pageviews(descending),
             "random" => Self::Random(descending),
-            _ => Self::Default(descending),
+            "last_editor" => Self::LastEditor(descending),
+            other => match Self::wikidata_property_from_param(other) {
+                Some(property) => Self::WikidataProperty(property, descending),
+                None => Self::Default(descending),
+            },
+        }
+    }
+
+    /// Parses "wd:P569" style sort parameters into the property ID
+    pub fn wikidata_property_from_param(s: &str) -> Option<String> {
+        lazy_static! {
+            static ref RE_WD_PROPERTY: Regex =
+                Regex::new(r"^wd:(P\d+)$").expect("PageListSort: Regex is invalid");
+        }
+        RE_WD_PROPERTY
+            .captures(s)
+            .map(|caps| caps.get(1).unwrap().as_str().to_string())
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// A Wikidata statement value, in a form that can be compared for sorting.
+/// Dates sort chronologically (by year, then the rest of the ISO timestamp),
+/// quantities numerically, and strings, external IDs, monolingual text,
+/// and URLs lexicographically. Items and properties sort by numeric ID,
+/// which is rarely meaningful. Other datatypes are ignored.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum WikidataSortValue {
+    Time(i64, String),
+    Quantity(f64),
+    Text(String),
+}
+
+impl WikidataSortValue {
+    /// Uses the first preferred-rank claim, otherwise the first normal-rank one
+    pub fn new_from_claims(claims: &Value) -> Option<Self> {
+        let claims = claims.as_array()?;
+        let claim = claims
+            .iter()
+            .find(|c| c["rank"].as_str() == Some("preferred"))
+            .or_else(|| claims.iter().find(|c| c["rank"].as_str() == Some("normal")))?;
+        Self::new_from_datavalue(&claim["mainsnak"]["datavalue"])
+    }
+
+    fn new_from_datavalue(datavalue: &Value) -> Option<Self> {
+        let value = &datavalue["value"];
+        match datavalue["type"].as_str()? {
+            "time" => {
+                let time = value["time"].as_str()?;
+                let sign = if time.starts_with('-') { -1 } else { 1 };
+                let time = time.trim_start_matches(|c| c == '+' || c == '-');
+                let pos = time.find('-')?;
+                let year = time[..pos].parse::<i64>().ok()?;
+                Some(Self::Time(sign * year, time[pos..].to_string()))
+            }
+            "quantity" => Some(Self::Quantity(
+                value["amount"].as_str()?.parse::<f64>().ok()?,
+            )),
+            "string" => Some(Self::Text(value.as_str()?.to_string())),
+            "monolingualtext" => Some(Self::Text(value["text"].as_str()?.to_string())),
+            "wikibase-entityid" => Some(Self::Quantity(value["numeric-id"].as_f64()?)),
+            _ => None,
         }
     }
 }
@@ -181,14 +335,31 @@ pub struct PageListEntry {
     pub incoming_links: Option<LinkCount>,
     pub link_count: Option<LinkCount>,
     pub redlink_count: Option<LinkCount>,
+    pub langlink_count: Option<LinkCount>,
+    pub editor_count: Option<u32>,
+    pub entityschema_conforms: TriState,
+    pub cascade_protected: TriState,
+    pub pageviews: Option<u64>,
+    pub random_key: Option<u64>,
     page_timestamp: Option<Box<String>>,
+    page_created: Option<Box<String>>,
+    page_creator: Option<Box<String>>,
+    last_editor: Option<Box<String>>,
+    redirect_target: Option<Box<Title>>,
     page_image: Option<Box<String>>,
     wikidata_item: Option<Box<String>>,
     wikidata_label: Option<Box<String>>,
     wikidata_description: Option<Box<String>>,
+    wikidata_labels: Option<Box<HashMap<String, String>>>,
+    wikidata_descriptions: Option<Box<HashMap<String, String>>>,
+    label_language_used: Option<Box<String>>,
     defaultsort: Option<Box<String>>,
     coordinates: Option<Box<PageCoordinates>>,
     file_info: Option<Box<FileInfo>>,
+    provenance: Option<Box<Vec<String>>>,
+    maintenance_templates: Option<Box<Vec<String>>>,
+    extra: Option<Box<HashMap<String, String>>>,
+    wikidata_sort_values: Option<Box<HashMap<String, WikidataSortValue>>>,
 }
 
 impl Hash for PageListEntry {
@@ -214,6 +385,10 @@ impl PageListEntry {
             page_id: None,
             page_bytes: None,
             page_timestamp: None,
+            page_created: None,
+            page_creator: None,
+            last_editor: None,
+            redirect_target: None,
             defaultsort: None,
             disambiguation: TriState::Unknown,
             incoming_links: None,
@@ -223,7 +398,20 @@ impl PageListEntry {
             file_info: None,
             wikidata_label: None,
             wikidata_description: None,
+            wikidata_labels: None,
+            wikidata_descriptions: None,
+            label_language_used: None,
             redlink_count: None,
+            langlink_count: None,
+            editor_count: None,
+            entityschema_conforms: TriState::Unknown,
+            cascade_protected: TriState::Unknown,
+            pageviews: None,
+            random_key: None,
+            provenance: None,
+            maintenance_templates: None,
+            extra: None,
+            wikidata_sort_values: None,
         }
     }
 
@@ -297,6 +485,72 @@ impl PageListEntry {
         }
     }
 
+    /// The language of the label, if it was chosen from a fallback chain
+    pub fn get_label_language_used(&self) -> Option<String> {
+        match &self.label_language_used {
+            Some(label_language_used) => Some(*(label_language_used.clone())),
+            None => None,
+        }
+    }
+
+    pub fn set_label_language_used(&mut self, label_language_used_option: Option<String>) {
+        self.label_language_used = match label_language_used_option {
+            Some(label_language_used) => Some(Box::new(label_language_used)),
+            None => None,
+        }
+    }
+
+    /// Sets the label, unless the current label is in a language that comes earlier in `chain`.
+    /// Labels in languages that are not in `chain` are ignored.
+    pub fn set_wikidata_label_from_chain(
+        &mut self,
+        language: &str,
+        label: String,
+        chain: &[String],
+    ) {
+        let rank = |language: &str| chain.iter().position(|l| l == language);
+        let new_rank = match rank(language) {
+            Some(new_rank) => new_rank,
+            None => return,
+        };
+        let is_better = match self.get_label_language_used() {
+            Some(used) => rank(&used).map(|r| new_rank < r).unwrap_or(true),
+            None => true,
+        };
+        if is_better {
+            self.set_wikidata_label(Some(label));
+            self.set_label_language_used(Some(language.to_string()));
+        }
+    }
+
+    /// Label in a specific language, if labels for several languages were loaded
+    pub fn get_wikidata_label_in(&self, language: &str) -> Option<String> {
+        match &self.wikidata_labels {
+            Some(labels) => labels.get(language).cloned(),
+            None => None,
+        }
+    }
+
+    pub fn set_wikidata_label_in(&mut self, language: &str, label: String) {
+        self.wikidata_labels
+            .get_or_insert_with(Default::default)
+            .insert(language.to_string(), label);
+    }
+
+    /// Description in a specific language, if descriptions for several languages were loaded
+    pub fn get_wikidata_description_in(&self, language: &str) -> Option<String> {
+        match &self.wikidata_descriptions {
+            Some(descriptions) => descriptions.get(language).cloned(),
+            None => None,
+        }
+    }
+
+    pub fn set_wikidata_description_in(&mut self, language: &str, description: String) {
+        self.wikidata_descriptions
+            .get_or_insert_with(Default::default)
+            .insert(language.to_string(), description);
+    }
+
     pub fn get_wikidata_item(&self) -> Option<String> {
         match &self.wikidata_item {
             Some(wikidata_item) => Some(*(wikidata_item.clone())),
@@ -339,28 +593,220 @@ impl PageListEntry {
         }
     }
 
+    /// Timestamp of the earliest revision, if loaded
+    pub fn get_page_created(&self) -> Option<String> {
+        match &self.page_created {
+            Some(page_created) => Some(*(page_created.clone())),
+            None => None,
+        }
+    }
+
+    pub fn set_page_created(&mut self, page_created_option: Option<String>) {
+        self.page_created = match page_created_option {
+            Some(page_created) => Some(Box::new(page_created)),
+            None => None,
+        }
+    }
+
+    /// User name of the page creator, if loaded
+    pub fn get_page_creator(&self) -> Option<String> {
+        match &self.page_creator {
+            Some(page_creator) => Some(*(page_creator.clone())),
+            None => None,
+        }
+    }
+
+    pub fn set_page_creator(&mut self, page_creator_option: Option<String>) {
+        self.page_creator = match page_creator_option {
+            Some(page_creator) => Some(Box::new(page_creator)),
+            None => None,
+        }
+    }
+
+    pub fn get_last_editor(&self) -> Option<String> {
+        match &self.last_editor {
+            Some(last_editor) => Some(*(last_editor.clone())),
+            None => None,
+        }
+    }
+
+    pub fn set_last_editor(&mut self, last_editor_option: Option<String>) {
+        self.last_editor = match last_editor_option {
+            Some(last_editor) => Some(Box::new(last_editor)),
+            None => None,
+        }
+    }
+
+    pub fn get_redirect_target(&self) -> Option<Title> {
+        match &self.redirect_target {
+            Some(redirect_target) => Some(*(redirect_target.clone())),
+            None => None,
+        }
+    }
+
+    pub fn set_redirect_target(&mut self, redirect_target_option: Option<Title>) {
+        self.redirect_target = match redirect_target_option {
+            Some(redirect_target) => Some(Box::new(redirect_target)),
+            None => None,
+        }
+    }
+
+    /// Names of the sources this entry came from, if provenance is tracked
+    pub fn get_provenance(&self) -> Option<Vec<String>> {
+        match &self.provenance {
+            Some(provenance) => Some(*(provenance.clone())),
+            None => None,
+        }
+    }
+
+    pub fn add_provenance(&mut self, source: &str) {
+        let mut provenance = self.get_provenance().unwrap_or(vec![]);
+        if !provenance.contains(&source.to_string()) {
+            provenance.push(source.to_string());
+            provenance.sort();
+        }
+        self.provenance = Some(Box::new(provenance));
+    }
+
+    pub fn merge_provenance(&mut self, other: &PageListEntry) {
+        match &other.provenance {
+            Some(provenance) => provenance
+                .iter()
+                .for_each(|source| self.add_provenance(source)),
+            None => {}
+        }
+    }
+
+    /// Additional named values for this entry, e.g. further SPARQL result variables
+    pub fn get_extra(&self, key: &str) -> Option<String> {
+        match &self.extra {
+            Some(extra) => extra.get(key).cloned(),
+            None => None,
+        }
+    }
+
+    pub fn set_extra(&mut self, key: &str, value: &str) {
+        let mut extra = match &self.extra {
+            Some(extra) => *(extra.clone()),
+            None => HashMap::new(),
+        };
+        extra.insert(key.to_string(), value.to_string());
+        self.extra = Some(Box::new(extra));
+    }
+
+    /// Copies extra values from another entry, without overwriting existing ones
+    pub fn merge_extra(&mut self, other: &PageListEntry) {
+        match &other.extra {
+            Some(extra) => extra.iter().for_each(|(k, v)| {
+                if self.get_extra(k).is_none() {
+                    self.set_extra(k, v)
+                }
+            }),
+            None => {}
+        }
+    }
+
+    /// Maintenance templates found on this page, if checked
+    pub fn get_maintenance_templates(&self) -> Option<Vec<String>> {
+        match &self.maintenance_templates {
+            Some(templates) => Some(*(templates.clone())),
+            None => None,
+        }
+    }
+
+    pub fn set_maintenance_templates(&mut self, templates: Option<Vec<String>>) {
+        self.maintenance_templates = match templates {
+            Some(templates) => Some(Box::new(templates)),
+            None => None,
+        };
+    }
+
+    /// The sort value of a Wikidata property ("P569") for this entry
+    pub fn get_wikidata_sort_value(&self, property: &str) -> Option<WikidataSortValue> {
+        self.wikidata_sort_values.as_ref()?.get(property).cloned()
+    }
+
+    pub fn set_wikidata_sort_value(&mut self, property: &str, value: Option<WikidataSortValue>) {
+        match value {
+            Some(value) => {
+                self.wikidata_sort_values
+                    .get_or_insert_with(Default::default)
+                    .insert(property.to_string(), value);
+            }
+            None => {
+                if let Some(values) = self.wikidata_sort_values.as_mut() {
+                    values.remove(property);
+                }
+            }
+        }
+    }
+
     pub fn title(&self) -> &Title {
         &self.title
     }
 
+    /// Returns the canonical entity URI, for Wikidata items/properties/lexemes, Commons media-info,
+    /// or the associated Wikidata item of a page on another wiki
+    pub fn entity_uri(&self, wiki: &str) -> Option<String> {
+        let entity = match (wiki, self.title.namespace_id()) {
+            ("wikidatawiki", 0) | ("wikidatawiki", 120) | ("wikidatawiki", 146) => {
+                self.title.with_underscores().to_string()
+            }
+            ("wikidatawiki", _) => return None,
+            ("commonswiki", 6) => {
+                return Some(format!(
+                    "https://commons.wikimedia.org/entity/M{}",
+                    self.page_id?
+                ))
+            }
+            _ => self.get_wikidata_item()?,
+        };
+        Some(format!("http://www.wikidata.org/entity/{}", entity))
+    }
+
     pub fn compare(&self, other: &Self, sorter: &PageListSort, is_wikidata: bool) -> Ordering {
+        self.compare_with_collator(other, sorter, is_wikidata, None)
+    }
+
+    /// Like `compare`, but titles and labels are compared with the collator, if given
+    pub fn compare_with_collator(
+        &self,
+        other: &Self,
+        sorter: &PageListSort,
+        is_wikidata: bool,
+        collator: Option<&Collator>,
+    ) -> Ordering {
         match sorter {
             PageListSort::Default(d) => self.compare_by_page_id(other, *d),
             PageListSort::Title(d) => {
                 if is_wikidata {
-                    self.compare_by_label(other, *d)
+                    self.compare_by_label(other, *d, collator)
                 } else {
-                    self.compare_by_title(other, *d)
+                    self.compare_by_title(other, *d, collator)
                 }
             }
-            PageListSort::NsTitle(d) => self.compare_by_ns_title(other, *d),
+            PageListSort::NsTitle(d) => self.compare_by_ns_title(other, *d, collator),
             PageListSort::Size(d) => self.compare_by_size(other, *d),
             PageListSort::IncomingLinks(d) => self.compare_by_incoming(other, *d),
             PageListSort::Date(d) => self.compare_by_date(other, *d),
             PageListSort::UploadDate(d) => self.compare_by_upload_date(other, *d),
             PageListSort::FileSize(d) => self.compare_by_file_size(other, *d),
+            PageListSort::Uploader(d) => self.compare_by_uploader(other, *d),
+            PageListSort::Mime(d) => self.compare_by_mime(other, *d),
+            PageListSort::Pageviews(d) => self.compare_by_pageviews(other, *d),
             PageListSort::RedlinksCount(d) => self.compare_by_redlinks(other, *d),
             PageListSort::Random(d) => self.compare_by_random(other, *d),
+            PageListSort::LastEditor(d) => self.compare_by_last_editor(other, *d),
+            PageListSort::WikidataProperty(property, d) => self.compare_by_opt(
+                &self.get_wikidata_sort_value(property),
+                &other.get_wikidata_sort_value(property),
+                *d,
+            ),
+            PageListSort::Multi(sorters) => sorters
+                .iter()
+                .map(|sorter| self.compare_with_collator(other, sorter, is_wikidata, collator))
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal),
         }
     }
 
@@ -382,14 +828,10 @@ impl PageListEntry {
 
     fn compare_by_random(
         self: &PageListEntry,
-        _other: &PageListEntry,
-        _descending: bool,
+        other: &PageListEntry,
+        descending: bool,
     ) -> Ordering {
-        if rand::random() {
-            Ordering::Less
-        } else {
-            Ordering::Greater
-        }
+        self.compare_by_opt(&self.random_key, &other.random_key, descending)
     }
 
     fn compare_by_size(self: &PageListEntry, other: &PageListEntry, descending: bool) -> Ordering {
@@ -440,6 +882,65 @@ impl PageListEntry {
         }
     }
 
+    fn compare_by_uploader(
+        self: &PageListEntry,
+        other: &PageListEntry,
+        descending: bool,
+    ) -> Ordering {
+        let uploader = |f: &FileInfo| f.img_user_text.as_ref().map(|u| u.to_lowercase());
+        match (&self.get_file_info(), &other.get_file_info()) {
+            (Some(f1), Some(f2)) => self.compare_by_opt(&uploader(f1), &uploader(f2), descending),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    fn compare_by_mime(self: &PageListEntry, other: &PageListEntry, descending: bool) -> Ordering {
+        let mime = |f: &FileInfo| match (&f.img_major_mime, &f.img_minor_mime) {
+            (Some(major), Some(minor)) => Some(format!("{}/{}", major, minor).to_lowercase()),
+            (Some(major), None) => Some(major.to_lowercase()),
+            _ => None,
+        };
+        match (&self.get_file_info(), &other.get_file_info()) {
+            (Some(f1), Some(f2)) => self.compare_by_opt(&mime(f1), &mime(f2), descending),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    fn compare_by_pageviews(
+        self: &PageListEntry,
+        other: &PageListEntry,
+        descending: bool,
+    ) -> Ordering {
+        // Entries without pageviews go last, in either direction
+        match (&self.pageviews, &other.pageviews) {
+            (Some(_), Some(_)) => {
+                self.compare_by_opt(&self.pageviews, &other.pageviews, descending)
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    fn compare_by_last_editor(
+        self: &PageListEntry,
+        other: &PageListEntry,
+        descending: bool,
+    ) -> Ordering {
+        // Entries without a (visible) last editor go last, in either direction
+        let editor = |e: &PageListEntry| e.get_last_editor().map(|u| u.to_lowercase());
+        match (editor(self), editor(other)) {
+            (Some(e1), Some(e2)) => self.compare_by_opt(&Some(e1), &Some(e2), descending),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
     fn compare_by_opt<T: PartialOrd>(
         &self,
         mine: &Option<T>,
@@ -461,9 +962,10 @@ impl PageListEntry {
         self: &PageListEntry,
         other: &PageListEntry,
         descending: bool,
+        collator: Option<&Collator>,
     ) -> Ordering {
         if self.title.namespace_id() == other.title.namespace_id() {
-            self.compare_by_title(other, descending)
+            self.compare_by_title(other, descending, collator)
         } else {
             self.compare_order(
                 self.title
@@ -475,7 +977,12 @@ impl PageListEntry {
         }
     }
 
-    fn compare_by_label(self: &PageListEntry, other: &PageListEntry, descending: bool) -> Ordering {
+    fn compare_by_label(
+        self: &PageListEntry,
+        other: &PageListEntry,
+        descending: bool,
+        collator: Option<&Collator>,
+    ) -> Ordering {
         let l1 = self
             .get_wikidata_label()
             .or_else(|| Some(self.title.pretty().to_owned()))
@@ -486,19 +993,29 @@ impl PageListEntry {
             .or_else(|| Some(self.title.pretty().to_owned()))
             .unwrap_or("".to_string())
             .to_lowercase();
-        self.compare_order(l1.partial_cmp(&l2).unwrap_or(Ordering::Less), descending)
+        self.compare_order(Self::compare_strings(&l1, &l2, collator), descending)
     }
 
-    fn compare_by_title(self: &PageListEntry, other: &PageListEntry, descending: bool) -> Ordering {
+    fn compare_by_title(
+        self: &PageListEntry,
+        other: &PageListEntry,
+        descending: bool,
+        collator: Option<&Collator>,
+    ) -> Ordering {
         self.compare_order(
-            self.title
-                .pretty()
-                .partial_cmp(other.title.pretty())
-                .unwrap_or(Ordering::Less),
+            Self::compare_strings(self.title.pretty(), other.title.pretty(), collator),
             descending,
         )
     }
 
+    /// Compares by Unicode collation if a collator is given, or by bytes otherwise
+    fn compare_strings(a: &str, b: &str, collator: Option<&Collator>) -> Ordering {
+        match collator {
+            Some(collator) => collator.compare(a, b),
+            None => a.partial_cmp(b).unwrap_or(Ordering::Less),
+        }
+    }
+
     fn compare_order(&self, ret: Ordering, descending: bool) -> Ordering {
         if descending {
             ret.reverse()
@@ -510,6 +1027,8 @@ impl PageListEntry {
 
 //________________________________________________________________________________________________________________________
 
+/// Entries are stored in a HashSet, so their iteration order is unspecified.
+/// Use to_sorted_vec_default() where a stable order matters.
 #[derive(Debug)]
 pub struct PageList {
     wiki: RwLock<Option<String>>,
@@ -567,6 +1086,24 @@ impl PageList {
         &self.entries
     }
 
+    /// Calls `f` for each entry, under a single read lock, without cloning entries
+    pub fn for_each_entry(&self, mut f: impl FnMut(&PageListEntry)) -> Result<(), String> {
+        self.entries
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .for_each(|entry| f(entry));
+        Ok(())
+    }
+
+    /// Passes the entries to `f` under a single read lock, and returns its result
+    pub fn with_entries<R>(
+        &self,
+        f: impl FnOnce(&HashSet<PageListEntry>) -> R,
+    ) -> Result<R, String> {
+        Ok(f(&*self.entries.read().map_err(|e| format!("{:?}", e))?))
+    }
+
     pub fn set_entries(&self, entries: HashSet<PageListEntry>) -> Result<(), String> {
         *self.entries.write().map_err(|e| format!("{:?}", e))? = entries;
         Ok(())
@@ -580,6 +1117,38 @@ impl PageList {
         Ok(())
     }
 
+    /// Keeps one entry per Wikidata item. Among entries sharing an item, the one
+    /// with the lowest namespace ID wins, then the longest title, then the
+    /// alphabetically first title. Entries without an item are kept as-is.
+    pub fn unique_by_wikidata_item(&self) -> Result<(), String> {
+        let mut entries = self.entries.write().map_err(|e| format!("{:?}", e))?;
+        let mut best: HashMap<String, PageListEntry> = HashMap::new();
+        let mut ret: HashSet<PageListEntry> = HashSet::new();
+        entries
+            .drain()
+            .for_each(|entry| match entry.get_wikidata_item() {
+                Some(q) => {
+                    let replace = match best.get(&q) {
+                        Some(other) => {
+                            let (a, b) = (entry.title(), other.title());
+                            (a.namespace_id(), b.pretty().len(), a.pretty())
+                                < (b.namespace_id(), a.pretty().len(), b.pretty())
+                        }
+                        None => true,
+                    };
+                    if replace {
+                        best.insert(q, entry);
+                    }
+                }
+                None => {
+                    ret.insert(entry);
+                }
+            });
+        ret.extend(best.drain().map(|(_q, entry)| entry));
+        *entries = ret;
+        Ok(())
+    }
+
     pub fn set_wiki(&self, wiki: Option<String>) -> Result<(), String> {
         *self.wiki.write().map_err(|e| format!("{:?}", e))? = wiki;
         Ok(())
@@ -589,31 +1158,92 @@ impl PageList {
         Ok(self.wiki.read().map_err(|e| format!("{:?}", e))?.clone())
     }
 
+    /// Gives every entry a random sort key, used by `PageListSort::Random`.
+    /// With a seed, the keys are the same for the same entries on every run.
+    pub fn assign_random_keys(&self, seed: Option<u64>) -> Result<(), String> {
+        let mut entries = self.entries.write().map_err(|e| format!("{:?}", e))?;
+        let mut ordered: Vec<PageListEntry> = entries.drain().collect();
+        ordered.par_sort_by(|a, b| a.compare_by_ns_title(b, false, None)); // Iteration order is unspecified
+        let mut rng = StdRng::seed_from_u64(seed.unwrap_or_else(rand::random));
+        entries.extend(ordered.into_iter().map(|mut entry| {
+            entry.random_key = Some(rng.gen());
+            entry
+        }));
+        Ok(())
+    }
+
     pub fn drain_into_sorted_vec(
         &self,
         sorter: PageListSort,
     ) -> Result<Vec<PageListEntry>, String> {
+        self.drain_into_collated_vec(sorter, None)
+    }
+
+    /// Sorts like `drain_into_sorted_vec`, but compares titles and labels with the collator, if given
+    pub fn drain_into_collated_vec(
+        &self,
+        sorter: PageListSort,
+        collator: Option<&Collator>,
+    ) -> Result<Vec<PageListEntry>, String> {
+        if sorter.uses_random()
+            && self
+                .entries
+                .read()
+                .map_err(|e| format!("{:?}", e))?
+                .iter()
+                .any(|entry| entry.random_key.is_none())
+        {
+            self.assign_random_keys(None)?;
+        }
         let mut ret: Vec<PageListEntry> = self
             .entries
             .write()
             .map_err(|e| format!("{:?}", e))?
             .drain()
             .collect();
-        ret.par_sort_by(|a, b| a.compare(b, &sorter, self.is_wikidata()));
+        let is_wikidata = self.is_wikidata();
+        match collator {
+            // Collators are not Sync
+            Some(collator) => {
+                ret.sort_by(|a, b| a.compare_with_collator(b, &sorter, is_wikidata, Some(collator)))
+            }
+            None => ret.par_sort_by(|a, b| a.compare(b, &sorter, is_wikidata)),
+        }
         Ok(ret)
     }
 
-    pub fn group_by_namespace(&self) -> Result<HashMap<NamespaceID, Vec<String>>, String> {
-        let mut ret: HashMap<NamespaceID, Vec<String>> = HashMap::new();
-        self.entries
+    /// Creates a Unicode collator for a language code like "sv" or "de"
+    pub fn collator_for_language(language: &str) -> Result<Collator, String> {
+        let locale: Locale = language
+            .parse()
+            .map_err(|e| format!("Invalid collation '{}': {:?}", language, e))?;
+        Collator::try_new(&(&locale).into(), CollatorOptions::new())
+            .map_err(|e| format!("No collation for '{}': {:?}", language, e))
+    }
+
+    /// Returns a copy of the entries, ordered by namespace and title
+    pub fn to_sorted_vec_default(&self) -> Result<Vec<PageListEntry>, String> {
+        let mut ret: Vec<PageListEntry> = self
+            .entries
             .read()
             .map_err(|e| format!("{:?}", e))?
             .iter()
-            .for_each(|entry| {
-                ret.entry(entry.title.namespace_id())
-                    .or_insert(vec![])
-                    .push(entry.title.with_underscores().to_string());
-            });
+            .cloned()
+            .collect();
+        ret.par_sort_by(|a, b| a.compare_by_ns_title(b, false, None));
+        Ok(ret)
+    }
+
+    /// Groups titles by namespace, in a stable order so SQL batches are reproducible
+    pub fn group_by_namespace(&self) -> Result<BTreeMap<NamespaceID, Vec<String>>, String> {
+        let mut ret: BTreeMap<NamespaceID, Vec<String>> = BTreeMap::new();
+        self.for_each_entry(|entry| {
+            ret.entry(entry.title.namespace_id())
+                .or_insert(vec![])
+                .push(entry.title.with_underscores().to_string());
+        })?;
+        ret.values_mut()
+            .for_each(|titles| titles.par_sort_unstable());
         Ok(ret)
     }
 
@@ -698,7 +1328,22 @@ impl PageList {
             .map_err(|e| format!("{:?}", e))?
             .iter()
             .for_each(|x| {
-                me.insert(x.to_owned());
+                let merged = match (&x.provenance, me.get(x)) {
+                    (Some(_), Some(existing)) => {
+                        let mut existing = existing.to_owned();
+                        existing.merge_provenance(x);
+                        Some(existing)
+                    }
+                    _ => None,
+                };
+                match merged {
+                    Some(entry) => {
+                        me.replace(entry);
+                    }
+                    None => {
+                        me.insert(x.to_owned());
+                    }
+                }
             });
         Platform::profile("PageList::union UNION DONE", None);
         Ok(())
@@ -712,13 +1357,25 @@ impl PageList {
         self.check_before_merging(&pagelist, platform)?;
         let other_entries = pagelist.entries();
         let other_entries = other_entries.read().map_err(|e| format!("{:?}", e))?;
-        self.entries
-            .write()
-            .map_err(|e| format!("{:?}", e))?
-            .retain(|x| other_entries.contains(&x));
+        let mut me = self.entries.write().map_err(|e| format!("{:?}", e))?;
+        me.retain(|x| other_entries.contains(&x));
+        if other_entries.iter().any(|x| x.provenance.is_some()) {
+            *me = me
+                .drain()
+                .map(|mut x| {
+                    match other_entries.get(&x) {
+                        Some(other) => x.merge_provenance(other),
+                        None => {}
+                    }
+                    x
+                })
+                .collect();
+        }
         Ok(())
     }
 
+    /// Removes entries present in `pagelist`. Provenance only reflects the positive side,
+    /// as entries from `pagelist` never survive.
     pub fn difference(
         &self,
         pagelist: &PageList,
@@ -734,6 +1391,81 @@ impl PageList {
         Ok(())
     }
 
+    /// Keeps entries present in exactly one of the two lists
+    pub fn symmetric_difference(
+        &self,
+        pagelist: &PageList,
+        platform: Option<&Platform>,
+    ) -> Result<(), String> {
+        self.check_before_merging(&pagelist, platform)?;
+        let other_entries = pagelist.entries();
+        let other_entries = other_entries.read().map_err(|e| format!("{:?}", e))?;
+        let mut me = self.entries.write().map_err(|e| format!("{:?}", e))?;
+        let other_only: Vec<PageListEntry> = other_entries
+            .iter()
+            .filter(|x| !me.contains(x))
+            .cloned()
+            .collect();
+        me.retain(|x| !other_entries.contains(&x));
+        me.extend(other_only);
+        Ok(())
+    }
+
+    /// Marks all entries as coming from the given source
+    pub fn set_provenance(&self, source: &str) -> Result<(), String> {
+        let mut entries = self.entries.write().map_err(|e| format!("{:?}", e))?;
+        *entries = entries
+            .drain()
+            .map(|mut entry| {
+                entry.add_provenance(source);
+                entry
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Returns the entries that carry provenance or extra values, for use across wiki conversion
+    fn entries_with_provenance(&self) -> Result<HashSet<PageListEntry>, String> {
+        Ok(self
+            .entries
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .filter(|entry| entry.provenance.is_some() || entry.extra.is_some())
+            .cloned()
+            .collect())
+    }
+
+    /// Copies provenance and extra values from the pre-conversion entry, and from an already converted entry with the same title
+    fn transfer_provenance(
+        &self,
+        mut entry: PageListEntry,
+        original: Option<PageListEntry>,
+        with_provenance: &HashSet<PageListEntry>,
+    ) -> PageListEntry {
+        if with_provenance.is_empty() {
+            return entry;
+        }
+        match original.and_then(|original| with_provenance.get(&original)) {
+            Some(original) => {
+                entry.merge_provenance(original);
+                entry.merge_extra(original);
+            }
+            None => {}
+        }
+        match self.entries.read() {
+            Ok(entries) => match entries.get(&entry) {
+                Some(existing) => {
+                    entry.merge_provenance(existing);
+                    entry.merge_extra(existing);
+                }
+                None => {}
+            },
+            _ => {}
+        }
+        entry
+    }
+
     pub fn to_sql_batches(&self, chunk_size: usize) -> Result<Vec<SQLtuple>, String> {
         let mut ret: Vec<SQLtuple> = vec![];
         if self.is_empty()? {
@@ -800,17 +1532,15 @@ impl PageList {
         state: &AppState,
         sql: &SQLtuple,
         wiki: &String,
-    ) -> Result<Vec<my::Row>, String> {
-        let db_user_pass = state
-            .get_db_mutex()
-            .lock()
-            .map_err(|e| format!("PageList::run_batch_query: {:?}", e))?;
-        let mut conn = state
-            .get_wiki_db_connection(&db_user_pass, &wiki)
-            .map_err(|e| format!("PageList::run_batch_query: get_wiki_db_connection: {:?}", e))?;
-        let result = conn
-            .prep_exec(&sql.0, &sql.1)
-            .map_err(|e| format!("PageList::run_batch_query: SQL query error: {:?}", e))?;
+    ) -> Result<Vec<my::Row>, PetScanError> {
+        let db_user_pass = state.get_db_mutex().lock()?;
+        let mut conn = state.get_wiki_db_connection(&db_user_pass, &wiki)?;
+        let result = conn.prep_exec(&sql.0, &sql.1).map_err(|e| {
+            PetScanError::Database(format!(
+                "PageList::run_batch_query: SQL query error: {:?}",
+                e
+            ))
+        })?;
         Ok(result.filter_map(|row| row.ok()).collect())
     }
 
@@ -819,7 +1549,7 @@ impl PageList {
         &self,
         state: &AppState,
         batches: Vec<SQLtuple>,
-    ) -> Result<Vec<my::Row>, String> {
+    ) -> Result<Vec<my::Row>, PetScanError> {
         let wiki = self
             .wiki()?
             .ok_or(format!("PageList::run_batch_queries: No wiki"))?;
@@ -838,7 +1568,7 @@ impl PageList {
         state: &AppState,
         batches: Vec<SQLtuple>,
         wiki: String,
-    ) -> Result<Vec<my::Row>, String> {
+    ) -> Result<Vec<my::Row>, PetScanError> {
         // TODO?: "SET STATEMENT max_statement_time = 300 FOR SELECT..."
         let mut rows: Vec<my::Row> = vec![];
         for sql in batches {
@@ -855,13 +1585,13 @@ impl PageList {
         state: &AppState,
         batches: Vec<SQLtuple>,
         wiki: String,
-    ) -> Result<Vec<my::Row>, String> {
+    ) -> Result<Vec<my::Row>, PetScanError> {
         // TODO?: "SET STATEMENT max_statement_time = 300 FOR SELECT..."
 
         Ok(batches
             .par_iter()
             .map(|sql| self.run_batch_query(state, sql, &wiki))
-            .collect::<Result<Vec<_>, String>>()?
+            .collect::<Result<Vec<_>, PetScanError>>()?
             .into_iter()
             .flatten()
             .collect())
@@ -873,7 +1603,7 @@ impl PageList {
         state: &AppState,
         batches: Vec<SQLtuple>,
         f: &dyn Fn(my::Row) -> Option<PageListEntry>,
-    ) -> Result<(), String> {
+    ) -> Result<(), PetScanError> {
         self.run_batch_queries(&state, batches)?
             .iter()
             .filter_map(|row| f(row.to_owned()))
@@ -910,7 +1640,7 @@ impl PageList {
         col_title: usize,
         col_ns: usize,
         f: &dyn Fn(my::Row, &mut PageListEntry),
-    ) -> Result<(), String> {
+    ) -> Result<(), PetScanError> {
         self.run_batch_queries(&state, batches)?
             .iter()
             .filter_map(|row| {
@@ -986,15 +1716,18 @@ impl PageList {
         Ok(())
     }
 
+    /// Loads page metadata, and labels/descriptions in `wikidata_languages` for Wikidata lists.
+    /// The first language is the primary one.
     pub fn load_missing_metadata(
         &self,
-        wikidata_language: Option<String>,
+        wikidata_languages: &[String],
+        label_fallback: &[String],
         platform: &Platform,
     ) -> Result<(), String> {
         self.load_missing_page_metadata(platform)?;
 
         // All done
-        if !self.is_wikidata() || wikidata_language.is_none() {
+        if !self.is_wikidata() || wikidata_languages.is_empty() {
             return Ok(());
         }
 
@@ -1003,28 +1736,71 @@ impl PageList {
             return Ok(());
         }
 
-        match wikidata_language {
-            Some(wikidata_language) => {
-                self.add_wikidata_labels_for_namespace(0, "item", &wikidata_language, platform)?;
-                self.add_wikidata_labels_for_namespace(
-                    120,
-                    "property",
-                    &wikidata_language,
-                    platform,
-                )?;
-            }
-            None => {}
-        }
+        self.add_wikidata_labels_for_namespace_languages(
+            0,
+            "item",
+            wikidata_languages,
+            label_fallback,
+            platform,
+        )?;
+        self.add_wikidata_labels_for_namespace_languages(
+            120,
+            "property",
+            wikidata_languages,
+            label_fallback,
+            platform,
+        )?;
         Ok(())
     }
 
-    fn add_wikidata_labels_for_namespace(
+    pub fn add_wikidata_labels_for_namespace(
         &self,
         namespace_id: NamespaceID,
         entity_type: &str,
         wikidata_language: &String,
         platform: &Platform,
     ) -> Result<(), String> {
+        self.add_wikidata_labels_for_namespace_languages(
+            namespace_id,
+            entity_type,
+            &[wikidata_language.to_string()],
+            &[],
+            platform,
+        )
+    }
+
+    /// Loads labels and descriptions in all `wikidata_languages` in one pass.
+    /// The first language fills the primary label/description; if there is more than one
+    /// language, all of them are also kept per language.
+    /// If the primary language has no label, the first available label from the
+    /// `label_fallback` languages is used instead, like MediaWiki's language fallback.
+    pub fn add_wikidata_labels_for_namespace_languages(
+        &self,
+        namespace_id: NamespaceID,
+        entity_type: &str,
+        wikidata_languages: &[String],
+        label_fallback: &[String],
+        platform: &Platform,
+    ) -> Result<(), String> {
+        let languages = Platform::prep_quote(wikidata_languages).1;
+        let primary_language = match languages.first() {
+            Some(language) => language.to_string(),
+            None => return Ok(()),
+        };
+        let per_language = languages.len() > 1;
+        let mut label_chain = vec![primary_language.to_owned()];
+        for language in Platform::prep_quote(label_fallback).1 {
+            if !label_chain.contains(&language) {
+                label_chain.push(language);
+            }
+        }
+        let mut query_languages = languages.to_owned();
+        for language in &label_chain {
+            if !query_languages.contains(language) {
+                query_languages.push(language.to_owned());
+            }
+        }
+        let language_placeholders = Platform::get_questionmarks(query_languages.len());
         let batches: Vec<SQLtuple> = self
             .to_sql_batches_namespace(PAGE_BATCH_SIZE,namespace_id)?
             .iter_mut()
@@ -1051,14 +1827,14 @@ impl PageList {
                     _ => return None
                 } ;
                 let item_ids = sql_batch.1.iter().map(|s|s[1..].to_string()).collect::<Vec<String>>().join(",");
-                sql_batch.1 = vec![wikidata_language.to_string()];
-                sql_batch.0 = format!("SELECT concat('{}',{}) AS term_full_entity_id,{} AS dummy_namespace,wbx_text as term_text,wby_name as term_type
+                sql_batch.1 = query_languages.clone();
+                sql_batch.0 = format!("SELECT concat('{}',{}) AS term_full_entity_id,{} AS dummy_namespace,wbx_text as term_text,wby_name as term_type,wbxl_language as term_language
 FROM {}
 INNER JOIN wbt_term_in_lang ON {} = wbtl_id
 INNER JOIN wbt_type ON wbtl_type_id = wby_id
 INNER JOIN wbt_text_in_lang ON wbtl_text_in_lang_id = wbxl_id
-INNER JOIN wbt_text ON wbxl_text_id = wbx_id AND wbxl_language=?
-WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&field_name,item_ids);
+INNER JOIN wbt_text ON wbxl_text_id = wbx_id AND wbxl_language IN ({})
+WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&language_placeholders,&field_name,item_ids);
                 Some(sql_batch.to_owned())
             })
             .collect::<Vec<SQLtuple>>();
@@ -1073,19 +1849,44 @@ WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&field_n
                 NamespaceID,
                 Vec<u8>,
                 Vec<u8>,
+                Vec<u8>,
             )>(row)
             {
-                Ok((_page_title, _page_namespace, term_text, term_type)) => {
+                Ok((_page_title, _page_namespace, term_text, term_type, term_language)) => {
                     let term_text = String::from_utf8_lossy(&term_text).into_owned();
+                    let term_language = String::from_utf8_lossy(&term_language).into_owned();
+                    let is_primary = term_language == primary_language;
                     match String::from_utf8_lossy(&term_type).into_owned().as_str() {
-                        "label" => entry.set_wikidata_label(Some(term_text)),
-                        "description" => entry.set_wikidata_description(Some(term_text)),
+                        "label" => {
+                            if per_language && languages.contains(&term_language) {
+                                entry.set_wikidata_label_in(&term_language, term_text.clone());
+                            }
+                            if label_chain.len() > 1 {
+                                entry.set_wikidata_label_from_chain(
+                                    &term_language,
+                                    term_text,
+                                    &label_chain,
+                                );
+                            } else if is_primary {
+                                entry.set_wikidata_label(Some(term_text));
+                            }
+                        }
+                        "description" => {
+                            if per_language && languages.contains(&term_language) {
+                                entry
+                                    .set_wikidata_description_in(&term_language, term_text.clone());
+                            }
+                            if is_primary {
+                                entry.set_wikidata_description(Some(term_text));
+                            }
+                        }
                         _ => {}
                     }
                 }
                 _ => {}
             },
-        )
+        )?;
+        Ok(())
     }
 
     pub fn convert_to_wiki(&self, wiki: &str, platform: &Platform) -> Result<(), String> {
@@ -1093,11 +1894,12 @@ WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&field_n
         if self.wiki()? == None || self.wiki()? == Some(wiki.to_string()) {
             return Ok(());
         }
+        let size_before = self.len()?;
         self.convert_to_wikidata(platform)?;
         if wiki != "wikidatawiki" {
             self.convert_from_wikidata(wiki, platform)?;
         }
-        Ok(())
+        platform.note_dropped_in_conversion(size_before.saturating_sub(self.len()?))
     }
 
     fn convert_to_wikidata(&self, platform: &Platform) -> Result<(), String> {
@@ -1108,19 +1910,17 @@ WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&field_n
         let batches: Vec<SQLtuple> = self.to_sql_batches(PAGE_BATCH_SIZE)?
             .par_iter_mut()
             .map(|sql|{
-                sql.0 = "SELECT pp_value FROM page_props,page WHERE page_id=pp_page AND pp_propname='wikibase_item' AND ".to_owned()+&sql.0;
+                sql.0 = "SELECT pp_value,page_namespace,page_title FROM page_props,page WHERE page_id=pp_page AND pp_propname='wikibase_item' AND ".to_owned()+&sql.0;
                 sql.to_owned()
             })
             .collect::<Vec<SQLtuple>>();
+        let with_provenance = self.entries_with_provenance()?;
         self.clear_entries()?;
         self.process_batch_results(&platform.state(), batches, &|row: my::Row| {
-            match my::from_row_opt::<Vec<u8>>(row) {
-                Ok(pp_value) => {
-                    let pp_value = String::from_utf8_lossy(&pp_value).into_owned();
-                    Some(PageListEntry::new(Title::new(&pp_value, 0)))
-                }
-                Err(_e) => None,
-            }
+            let pp_value = Self::string_from_row(&row, 0)?;
+            let entry = PageListEntry::new(Title::new(&pp_value, 0));
+            let original = self.entry_from_row(&row, 2, 1);
+            Some(self.transfer_provenance(entry, original, &with_provenance))
         })?;
         self.set_wiki(Some("wikidatawiki".to_string()))?;
         Ok(())
@@ -1134,7 +1934,7 @@ WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&field_n
         let batches = self.to_sql_batches(PAGE_BATCH_SIZE*2)?
             .par_iter_mut()
             .map(|sql|{
-                sql.0 = "SELECT ips_site_page FROM wb_items_per_site,page WHERE ips_item_id=substr(page_title,2)*1 AND ".to_owned()+&sql.0+" AND ips_site_id=?";
+                sql.0 = "SELECT ips_site_page,page_namespace,page_title FROM wb_items_per_site,page WHERE ips_item_id=substr(page_title,2)*1 AND ".to_owned()+&sql.0+" AND ips_site_id=?";
                 sql.1.push(wiki.to_string());
                 sql.to_owned()
             })
@@ -1145,6 +1945,20 @@ WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&field_n
             Some(batches.len()),
         );
 
+        let with_provenance = self.entries_with_provenance()?;
+        let label_fallback = platform.has_param("convert_label_fallback");
+        let items_before: Vec<PageListEntry> = match label_fallback {
+            true => self
+                .entries
+                .read()
+                .map_err(|e| format!("{:?}", e))?
+                .iter()
+                .filter(|entry| entry.title().namespace_id() == 0)
+                .cloned()
+                .collect(),
+            false => vec![],
+        };
+        let converted_items: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
         self.clear_entries()?;
         let api = platform.state().get_api_for_wiki(wiki.to_string())?;
         Platform::profile("PageList::convert_from_wikidata STARTING BATCHES", None);
@@ -1155,12 +1969,19 @@ WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&field_n
                 &platform.state(),
                 batch_chunk.to_vec(),
                 &|row: my::Row| {
-                    let ips_site_page = my::from_row_opt::<Vec<u8>>(row).ok()?;
-                    let ips_site_page = String::from_utf8_lossy(&ips_site_page).into_owned();
-                    Some(PageListEntry::new(Title::new_from_full(
-                        &ips_site_page,
-                        &api,
-                    )))
+                    let ips_site_page = Self::string_from_row(&row, 0)?;
+                    let entry = PageListEntry::new(Title::new_from_full(&ips_site_page, &api));
+                    let original = self.entry_from_row(&row, 2, 1);
+                    if label_fallback {
+                        if let Some(original) = &original {
+                            // A poisoned lock is reported once all batches are done
+                            converted_items
+                                .lock()
+                                .ok()?
+                                .insert(original.title().with_underscores().to_string());
+                        }
+                    }
+                    Some(self.transfer_provenance(entry, original, &with_provenance))
                 },
             );
             match res {
@@ -1175,23 +1996,118 @@ WHERE {} IN ({})",prefix,&field_name,namespace_id,table,term_in_lang_id,&field_n
         });
         Platform::profile("PageList::convert_from_wikidata ALL BATCHES COMPLETE", None);
         self.set_wiki(Some(wiki.to_string()))?;
+        if label_fallback {
+            let converted_items = converted_items
+                .into_inner()
+                .map_err(|e| format!("{:?}", e))?;
+            let unconverted: HashSet<PageListEntry> = items_before
+                .into_iter()
+                .filter(|entry| !converted_items.contains(&entry.title().with_underscores()))
+                .collect();
+            self.convert_by_label_fallback(unconverted, wiki, &api, platform)?;
+        }
         Platform::profile("PageList::convert_from_wikidata END", None);
         Ok(())
     }
 
-    pub fn regexp_filter(&self, regexp: &String) -> Result<(), String> {
+    /// Opt-in fallback for items without a sitelink to the target wiki: if a page on that wiki
+    /// has the item's label (in the wiki's language) as its title, it is added to the result.
+    /// This is a heuristic; the page may well be about something else with the same name
+    /// (e.g. a disambiguation page, or a different person), so it is off by default, and matches
+    /// are marked with the item they came from in the "label_match_item" field.
+    fn convert_by_label_fallback(
+        &self,
+        items: HashSet<PageListEntry>,
+        wiki: &str,
+        api: &Api,
+        platform: &Platform,
+    ) -> Result<(), String> {
+        if items.is_empty() {
+            return Ok(());
+        }
+        let language = match api.get_site_info()["query"]["general"]["lang"].as_str() {
+            Some(language) => language.to_string(),
+            None => return Ok(()),
+        };
+        let item_list = PageList::new_from_wiki("wikidatawiki");
+        item_list.set_entries(items)?;
+        item_list.add_wikidata_labels_for_namespace(0, "item", &language, platform)?;
+
+        let candidates: HashMap<String, String> = item_list
+            .entries
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .filter_map(|entry| {
+                let title = Platform::normalize_title(&entry.get_wikidata_label()?, api);
+                match title.namespace_id() {
+                    0 => Some((
+                        title.with_underscores().to_string(),
+                        entry.title().with_underscores().to_string(),
+                    )),
+                    _ => None,
+                }
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        let candidate_list = PageList::new_from_wiki(wiki);
+        candidate_list.set_entries(
+            candidates
+                .keys()
+                .map(|title| PageListEntry::new(Title::new(title, 0)))
+                .collect(),
+        )?;
+        let batches: Vec<SQLtuple> = candidate_list
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace FROM page WHERE ".to_owned() + &sql.0;
+                sql.to_owned()
+            })
+            .collect();
+        let found = PageList::new_from_wiki(wiki);
+        found.process_batch_results(&platform.state(), batches, &|row: my::Row| {
+            let page_title = Self::string_from_row(&row, 0)?;
+            let mut entry = PageListEntry::new(Title::new(&page_title, 0));
+            entry.set_extra("label_match_item", candidates.get(&page_title)?);
+            Some(entry)
+        })?;
+
+        let mut entries = self.entries.write().map_err(|e| format!("{:?}", e))?;
+        found
+            .entries
+            .write()
+            .map_err(|e| format!("{:?}", e))?
+            .drain()
+            .for_each(|entry| {
+                if !entries.contains(&entry) {
+                    entries.insert(entry);
+                }
+            });
+        Ok(())
+    }
+
+    /// Keeps entries whose title (label, for Wikidata) matches `regexp` completely.
+    /// With `invert`, removes those entries instead, and keeps all others.
+    pub fn regexp_filter(&self, regexp: &String, invert: bool) -> Result<(), PetScanError> {
         let regexp_all = "^".to_string() + regexp + "$";
         let is_wikidata = self.is_wikidata();
-        match Regex::new(&regexp_all) {
-            Ok(re) => self.retain_entries(&|entry: &PageListEntry| match is_wikidata {
+        let re = Regex::new(&regexp_all).map_err(|e| {
+            PetScanError::BadParameter(format!("Invalid regexp_filter '{}': {}", regexp, e))
+        })?;
+        self.retain_entries(&|entry: &PageListEntry| {
+            let is_match = match is_wikidata {
                 true => match &entry.wikidata_label {
                     Some(s) => re.is_match(s.as_str()),
                     None => false,
                 },
                 false => re.is_match(entry.title().pretty()),
-            })?,
-            _ => {}
-        }
+            };
+            is_match != invert
+        })?;
         Ok(())
     }
 
@@ -1218,6 +2134,60 @@ mod tests {
             PageListSort::new_from_params(&"this is not a sort parameter".to_string(), true),
             PageListSort::Default(true)
         );
+        assert_eq!(
+            PageListSort::new_from_params(&"wd:P569".to_string(), false),
+            PageListSort::WikidataProperty("P569".to_string(), false)
+        );
+        assert_eq!(
+            PageListSort::new_from_params(&"wd:Q5".to_string(), false),
+            PageListSort::Default(false)
+        );
+    }
+
+    #[test]
+    fn wikidata_sort_value() {
+        let claims = json!([
+            {"rank":"normal","mainsnak":{"datavalue":{"type":"time","value":{"time":"+1952-03-11T00:00:00Z"}}}},
+            {"rank":"preferred","mainsnak":{"datavalue":{"type":"time","value":{"time":"+1879-03-14T00:00:00Z"}}}}
+        ]);
+        let einstein = WikidataSortValue::new_from_claims(&claims).unwrap();
+        assert_eq!(
+            einstein,
+            WikidataSortValue::Time(1879, "-03-14T00:00:00Z".to_string())
+        );
+        let bc = json!([{"rank":"normal","mainsnak":{"datavalue":{"type":"time","value":{"time":"-0500-00-00T00:00:00Z"}}}}]);
+        let bc = WikidataSortValue::new_from_claims(&bc).unwrap();
+        assert!(bc < einstein);
+        let quantity = json!([{"rank":"normal","mainsnak":{"datavalue":{"type":"quantity","value":{"amount":"+12.5"}}}}]);
+        assert_eq!(
+            WikidataSortValue::new_from_claims(&quantity),
+            Some(WikidataSortValue::Quantity(12.5))
+        );
+        let deprecated =
+            json!([{"rank":"deprecated","mainsnak":{"datavalue":{"type":"string","value":"x"}}}]);
+        assert_eq!(WikidataSortValue::new_from_claims(&deprecated), None);
+
+        // Each property is sorted by its own values
+        let item = |name: &str, p1: f64, p2: f64| {
+            let mut entry = PageListEntry::new(Title::new(name, 0));
+            entry.set_wikidata_sort_value("P1", Some(WikidataSortValue::Quantity(p1)));
+            entry.set_wikidata_sort_value("P2", Some(WikidataSortValue::Quantity(p2)));
+            entry
+        };
+        let entries = vec![item("Q1", 1.0, 2.0), item("Q2", 2.0, 1.0)];
+        let first_by = |sortby: &str| {
+            let sorter = PageListSort::new_from_params(&sortby.to_string(), false);
+            let mut v = entries.clone();
+            v.sort_by(|a, b| a.compare(b, &sorter, false));
+            v[0].title().pretty().to_string()
+        };
+        assert_eq!(first_by("wd:P1"), "Q1");
+        assert_eq!(first_by("wd:P2"), "Q2");
+        assert_eq!(first_by("wd:P2,wd:P1"), "Q2");
+        let mut entry = item("Q3", 1.0, 1.0);
+        entry.set_wikidata_sort_value("P1", None);
+        assert_eq!(entry.get_wikidata_sort_value("P1"), None);
+        assert!(entry.get_wikidata_sort_value("P2").is_some());
     }
 
     #[test]
@@ -1275,4 +2245,387 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn unique_by_wikidata_item() {
+        let entry = |title: &str, ns: NamespaceID, q: Option<&str>| {
+            let mut entry = PageListEntry::new(Title::new(title, ns));
+            entry.set_wikidata_item(q.map(|q| q.to_string()));
+            entry
+        };
+        let list = PageList::new_from_wiki("enwiki");
+        list.add_entry(entry("Douglas Adams", 0, Some("Q42")))
+            .unwrap();
+        list.add_entry(entry("Douglas Noel Adams", 0, Some("Q42")))
+            .unwrap();
+        list.add_entry(entry("Douglas Adams (author)", 4, Some("Q42")))
+            .unwrap();
+        list.add_entry(entry("Earth", 0, Some("Q2"))).unwrap();
+        list.add_entry(entry("No item", 0, None)).unwrap();
+        list.add_entry(entry("No item either", 0, None)).unwrap();
+        list.unique_by_wikidata_item().unwrap();
+        let mut titles: Vec<String> = list
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| e.title().pretty().to_string())
+            .collect();
+        titles.sort();
+        assert_eq!(
+            titles,
+            vec!["Douglas Noel Adams", "Earth", "No item", "No item either"]
+        );
+    }
+
+    #[test]
+    fn sorted_vec_default_and_batches() {
+        let list = PageList::new_from_wiki("enwiki");
+        ["Zeta", "Alpha", "Mu"].iter().for_each(|t| {
+            list.add_entry(PageListEntry::new(Title::new(t, 0)))
+                .unwrap()
+        });
+        list.add_entry(PageListEntry::new(Title::new("Beta", 4)))
+            .unwrap();
+        let titles: Vec<String> = list
+            .to_sorted_vec_default()
+            .unwrap()
+            .iter()
+            .map(|e| e.title().pretty().to_string())
+            .collect();
+        assert_eq!(titles, vec!["Alpha", "Mu", "Zeta", "Beta"]);
+        assert_eq!(
+            list.to_sql_batches(2).unwrap(),
+            vec![
+                (
+                    "(page_namespace=0 AND page_title IN(?,?))".to_string(),
+                    vec!["Alpha".to_string(), "Mu".to_string()]
+                ),
+                (
+                    "(page_namespace=0 AND page_title IN(?))".to_string(),
+                    vec!["Zeta".to_string()]
+                ),
+                (
+                    "(page_namespace=4 AND page_title IN(?))".to_string(),
+                    vec!["Beta".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_by_title_with_collation() {
+        let list = PageList::new_from_wiki("svwiki");
+        let titles = vec!["Öl", "Zebra", "Åsa", "Ärlig"];
+        let sorted = |collation: Option<&str>| {
+            titles.iter().for_each(|t| {
+                list.add_entry(PageListEntry::new(Title::new(t, 0)))
+                    .unwrap()
+            });
+            let collator = collation.map(|c| PageList::collator_for_language(c).unwrap());
+            list.drain_into_collated_vec(PageListSort::Title(false), collator.as_ref())
+                .unwrap()
+                .iter()
+                .map(|e| e.title().pretty().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(sorted(None), vec!["Zebra", "Ärlig", "Åsa", "Öl"]);
+        assert_eq!(sorted(Some("sv")), vec!["Zebra", "Åsa", "Ärlig", "Öl"]);
+        assert_eq!(sorted(Some("de")), vec!["Ärlig", "Åsa", "Öl", "Zebra"]);
+        assert!(PageList::collator_for_language("not a language!").is_err());
+    }
+
+    #[test]
+    fn sort_by_multiple_keys() {
+        assert_eq!(
+            PageListSort::new_from_params(&"ns_title, size:desc".to_string(), false),
+            PageListSort::Multi(vec![PageListSort::NsTitle(false), PageListSort::Size(true)])
+        );
+        assert_eq!(
+            PageListSort::new_from_params(&"wd:P569".to_string(), true),
+            PageListSort::WikidataProperty("P569".to_string(), true)
+        );
+        assert_eq!(
+            PageListSort::sort_key_names("size:asc,wd:P569:desc"),
+            vec!["size", "wd:P569"]
+        );
+
+        let page = |name: &str, size: u32| {
+            let mut entry = PageListEntry::new(Title::new(name, 0));
+            entry.page_bytes = Some(size);
+            entry
+        };
+        let entries = vec![page("B", 10), page("C", 5), page("A", 10)];
+        let sorted = |sortby: &str, descending: bool| {
+            let sorter = PageListSort::new_from_params(&sortby.to_string(), descending);
+            let mut v = entries.clone();
+            v.sort_by(|x, y| x.compare(y, &sorter, false));
+            v.iter()
+                .map(|e| e.title().pretty().to_string())
+                .collect::<Vec<String>>()
+        };
+        // Ties on size are broken by title
+        assert_eq!(sorted("size,title", false), vec!["C", "A", "B"]);
+        assert_eq!(sorted("size,title", true), vec!["B", "A", "C"]);
+        assert_eq!(sorted("size:desc,title", false), vec!["A", "B", "C"]);
+        assert_eq!(sorted("size,title:asc", true), vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn sort_by_last_editor() {
+        let page = |name: &str, editor: Option<&str>| {
+            let mut entry = PageListEntry::new(Title::new(name, 0));
+            entry.set_last_editor(editor.map(|e| e.to_string()));
+            entry
+        };
+        let entries = vec![
+            page("Hidden", None),
+            page("Second", Some("Zebra")),
+            page("First", Some("aardvark")),
+        ];
+        assert_eq!(
+            PageListSort::new_from_params(&"last_editor".to_string(), false),
+            PageListSort::LastEditor(false)
+        );
+        let sorted = |descending: bool| {
+            let sorter = PageListSort::LastEditor(descending);
+            let mut v = entries.clone();
+            v.sort_by(|x, y| x.compare(y, &sorter, false));
+            v.iter()
+                .map(|e| e.title().pretty().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(sorted(false), vec!["First", "Second", "Hidden"]);
+        assert_eq!(sorted(true), vec!["Second", "First", "Hidden"]);
+    }
+
+    #[test]
+    fn wikidata_label_fallback_chain() {
+        let chain: Vec<String> = vec!["de".to_string(), "en".to_string(), "mul".to_string()];
+        // No "de" label, but "en" and "mul"; rows may come in any order
+        let mut entry = PageListEntry::new(Title::new("Q12345", 0));
+        entry.set_wikidata_label_from_chain("mul", "Count".to_string(), &chain);
+        entry.set_wikidata_label_from_chain("en", "Count von Count".to_string(), &chain);
+        entry.set_wikidata_label_from_chain("fr", "Comte".to_string(), &chain);
+        assert_eq!(
+            entry.get_wikidata_label(),
+            Some("Count von Count".to_string())
+        );
+        assert_eq!(entry.get_label_language_used(), Some("en".to_string()));
+
+        // A label in the requested language wins
+        entry.set_wikidata_label_from_chain("de", "Graf Zahl".to_string(), &chain);
+        entry.set_wikidata_label_from_chain("en", "Count von Count".to_string(), &chain);
+        assert_eq!(entry.get_wikidata_label(), Some("Graf Zahl".to_string()));
+        assert_eq!(entry.get_label_language_used(), Some("de".to_string()));
+    }
+
+    #[test]
+    fn sum_page_bytes_without_cloning() {
+        let list = PageList::new_from_wiki("enwiki");
+        for (name, size) in &[("A", 100), ("B", 20), ("C", 3)] {
+            let mut entry = PageListEntry::new(Title::new(name, 0));
+            entry.page_bytes = Some(*size);
+            list.add_entry(entry).unwrap();
+        }
+        list.add_entry(PageListEntry::new(Title::new("D", 0)))
+            .unwrap();
+        let total = list
+            .with_entries(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|entry| entry.page_bytes)
+                    .sum::<u32>()
+            })
+            .unwrap();
+        assert_eq!(total, 123);
+        let mut total = 0;
+        list.for_each_entry(|entry| total += entry.page_bytes.unwrap_or(0))
+            .unwrap();
+        assert_eq!(total, 123);
+    }
+
+    #[test]
+    fn group_by_namespace_sorted() {
+        let list = PageList::new_from_wiki("enwiki");
+        for (name, ns) in &[("C d", 0), ("A", 1), ("B", 0), ("A", 0)] {
+            list.add_entry(PageListEntry::new(Title::new(name, *ns)))
+                .unwrap();
+        }
+        let by_ns = list.group_by_namespace().unwrap();
+        assert_eq!(by_ns.keys().cloned().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(by_ns[&0], vec!["A", "B", "C_d"]);
+        assert_eq!(by_ns[&1], vec!["A"]);
+    }
+
+    #[test]
+    fn sort_by_random_with_seed() {
+        let sorted = |seed: u64| {
+            let list = PageList::new_from_wiki("enwiki");
+            (1..=50).for_each(|i| {
+                list.add_entry(PageListEntry::new(Title::new(&format!("Page {}", i), 0)))
+                    .unwrap()
+            });
+            list.assign_random_keys(Some(seed)).unwrap();
+            list.drain_into_sorted_vec(PageListSort::new_from_params(&"random".to_string(), false))
+                .unwrap()
+                .iter()
+                .map(|e| e.title().pretty().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(sorted(42), sorted(42));
+        assert_ne!(sorted(42), sorted(43));
+    }
+
+    #[test]
+    fn sort_by_pageviews() {
+        let page = |name: &str, pageviews: Option<u64>| {
+            let mut entry = PageListEntry::new(Title::new(name, 0));
+            entry.pageviews = pageviews;
+            entry
+        };
+        let entries = vec![
+            page("Unknown", None),
+            page("Few", Some(10)),
+            page("Many", Some(5000)),
+        ];
+        assert_eq!(
+            PageListSort::new_from_params(&"pageviews".to_string(), true),
+            PageListSort::Pageviews(true)
+        );
+        let sorted = |sorter: PageListSort| {
+            let mut v = entries.clone();
+            v.sort_by(|x, y| x.compare(y, &sorter, false));
+            v.iter()
+                .map(|e| e.title().pretty().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            sorted(PageListSort::Pageviews(true)),
+            vec!["Many", "Few", "Unknown"]
+        );
+        assert_eq!(
+            sorted(PageListSort::Pageviews(false)),
+            vec!["Few", "Many", "Unknown"]
+        );
+    }
+
+    #[test]
+    fn sort_by_uploader_and_mime() {
+        let file = |name: &str, user: &str, major: &str, minor: &str| {
+            let mut entry = PageListEntry::new(Title::new(name, 6));
+            let mut fi = FileInfo::new();
+            fi.img_user_text = Some(user.to_string());
+            fi.img_major_mime = Some(major.to_string());
+            fi.img_minor_mime = Some(minor.to_string());
+            entry.set_file_info(Some(fi));
+            entry
+        };
+        let a = file("A.jpg", "bob", "image", "jpeg");
+        let b = file("B.svg", "Alice", "image", "svg+xml");
+        let c = file("C.pdf", "Carol", "application", "pdf");
+        let no_info = PageListEntry::new(Title::new("D.png", 6));
+        assert_eq!(
+            PageListSort::new_from_params(&"uploader".to_string(), false),
+            PageListSort::Uploader(false)
+        );
+        assert_eq!(
+            PageListSort::new_from_params(&"mime".to_string(), true),
+            PageListSort::Mime(true)
+        );
+
+        let sorted = |sorter: PageListSort| {
+            let mut v = vec![no_info.clone(), a.clone(), b.clone(), c.clone()];
+            v.sort_by(|x, y| x.compare(y, &sorter, false));
+            v.iter()
+                .map(|e| e.title().pretty().to_string())
+                .collect::<Vec<String>>()
+        };
+        // Case-insensitive; entries without file info go last
+        assert_eq!(
+            sorted(PageListSort::Uploader(false)),
+            vec!["B.svg", "A.jpg", "C.pdf", "D.png"]
+        );
+        assert_eq!(
+            sorted(PageListSort::Uploader(true)),
+            vec!["C.pdf", "A.jpg", "B.svg", "D.png"]
+        );
+        assert_eq!(
+            sorted(PageListSort::Mime(false)),
+            vec!["C.pdf", "A.jpg", "B.svg", "D.png"]
+        );
+    }
+
+    #[test]
+    fn entity_uri() {
+        let entry = |title: &str, ns: NamespaceID| PageListEntry::new(Title::new(title, ns));
+        assert_eq!(
+            entry("Q42", 0).entity_uri("wikidatawiki"),
+            Some("http://www.wikidata.org/entity/Q42".to_string())
+        );
+        assert_eq!(
+            entry("P31", 120).entity_uri("wikidatawiki"),
+            Some("http://www.wikidata.org/entity/P31".to_string())
+        );
+        assert_eq!(
+            entry("L7", 146).entity_uri("wikidatawiki"),
+            Some("http://www.wikidata.org/entity/L7".to_string())
+        );
+        assert_eq!(entry("Q42", 1).entity_uri("wikidatawiki"), None);
+
+        let mut file = entry("Example.jpg", 6);
+        assert_eq!(file.entity_uri("commonswiki"), None);
+        file.page_id = Some(12345);
+        assert_eq!(
+            file.entity_uri("commonswiki"),
+            Some("https://commons.wikimedia.org/entity/M12345".to_string())
+        );
+
+        let mut article = entry("Douglas_Adams", 0);
+        assert_eq!(article.entity_uri("enwiki"), None);
+        article.set_wikidata_item(Some("Q42".to_string()));
+        assert_eq!(
+            article.entity_uri("enwiki"),
+            Some("http://www.wikidata.org/entity/Q42".to_string())
+        );
+    }
+
+    #[test]
+    fn provenance() {
+        let entry = |title: &str| PageListEntry::new(Title::new(title, 0));
+        let provenance = |list: &PageList, title: &str| {
+            list.entries()
+                .read()
+                .unwrap()
+                .get(&entry(title))
+                .and_then(|e| e.get_provenance())
+        };
+        let a = PageList::new_from_wiki("enwiki");
+        a.add_entry(entry("Both")).unwrap();
+        a.add_entry(entry("Only A")).unwrap();
+        a.set_provenance("categories").unwrap();
+        let b = PageList::new_from_wiki("enwiki");
+        b.add_entry(entry("Both")).unwrap();
+        b.add_entry(entry("Only B")).unwrap();
+        b.set_provenance("sparql").unwrap();
+
+        let union = PageList::new_from_wiki("enwiki");
+        union.union(&a, None).unwrap();
+        union.union(&b, None).unwrap();
+        assert_eq!(
+            provenance(&union, "Both"),
+            Some(vec!["categories".to_string(), "sparql".to_string()])
+        );
+        assert_eq!(
+            provenance(&union, "Only B"),
+            Some(vec!["sparql".to_string()])
+        );
+
+        a.intersection(&b, None).unwrap();
+        assert_eq!(a.len(), Ok(1));
+        assert_eq!(
+            provenance(&a, "Both"),
+            Some(vec!["categories".to_string(), "sparql".to_string()])
+        );
+    }
 }
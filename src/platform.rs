@@ -1,23 +1,107 @@
 use crate::app_state::AppState;
 use crate::datasource::*;
 use crate::datasource_database::{SourceDatabase, SourceDatabaseParameters};
+use crate::error::PetScanError;
 use crate::form_parameters::FormParameters;
 use crate::pagelist::*;
 use crate::render::*;
 use crate::wdfist::*;
 use actix_web::{Error, HttpResponse};
-use chrono::Local;
+use chrono::{Local, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use mysql as my;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use rayon::prelude::*;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex, RwLock};
-use std::time::{Duration, SystemTime};
-use wikibase::mediawiki::api::NamespaceID;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use wikibase::mediawiki::api::{Api, NamespaceID};
 use wikibase::mediawiki::title::Title;
 
 pub static PAGE_BATCH_SIZE: usize = 20000;
+/// The EntitySchema validator checks one entity per request; this limits how many run in parallel
+static ENTITYSCHEMA_BATCH_SIZE: usize = 50;
+static WIKIDATA_STATEMENT_BATCH_SIZE: usize = 500;
+static MAX_COMBINATION_DEPTH: usize = 32;
+static MAX_EXPLAIN_ENTRIES: usize = 100;
+static CONTENT_BATCH_SIZE: usize = 50;
+static MAX_CONTENT_THREADS: usize = 4;
+static MAX_PAGE_CONTENT_BYTES: u64 = 1_000_000;
+static WBGETENTITIES_BATCH_SIZE: usize = 50;
+static DEFAULT_PAGEVIEWS_DAYS: usize = 30;
+static MAX_PAGEVIEWS_PAGES: usize = 2000;
+static PAGEVIEWS_USER_AGENT: &str = "PetScan (https://petscan.wmflabs.org/)";
+static DEFAULT_STUB_MAX_BYTES: usize = 1500;
+static DEFAULT_STUB_TEMPLATE: &str = "*-stub";
+/// Responses smaller than this are not worth compressing
+static GZIP_MIN_BYTES: usize = 4096;
+static DEFAULT_SOURCE_TIMEOUT_SECONDS: u64 = 300;
+/// Leading characters of a case-insensitive label pattern that are matched by (indexed) prefix
+static LABEL_PREFIX_CHARS: usize = 3;
+static MIN_SOURCE_TIMEOUT_SECONDS: u64 = 5;
+/// Parameters for filtering files by their pixel dimensions
+static IMAGE_DIMENSION_PARAMS: &[&str] = &[
+    "min_width",
+    "max_width",
+    "min_height",
+    "max_height",
+    "min_megapixels",
+];
+/// Change tags that mark an edit as a revert, for "latest_edit_revert"
+static REVERT_CHANGE_TAGS: &[&str] = &["mw-rollback", "mw-undo", "mw-manual-revert"];
+
+/// Default cleanup templates for "maintenance_tagged", per wiki; "*" is a wildcard.
+/// Override with a newline-separated "maintenance_templates" list.
+static DEFAULT_MAINTENANCE_TEMPLATES: &[(&str, &[&str])] = &[
+    (
+        "enwiki",
+        &[
+            "Citation_needed",
+            "Unreferenced*",
+            "More_citations_needed*",
+            "Refimprove*",
+            "Cleanup*",
+            "Orphan",
+            "Notability",
+            "Advert",
+            "POV",
+            "Update",
+            "Dead_link",
+            "Multiple_issues",
+        ],
+    ),
+    (
+        "dewiki",
+        &[
+            "Belege_fehlen",
+            "Quellen_fehlen",
+            "Überarbeiten",
+            "Lückenhaft",
+            "Neutralität",
+            "Veraltet",
+        ],
+    ),
+    (
+        "frwiki",
+        &[
+            "Référence_nécessaire",
+            "Sans_source",
+            "À_sourcer",
+            "À_wikifier",
+            "Admissibilité_à_vérifier",
+            "Neutralité_douteuse",
+        ],
+    ),
+];
+
+/// Cache key for page contents: (wiki, namespace ID, title with underscores)
+type PageContentKey = (String, NamespaceID, String);
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ContentType {
@@ -27,6 +111,13 @@ pub enum ContentType {
     JSONP,
     CSV,
     TSV,
+    GeoJSON,
+    KML,
+    XLSX,
+    RSS,
+    JSONL,
+    ZIP,
+    GZIP,
 }
 
 impl ContentType {
@@ -38,6 +129,33 @@ impl ContentType {
             Self::JSONP => "application/javascript",
             Self::CSV => "text/csv; charset=utf-8",
             Self::TSV => "text/tab-separated-values; charset=utf-8",
+            Self::GeoJSON => "application/geo+json",
+            Self::KML => "application/vnd.google-earth.kml+xml",
+            Self::XLSX => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Self::RSS => "application/rss+xml; charset=utf-8",
+            Self::JSONL => "application/x-ndjson",
+            Self::ZIP => "application/zip",
+            Self::GZIP => "application/gzip",
+        }
+    }
+
+    /// Formats that are compressed already, and are not worth compressing again
+    pub fn is_compressed(&self) -> bool {
+        match self {
+            Self::XLSX | Self::ZIP | Self::GZIP => true,
+            _ => false,
+        }
+    }
+
+    /// File name extension for formats that are downloaded rather than shown in the browser
+    pub fn attachment_extension(&self) -> Option<&str> {
+        match self {
+            Self::CSV => Some("csv"),
+            Self::TSV => Some("tsv"),
+            Self::XLSX => Some("xlsx"),
+            Self::KML => Some("kml"),
+            Self::ZIP => Some("zip"),
+            _ => None,
         }
     }
 }
@@ -46,16 +164,88 @@ impl ContentType {
 pub struct MyResponse {
     pub s: String,
     pub content_type: ContentType,
+    /// Body for binary formats; `s` is used if this is not set
+    pub binary: Option<Vec<u8>>,
+    /// If set, the response is sent as a download with this file name
+    pub filename: Option<String>,
 }
 
 impl MyResponse {
-    pub fn respond(&self) -> Result<HttpResponse, Error> {
-        Ok(HttpResponse::Ok()
-            .content_type(self.content_type.as_str())
-            .body(self.s.to_owned())) // TODO FIXME duplication of output
+    /// Creates the HTTP response; the body is gzip-compressed if the client accepts it and it is large enough
+    pub fn respond(&self, accept_gzip: bool) -> Result<HttpResponse, Error> {
+        let mut response = HttpResponse::Ok();
+        response.content_type(self.content_type.as_str());
+        response.header("Vary", "Accept-Encoding");
+        match &self.filename {
+            Some(filename) => {
+                response.header(
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{}\"", filename),
+                );
+            }
+            None => {}
+        }
+        let body: &[u8] = match &self.binary {
+            Some(binary) => binary,
+            None => self.s.as_bytes(),
+        };
+        let compressed: Vec<u8>;
+        let body =
+            if accept_gzip && !self.content_type.is_compressed() && body.len() > GZIP_MIN_BYTES {
+                compressed =
+                    Self::gzip(body).map_err(actix_web::error::ErrorInternalServerError)?;
+                response.header("Content-Encoding", "gzip");
+                &compressed
+            } else {
+                body
+            };
+        Ok(response.body(body.to_owned())) // TODO FIXME duplication of output
+    }
+
+    /// Checks an Accept-Encoding header value for gzip, honoring "gzip;q=0"
+    pub fn accepts_gzip(accept_encoding: Option<&str>) -> bool {
+        accept_encoding
+            .unwrap_or("")
+            .split(',')
+            .filter_map(|part| {
+                let mut parts = part.split(';').map(|s| s.trim());
+                let coding = parts.next()?.to_lowercase();
+                let q = parts
+                    .filter_map(|p| p.strip_prefix("q="))
+                    .filter_map(|q| q.parse::<f32>().ok())
+                    .next()
+                    .unwrap_or(1.0);
+                Some((coding, q))
+            })
+            .any(|(coding, q)| (coding == "gzip" || coding == "*") && q > 0.0)
+    }
+
+    pub fn gzip(data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).map_err(|e| format!("{:?}", e))?;
+        encoder.finish().map_err(|e| format!("{:?}", e))
+    }
+}
+
+/// A custom filter for library users; entries for which it returns false are removed
+pub struct EntryFilter(Box<dyn Fn(&PageListEntry) -> bool + Send + Sync>);
+
+impl std::fmt::Debug for EntryFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EntryFilter")
     }
 }
 
+/// Summary counts over the final result, before sorting and output limits are applied
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ResultStats {
+    pub total: usize,
+    pub by_namespace: BTreeMap<NamespaceID, usize>,
+    pub with_wikidata_item: usize,
+    pub with_coordinates: usize,
+    pub dropped_in_conversion: usize,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Combination {
     None,
@@ -63,6 +253,7 @@ pub enum Combination {
     Intersection((Box<Combination>, Box<Combination>)),
     Union((Box<Combination>, Box<Combination>)),
     Not((Box<Combination>, Box<Combination>)),
+    Xor((Box<Combination>, Box<Combination>)),
 }
 
 impl Combination {
@@ -79,6 +270,43 @@ impl Combination {
             Combination::Not((a, b)) => {
                 "(".to_string() + &a.to_string() + " NOT " + &b.to_string() + ")"
             }
+            Combination::Xor((a, b)) => {
+                "(".to_string() + &a.to_string() + " XOR " + &b.to_string() + ")"
+            }
+        }
+    }
+
+    /// Removes the given sources. AND, OR, and XOR keep the remaining side; NOT drops
+    /// an unavailable subtrahend, but is empty without its minuend.
+    pub fn without_sources(&self, sources: &[String]) -> Combination {
+        match self {
+            Combination::Source(s) if sources.contains(s) => Combination::None,
+            Combination::None | Combination::Source(_) => self.clone(),
+            Combination::Intersection((a, b)) => {
+                match (a.without_sources(sources), b.without_sources(sources)) {
+                    (Combination::None, c) | (c, Combination::None) => c,
+                    (a, b) => Combination::Intersection((Box::new(a), Box::new(b))),
+                }
+            }
+            Combination::Union((a, b)) => {
+                match (a.without_sources(sources), b.without_sources(sources)) {
+                    (Combination::None, c) | (c, Combination::None) => c,
+                    (a, b) => Combination::Union((Box::new(a), Box::new(b))),
+                }
+            }
+            Combination::Xor((a, b)) => {
+                match (a.without_sources(sources), b.without_sources(sources)) {
+                    (Combination::None, c) | (c, Combination::None) => c,
+                    (a, b) => Combination::Xor((Box::new(a), Box::new(b))),
+                }
+            }
+            Combination::Not((a, b)) => {
+                match (a.without_sources(sources), b.without_sources(sources)) {
+                    (Combination::None, _) => Combination::None,
+                    (a, Combination::None) => a,
+                    (a, b) => Combination::Not((Box::new(a), Box::new(b))),
+                }
+            }
         }
     }
 }
@@ -94,9 +322,22 @@ pub struct Platform {
     output_redlinks: bool,
     query_time: Option<Duration>,
     wiki_by_source: HashMap<String, String>,
+    sparql_extra_vars: RwLock<Vec<String>>,
     wdfist_result: Option<Value>,
+    set_comparison: Option<Value>,
     warnings: RwLock<Vec<String>>,
+    truncations: RwLock<Vec<String>>,
+    pruned_subcats: RwLock<Vec<String>>,
+    dropped_in_conversion: RwLock<usize>,
+    result_stats: RwLock<Option<ResultStats>>,
     namespace_case_sensitivity_cache: RwLock<HashMap<(String, NamespaceID), bool>>,
+    explain_steps: RwLock<Vec<Value>>,
+    explain_added: RwLock<Vec<Option<HashSet<String>>>>,
+    explain_kept: RwLock<Vec<Value>>,
+    page_contents: RwLock<HashMap<PageContentKey, Option<String>>>,
+    entry_filter: Option<EntryFilter>,
+    timed_out_sources: Vec<String>,
+    source_errors: Vec<(String, String)>,
 }
 
 impl Platform {
@@ -111,12 +352,37 @@ impl Platform {
             output_redlinks: false,
             query_time: None,
             wiki_by_source: HashMap::new(),
+            sparql_extra_vars: RwLock::new(vec![]),
             wdfist_result: None,
+            set_comparison: None,
             warnings: RwLock::new(vec![]),
+            truncations: RwLock::new(vec![]),
+            pruned_subcats: RwLock::new(vec![]),
+            dropped_in_conversion: RwLock::new(0),
+            result_stats: RwLock::new(None),
             namespace_case_sensitivity_cache: RwLock::new(HashMap::new()),
+            explain_steps: RwLock::new(vec![]),
+            explain_added: RwLock::new(vec![]),
+            explain_kept: RwLock::new(vec![]),
+            page_contents: RwLock::new(HashMap::new()),
+            entry_filter: None,
+            timed_out_sources: vec![],
+            source_errors: vec![],
         }
     }
 
+    /// Errors of data sources that failed, as (source name, error)
+    pub fn source_errors(&self) -> &Vec<(String, String)> {
+        &self.source_errors
+    }
+
+    /// Sets a custom filter, applied in `post_process_result` after all built-in filters
+    /// and metadata loading (so the entries have their annotations, labels etc.),
+    /// but before redlink and creator processing.
+    pub fn set_entry_filter(&mut self, filter: Box<dyn Fn(&PageListEntry) -> bool + Send + Sync>) {
+        self.entry_filter = Some(EntryFilter(filter));
+    }
+
     pub fn warnings(&self) -> Result<Vec<String>, String> {
         Ok(self
             .warnings
@@ -133,6 +399,79 @@ impl Platform {
         Ok(())
     }
 
+    /// Notes that the results are partial, and why.
+    /// Renderers flag the output as truncated, with these reasons.
+    pub fn note_truncation(&self, s: String) -> Result<(), String> {
+        self.truncations
+            .write()
+            .map_err(|e| format!("{:?}", e))?
+            .push(s);
+        Ok(())
+    }
+
+    pub fn truncations(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .truncations
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .clone())
+    }
+
+    /// Records subcategories skipped during category tree traversal because of exclude_subcats
+    pub fn note_pruned_subcats(&self, mut subcats: Vec<String>) -> Result<(), String> {
+        self.pruned_subcats
+            .write()
+            .map_err(|e| format!("{:?}", e))?
+            .append(&mut subcats);
+        Ok(())
+    }
+
+    pub fn pruned_subcats(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .pruned_subcats
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .clone())
+    }
+
+    pub fn note_dropped_in_conversion(&self, dropped: usize) -> Result<(), String> {
+        *self
+            .dropped_in_conversion
+            .write()
+            .map_err(|e| format!("{:?}", e))? += dropped;
+        Ok(())
+    }
+
+    /// Returns statistics for the final result. These are computed on first use, and kept
+    /// for later calls, as get_response() drains the result.
+    pub fn result_stats(&self) -> Result<ResultStats, String> {
+        if let Some(stats) = &*self.result_stats.read().map_err(|e| format!("{:?}", e))? {
+            return Ok(stats.clone());
+        }
+        let mut stats = ResultStats::default();
+        stats.dropped_in_conversion = *self
+            .dropped_in_conversion
+            .read()
+            .map_err(|e| format!("{:?}", e))?;
+        if let Some(result) = &self.result {
+            result.for_each_entry(|entry| {
+                stats.total += 1;
+                *stats
+                    .by_namespace
+                    .entry(entry.title().namespace_id())
+                    .or_insert(0) += 1;
+                if entry.get_wikidata_item().is_some() {
+                    stats.with_wikidata_item += 1;
+                }
+                if entry.get_coordinates().is_some() {
+                    stats.with_coordinates += 1;
+                }
+            })?;
+        }
+        *self.result_stats.write().map_err(|e| format!("{:?}", e))? = Some(stats.clone());
+        Ok(stats)
+    }
+
     pub fn label_exists(&self, label: &String) -> bool {
         // TODO normalization?
         match self.existing_labels.read() {
@@ -149,6 +488,50 @@ impl Platform {
         self.output_redlinks
     }
 
+    /// The wiki each data source returned its results for, before combination
+    pub fn wiki_by_source(&self) -> &HashMap<String, String> {
+        &self.wiki_by_source
+    }
+
+    pub fn set_sparql_extra_vars(&self, vars: Vec<String>) -> Result<(), String> {
+        *self
+            .sparql_extra_vars
+            .write()
+            .map_err(|e| format!("{:?}", e))? = vars;
+        Ok(())
+    }
+
+    /// Languages to load Wikidata labels and descriptions in; the first one is the primary language.
+    /// `wikidata_label_languages` is a comma-separated list, overriding `wikidata_label_language`.
+    pub fn wikidata_label_languages(&self) -> Vec<String> {
+        let languages = self.language_list_param("wikidata_label_languages");
+        if !languages.is_empty() {
+            return languages;
+        }
+        vec![self.get_param_default(
+            "wikidata_label_language",
+            &self.get_param_default("interface_language", "en"),
+        )]
+    }
+
+    /// A comma-separated list of language codes, e.g. "de,en,mul"
+    fn language_list_param(&self, key: &str) -> Vec<String> {
+        self.get_param_blank(key)
+            .split(',')
+            .map(|language| language.trim().to_lowercase())
+            .filter(|language| !language.is_empty())
+            .collect()
+    }
+
+    /// SPARQL result variables, besides the entity, that are kept as extra columns
+    pub fn sparql_extra_vars(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .sparql_extra_vars
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .clone())
+    }
+
     pub fn query_time(&self) -> Option<Duration> {
         self.query_time.to_owned()
     }
@@ -196,10 +579,22 @@ impl Platform {
         ret
     }
 
-    pub fn run(&mut self) -> Result<(), String> {
+    pub fn run(&mut self) -> Result<(), PetScanError> {
         Platform::profile("begin run", None);
         let start_time = SystemTime::now();
+        self.validate_wikis()?;
+        self.resolve_namespaces()?;
         self.output_redlinks = self.has_param("show_redlinks");
+        let skip_failed_sources = match self.get_param_blank("on_source_error").as_str() {
+            "" | "fail" => false,
+            "skip" => true,
+            other => {
+                return Err(PetScanError::BadParameter(format!(
+                    "Unknown on_source_error '{}'",
+                    other
+                )))
+            }
+        };
         let mut candidate_sources: Vec<RwLock<Box<dyn DataSource + Send + Sync>>> = vec![];
         candidate_sources.push(RwLock::new(Box::new(SourceDatabase::new(
             SourceDatabaseParameters::db_params(self),
@@ -208,7 +603,13 @@ impl Platform {
         candidate_sources.push(RwLock::new(Box::new(SourceManual::new())));
         candidate_sources.push(RwLock::new(Box::new(SourcePagePile::new())));
         candidate_sources.push(RwLock::new(Box::new(SourceSearch::new())));
+        candidate_sources.push(RwLock::new(Box::new(SourceDeepcat::new())));
         candidate_sources.push(RwLock::new(Box::new(SourceWikidata::new())));
+        candidate_sources.push(RwLock::new(Box::new(SourceLinksHere::new())));
+        candidate_sources.push(RwLock::new(Box::new(SourceTransclusions::new())));
+        candidate_sources.push(RwLock::new(Box::new(SourcePetScan::new())));
+        candidate_sources.push(RwLock::new(Box::new(SourceQuarry::new())));
+        candidate_sources.push(RwLock::new(Box::new(SourceContributions::new())));
 
         if !candidate_sources
             .par_iter()
@@ -217,6 +618,11 @@ impl Platform {
                 _ => false,
             })
         {
+            let mut unmet_requirements: Vec<String> = candidate_sources
+                .iter()
+                .filter_map(|source| source.read().ok())
+                .flat_map(|source| source.unmet_requirements(&self))
+                .collect();
             candidate_sources = vec![];
             candidate_sources.push(RwLock::new(Box::new(SourceLabels::new())));
             if !candidate_sources
@@ -226,25 +632,58 @@ impl Platform {
                     _ => false,
                 })
             {
-                return Err(format!("No possible data source found in parameters"));
+                candidate_sources
+                    .iter()
+                    .filter_map(|source| source.read().ok())
+                    .for_each(|source| {
+                        unmet_requirements.append(&mut source.unmet_requirements(&self))
+                    });
+                return Err(PetScanError::NoDataSource(Self::no_data_source_error(
+                    &unmet_requirements,
+                )));
             }
         }
 
-        Platform::profile("begin threads 1", None);
-        let mut results: HashMap<String, PageList> = candidate_sources
+        let mut available_sources: Vec<String> = candidate_sources
             .par_iter()
-            .filter(|ds| match ds.read() {
+            .filter(|s| match s.read() {
                 Ok(s) => s.can_run(&self),
                 _ => false,
             })
-            .filter_map(|ds| match ds.write() {
-                Ok(mut ds) => match ds.run(&self) {
-                    Ok(data) => Some((ds.name(), data)),
-                    _ => None,
-                },
+            .filter_map(|s| match s.read() {
+                Ok(s) => Some(s.name()),
                 _ => None,
             })
             .collect();
+
+        Platform::profile("begin threads 1", None);
+        let sources = candidate_sources
+            .into_iter()
+            .filter_map(|ds| ds.into_inner().ok())
+            .filter(|ds| ds.can_run(&self))
+            .collect();
+        let mut results = self.run_sources(sources)?;
+        let failed_sources: Vec<String> = self
+            .source_errors
+            .iter()
+            .map(|(name, _)| name.to_owned())
+            .chain(self.timed_out_sources.iter().cloned())
+            .collect();
+        if skip_failed_sources {
+            for (name, error) in &self.source_errors {
+                self.warn(format!("Source {} failed and was skipped: {}", name, error))?;
+            }
+            available_sources.retain(|name| !failed_sources.contains(name));
+        } else if let Some((name, error)) = self.source_errors.first() {
+            return Err(format!("Source {} failed: {}", name, error).into());
+        } else if let Some(name) = self.timed_out_sources.first() {
+            return Err(PetScanError::Timeout(format!("Source {} timed out", name)));
+        }
+        if self.has_param("show_provenance") {
+            for (name, data) in &results {
+                data.set_provenance(name)?;
+            }
+        }
         self.wiki_by_source = results
             .iter()
             .filter_map(|(name, data)| match data.wiki().unwrap_or(None) {
@@ -253,21 +692,20 @@ impl Platform {
             })
             .collect();
         Platform::profile("end threads 1", None);
-
-        let available_sources = candidate_sources
-            .par_iter()
-            .filter(|s| match s.read() {
-                Ok(s) => s.can_run(&self),
-                _ => false,
-            })
-            .filter_map(|s| match s.read() {
-                Ok(s) => Some(s.name()),
-                _ => None,
-            })
-            .collect();
-        self.combination = self.get_combination(&available_sources);
+        if self.has_param("set_compare") {
+            self.set_comparison = Some(self.compare_source_results(&mut results)?);
+            self.query_time = start_time.elapsed().ok();
+            return Ok(());
+        }
+        self.combination = self.get_combination(&available_sources)?;
+        if skip_failed_sources && !failed_sources.is_empty() {
+            self.combination = self.combination.without_sources(&failed_sources);
+            if self.combination == Combination::None {
+                return Err(format!("No results, as all sources in the combination failed").into());
+            }
+        }
         Platform::profile("before combine_results", None);
-        let result = self.combine_results(&mut results, &self.combination)?;
+        let result = self.combine_results(&mut results, &self.combination, 0)?;
         drop(results);
 
         self.result = Some(result);
@@ -284,7 +722,7 @@ impl Platform {
                             format!("Failed to convert result to Wikidata for WDfist: {}", e)
                         })?;
                 }
-                None => return Err(format!("No result set for WDfist")),
+                None => return Err(format!("No result set for WDfist").into()),
             }
             //self.result = Some(pagelist);
             let mut wdfist =
@@ -299,6 +737,179 @@ impl Platform {
         Ok(())
     }
 
+    /// Runs each data source in its own thread, and collects the results of those that succeed.
+    /// Sources that do not finish within `source_timeout` are recorded as timed out.
+    /// Timed-out sources are not cancelled: their threads keep running (and keep their database
+    /// connections and API requests) until they finish on their own, and their results are
+    /// discarded. Sources whose thread panicked are recorded as failed.
+    fn run_sources(
+        &mut self,
+        sources: Vec<Box<dyn DataSource + Send + Sync>>,
+    ) -> Result<HashMap<String, PageList>, String> {
+        let timeout = self.source_timeout();
+        // Sources need a 'static platform to run on a detached thread
+        let worker = Arc::new(self.new_source_worker());
+        let (tx, rx) = mpsc::channel();
+        let mut pending: HashSet<String> = HashSet::new();
+        for mut source in sources {
+            pending.insert(source.name());
+            let tx = tx.clone();
+            let worker = worker.clone();
+            thread::spawn(move || {
+                let result = source.run(&worker);
+                let _ = tx.send((source.name(), result)); // Receiver is gone after a timeout
+            });
+        }
+        drop(tx);
+
+        let deadline = Instant::now() + timeout;
+        let mut results = HashMap::new();
+        let mut source_errors = vec![];
+        while !pending.is_empty() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining) {
+                Ok((name, result)) => {
+                    pending.remove(&name);
+                    match result {
+                        Ok(data) => {
+                            results.insert(name, data);
+                        }
+                        Err(error) => source_errors.push((name, error)),
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    // All threads are done, so the pending ones ended without sending a result
+                    for name in pending.drain() {
+                        source_errors.push((name, "The source stopped unexpectedly".to_string()));
+                    }
+                }
+            }
+        }
+        self.merge_source_worker(&worker)?;
+        source_errors.sort();
+        self.source_errors = source_errors;
+        self.timed_out_sources = pending.into_iter().collect();
+        self.timed_out_sources.sort();
+        for name in &self.timed_out_sources {
+            self.warn(format!(
+                "Source {} timed out after {} seconds",
+                name,
+                timeout.as_secs()
+            ))?;
+        }
+        Ok(results)
+    }
+
+    /// Time allowed for each data source. The "source_timeout" parameter (seconds) can lower,
+    /// but not raise, the "source_timeout" in the config file, and not below
+    /// MIN_SOURCE_TIMEOUT_SECONDS.
+    fn source_timeout(&self) -> Duration {
+        let max_seconds = self.state.config["source_timeout"]
+            .as_u64()
+            .unwrap_or(DEFAULT_SOURCE_TIMEOUT_SECONDS);
+        let seconds = match self.usize_option_from_param("source_timeout") {
+            Some(seconds) => (seconds as u64)
+                .max(MIN_SOURCE_TIMEOUT_SECONDS)
+                .min(max_seconds),
+            None => max_seconds,
+        };
+        Duration::from_secs(seconds)
+    }
+
+    /// A copy of the platform for data sources to run on
+    fn new_source_worker(&self) -> Self {
+        let mut ret = Self::new_from_parameters(&self.form_parameters, self.state.clone());
+        ret.psid = self.psid;
+        ret.output_redlinks = self.output_redlinks;
+        ret
+    }
+
+    /// Copies what data sources noted on a worker platform
+    fn merge_source_worker(&self, worker: &Platform) -> Result<(), String> {
+        for warning in worker.warnings()? {
+            self.warn(warning)?;
+        }
+        for truncation in worker.truncations()? {
+            self.note_truncation(truncation)?;
+        }
+        self.note_pruned_subcats(worker.pruned_subcats()?)?;
+        let dropped = *worker
+            .dropped_in_conversion
+            .read()
+            .map_err(|e| format!("{:?}", e))?;
+        self.note_dropped_in_conversion(dropped)?;
+        let sparql_extra_vars = worker.sparql_extra_vars()?;
+        if !sparql_extra_vars.is_empty() {
+            self.set_sparql_extra_vars(sparql_extra_vars)?;
+        }
+        Ok(())
+    }
+
+    /// For "set_compare", counts the overlap of the results of exactly two sources.
+    /// The second source (by name) is converted to the wiki of the first one, if necessary.
+    fn compare_source_results(
+        &self,
+        results: &mut HashMap<String, PageList>,
+    ) -> Result<Value, String> {
+        if results.len() != 2 {
+            return Err(format!(
+                "set_compare requires exactly two sources, but {} returned results",
+                results.len()
+            ));
+        }
+        let mut names: Vec<String> = results.keys().cloned().collect();
+        names.sort();
+        let a = results
+            .remove(&names[0])
+            .ok_or(format!("No result for {}", &names[0]))?;
+        let b = results
+            .remove(&names[1])
+            .ok_or(format!("No result for {}", &names[1]))?;
+        let copy = |list: &PageList| -> Result<PageList, String> {
+            let wiki = list.wiki()?.ok_or(format!("No wiki set for result"))?;
+            let ret = PageList::new_from_wiki(&wiki);
+            ret.set_entries(
+                list.entries()
+                    .read()
+                    .map_err(|e| format!("{:?}", e))?
+                    .clone(),
+            )?;
+            Ok(ret)
+        };
+
+        let intersection = copy(&a)?;
+        intersection.intersection(&b, Some(self))?; // Converts b to the wiki of a
+        let a_minus_b = copy(&a)?;
+        a_minus_b.difference(&b, Some(self))?;
+        let b_minus_a = copy(&b)?;
+        b_minus_a.difference(&a, Some(self))?;
+        let intersection = intersection.len()?;
+        Ok(json!({
+            "status": "OK",
+            "wiki": a.wiki()?,
+            "sources": {"a": names[0], "b": names[1]},
+            "counts": {
+                "a": a.len()?,
+                "b": b.len()?,
+                "intersection": intersection,
+                "a_minus_b": a_minus_b.len()?,
+                "b_minus_a": b_minus_a.len()?,
+                "union": a.len()? + b.len()? - intersection,
+            }
+        }))
+    }
+
+    fn no_data_source_error(unmet_requirements: &Vec<String>) -> String {
+        if unmet_requirements.is_empty() {
+            return format!("No possible data source found in parameters");
+        }
+        format!(
+            "No possible data source found in parameters; incomplete sources: {}",
+            unmet_requirements.join("; ")
+        )
+    }
+
     pub fn profile(label: &str, num: Option<usize>) {
         if false {
             println!(
@@ -310,7 +921,7 @@ impl Platform {
         }
     }
 
-    fn post_process_result(&self, available_sources: &Vec<String>) -> Result<(), String> {
+    fn post_process_result(&self, available_sources: &Vec<String>) -> Result<(), PetScanError> {
         Platform::profile("post_process_result begin", None);
         let result = match self.result.as_ref() {
             Some(res) => res,
@@ -319,54 +930,223 @@ impl Platform {
 
         // Filter and post-process
         Platform::profile("before filter_wikidata", Some(result.len()?));
-        self.filter_wikidata(&result)?;
-        Platform::profile("after filter_wikidata", Some(result.len()?));
-        self.process_sitelinks(&result)?;
-        Platform::profile("after process_sitelinks", None);
+        self.post_process_step("filter_wikidata", result, &|r| self.filter_wikidata(r))?;
+        self.post_process_step("process_sitelinks", result, &|r| self.process_sitelinks(r))?;
         if *available_sources != vec!["labels".to_string()] {
-            self.process_labels(&result)?;
-            Platform::profile("after process_labels", Some(result.len()?));
+            self.post_process_step("process_labels", result, &|r| self.process_labels(r))?;
         }
 
-        self.convert_to_common_wiki(&result)?;
-        Platform::profile("after convert_to_common_wiki", Some(result.len()?));
+        self.post_process_step("convert_to_common_wiki", result, &|r| {
+            self.convert_to_common_wiki(r)
+        })?;
+        self.post_process_step("process_blacklist", result, &|r| self.process_blacklist(r))?;
 
         if !available_sources.contains(&"categories".to_string()) {
-            self.process_missing_database_filters(&result)?;
-            Platform::profile(
-                "after process_missing_database_filters",
-                Some(result.len()?),
-            );
+            self.post_process_step("process_missing_database_filters", result, &|r| {
+                self.process_missing_database_filters(r)
+            })?;
         }
-        self.process_by_wikidata_item(&result)?;
-        Platform::profile("after process_by_wikidata_item", Some(result.len()?));
-        self.process_files(&result)?;
-        Platform::profile("after process_files", Some(result.len()?));
-        self.process_pages(&result)?;
-        Platform::profile("after process_pages", Some(result.len()?));
-        self.process_subpages(&result)?;
-        Platform::profile("after process_subpages", Some(result.len()?));
-        self.annotate_with_wikidata_item(result)?;
-        Platform::profile("after annotate_with_wikidata_item [2]", Some(result.len()?));
+        self.post_process_step("process_by_wikidata_item", result, &|r| {
+            self.process_by_wikidata_item(r)
+        })?;
+        self.post_process_step("process_unlinked_with_langlinks", result, &|r| {
+            self.process_unlinked_with_langlinks(r)
+        })?;
+        self.post_process_step("process_langlink_count", result, &|r| {
+            self.process_langlink_count(r)
+        })?;
+        self.post_process_step("process_title_label_match", result, &|r| {
+            self.process_title_label_match(r)
+        })?;
+        self.post_process_step("process_editor_count", result, &|r| {
+            self.process_editor_count(r)
+        })?;
+        self.post_process_step("process_latest_edit_revert", result, &|r| {
+            self.process_latest_edit_revert(r)
+        })?;
+        self.post_process_step("process_by_wikidata_entity_type", result, &|r| {
+            self.process_by_wikidata_entity_type(r)
+        })?;
+        self.post_process_step("process_talk_pages", result, &|r| {
+            self.process_talk_pages(r)
+        })?;
+        self.post_process_step("process_files", result, &|r| self.process_files(r))?;
+        self.post_process_step("process_pages", result, &|r| self.process_pages(r))?;
+        self.post_process_step("process_min_page_age", result, &|r| {
+            self.process_min_page_age(r)
+        })?;
+        self.post_process_step("process_created_before_after", result, &|r| {
+            self.process_created_before_after(r)
+        })?;
+        self.post_process_step("process_last_editor", result, &|r| {
+            self.process_last_editor(r)
+        })?;
+        self.post_process_step("process_new_redirects", result, &|r| {
+            self.process_new_redirects(r)
+        })?;
+        self.post_process_step("process_stubs", result, &|r| self.process_stubs(r))?;
+        self.post_process_step("process_maintenance_tagged", result, &|r| {
+            self.process_maintenance_tagged(r)
+        })?;
+        self.post_process_step("process_cascade_protected", result, &|r| {
+            self.process_cascade_protected(r)
+        })?;
+        self.post_process_step("process_links_to_page", result, &|r| {
+            self.process_links_to_page(r)
+        })?;
+        self.post_process_step("process_subpages", result, &|r| self.process_subpages(r))?;
+        self.post_process_step("annotate_with_wikidata_item", result, &|r| {
+            self.annotate_with_wikidata_item(r)
+        })?;
+        if self.has_param("unique_by_item") {
+            self.post_process_step("unique_by_item", result, &|r| r.unique_by_wikidata_item())?;
+        }
+        self.post_process_step("load_wikidata_sort_values", result, &|r| {
+            self.load_wikidata_sort_values(r)
+        })?;
+        self.post_process_step("load_pageviews", result, &|r| self.load_pageviews(r))?;
+        self.post_process_step("process_entityschema", result, &|r| {
+            self.process_entityschema(r)
+        })?;
 
-        let wikidata_label_language = self.get_param_default(
-            "wikidata_label_language",
-            &self.get_param_default("interface_language", "en"),
-        );
-        result.load_missing_metadata(Some(wikidata_label_language), &self)?;
-        Platform::profile("after load_missing_metadata", Some(result.len()?));
+        let wikidata_label_languages = self.wikidata_label_languages();
+        let label_fallback = self.language_list_param("label_fallback");
+        self.post_process_step("load_missing_metadata", result, &|r| {
+            r.load_missing_metadata(&wikidata_label_languages, &label_fallback, &self)
+        })?;
         match self.get_param("regexp_filter") {
-            Some(regexp) => result.regexp_filter(&regexp)?,
+            Some(regexp) => {
+                let invert = self.has_param("regexp_filter_invert");
+                self.post_process_step("regexp_filter", result, &|r| {
+                    r.regexp_filter(&regexp, invert)
+                })?
+            }
             None => {}
         }
-        self.process_redlinks(&result)?;
-        Platform::profile("after process_redlinks", Some(result.len()?));
-        self.process_creator(&result)?;
-        Platform::profile("after process_creator", Some(result.len()?));
+        self.post_process_step("process_content_regexp", result, &|r| {
+            self.process_content_regexp(r)
+        })?;
+        if let Some(filter) = &self.entry_filter {
+            self.post_process_step("entry_filter", result, &|r| {
+                r.retain_entries(&|entry| (filter.0)(entry))
+            })?;
+        }
+        self.post_process_step("process_redlinks", result, &|r| self.process_redlinks(r))?;
+        self.post_process_step("process_creator", result, &|r| self.process_creator(r))?;
+        self.explain_kept_entries(result)?;
+
+        Ok(())
+    }
+
+    /// Runs a single post-processing step. In "explain" mode, records which entries it dropped.
+    fn post_process_step<E: Into<PetScanError>>(
+        &self,
+        step: &str,
+        result: &PageList,
+        f: &dyn Fn(&PageList) -> Result<(), E>,
+    ) -> Result<(), PetScanError> {
+        let before = match self.has_param("explain") {
+            true => Some((result.wiki()?, Self::explain_titles(result)?)),
+            false => None,
+        };
+        f(result).map_err(|e| e.into())?;
+        Platform::profile(&format!("after {}", step), Some(result.len()?));
+
+        let (wiki_before, titles_before) = match before {
+            Some(before) => before,
+            None => return Ok(()),
+        };
+        let mut explanation = json!({ "step": step });
+        let added = if wiki_before != result.wiki()? {
+            // Titles are not comparable across wikis
+            explanation["converted_to"] = json!(result.wiki()?);
+            None
+        } else {
+            let titles_after = Self::explain_titles(result)?;
+            let mut dropped: Vec<&String> = titles_before.difference(&titles_after).collect();
+            dropped.sort();
+            explanation["dropped"] = json!(dropped.len());
+            explanation["dropped_sample"] =
+                json!(dropped.iter().take(MAX_EXPLAIN_ENTRIES).collect::<Vec<_>>());
+            Some(
+                titles_after
+                    .difference(&titles_before)
+                    .cloned()
+                    .collect::<HashSet<String>>(),
+            )
+        };
+        self.explain_steps
+            .write()
+            .map_err(|e| format!("{:?}", e))?
+            .push(explanation);
+        self.explain_added
+            .write()
+            .map_err(|e| format!("{:?}", e))?
+            .push(added);
+        Ok(())
+    }
 
+    fn explain_titles(result: &PageList) -> Result<HashSet<String>, String> {
+        Ok(result
+            .entries()
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .map(|entry| Self::explain_title(entry))
+            .collect())
+    }
+
+    fn explain_title(entry: &PageListEntry) -> String {
+        format!(
+            "{}:{}",
+            entry.title().namespace_id(),
+            entry.title().with_underscores()
+        )
+    }
+
+    /// In "explain" mode, records the steps a sample of the surviving entries passed.
+    /// An entry only passed the steps after it was added to the list, or after the last wiki conversion.
+    fn explain_kept_entries(&self, result: &PageList) -> Result<(), String> {
+        if !self.has_param("explain") {
+            return Ok(());
+        }
+        let steps = self.explain_steps.read().map_err(|e| format!("{:?}", e))?;
+        let added = self.explain_added.read().map_err(|e| format!("{:?}", e))?;
+        let kept: Vec<Value> = result
+            .entries()
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .take(MAX_EXPLAIN_ENTRIES)
+            .map(|entry| {
+                let title = Self::explain_title(entry);
+                let mut passed: Vec<Value> = vec![];
+                for (step, added) in steps.iter().zip(added.iter()) {
+                    match added {
+                        Some(added) if !added.contains(&title) => {
+                            passed.push(step["step"].to_owned())
+                        }
+                        _ => passed.clear(),
+                    }
+                }
+                json!({"title": title, "passed": passed})
+            })
+            .collect();
+        *self.explain_kept.write().map_err(|e| format!("{:?}", e))? = kept;
         Ok(())
     }
 
+    /// Returns the "explain" information of the last run, if requested
+    pub fn explain(&self) -> Option<Value> {
+        if !self.has_param("explain") {
+            return None;
+        }
+        Some(json!({
+            "steps": self.explain_steps.read().ok()?.clone(),
+            "kept_sample": self.explain_kept.read().ok()?.clone(),
+        }))
+    }
+
     pub fn state(&self) -> Arc<AppState> {
         self.state.clone()
     }
@@ -410,16 +1190,98 @@ impl Platform {
         Ok(())
     }
 
-    fn apply_results_limit(&self, pages: &mut Vec<PageListEntry>) {
-        let limit = self
-            .get_param_default("output_limit", "0")
-            .parse::<usize>()
-            .unwrap_or(0);
-        if limit != 0 && limit < pages.len() {
-            pages.resize(limit, PageListEntry::new(Title::new("", 0)));
-        }
-    }
-
+    /// Removes entries listed in "blacklist", one title per line, as in the manual list.
+    /// Page IDs can be given as "#" followed by the ID, e.g. "#1984"; "1984" is a title.
+    fn process_blacklist(&self, result: &PageList) -> Result<(), String> {
+        let blacklist = match self.get_param("blacklist") {
+            Some(blacklist) => blacklist,
+            None => return Ok(()),
+        };
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => return Ok(()),
+        };
+        let api = self.state().get_api_for_wiki(wiki)?;
+        let lines: HashSet<String> = blacklist
+            .split("\n")
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        let (page_id_lines, title_lines): (Vec<&String>, Vec<&String>) =
+            lines.iter().partition(|line| line.starts_with('#'));
+        let titles: HashSet<PageListEntry> = title_lines
+            .iter()
+            .map(|line| PageListEntry::new(Self::normalize_title(line, &api)))
+            .collect();
+        let page_ids: HashSet<u32> = page_id_lines
+            .iter()
+            .map(|line| {
+                line[1..]
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid page ID '{}' in blacklist", line))
+            })
+            .collect::<Result<_, String>>()?;
+        let before = result.len()?;
+        result.retain_entries(&|entry| {
+            !titles.contains(entry)
+                && !entry
+                    .page_id
+                    .map(|page_id| page_ids.contains(&page_id))
+                    .unwrap_or(false)
+        })?;
+        let removed = before - result.len()?;
+        if removed > 0 {
+            self.warn(format!(
+                "<span tt='warn_blacklist_removed' num='{}'></span>",
+                removed
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn apply_results_limit(&self, pages: &mut Vec<PageListEntry>) -> Result<(), String> {
+        let limit = self
+            .get_param_default("output_limit", "0")
+            .parse::<usize>()
+            .unwrap_or(0);
+        if limit != 0 && limit < pages.len() {
+            self.note_truncation(format!(
+                "output_limit: showing {} of {} results",
+                limit,
+                pages.len()
+            ))?;
+            pages.truncate(limit);
+        }
+        Ok(())
+    }
+
+    /// Keeps at most "limit_per_namespace" entries of each namespace, in sort order
+    fn apply_namespace_limit(&self, pages: &mut Vec<PageListEntry>) -> Result<(), String> {
+        let limit = match self.usize_option_from_param("limit_per_namespace") {
+            Some(limit) if limit > 0 => limit,
+            _ => return Ok(()),
+        };
+        let mut counts: HashMap<NamespaceID, usize> = HashMap::new();
+        pages.retain(|entry| {
+            let count = counts.entry(entry.title().namespace_id()).or_insert(0);
+            *count += 1;
+            *count <= limit
+        });
+        let mut truncated: Vec<(NamespaceID, usize)> = counts
+            .into_iter()
+            .filter(|(_, count)| *count > limit)
+            .collect();
+        truncated.sort();
+        for (namespace_id, count) in truncated {
+            self.note_truncation(format!(
+                "limit_per_namespace: showing {} of {} results in namespace {}",
+                limit, count, namespace_id
+            ))?;
+        }
+        Ok(())
+    }
+
     // Prepares for JS "creator" mode
     // Chackes which labels already exist on Wikidata
     fn process_creator(&self, result: &PageList) -> Result<(), String> {
@@ -536,10 +1398,7 @@ impl Platform {
                             .get_db_mutex()
                             .lock()
                             .map_err(|e| format!("{:?}", e))?;
-                        let mut conn = self
-                            .state
-                            .get_wiki_db_connection(&db_user_pass, &wiki)
-                            .map_err(|e| format!("{:?}", e))?;
+                        let mut conn = self.state.get_wiki_db_connection(&db_user_pass, &wiki)?;
                         let new_result = conn
                             .prep_exec(&sql.0, &sql.1)
                             .map_err(|e| format!("{:?}", e))?;
@@ -657,12 +1516,87 @@ impl Platform {
         Ok(())
     }
 
+    /// Adds the existing talk pages of subject pages ("with_talk"), and/or the subject pages of talk pages ("with_subject")
+    fn process_talk_pages(&self, result: &PageList) -> Result<(), String> {
+        let with_talk = self.has_param("with_talk");
+        let with_subject = self.has_param("with_subject");
+        if !with_talk && !with_subject {
+            return Ok(());
+        }
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => {
+                return Err(format!(
+                    "Platform::process_talk_pages: no wiki set in result"
+                ))
+            }
+        };
+        let candidates = PageList::new_from_wiki(&wiki);
+        {
+            let entries = result.entries().read().map_err(|e| format!("{:?}", e))?;
+            entries
+                .iter()
+                .filter_map(|entry| {
+                    let namespace_id = entry.title().namespace_id();
+                    if namespace_id < 0 {
+                        return None;
+                    }
+                    let counterpart_namespace_id = if namespace_id % 2 == 0 {
+                        if !with_talk {
+                            return None;
+                        }
+                        namespace_id + 1
+                    } else {
+                        if !with_subject {
+                            return None;
+                        }
+                        namespace_id - 1
+                    };
+                    let counterpart = PageListEntry::new(Title::new(
+                        entry.title().pretty(),
+                        counterpart_namespace_id,
+                    ));
+                    if entries.contains(&counterpart) {
+                        None
+                    } else {
+                        Some(counterpart)
+                    }
+                })
+                .for_each(|entry| candidates.add_entry(entry).unwrap_or(()));
+        }
+        if candidates.is_empty()? {
+            return Ok(());
+        }
+
+        // Only add pages that exist, with basic metadata
+        let batches: Vec<SQLtuple> = candidates
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace,page_id,page_len FROM page WHERE "
+                    .to_string()
+                    + &sql.0;
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
+        result.process_batch_results(&self.state(), batches, &|row: my::Row| {
+            let (page_title, page_namespace, page_id, page_len) =
+                my::from_row_opt::<(Vec<u8>, NamespaceID, u32, u32)>(row).ok()?;
+            let page_title = String::from_utf8_lossy(&page_title).into_owned();
+            let mut entry = PageListEntry::new(Title::new(&page_title, page_namespace));
+            entry.page_id = Some(page_id);
+            entry.page_bytes = Some(page_len);
+            Some(entry)
+        })?;
+        Ok(())
+    }
+
     fn process_pages(&self, result: &PageList) -> Result<(), String> {
         let add_coordinates = self.has_param("add_coordinates");
         let add_image = self.has_param("add_image");
         let add_defaultsort = self.has_param("add_defaultsort");
         let add_disambiguation = self.has_param("add_disambiguation");
-        let add_incoming_links = self.get_param_blank("sortby") == "incoming_links";
+        let add_incoming_links = self.sorts_by("incoming_links");
         if !add_coordinates
             && !add_image
             && !add_defaultsort
@@ -731,1366 +1665,5100 @@ impl Platform {
                     };
                 }
             },
-        )
+        )?;
+        Ok(())
     }
 
-    fn process_files(&self, result: &PageList) -> Result<(), String> {
-        let giu = self.has_param("giu");
-        let file_data = self.has_param("ext_image_data")
-            || self.get_param("sortby") == Some("filesize".to_string())
-            || self.get_param("sortby") == Some("uploaddate".to_string());
-        let file_usage = giu || self.has_param("file_usage_data");
-        let file_usage_data_ns0 = self.has_param("file_usage_data_ns0");
+    /// Loads the creation timestamp (earliest revision) for pages that do not have it yet
+    fn load_page_created(&self, result: &PageList) -> Result<(), String> {
+        let missing = PageList::new_from_wiki(
+            &result
+                .wiki()?
+                .ok_or(format!("Platform::load_page_created: result has no wiki"))?,
+        );
+        result
+            .entries()
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .filter(|entry| entry.get_page_created().is_none())
+            .for_each(|entry| missing.add_entry(entry.to_owned()).unwrap_or(()));
+        let batches: Vec<SQLtuple> = missing
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace,(SELECT MIN(rev_timestamp) FROM revision WHERE rev_page=page_id) AS created FROM page WHERE ".to_string() + &sql.0;
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| {
+                entry.set_page_created(PageList::string_from_row(&row, 2));
+            },
+        )?;
+        Ok(())
+    }
 
-        if file_usage {
-            let batches: Vec<SQLtuple> = result
-                .to_sql_batches_namespace(PAGE_BATCH_SIZE,6)?
-                .par_iter_mut()
-                .map(|mut sql_batch| {
-                    let tmp = Platform::prep_quote(&sql_batch.1);
-                    sql_batch.0 = "SELECT gil_to,6 AS namespace_id,GROUP_CONCAT(gil_wiki,':',gil_page_namespace_id,':',gil_page_namespace,':',gil_page_title SEPARATOR '|') AS gil_group FROM globalimagelinks WHERE gil_to IN (".to_string() ;
-                    sql_batch.0 += &tmp.0 ;
-                    sql_batch.0 += ")";
-                    if file_usage_data_ns0  {sql_batch.0 += " AND gil_page_namespace_id=0" ;}
-                    sql_batch.0 += " GROUP BY gil_to" ;
-                    sql_batch.to_owned()
-                })
-                .collect::<Vec<SQLtuple>>();
+    /// Returns the MediaWiki timestamp of `days` days ago
+    fn timestamp_days_ago(days: i64) -> String {
+        (Utc::now() - chrono::Duration::days(days))
+            .format("%Y%m%d%H%M%S")
+            .to_string()
+    }
 
-            result.annotate_batch_results(
-                &self.state(),
-                batches,
-                0,
-                1,
-                &|row: my::Row, entry: &mut PageListEntry| match PageList::string_from_row(&row, 2)
-                {
-                    Some(gil_group) => {
-                        let fi = FileInfo::new_from_gil_group(&gil_group);
-                        entry.set_file_info(Some(fi));
-                    }
-                    None => {}
-                },
-            )?;
+    /// The "random_seed" parameter, which makes random ordering reproducible
+    fn random_seed(&self) -> Result<Option<u64>, String> {
+        match self.get_param("random_seed") {
+            Some(seed) => Ok(Some(
+                seed.trim()
+                    .parse::<u64>()
+                    .map_err(|e| format!("random_seed: {:?}", e))?,
+            )),
+            None => Ok(None),
         }
+    }
 
-        if file_data {
-            let batches: Vec<SQLtuple> = result
-                .to_sql_batches(PAGE_BATCH_SIZE)?
-                .par_iter_mut()
-                .map(|mut sql_batch| {
-                    let tmp = Platform::prep_quote(&sql_batch.1);
-                    sql_batch.0 = "SELECT img_name,6 AS namespace_id,img_size,img_width,img_height,img_media_type,img_major_mime,img_minor_mime,img_user_text,img_timestamp,img_sha1 FROM image_compat WHERE img_name IN (".to_string() ;
-                    sql_batch.0 += &tmp.0 ;
-                    sql_batch.0 += ")";
-                    sql_batch.to_owned()
-                })
-                .collect::<Vec<SQLtuple>>();
+    /// Removes pages created less than "min_page_age" days ago
+    fn process_min_page_age(&self, result: &PageList) -> Result<(), String> {
+        let days = match self.get_param("min_page_age") {
+            Some(days) => days
+                .trim()
+                .parse::<i64>()
+                .map_err(|e| format!("min_page_age: {:?}", e))?,
+            None => return Ok(()),
+        };
+        if result.is_empty()? {
+            return Ok(());
+        }
+        self.load_page_created(result)?;
+        let cutoff = Self::timestamp_days_ago(days);
+        result.retain_entries(&|entry| match entry.get_page_created() {
+            Some(created) => created <= cutoff,
+            None => false,
+        })
+    }
 
-            result.annotate_batch_results(
-                &self.state(),
-                batches,
-                0,
-                1,
-                &|row: my::Row, entry: &mut PageListEntry| {
-                    let (
-                        _img_name,
-                        _namespace_id,
-                        img_size,
-                        img_width,
-                        img_height,
-                        img_media_type,
-                        img_major_mime,
-                        img_minor_mime,
-                        img_user_text,
-                        img_timestamp,
-                        img_sha1,
-                    ) = my::from_row::<(
-                        String,
-                        usize,
-                        usize,
-                        usize,
-                        usize,
-                        String,
-                        String,
-                        String,
-                        String,
-                        String,
-                        String,
-                    )>(row);
-                    let mut file_info = match entry.get_file_info() {
-                        Some(fi) => fi,
-                        None => FileInfo::new(),
-                    };
-                    file_info.img_size = Some(img_size);
-                    file_info.img_width = Some(img_width);
-                    file_info.img_height = Some(img_height);
-                    file_info.img_media_type = Some(img_media_type);
-                    file_info.img_major_mime = Some(img_major_mime);
-                    file_info.img_minor_mime = Some(img_minor_mime);
-                    file_info.img_user_text = Some(img_user_text);
-                    file_info.img_timestamp = Some(img_timestamp);
-                    file_info.img_sha1 = Some(img_sha1);
-                    entry.set_file_info(Some(file_info));
-                },
-            )?;
+    /// Keeps pages created (first revision) before "created_before" and/or at or after "created_after".
+    /// Both are timestamps like "2020-01-31" or "20200131120000"; missing digits are zeros.
+    fn process_created_before_after(&self, result: &PageList) -> Result<(), String> {
+        let before = self
+            .get_param("created_before")
+            .map(|s| Self::mediawiki_timestamp_from_param("created_before", &s))
+            .transpose()?;
+        let after = self
+            .get_param("created_after")
+            .map(|s| Self::mediawiki_timestamp_from_param("created_after", &s))
+            .transpose()?;
+        if (before.is_none() && after.is_none()) || result.is_empty()? || result.is_wikidata() {
+            return Ok(());
         }
-        Ok(())
+        self.load_page_created(result)?;
+        result.retain_entries(&|entry| match entry.get_page_created() {
+            Some(created) => {
+                before.as_ref().map_or(true, |before| created < *before)
+                    && after.as_ref().map_or(true, |after| created >= *after)
+            }
+            None => false,
+        })
     }
 
-    fn annotate_with_wikidata_item(&self, result: &PageList) -> Result<(), String> {
-        if result.is_wikidata() {
+    /// Keeps pages last edited by "last_editor", and/or not by "last_editor_not".
+    /// Last editors are also loaded when sorting by them.
+    fn process_last_editor(&self, result: &PageList) -> Result<(), String> {
+        let last_editor = self
+            .get_param("last_editor")
+            .map(|user| Platform::normalize_user_name(&user));
+        let last_editor_not = self
+            .get_param("last_editor_not")
+            .map(|user| Platform::normalize_user_name(&user));
+        if last_editor.is_none() && last_editor_not.is_none() {
+            if self.sorts_by("last_editor") {
+                self.load_last_editors(result)?;
+            }
             return Ok(());
         }
+        self.load_last_editors(result)?;
+        result.retain_entries(&|entry| {
+            let editor = entry
+                .get_last_editor()
+                .map(|user| Platform::normalize_user_name(&user));
+            last_editor
+                .as_ref()
+                .map_or(true, |user| editor.as_ref() == Some(user))
+                && last_editor_not
+                    .as_ref()
+                    .map_or(true, |user| editor.as_ref() != Some(user))
+        })
+    }
 
-        let wiki = match result.wiki()? {
-            Some(wiki) => wiki.to_string(),
-            None => return Ok(()), // TODO is it OK to just ignore? Error for "no wiki set"?
+    /// Sets the user name of the latest revision of each page.
+    /// Users hidden by revision deletion are left unset.
+    fn load_last_editors(&self, result: &PageList) -> Result<(), String> {
+        if result.is_empty()? || result.is_wikidata() {
+            return Ok(());
+        }
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace,actor_name FROM page INNER JOIN revision ON rev_id=page_latest LEFT JOIN actor ON actor_id=rev_actor WHERE ".to_string() + &sql.0;
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| {
+                entry.set_last_editor(PageList::string_from_row(&row, 2));
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Converts a date like "2020-01-31" or "2020-01-31 12:00" into a 14-digit MediaWiki timestamp
+    pub fn mediawiki_timestamp_from_param(param: &str, s: &str) -> Result<String, String> {
+        let digits: String = s.chars().filter(|c| c.is_ascii_digit()).collect();
+        if digits.len() < 4 || digits.len() > 14 {
+            return Err(format!("{}: '{}' is not a valid timestamp", param, s));
+        }
+        Ok(format!("{:0<14}", digits))
+    }
+
+    /// Keeps only redirects created in the last "new_redirects_days" days,
+    /// annotated with their target, creation time, and creator
+    fn process_new_redirects(&self, result: &PageList) -> Result<(), String> {
+        let days = match self.get_param("new_redirects_days") {
+            Some(days) => days
+                .trim()
+                .parse::<i64>()
+                .map_err(|e| format!("new_redirects_days: {:?}", e))?,
+            None => return Ok(()),
         };
-        let api = self.state.get_api_for_wiki(wiki.to_owned())?;
+        if result.is_empty()? || result.is_wikidata() {
+            return Ok(());
+        }
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace,rd_namespace,rd_title,rev_timestamp,actor_name FROM page,redirect,revision,actor WHERE rd_from=page_id AND page_is_redirect=1 AND rev_page=page_id AND rev_parent_id=0 AND actor_id=rev_actor AND ".to_string() + &sql.0;
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
 
-        // Using Wikidata
-        let titles: Vec<String> = result
-            .entries()
-            .read()
-            .map_err(|e| format!("{:?}", e))?
-            .par_iter()
-            .filter_map(|entry| entry.title().full_pretty(&api))
+        // Clear redirect targets so only pages found here are kept
+        let mut entries = result.entries().write().map_err(|e| format!("{:?}", e))?;
+        *entries = entries
+            .drain()
+            .map(|mut entry| {
+                entry.set_redirect_target(None);
+                entry
+            })
             .collect();
+        drop(entries);
 
-        let mut batches: Vec<SQLtuple> = vec![];
-        titles.chunks(PAGE_BATCH_SIZE).for_each(|chunk| {
-
-            let escaped: Vec<String> = chunk
-                .par_iter()
-                .filter_map(|s| match s.trim() {
-                    "" => None,
-                    other => Some(other.to_string()),
-                })
-                .collect();
-            let mut sql = (Platform::get_questionmarks(escaped.len()), escaped);
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| match my::from_row_opt::<(
+                Vec<u8>,
+                NamespaceID,
+                NamespaceID,
+                Vec<u8>,
+                Vec<u8>,
+                Vec<u8>,
+            )>(row)
+            {
+                Ok((
+                    _page_title,
+                    _page_namespace,
+                    rd_namespace,
+                    rd_title,
+                    rev_timestamp,
+                    actor_name,
+                )) => {
+                    let rd_title = String::from_utf8_lossy(&rd_title).into_owned();
+                    entry.set_redirect_target(Some(Title::new(&rd_title, rd_namespace)));
+                    entry.set_page_created(Some(
+                        String::from_utf8_lossy(&rev_timestamp).into_owned(),
+                    ));
+                    entry.set_page_creator(Some(String::from_utf8_lossy(&actor_name).into_owned()));
+                }
+                Err(_e) => {}
+            },
+        )?;
 
-            sql.0 = format!("SELECT ips_site_page,ips_item_id FROM wb_items_per_site WHERE ips_site_id='{}' and ips_site_page IN ({})", &wiki,&sql.0);
-            batches.push(sql);
-        });
+        let cutoff = Self::timestamp_days_ago(days);
+        result.retain_entries(&|entry| {
+            entry.get_redirect_target().is_some()
+                && match entry.get_page_created() {
+                    Some(created) => created >= cutoff,
+                    None => false,
+                }
+        })
+    }
 
-        // Duplicated from Patelist::annotate_batch_results
-        let rows: Mutex<Vec<my::Row>> = Mutex::new(vec![]);
+    /// Keeps only pages whose wikitext matches "content_regexp" (unanchored).
+    /// "content_regexp_case_insensitive" and "content_regexp_multiline" set the regex flags.
+    /// This fetches the content of every result page, so it is expensive; combine it
+    /// with a narrowing source. Pages over MAX_PAGE_CONTENT_BYTES are never matched.
+    fn process_content_regexp(&self, result: &PageList) -> Result<(), String> {
+        let regexp = match self.get_param("content_regexp") {
+            Some(regexp) => regexp,
+            None => return Ok(()),
+        };
+        let re = RegexBuilder::new(&regexp)
+            .case_insensitive(self.has_param("content_regexp_case_insensitive"))
+            .multi_line(self.has_param("content_regexp_multiline"))
+            .build()
+            .map_err(|e| format!("content_regexp: {}", e))?;
+        if result.is_empty()? {
+            return Ok(());
+        }
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => return Ok(()),
+        };
+        self.load_page_contents(result)?;
+        result.retain_entries(&|entry| match self.get_page_content(&wiki, entry.title()) {
+            Some(content) => re.is_match(&content),
+            None => false,
+        })
+    }
 
-        batches.par_iter().map(|sql| {
-            // Get DB connection
-            let db_user_pass = self.state.get_db_mutex().lock().map_err(|e| format!("{:?}", e))?;
-            let mut conn = self.state.get_wiki_db_connection(&db_user_pass, &"wikidatawiki".to_string()).map_err(|e| format!("{:?}", e))?;
+    fn page_content_key(wiki: &str, title: &Title) -> PageContentKey {
+        (
+            wiki.to_string(),
+            title.namespace_id(),
+            title.with_underscores().to_string(),
+        )
+    }
 
-            // Run query
-            let mut result = conn.prep_exec(&sql.0, &sql.1).map_err(|e| format!("Platform::annotate_with_wikidata_item: Can't connect to wikidatawiki: {:?}", e))?
-            .filter_map(|row| row.ok()).collect();
+    /// Returns the cached wikitext of a page, as loaded by load_page_contents.
+    /// Returns None for pages that were not loaded, are missing, or were too large.
+    pub fn get_page_content(&self, wiki: &str, title: &Title) -> Option<String> {
+        match self.page_contents.read() {
+            Ok(cache) => match cache.get(&Self::page_content_key(wiki, title)) {
+                Some(content) => content.to_owned(),
+                None => None,
+            },
+            Err(_) => None,
+        }
+    }
 
-            rows.lock()
-            .map_err(|e| format!("Platform::annotate_with_wikidata_item: Can't unlock mutex: {:?}", e))?
-            .append(&mut result);
-            Ok(())
-        })
-        .collect::<Result<_,String>>()?;
+    /// Loads the current wikitext of all result pages into the per-run cache, so
+    /// multiple content-based filters share a single fetch.
+    /// Pages larger than MAX_PAGE_CONTENT_BYTES are skipped with a warning.
+    pub fn load_page_contents(&self, result: &PageList) -> Result<(), String> {
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => return Ok(()),
+        };
+        let api = self.state.get_api_for_wiki(wiki.to_owned())?;
 
-        // Rows to entries
-        rows.lock()
-            .map_err(|e| format!("{:?}", e))?
-            .iter()
-            .for_each(|row| {
-                let full_page_title = match row.get(0) {
-                    Some(title) => match title {
-                        my::Value::Bytes(uv) => match String::from_utf8(uv) {
-                            Ok(s) => s,
-                            Err(_) => return,
-                        },
-                        _ => return,
-                    },
-                    None => return,
-                };
-                let ips_item_id = match row.get(1) {
-                    Some(title) => match title {
-                        my::Value::Int(i) => i,
-                        _ => return,
-                    },
-                    None => return,
-                };
-                let title = Title::new_from_full(&full_page_title, &api);
-                let tmp_entry = PageListEntry::new(title);
-                let mut entry = match result.entries().read().unwrap().get(&tmp_entry) {
-                    Some(e) => (*e).clone(),
-                    None => return,
-                };
+        let mut oversized: Vec<PageContentKey> = vec![];
+        let mut titles: Vec<Title> = vec![];
+        {
+            let cache = self.page_contents.read().map_err(|e| format!("{:?}", e))?;
+            for entry in result
+                .entries()
+                .read()
+                .map_err(|e| format!("{:?}", e))?
+                .iter()
+            {
+                let key = Self::page_content_key(&wiki, entry.title());
+                if cache.contains_key(&key) {
+                    continue;
+                }
+                match entry.page_bytes {
+                    Some(bytes) if bytes as u64 > MAX_PAGE_CONTENT_BYTES => oversized.push(key),
+                    _ => titles.push(entry.title().to_owned()),
+                }
+            }
+        }
 
-                let q = "Q".to_string() + &ips_item_id.to_string();
-                entry.set_wikidata_item(Some(q));
+        let loaded = rayon::ThreadPoolBuilder::new()
+            .num_threads(MAX_CONTENT_THREADS)
+            .build()
+            .map_err(|e| {
+                format!(
+                    "Platform::load_page_contents: Can't build ThreadPool: {:?}",
+                    e
+                )
+            })?
+            .install(|| {
+                titles
+                    .par_chunks(CONTENT_BATCH_SIZE)
+                    .map(|chunk| Self::load_page_contents_batch(&api, &wiki, chunk))
+                    .collect::<Result<Vec<_>, String>>()
+            })?;
 
-                result.add_entry(entry).unwrap_or(());
-            });
+        let mut cache = self.page_contents.write().map_err(|e| format!("{:?}", e))?;
+        for (key, content) in loaded.into_iter().flatten() {
+            if content.is_none() {
+                oversized.push(key.to_owned());
+            }
+            cache.insert(key, content);
+        }
+        let num_oversized = oversized.len();
+        oversized.into_iter().for_each(|key| {
+            cache.insert(key, None);
+        });
+        drop(cache);
+
+        if num_oversized > 0 {
+            self.warn(format!(
+                "Content of {} pages larger than {} bytes was not loaded",
+                num_oversized, MAX_PAGE_CONTENT_BYTES
+            ))?;
+        }
         Ok(())
+    }
 
-        /*
-        // THIS WOULD BE NICE BUT page_props HAS DAYS OF DATA LAG OR IS FAULTY
-        // Batches
-        let batches: Vec<SQLtuple> = result.to_sql_batches(PAGE_BATCH_SIZE)
-            .iter_mut()
-            .map(|sql|{
-                sql.0 = "SELECT page_title,page_namespace,pp_value FROM page_props,page WHERE page_id=pp_page AND pp_propname='wikibase_item' AND ".to_owned()+&sql.0;
+    /// Fetches the wikitext for one batch of titles.
+    /// Oversized pages are returned with None content; missing pages are left out.
+    fn load_page_contents_batch(
+        api: &Api,
+        wiki: &str,
+        titles: &[Title],
+    ) -> Result<Vec<(PageContentKey, Option<String>)>, String> {
+        let titles: Vec<String> = titles
+            .iter()
+            .filter_map(|title| title.full_pretty(api))
+            .collect();
+        if titles.is_empty() {
+            return Ok(vec![]);
+        }
+        let params = api.params_into(&vec![
+            ("action", "query"),
+            ("prop", "revisions"),
+            ("rvprop", "content|size"),
+            ("rvslots", "main"),
+            ("formatversion", "2"),
+            ("titles", titles.join("|").as_str()),
+        ]);
+        let j = api
+            .get_query_api_json(&params)
+            .map_err(|e| format!("Platform::load_page_contents_batch: {:?}", e))?;
+        let pages = match j["query"]["pages"].as_array() {
+            Some(pages) => pages,
+            None => return Ok(vec![]),
+        };
+        Ok(pages
+            .iter()
+            .filter_map(|page| {
+                let title = Title::new_from_full(page["title"].as_str()?, api);
+                let revision = &page["revisions"][0];
+                let content = revision["slots"]["main"]["content"].as_str()?;
+                let key = Self::page_content_key(wiki, &title);
+                match revision["size"].as_u64() {
+                    Some(size) if size > MAX_PAGE_CONTENT_BYTES => Some((key, None)),
+                    _ => Some((key, Some(content.to_string()))),
+                }
+            })
+            .collect())
+    }
+
+    /// Turns a template name with "*" wildcards into a LIKE pattern for tl_title
+    fn template_like_pattern(template: &str) -> String {
+        template
+            .trim()
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+            .replace(' ', "\\_")
+            .replace('*', "%")
+    }
+
+    /// "stubs_only" keeps likely stubs, using a heuristic: pages smaller than
+    /// "stubs_max_bytes" (default 1500) OR transcluding a template matching
+    /// "stubs_template" (default "*-stub", "*" being a wildcard).
+    /// Neither criterion checks the actual prose length.
+    fn process_stubs(&self, result: &PageList) -> Result<(), String> {
+        if !self.has_param("stubs_only") || result.is_wikidata() || result.is_empty()? {
+            return Ok(());
+        }
+        let max_bytes = self
+            .usize_option_from_param("stubs_max_bytes")
+            .unwrap_or(DEFAULT_STUB_MAX_BYTES);
+        let template = self.get_param_default("stubs_template", DEFAULT_STUB_TEMPLATE);
+        let template = Self::template_like_pattern(&template);
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = format!("SELECT page_title,page_namespace,page_len FROM page WHERE {} AND (page_len<{} OR EXISTS (SELECT * FROM templatelinks WHERE tl_from=page_id AND tl_namespace=10 AND tl_title LIKE ?))",&sql.0,max_bytes);
+                sql.1.push(template.to_owned());
                 sql.to_owned()
             })
             .collect::<Vec<SQLtuple>>();
 
+        // Clear page sizes so only stubs found here are kept
+        let mut entries = result.entries().write().map_err(|e| format!("{:?}", e))?;
+        *entries = entries
+            .drain()
+            .map(|mut entry| {
+                entry.page_bytes = None;
+                entry
+            })
+            .collect();
+        drop(entries);
+
         result.annotate_batch_results(
-            self.state(),
+            &self.state(),
             batches,
             0,
             1,
-            &|row: my::Row, entry: &mut PageListEntry| {
-                let (_page_title, _page_namespace, pp_value) =
-                    my::from_row::<(String, NamespaceID, String)>(row);
-                entry.wikidata_item = Some(pp_value);
+            &|row: my::Row, entry: &mut PageListEntry| match my::from_row_opt::<(
+                Vec<u8>,
+                NamespaceID,
+                u32,
+            )>(row)
+            {
+                Ok((_page_title, _page_namespace, page_len)) => entry.page_bytes = Some(page_len),
+                Err(_e) => {}
             },
-        )
-        */
+        )?;
+        result.retain_entries(&|entry| entry.page_bytes.is_some())
     }
 
-    /// Filters on whether a page has a Wikidata item, depending on the "wikidata_item"
-    fn process_by_wikidata_item(&self, result: &PageList) -> Result<(), String> {
-        if result.is_wikidata() {
-            return Ok(());
-        }
-        let wdi = self.get_param_default("wikidata_item", "no");
-        if wdi != "any" && wdi != "with" && wdi != "without" {
+    /// "maintenance_tagged" keeps pages that use any of the maintenance templates,
+    /// and lists the ones found. See DEFAULT_MAINTENANCE_TEMPLATES.
+    fn process_maintenance_tagged(&self, result: &PageList) -> Result<(), String> {
+        if !self.has_param("maintenance_tagged") || result.is_wikidata() || result.is_empty()? {
             return Ok(());
         }
-        self.annotate_with_wikidata_item(result)?;
-        if wdi == "with" {
-            result.retain_entries(&|entry| entry.get_wikidata_item().is_some())?;
-        }
-        if wdi == "without" {
-            result.retain_entries(&|entry| entry.get_wikidata_item().is_none())?;
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => return Ok(()),
+        };
+        let mut templates = self.get_param_as_vec("maintenance_templates", "\n");
+        if templates.is_empty() {
+            templates = match DEFAULT_MAINTENANCE_TEMPLATES
+                .iter()
+                .find(|(w, _)| *w == wiki)
+            {
+                Some((_, list)) => list.iter().map(|t| t.to_string()).collect(),
+                None => {
+                    self.warn(format!("<span tt='warn_no_maintenance_templates'></span>"))?;
+                    return Ok(());
+                }
+            };
         }
-        Ok(())
-    }
+        let patterns: Vec<String> = templates
+            .iter()
+            .map(|t| Self::template_like_pattern(&Title::first_letter_uppercase(t)))
+            .collect();
+        let like = vec!["tl_title LIKE ?"; patterns.len()].join(" OR ");
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                let mut params = patterns.clone();
+                params.append(&mut sql.1);
+                sql.0 = format!("SELECT page_title,page_namespace,GROUP_CONCAT(DISTINCT tl_title SEPARATOR '|') FROM page,templatelinks WHERE tl_from=page_id AND tl_namespace=10 AND ({}) AND {} GROUP BY page_id",like,&sql.0);
+                sql.1 = params;
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
 
-    /// Adds page properties that might be missing if none of the original sources was "categories"
-    fn process_missing_database_filters(&self, result: &PageList) -> Result<(), String> {
-        let mut params = SourceDatabaseParameters::db_params(self);
-        params.set_wiki(Some(result.wiki()?.ok_or(format!(
-            "Platform::process_missing_database_filters: result has no wiki"
-        ))?));
-        let mut db = SourceDatabase::new(params);
-        let new_result = db.get_pages(&self.state, Some(result))?;
-        result.set_from(new_result)?;
-        Ok(())
+        let mut entries = result.entries().write().map_err(|e| format!("{:?}", e))?;
+        *entries = entries
+            .drain()
+            .map(|mut entry| {
+                entry.set_maintenance_templates(None);
+                entry
+            })
+            .collect();
+        drop(entries);
+
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| match PageList::string_from_row(&row, 2) {
+                Some(found) => entry.set_maintenance_templates(Some(
+                    found.split('|').map(|t| t.to_string()).collect(),
+                )),
+                None => {}
+            },
+        )?;
+        result.retain_entries(&|entry| entry.get_maintenance_templates().is_some())
     }
 
-    fn process_labels_old(&self, result: &PageList) -> Result<(), String> {
-        let mut sql = self.get_label_sql();
-        if sql.1.is_empty() {
-            return Ok(());
-        }
-        result.convert_to_wiki("wikidatawiki", &self)?;
-        if result.is_empty()? {
+    /// Annotates whether pages are cascade-protected, i.e. transcluded on a page with
+    /// cascading protection. "cascade_protected=yes" or "=no" also filters on it.
+    fn process_cascade_protected(&self, result: &PageList) -> Result<(), String> {
+        let mode = match self.get_param("cascade_protected") {
+            Some(mode) => mode,
+            None => return Ok(()),
+        };
+        if result.is_wikidata() || result.is_empty()? {
             return Ok(());
         }
-        sql.0 += " AND term_full_entity_id IN (";
-
-        // Batches
         let batches: Vec<SQLtuple> = result
             .to_sql_batches(PAGE_BATCH_SIZE)?
             .par_iter_mut()
-            .map(|sql_batch| {
-                let tmp = Platform::prep_quote(&sql_batch.1);
-                sql_batch.0 = sql.0.to_owned() + &tmp.0 + ")";
-                sql_batch.1.splice(..0, sql.1.to_owned());
-                sql_batch.to_owned()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace FROM page WHERE EXISTS (SELECT * FROM templatelinks,page_restrictions WHERE tl_namespace=page_namespace AND tl_title=page_title AND pr_page=tl_from AND pr_cascade=1) AND ".to_string() + &sql.0;
+                sql.to_owned()
             })
             .collect::<Vec<SQLtuple>>();
 
-        result.clear_entries()?;
-        result.process_batch_results(&self.state(), batches, &|row: my::Row| {
-            let term_full_entity_id = my::from_row::<String>(row);
-            Platform::entry_from_entity(&term_full_entity_id)
-        })
-    }
-
-    //________________________________________________________________________________________________
+        let mut entries = result.entries().write().map_err(|e| format!("{:?}", e))?;
+        *entries = entries
+            .drain()
+            .map(|mut entry| {
+                entry.cascade_protected = TriState::No;
+                entry
+            })
+            .collect();
+        drop(entries);
 
-    fn get_label_sql_helper_new(&self, ret: &mut SQLtuple, part1: &str) {
-        let mut wbt_type: Vec<String> = vec![];
-        if self.has_param(&("cb_labels_".to_owned() + part1 + "_l")) {
-            wbt_type.push("1".to_string());
-        }
-        if self.has_param(&("cb_labels_".to_owned() + part1 + "_a")) {
-            wbt_type.push("3".to_string());
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|_row: my::Row, entry: &mut PageListEntry| {
+                entry.cascade_protected = TriState::Yes;
+            },
+        )?;
+        match mode.as_str() {
+            "yes" => result.retain_entries(&|entry| entry.cascade_protected == TriState::Yes),
+            "no" => result.retain_entries(&|entry| entry.cascade_protected == TriState::No),
+            _ => Ok(()),
         }
-        if self.has_param(&("cb_labels_".to_owned() + part1 + "_d")) {
-            wbt_type.push("2".to_string());
+    }
+
+    /// Keeps pages that link to any/all/none of the titles in `links_to_page`
+    fn process_links_to_page(&self, result: &PageList) -> Result<(), String> {
+        let targets = self.get_param_as_vec("links_to_page", "\n");
+        if targets.is_empty() || result.is_wikidata() || result.is_empty()? {
+            return Ok(());
         }
-        if !wbt_type.is_empty() {
-            if wbt_type.len() == 1 {
-                ret.0 += &format!(" AND wbtl_type_id={}", wbt_type.join(","));
-            } else {
-                ret.0 += &format!(" AND wbtl_type_id IN ({})", wbt_type.join(","));
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => return Ok(()),
+        };
+        let api = self.state().get_api_for_wiki(wiki)?;
+        let parts: Vec<SQLtuple> = targets
+            .iter()
+            .map(|target| {
+                let title = Platform::normalize_title(target, &api);
+                (
+                    "(SELECT * FROM pagelinks WHERE pl_from=page_id AND pl_namespace=? AND pl_title=?)".to_string(),
+                    vec![
+                        title.namespace_id().to_string(),
+                        title.with_underscores().to_string(),
+                    ],
+                )
+            })
+            .collect();
+
+        let mode = self.get_param_default("links_to_page_mode", "any");
+        let mut sql_post: SQLtuple = ("".to_string(), vec![]);
+        match mode.as_str() {
+            "all" => {
+                parts.iter().for_each(|sql| {
+                    sql_post.0 += &(" AND EXISTS ".to_owned() + &sql.0);
+                    sql_post.1.append(&mut sql.1.to_owned());
+                });
+            }
+            "any" | "none" => {
+                sql_post.0 += " AND (0";
+                parts.iter().for_each(|sql| {
+                    sql_post.0 += &(" OR EXISTS ".to_owned() + &sql.0);
+                    sql_post.1.append(&mut sql.1.to_owned());
+                });
+                sql_post.0 += ")";
             }
+            other => return Err(format!("Unknown links_to_page_mode '{}'", other)),
         }
-    }
 
-    fn get_label_sql_subquery_new(
-        &self,
-        ret: &mut SQLtuple,
-        key: &str,
-        languages: &Vec<String>,
-        s: &String,
-    ) {
-        let has_pattern = !s.is_empty() && s != "%";
-        let has_languages = !languages.is_empty();
-        ret.0 += "SELECT * FROM wbt_term_in_lang,wbt_item_terms t2";
-        if has_languages || has_pattern {
-            ret.0 += ",wbt_text_in_lang";
-        }
-        if has_pattern {
-            ret.0 += ",wbt_text";
-        }
-        ret.0 += " WHERE t2.wbit_item_id=t1.wbit_item_id AND wbtl_id=t2.wbit_term_in_lang_id";
-        self.get_label_sql_helper_new(ret, key);
-        if has_languages || has_pattern {
-            let mut tmp = Self::prep_quote(&languages);
-            ret.0 += " AND wbtl_text_in_lang_id=wbxl_id";
-            if !tmp.1.is_empty() {
-                if tmp.1.len() == 1 {
-                    ret.0 += &(" AND wbxl_language=".to_owned() + &tmp.0);
-                } else {
-                    ret.0 += &(" AND wbxl_language IN (".to_owned() + &tmp.0 + ")");
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace FROM page WHERE ".to_owned()
+                    + &sql.0
+                    + &sql_post.0;
+                sql.1.append(&mut sql_post.1.to_owned());
+                sql.to_owned()
+            })
+            .collect();
+
+        let linking: Mutex<HashSet<(NamespaceID, String)>> = Mutex::new(HashSet::new());
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|_row: my::Row, entry: &mut PageListEntry| {
+                if let Ok(mut linking) = linking.lock() {
+                    linking.insert((
+                        entry.title().namespace_id(),
+                        entry.title().with_underscores().to_string(),
+                    ));
                 }
-                ret.1.append(&mut tmp.1);
-            }
-            if has_pattern {
-                ret.0 += " AND wbxl_text_id=wbx_id AND wbx_text LIKE ?";
-                ret.1.push(s.to_string());
-            }
-        }
+            },
+        )?;
+        let linking = linking.lock().map_err(|e| format!("{:?}", e))?;
+        let keep_linking = mode != "none";
+        result.retain_entries(&|entry| {
+            let key = (
+                entry.title().namespace_id(),
+                entry.title().with_underscores().to_string(),
+            );
+            linking.contains(&key) == keep_linking
+        })
     }
 
-    fn get_label_sql_new(&self, namespace_id: &NamespaceID) -> Option<SQLtuple> {
-        lazy_static! {
-            static ref RE1: Regex =
-                Regex::new(r#"[^a-z,]"#).expect("Platform::get_label_sql Regex is invalid");
-        }
-        let mut ret: SQLtuple = ("".to_string(), vec![]);
-        let yes = self.get_param_as_vec("labels_yes", "\n");
-        let any = self.get_param_as_vec("labels_any", "\n");
-        let no = self.get_param_as_vec("labels_no", "\n");
-        if yes.len() + any.len() + no.len() == 0 {
-            return None;
-        }
+    fn process_files(&self, result: &PageList) -> Result<(), String> {
+        let giu = self.has_param("giu");
+        let file_data = self.has_param("ext_image_data")
+            || self.has_param("uploader_is")
+            || self.has_param("uploader_anon")
+            || self.sorts_by("filesize")
+            || self.sorts_by("uploaddate")
+            || self.sorts_by("uploader")
+            || self.sorts_by("mime")
+            || self.has_dimension_filter();
+        let file_usage = giu || self.has_param("file_usage_data");
+        let file_usage_data_ns0 = self.has_param("file_usage_data_ns0");
 
-        let langs_yes = self.get_param_as_vec("langs_labels_yes", ",");
-        let langs_any = self.get_param_as_vec("langs_labels_any", ",");
-        let langs_no = self.get_param_as_vec("langs_labels_no", ",");
+        if file_usage {
+            let batches: Vec<SQLtuple> = result
+                .to_sql_batches_namespace(PAGE_BATCH_SIZE,6)?
+                .par_iter_mut()
+                .map(|mut sql_batch| {
+                    let tmp = Platform::prep_quote(&sql_batch.1);
+                    sql_batch.0 = "SELECT gil_to,6 AS namespace_id,GROUP_CONCAT(gil_wiki,':',gil_page_namespace_id,':',gil_page_namespace,':',gil_page_title SEPARATOR '|') AS gil_group FROM globalimagelinks WHERE gil_to IN (".to_string() ;
+                    sql_batch.0 += &tmp.0 ;
+                    sql_batch.0 += ")";
+                    if file_usage_data_ns0  {sql_batch.0 += " AND gil_page_namespace_id=0" ;}
+                    sql_batch.0 += " GROUP BY gil_to" ;
+                    sql_batch.to_owned()
+                })
+                .collect::<Vec<SQLtuple>>();
 
-        if *namespace_id == 0 {
-            ret.0 =
-                "SELECT DISTINCT CONCAT('Q',wbit_item_id) AS term_full_entity_id FROM wbt_item_terms t1 WHERE 1=1".to_string();
-        } else if *namespace_id == 120 {
-            ret.0 = "SELECT DISTINCT CONACT('P',wbit_property_id) AS term_full_entity_id FROM wbt_property_terms t1 WHERE 1=1"
-                .to_string();
-        } else {
-            return None;
+            result.annotate_batch_results(
+                &self.state(),
+                batches,
+                0,
+                1,
+                &|row: my::Row, entry: &mut PageListEntry| match PageList::string_from_row(&row, 2)
+                {
+                    Some(gil_group) => {
+                        let fi = FileInfo::new_from_gil_group(&gil_group);
+                        entry.set_file_info(Some(fi));
+                    }
+                    None => {}
+                },
+            )?;
         }
 
-        yes.iter().for_each(|s| {
-            ret.0 += " AND EXISTS (";
-            self.get_label_sql_subquery_new(&mut ret, "yes", &langs_yes, s);
-            ret.0 += ")";
-        });
+        if file_data {
+            let batches: Vec<SQLtuple> = result
+                .to_sql_batches(PAGE_BATCH_SIZE)?
+                .par_iter_mut()
+                .map(|mut sql_batch| {
+                    let tmp = Platform::prep_quote(&sql_batch.1);
+                    sql_batch.0 = "SELECT img_name,6 AS namespace_id,img_size,img_width,img_height,img_media_type,img_major_mime,img_minor_mime,img_user_text,img_timestamp,img_sha1 FROM image_compat WHERE img_name IN (".to_string() ;
+                    sql_batch.0 += &tmp.0 ;
+                    sql_batch.0 += ")";
+                    sql_batch.to_owned()
+                })
+                .collect::<Vec<SQLtuple>>();
 
-        if !langs_any.is_empty() {
-            ret.0 += " AND (0=1";
-            any.iter().for_each(|s| {
-                ret.0 += " OR EXISTS (";
-                self.get_label_sql_subquery_new(&mut ret, "any", &langs_any, s);
-                ret.0 += ")";
-            });
-            ret.0 += ")";
+            result.annotate_batch_results(
+                &self.state(),
+                batches,
+                0,
+                1,
+                &|row: my::Row, entry: &mut PageListEntry| {
+                    let (
+                        _img_name,
+                        _namespace_id,
+                        img_size,
+                        img_width,
+                        img_height,
+                        img_media_type,
+                        img_major_mime,
+                        img_minor_mime,
+                        img_user_text,
+                        img_timestamp,
+                        img_sha1,
+                    ) = my::from_row::<(
+                        String,
+                        usize,
+                        usize,
+                        usize,
+                        usize,
+                        String,
+                        String,
+                        String,
+                        String,
+                        String,
+                        String,
+                    )>(row);
+                    let mut file_info = match entry.get_file_info() {
+                        Some(fi) => fi,
+                        None => FileInfo::new(),
+                    };
+                    file_info.img_size = Some(img_size);
+                    file_info.img_width = Some(img_width);
+                    file_info.img_height = Some(img_height);
+                    file_info.img_media_type = Some(img_media_type);
+                    file_info.img_major_mime = Some(img_major_mime);
+                    file_info.img_minor_mime = Some(img_minor_mime);
+                    file_info.img_user_text = Some(img_user_text);
+                    file_info.img_timestamp = Some(img_timestamp);
+                    file_info.img_sha1 = Some(img_sha1);
+                    entry.set_file_info(Some(file_info));
+                },
+            )?;
         }
+        self.filter_by_uploader(result)?;
+        self.filter_by_dimensions(result)
+    }
 
-        no.iter().for_each(|s| {
-            ret.0 += " AND NOT EXISTS (";
-            self.get_label_sql_subquery_new(&mut ret, "no", &langs_no, s);
-            ret.0 += ")";
-        });
-        Some(ret)
+    fn has_dimension_filter(&self) -> bool {
+        IMAGE_DIMENSION_PARAMS
+            .iter()
+            .any(|param| self.has_param(param))
     }
 
-    /// Using new wbt_item_terms
-    fn process_labels_new(&self, result: &PageList) -> Result<(), String> {
-        if self.get_label_sql_new(&0).is_none() {
+    /// Keeps only files within `min_width`, `max_width`, `min_height`, `max_height`, and `min_megapixels`.
+    /// Other pages are removed if any of these is set.
+    fn filter_by_dimensions(&self, result: &PageList) -> Result<(), String> {
+        if !self.has_dimension_filter() {
             return Ok(());
         }
-        result.convert_to_wiki("wikidatawiki", &self)?;
-        if result.is_empty()? {
+        let min_width = self.usize_option_from_param("min_width");
+        let max_width = self.usize_option_from_param("max_width");
+        let min_height = self.usize_option_from_param("min_height");
+        let max_height = self.usize_option_from_param("max_height");
+        let min_pixels = self
+            .get_param("min_megapixels")
+            .and_then(|s| s.trim().parse::<f64>().ok())
+            .map(|megapixels| megapixels * 1_000_000.0);
+        result.retain_entries(&|entry| {
+            let (width, height) = match entry.get_file_info() {
+                Some(fi) => match (fi.img_width, fi.img_height) {
+                    (Some(width), Some(height)) => (width, height),
+                    _ => return false,
+                },
+                None => return false,
+            };
+            min_width.map_or(true, |min| width >= min)
+                && max_width.map_or(true, |max| width <= max)
+                && min_height.map_or(true, |min| height >= min)
+                && max_height.map_or(true, |max| height <= max)
+                && min_pixels.map_or(true, |min| (width * height) as f64 >= min)
+        })
+    }
+
+    /// Keeps only files uploaded by one of the users in `uploader_is`, or by IPs if `uploader_anon` is set
+    fn filter_by_uploader(&self, result: &PageList) -> Result<(), String> {
+        let uploaders: HashSet<String> = self
+            .get_param_as_vec("uploader_is", ",")
+            .iter()
+            .map(|user| Platform::normalize_user_name(user))
+            .collect();
+        let anon = self.has_param("uploader_anon");
+        if uploaders.is_empty() && !anon {
             return Ok(());
         }
-
-        // Batches
-        let batches: Vec<SQLtuple> = result
-            .group_by_namespace()?
-            .par_iter()
-            .filter_map(|(namespace_id, titles)| {
-                let mut sql = self.get_label_sql_new(namespace_id)?;
-                if *namespace_id == 0 {
-                    sql.0 += " AND wbit_item_id IN (";
-                } else if *namespace_id == 120 {
-                    sql.0 += " AND wbit_property_id IN (";
-                } else {
-                    return None;
+        result.retain_entries(
+            &|entry| match entry.get_file_info().and_then(|fi| fi.img_user_text) {
+                Some(user) => {
+                    uploaders.contains(&Platform::normalize_user_name(&user))
+                        || (anon && user.parse::<std::net::IpAddr>().is_ok())
                 }
-                sql.0 += &titles
-                    .par_iter()
-                    .map(|title| title[1..].to_string())
-                    .collect::<Vec<String>>()
-                    .join(",");
-                sql.0 += ")";
-                Some(sql)
-            })
-            .collect();
-
-        result.clear_entries()?;
-        result.process_batch_results(&self.state(), batches, &|row: my::Row| {
-            let term_full_entity_id = my::from_row::<String>(row);
-            Platform::entry_from_entity(&term_full_entity_id)
-        })
+                None => false,
+            },
+        )
     }
 
-    fn process_labels(&self, result: &PageList) -> Result<(), String> {
-        if false {
-            self.process_labels_old(result)
-        } else {
-            self.process_labels_new(result)
+    pub fn normalize_user_name(user: &str) -> String {
+        let user = user.trim().replace('_', " ");
+        // IPv6 addresses are stored in upper case
+        if user.parse::<std::net::IpAddr>().is_ok() {
+            return user.to_uppercase();
+        }
+        let mut chars = user.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+            None => user,
         }
     }
 
-    fn process_sitelinks(&self, result: &PageList) -> Result<(), String> {
-        if result.is_empty()? {
+    fn annotate_with_wikidata_item(&self, result: &PageList) -> Result<(), String> {
+        if result.is_wikidata() {
             return Ok(());
         }
 
-        let sitelinks_yes = self.get_param_as_vec("sitelinks_yes", "\n");
-        let sitelinks_any = self.get_param_as_vec("sitelinks_any", "\n");
-        let sitelinks_no = self.get_param_as_vec("sitelinks_no", "\n");
-        let sitelinks_min = self.get_param_blank("min_sitelink_count");
-        let sitelinks_max = self.get_param_blank("max_sitelink_count");
-
-        if sitelinks_yes.is_empty()
-            && sitelinks_any.is_empty()
-            && sitelinks_no.is_empty()
-            && sitelinks_min.is_empty()
-            && sitelinks_max.is_empty()
-        {
-            return Ok(());
-        }
-        let old_wiki = result.wiki()?.to_owned();
-        result.convert_to_wiki("wikidatawiki", &self)?;
-        if result.is_empty()? {
-            return Ok(());
-        }
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki.to_string(),
+            None => return Ok(()), // TODO is it OK to just ignore? Error for "no wiki set"?
+        };
+        let api = self.state.get_api_for_wiki(wiki.to_owned())?;
 
-        let use_min_max = !sitelinks_min.is_empty() || !sitelinks_max.is_empty();
+        // Using Wikidata
+        let titles: Vec<String> = result
+            .entries()
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .par_iter()
+            .filter_map(|entry| entry.title().full_pretty(&api))
+            .collect();
 
-        let mut sql: SQLtuple = ("".to_string(), vec![]);
-        sql.0 += "SELECT ";
-        if use_min_max {
-            sql.0 += "page_title,(SELECT count(*) FROM wb_items_per_site WHERE ips_item_id=substr(page_title,2)*1) AS sitelink_count" ;
-        } else {
-            sql.0 += "DISTINCT page_title,0";
-        }
-        sql.0 += " FROM page WHERE page_namespace=0";
+        let mut batches: Vec<SQLtuple> = vec![];
+        titles.chunks(PAGE_BATCH_SIZE).for_each(|chunk| {
 
-        sitelinks_yes.iter().for_each(|site|{
-            sql.0 += " AND EXISTS (SELECT * FROM wb_items_per_site WHERE ips_item_id=substr(page_title,2)*1 AND ips_site_id=? LIMIT 1)" ;
-            sql.1.push(site.to_string());
-        });
-        if !sitelinks_any.is_empty() {
-            sql.0 += " AND EXISTS (SELECT * FROM wb_items_per_site WHERE ips_item_id=substr(page_title,2)*1 AND ips_site_id IN (" ;
-            let tmp = Platform::prep_quote(&sitelinks_any);
-            Platform::append_sql(&mut sql, tmp);
-            sql.0 += ") LIMIT 1)";
-        }
-        sitelinks_no.iter().for_each(|site|{
-            sql.0 += " AND NOT EXISTS (SELECT * FROM wb_items_per_site WHERE ips_item_id=substr(page_title,2)*1 AND ips_site_id=? LIMIT 1)" ;
-            sql.1.push(site.to_string());
+            let escaped: Vec<String> = chunk
+                .par_iter()
+                .filter_map(|s| match s.trim() {
+                    "" => None,
+                    other => Some(other.to_string()),
+                })
+                .collect();
+            let mut sql = (Platform::get_questionmarks(escaped.len()), escaped);
+
+            sql.0 = format!("SELECT ips_site_page,ips_item_id FROM wb_items_per_site WHERE ips_site_id='{}' and ips_site_page IN ({})", &wiki,&sql.0);
+            batches.push(sql);
         });
-        sql.0 += " AND ";
 
-        let mut having: Vec<String> = vec![];
-        match sitelinks_min.parse::<usize>() {
-            Ok(s) => having.push(format!("sitelink_count>={}", s)),
-            _ => {}
-        }
-        match sitelinks_max.parse::<usize>() {
-            Ok(s) => having.push(format!("sitelink_count<={}", s)),
-            _ => {}
-        }
+        // Duplicated from Patelist::annotate_batch_results
+        let rows: Mutex<Vec<my::Row>> = Mutex::new(vec![]);
 
-        let mut sql_post = "".to_string();
-        if use_min_max {
-            sql_post += " GROUP BY page_title";
-        }
-        if !having.is_empty() {
-            sql_post += " HAVING ";
-            sql_post += &having.join(" AND ");
-        }
+        batches.par_iter().map(|sql| {
+            // Get DB connection
+            let db_user_pass = self.state.get_db_mutex().lock().map_err(|e| format!("{:?}", e))?;
+            let mut conn = self.state.get_wiki_db_connection(&db_user_pass, &"wikidatawiki".to_string())?;
+
+            // Run query
+            let mut result = conn.prep_exec(&sql.0, &sql.1).map_err(|e| format!("Platform::annotate_with_wikidata_item: Can't connect to wikidatawiki: {:?}", e))?
+            .filter_map(|row| row.ok()).collect();
+
+            rows.lock()
+            .map_err(|e| format!("Platform::annotate_with_wikidata_item: Can't unlock mutex: {:?}", e))?
+            .append(&mut result);
+            Ok(())
+        })
+        .collect::<Result<_,String>>()?;
+
+        // Rows to entries
+        rows.lock()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .for_each(|row| {
+                let full_page_title = match row.get(0) {
+                    Some(title) => match title {
+                        my::Value::Bytes(uv) => match String::from_utf8(uv) {
+                            Ok(s) => s,
+                            Err(_) => return,
+                        },
+                        _ => return,
+                    },
+                    None => return,
+                };
+                let ips_item_id = match row.get(1) {
+                    Some(title) => match title {
+                        my::Value::Int(i) => i,
+                        _ => return,
+                    },
+                    None => return,
+                };
+                let title = Title::new_from_full(&full_page_title, &api);
+                let tmp_entry = PageListEntry::new(title);
+                let mut entry = match result.entries().read().unwrap().get(&tmp_entry) {
+                    Some(e) => (*e).clone(),
+                    None => return,
+                };
+
+                let q = "Q".to_string() + &ips_item_id.to_string();
+                entry.set_wikidata_item(Some(q));
 
+                result.add_entry(entry).unwrap_or(());
+            });
+        Ok(())
+
+        /*
+        // THIS WOULD BE NICE BUT page_props HAS DAYS OF DATA LAG OR IS FAULTY
         // Batches
-        let batches: Vec<SQLtuple> = result
-            .to_sql_batches(PAGE_BATCH_SIZE)?
-            .par_iter_mut()
-            .map(|sql_batch| {
-                sql_batch.0 = sql.0.to_owned() + &sql_batch.0 + &sql_post;
-                sql_batch.1.splice(..0, sql.1.to_owned());
-                sql_batch.to_owned()
+        let batches: Vec<SQLtuple> = result.to_sql_batches(PAGE_BATCH_SIZE)
+            .iter_mut()
+            .map(|sql|{
+                sql.0 = "SELECT page_title,page_namespace,pp_value FROM page_props,page WHERE page_id=pp_page AND pp_propname='wikibase_item' AND ".to_owned()+&sql.0;
+                sql.to_owned()
             })
             .collect::<Vec<SQLtuple>>();
 
-        result.clear_entries()?;
-        result.process_batch_results(&self.state(), batches, &|row: my::Row| {
-            let (page_title, _sitelinks_count) = my::from_row::<(String, usize)>(row);
-            Some(PageListEntry::new(Title::new(&page_title, 0)))
-        })?;
+        result.annotate_batch_results(
+            self.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| {
+                let (_page_title, _page_namespace, pp_value) =
+                    my::from_row::<(String, NamespaceID, String)>(row);
+                entry.wikidata_item = Some(pp_value);
+            },
+        )
+        */
+    }
 
-        match old_wiki {
-            Some(wiki) => result.convert_to_wiki(&wiki, &self)?,
-            None => {}
+    /// For "sortby=wd:Pxxx", loads the value of that property for the item of each entry
+    fn load_wikidata_sort_values(&self, result: &PageList) -> Result<(), String> {
+        let properties: Vec<String> = PageListSort::sort_key_names(&self.get_param_blank("sortby"))
+            .iter()
+            .filter_map(|key| PageListSort::wikidata_property_from_param(key))
+            .collect();
+        if properties.is_empty() || result.is_empty()? {
+            return Ok(());
         }
+        let is_wikidata = result.is_wikidata();
+        let items: Vec<String> = result
+            .entries()
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .filter_map(|entry| match is_wikidata {
+                true => Some(entry.title().with_underscores().to_string()),
+                false => entry.get_wikidata_item(),
+            })
+            .collect();
+
+        let api = self.state.get_api_for_wiki("wikidatawiki".to_string())?;
+        // (item, property) => value
+        let values: Mutex<HashMap<(String, String), WikidataSortValue>> =
+            Mutex::new(HashMap::new());
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(MAX_WIKI_API_THREADS)
+            .build()
+            .map_err(|e| {
+                format!(
+                    "Platform::load_wikidata_sort_values: Can't build ThreadPool: {:?}",
+                    e
+                )
+            })?
+            .install(|| {
+                items
+                    .par_chunks(WBGETENTITIES_BATCH_SIZE)
+                    .map(|chunk| {
+                        let params = api.params_into(&vec![
+                            ("action", "wbgetentities"),
+                            ("props", "claims"),
+                            ("ids", chunk.join("|").as_str()),
+                        ]);
+                        let j = api
+                            .get_query_api_json(&params)
+                            .map_err(|e| format!("Platform::load_wikidata_sort_values: {:?}", e))?;
+                        let mut values = values.lock().map_err(|e| format!("{:?}", e))?;
+                        chunk.iter().for_each(|item| {
+                            properties.iter().for_each(|property| {
+                                match WikidataSortValue::new_from_claims(
+                                    &j["entities"][item]["claims"][property],
+                                ) {
+                                    Some(value) => {
+                                        values.insert(
+                                            (item.to_string(), property.to_string()),
+                                            value,
+                                        );
+                                    }
+                                    None => {}
+                                }
+                            })
+                        });
+                        Ok(())
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+            })?;
+
+        let values = values.lock().map_err(|e| format!("{:?}", e))?;
+        let mut entries = result.entries().write().map_err(|e| format!("{:?}", e))?;
+        *entries = entries
+            .drain()
+            .map(|mut entry| {
+                let item = match is_wikidata {
+                    true => Some(entry.title().with_underscores().to_string()),
+                    false => entry.get_wikidata_item(),
+                };
+                properties.iter().for_each(|property| {
+                    let value = match &item {
+                        Some(item) => values
+                            .get(&(item.to_string(), property.to_string()))
+                            .cloned(),
+                        None => None,
+                    };
+                    entry.set_wikidata_sort_value(property, value);
+                });
+                entry
+            })
+            .collect();
         Ok(())
     }
 
-    fn filter_wikidata(&self, result: &PageList) -> Result<(), String> {
+    /// For "sortby=pageviews" or "add_pageviews", loads the user pageviews of each page over the last
+    /// "pageviews_days" days from the Wikimedia REST API, one request per page. Only the first
+    /// MAX_PAGEVIEWS_PAGES pages (by title) are looked up. Pages without data keep `None`.
+    fn load_pageviews(&self, result: &PageList) -> Result<(), String> {
+        if !self.sorts_by("pageviews") && !self.has_param("add_pageviews") {
+            return Ok(());
+        }
         if result.is_empty()? {
             return Ok(());
         }
-        let no_statements = self.has_param("wpiu_no_statements");
-        let no_sitelinks = self.has_param("wpiu_no_sitelinks");
-        let wpiu = self.get_param_default("wpiu", "any");
-        let list = self.get_param_blank("wikidata_prop_item_use");
-        let list = list.trim();
-        if list.is_empty() && !no_statements && !no_sitelinks {
+        let days = self
+            .usize_option_from_param("pageviews_days")
+            .unwrap_or(DEFAULT_PAGEVIEWS_DAYS)
+            .max(1);
+        let wiki = result
+            .wiki()?
+            .ok_or(format!("Platform::load_pageviews: result has no wiki"))?;
+        let project = self
+            .state
+            .get_server_url_for_wiki(&wiki)?
+            .replace("https://", "")
+            .replace("http://", "");
+        let api = self.state.get_api_for_wiki(wiki.to_string())?;
+        let end = Utc::now() - chrono::Duration::days(1);
+        let start = end - chrono::Duration::days(days as i64 - 1);
+        let mut titles: Vec<((NamespaceID, String), String)> = result
+            .entries()
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .filter_map(|entry| {
+                let full_title = entry.title().full_pretty(&api)?.replace(" ", "_");
+                let key = (
+                    entry.title().namespace_id(),
+                    entry.title().with_underscores(),
+                );
+                Some((key, full_title))
+            })
+            .collect();
+        if titles.len() > MAX_PAGEVIEWS_PAGES {
+            self.warn(format!(
+                "Pageviews were only loaded for {} of {} pages",
+                MAX_PAGEVIEWS_PAGES,
+                titles.len()
+            ))?;
+            titles.sort();
+            titles.truncate(MAX_PAGEVIEWS_PAGES);
+        }
+
+        let pageviews: HashMap<(NamespaceID, String), u64> = rayon::ThreadPoolBuilder::new()
+            .num_threads(MAX_WIKI_API_THREADS)
+            .build()
+            .map_err(|e| format!("Platform::load_pageviews: Can't build ThreadPool: {:?}", e))?
+            .install(|| {
+                titles
+                    .par_iter()
+                    .filter_map(|(key, full_title)| {
+                        let url = format!(
+                            "https://wikimedia.org/api/rest_v1/metrics/pageviews/per-article/{}/all-access/user/{}/daily/{}/{}",
+                            project,
+                            utf8_percent_encode(full_title, NON_ALPHANUMERIC),
+                            start.format("%Y%m%d00"),
+                            end.format("%Y%m%d00")
+                        );
+                        // Other failures leave the count unknown
+                        let response = api
+                            .client()
+                            .get(&url)
+                            .header(reqwest::header::USER_AGENT, PAGEVIEWS_USER_AGENT)
+                            .send()
+                            .ok()?;
+                        let status = response.status();
+                        let text = response.text().ok()?;
+                        let views = Self::pageviews_from_response(status, &text)?;
+                        Some((key.to_owned(), views))
+                    })
+                    .collect()
+            });
+
+        let mut entries = result.entries().write().map_err(|e| format!("{:?}", e))?;
+        *entries = entries
+            .drain()
+            .map(|mut entry| {
+                let key = (
+                    entry.title().namespace_id(),
+                    entry.title().with_underscores(),
+                );
+                entry.pageviews = pageviews.get(&key).cloned();
+                entry
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Sums the daily views in a pageviews API response.
+    /// The API returns 404 for pages without any views in the time range.
+    fn pageviews_from_response(status: reqwest::StatusCode, text: &str) -> Option<u64> {
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Some(0);
+        }
+        if !status.is_success() {
+            return None;
+        }
+        let j: Value = serde_json::from_str(text).ok()?;
+        Some(
+            j["items"]
+                .as_array()?
+                .iter()
+                .filter_map(|item| item["views"].as_u64())
+                .sum(),
+        )
+    }
+
+    /// Filters on whether a page has a Wikidata item, depending on the "wikidata_item"
+    fn process_by_wikidata_item(&self, result: &PageList) -> Result<(), String> {
+        if result.is_wikidata() {
             return Ok(());
         }
-        let original_wiki = result.wiki()?;
-        Platform::profile(
-            "before filter_wikidata:convert_to_wiki",
-            Some(result.len()?),
-        );
-        result.convert_to_wiki("wikidatawiki", &self)?;
-        Platform::profile("after filter_wikidata:convert_to_wiki", Some(result.len()?));
-        if result.is_empty()? {
-            match original_wiki {
-                Some(wiki) => result.convert_to_wiki(&wiki, &self)?,
-                None => {}
-            }
+        let wdi = self.get_param_default("wikidata_item", "no");
+        if wdi != "any" && wdi != "with" && wdi != "without" {
             return Ok(());
         }
-        // For all/any/none
-        let parts = list
-            .split_terminator(',')
-            .filter_map(|s| match s.chars().nth(0) {
-                Some('Q') => Some((
-                    "(SELECT * FROM pagelinks WHERE pl_from=page_id AND pl_namespace=0 AND pl_title=?)".to_string(),
-                    vec![s.to_string()],
-                )),
-                Some('P') => Some((
-                    "(SELECT * FROM pagelinks WHERE pl_from=page_id AND pl_namespace=120 AND pl_title=?)".to_string(),
-                    vec![s.to_string()],
-                )),
-                _ => None,
+        self.annotate_with_wikidata_item(result)?;
+        if wdi == "with" {
+            result.retain_entries(&|entry| entry.get_wikidata_item().is_some())?;
+        }
+        if wdi == "without" {
+            result.retain_entries(&|entry| entry.get_wikidata_item().is_none())?;
+        }
+        Ok(())
+    }
+
+    /// For "unlinked_with_langlinks", keeps pages that have no Wikidata item but do have
+    /// old-style interlanguage links; these should usually be connected to an item
+    fn process_unlinked_with_langlinks(&self, result: &PageList) -> Result<(), String> {
+        if !self.has_param("unlinked_with_langlinks") || result.is_wikidata() {
+            return Ok(());
+        }
+        self.annotate_with_wikidata_item(result)?;
+        result.retain_entries(&|entry| entry.get_wikidata_item().is_none())?;
+        self.annotate_langlink_counts(result)?;
+        result.retain_entries(&|entry| entry.langlink_count.unwrap_or(0) > 0)
+    }
+
+    /// Compares page titles to the label of their Wikidata item, in "title_label_language"
+    /// (default: the language of the wiki). "title_label_match=matches" keeps pages whose title
+    /// is the same as the label, "=differs" keeps those where it is not. Pages without an item,
+    /// or with an item without a label in that language, are removed either way.
+    /// The label is kept in the "item_label" column.
+    fn process_title_label_match(&self, result: &PageList) -> Result<(), String> {
+        let keep_matches = match self.get_param_blank("title_label_match").as_str() {
+            "" => return Ok(()),
+            "matches" => true,
+            "differs" => false,
+            other => return Err(format!("Unknown title_label_match '{}'", other)),
+        };
+        if result.is_wikidata() {
+            return Ok(());
+        }
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => return Ok(()),
+        };
+        let api = self.state().get_api_for_wiki(wiki)?;
+        let language = match self.get_param("title_label_language") {
+            Some(language) => language,
+            None => match api.get_site_info()["query"]["general"]["lang"].as_str() {
+                Some(language) => language.to_string(),
+                None => return Err(format!("Can not determine the language of the wiki")),
+            },
+        };
+
+        self.annotate_with_wikidata_item(result)?;
+        let item_list = PageList::new_from_wiki("wikidatawiki");
+        item_list.set_entries(
+            result
+                .entries()
+                .read()
+                .map_err(|e| format!("{:?}", e))?
+                .iter()
+                .filter_map(|entry| entry.get_wikidata_item())
+                .map(|q| PageListEntry::new(Title::new(&q, 0)))
+                .collect(),
+        )?;
+        item_list.add_wikidata_labels_for_namespace(0, "item", &language, self)?;
+        let labels: HashMap<String, String> = item_list
+            .entries()
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .filter_map(|entry| {
+                Some((
+                    entry.title().pretty().to_string(),
+                    entry.get_wikidata_label()?,
+                ))
             })
-            .collect::<Vec<SQLtuple>>();
+            .collect();
+
+        let entries: HashSet<PageListEntry> = result
+            .entries()
+            .write()
+            .map_err(|e| format!("{:?}", e))?
+            .drain()
+            .filter_map(|mut entry| {
+                let label = labels.get(&entry.get_wikidata_item()?)?;
+                // Labels are often lower-case, titles are not
+                if (Platform::normalize_title(label, &api) == *entry.title()) != keep_matches {
+                    return None;
+                }
+                entry.set_extra("item_label", label);
+                Some(entry)
+            })
+            .collect();
+        result.set_entries(entries)?;
+        Ok(())
+    }
+
+    /// Keeps pages with a number of distinct editors between "min_editors" and "max_editors"
+    fn process_editor_count(&self, result: &PageList) -> Result<(), String> {
+        let min_editors = self.usize_option_from_param("min_editors");
+        let max_editors = self.usize_option_from_param("max_editors");
+        if (min_editors.is_none() && max_editors.is_none())
+            || result.is_wikidata()
+            || result.is_empty()?
+        {
+            return Ok(());
+        }
+        // Counting distinct actors over all revisions is expensive, so use smaller batches
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE / 10)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace,(SELECT count(DISTINCT rev_actor) FROM revision WHERE rev_page=page_id) AS cnt FROM page WHERE ".to_string() + &sql.0;
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| {
+                let (_page_title, _page_namespace, editor_count) =
+                    my::from_row::<(String, NamespaceID, u32)>(row);
+                entry.editor_count = Some(editor_count);
+            },
+        )?;
+        result.retain_entries(&|entry| match entry.editor_count {
+            Some(editor_count) => {
+                min_editors.map_or(true, |min| editor_count as usize >= min)
+                    && max_editors.map_or(true, |max| editor_count as usize <= max)
+            }
+            None => false,
+        })
+    }
+
+    /// Keeps pages whose latest edit is ("latest_edit_revert=yes") or is not ("=no") a revert,
+    /// as marked by one of the REVERT_CHANGE_TAGS. The tags are kept in the "revert_tags" column.
+    /// Wikis that do not use any of these tags are not filtered.
+    fn process_latest_edit_revert(&self, result: &PageList) -> Result<(), String> {
+        let keep_reverts = match self.get_param_blank("latest_edit_revert").as_str() {
+            "" | "both" => return Ok(()),
+            "yes" => true,
+            "no" => false,
+            other => return Err(format!("Unknown latest_edit_revert '{}'", other)),
+        };
+        if result.is_empty()? {
+            return Ok(());
+        }
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => return Ok(()),
+        };
+
+        let tag_ids: Vec<String> = {
+            let db_user_pass = self
+                .state
+                .get_db_mutex()
+                .lock()
+                .map_err(|e| format!("{:?}", e))?;
+            let mut conn = self.state.get_wiki_db_connection(&db_user_pass, &wiki)?;
+            let tags: Vec<String> = REVERT_CHANGE_TAGS.iter().map(|s| s.to_string()).collect();
+            let sql = format!(
+                "SELECT ctd_id FROM change_tag_def WHERE ctd_name IN ({})",
+                Platform::get_questionmarks(tags.len())
+            );
+            conn.prep_exec(sql, tags)
+                .map_err(|e| format!("{:?}", e))?
+                .filter_map(|row| row.ok())
+                .map(|row| my::from_row::<u32>(row).to_string())
+                .collect()
+        };
+        if tag_ids.is_empty() {
+            return Ok(());
+        }
+
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = format!("SELECT page_title,page_namespace,GROUP_CONCAT(ctd_name SEPARATOR '|') FROM page,change_tag,change_tag_def WHERE ct_rev_id=page_latest AND ctd_id=ct_tag_id AND ct_tag_id IN ({}) AND {} GROUP BY page_id",tag_ids.join(","),&sql.0);
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| {
+                let (_page_title, _page_namespace, tags) =
+                    my::from_row::<(String, NamespaceID, String)>(row);
+                entry.set_extra("revert_tags", &tags);
+            },
+        )?;
+        result.retain_entries(&|entry| entry.get_extra("revert_tags").is_some() == keep_reverts)
+    }
+
+    /// Keeps pages with a number of interlanguage links between "min_langlinks" and "max_langlinks"
+    fn process_langlink_count(&self, result: &PageList) -> Result<(), String> {
+        let min_langlinks = self.usize_option_from_param("min_langlinks");
+        let max_langlinks = self.usize_option_from_param("max_langlinks");
+        if (min_langlinks.is_none() && max_langlinks.is_none()) || result.is_wikidata() {
+            return Ok(());
+        }
+        self.annotate_langlink_counts(result)?;
+        result.retain_entries(&|entry| match entry.langlink_count {
+            Some(langlink_count) => {
+                min_langlinks.map_or(true, |min| langlink_count as usize >= min)
+                    && max_langlinks.map_or(true, |max| langlink_count as usize <= max)
+            }
+            None => false,
+        })
+    }
+
+    /// Sets the number of interlanguage links from the `langlinks` table for each page
+    fn annotate_langlink_counts(&self, result: &PageList) -> Result<(), String> {
+        if result.is_wikidata() || result.is_empty()? {
+            return Ok(());
+        }
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql| {
+                sql.0 = "SELECT page_title,page_namespace,(SELECT count(*) FROM langlinks WHERE ll_from=page_id) AS cnt FROM page WHERE ".to_string() + &sql.0;
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
+        result.annotate_batch_results(
+            &self.state(),
+            batches,
+            0,
+            1,
+            &|row: my::Row, entry: &mut PageListEntry| match my::from_row_opt::<(
+                Vec<u8>,
+                NamespaceID,
+                LinkCount,
+            )>(row)
+            {
+                Ok((_page_title, _page_namespace, langlink_count)) => {
+                    entry.langlink_count = Some(langlink_count)
+                }
+                Err(_e) => {}
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Filters Wikidata results on entity type (item/property/lexeme), depending on "wikidata_entity_type"
+    fn process_by_wikidata_entity_type(&self, result: &PageList) -> Result<(), String> {
+        let namespace_id: NamespaceID = match self.get_param_blank("wikidata_entity_type").as_str()
+        {
+            "" | "any" => return Ok(()),
+            "item" => 0,
+            "property" => 120,
+            "lexeme" => 146,
+            other => return Err(format!("Unknown wikidata_entity_type '{}'", other)),
+        };
+        if !result.is_wikidata() {
+            return Ok(());
+        }
+        result.retain_entries(&|entry| entry.title().namespace_id() == namespace_id)?;
+        Ok(())
+    }
+
+    /// Returns the Wikidata item to validate for an entry, if any
+    fn entityschema_entity(entry: &PageListEntry, is_wikidata: bool) -> Option<String> {
+        if is_wikidata {
+            match entry.title().namespace_id() {
+                0 => Some(entry.title().pretty().to_string()),
+                _ => None,
+            }
+        } else {
+            entry.get_wikidata_item()
+        }
+    }
+
+    /// Keeps only entries whose Wikidata item conforms to the EntitySchema in "validate_entityschema".
+    /// If the validator is unavailable, this is a no-op with a warning. Entries whose item could
+    /// not be validated are kept, with an unknown conformance, and a warning.
+    fn process_entityschema(&self, result: &PageList) -> Result<(), String> {
+        lazy_static! {
+            static ref RE_ENTITYSCHEMA: Regex =
+                Regex::new(r#"^E\d+$"#).expect("Platform::process_entityschema: Regex is invalid");
+        }
+        let entityschema = match self.get_param("validate_entityschema") {
+            Some(e) => e.trim().to_uppercase(),
+            None => return Ok(()),
+        };
+        if !RE_ENTITYSCHEMA.is_match(&entityschema) {
+            return Err(format!("Invalid EntitySchema ID '{}'", &entityschema));
+        }
+        if self.state.get_entityschema_validator().is_none() {
+            self.warn(format!("<span tt='warn_entityschema_unavailable'></span>"))?;
+            return Ok(());
+        }
+        let is_wikidata = result.is_wikidata();
+        let entities: Vec<String> = result
+            .entries()
+            .read()
+            .map_err(|e| format!("{:?}", e))?
+            .iter()
+            .filter_map(|entry| Self::entityschema_entity(entry, is_wikidata))
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+        if entities.is_empty() {
+            return Ok(());
+        }
+        let conformance: HashMap<String, Option<bool>> = entities
+            .chunks(ENTITYSCHEMA_BATCH_SIZE)
+            .flat_map(|batch| {
+                batch
+                    .par_iter()
+                    .map(|entity| {
+                        let valid = self
+                            .state
+                            .get_entityschema_conformance(&entityschema, entity);
+                        (entity.to_owned(), valid)
+                    })
+                    .collect::<Vec<(String, Option<bool>)>>()
+            })
+            .collect();
+        let failed = conformance.values().filter(|valid| valid.is_none()).count();
+        if failed == conformance.len() {
+            self.warn(format!("<span tt='warn_entityschema_unavailable'></span>"))?;
+            return Ok(());
+        }
+        if failed > 0 {
+            self.warn(format!(
+                "<span tt='warn_entityschema_failed' num='{}'></span>",
+                failed
+            ))?;
+        }
+
+        let mut entries = result.entries().write().map_err(|e| format!("{:?}", e))?;
+        *entries = entries
+            .drain()
+            .filter_map(|mut entry| {
+                let entity = Self::entityschema_entity(&entry, is_wikidata)?;
+                match conformance.get(&entity)? {
+                    Some(true) => entry.entityschema_conforms = TriState::Yes,
+                    Some(false) => return None,
+                    None => entry.entityschema_conforms = TriState::Unknown,
+                }
+                Some(entry)
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Adds page properties that might be missing if none of the original sources was "categories"
+    fn process_missing_database_filters(&self, result: &PageList) -> Result<(), String> {
+        let mut params = SourceDatabaseParameters::db_params(self);
+        params.set_wiki(Some(result.wiki()?.ok_or(format!(
+            "Platform::process_missing_database_filters: result has no wiki"
+        ))?));
+        let mut db = SourceDatabase::new(params);
+        let new_result = db.get_pages(&self.state, Some(result))?;
+        result.set_from(new_result)?;
+        Ok(())
+    }
+
+    //________________________________________________________________________________________________
+
+    fn get_label_sql_helper(&self, ret: &mut SQLtuple, part1: &str) {
+        let mut wbt_type: Vec<String> = vec![];
+        if self.has_param(&("cb_labels_".to_owned() + part1 + "_l")) {
+            wbt_type.push("1".to_string());
+        }
+        if self.has_param(&("cb_labels_".to_owned() + part1 + "_a")) {
+            wbt_type.push("3".to_string());
+        }
+        if self.has_param(&("cb_labels_".to_owned() + part1 + "_d")) {
+            wbt_type.push("2".to_string());
+        }
+        if !wbt_type.is_empty() {
+            if wbt_type.len() == 1 {
+                ret.0 += &format!(" AND wbtl_type_id={}", wbt_type.join(","));
+            } else {
+                ret.0 += &format!(" AND wbtl_type_id IN ({})", wbt_type.join(","));
+            }
+        }
+    }
+
+    /// The LIKE condition for a label pattern. `wbx_text` is binary, so matching is case-sensitive.
+    /// By default, matching is case-insensitive: the literal start of the pattern is matched
+    /// against all case variants of its first LABEL_PREFIX_CHARS characters, which can use the
+    /// index, and only those rows are converted to a case-insensitive collation. Unlike in the
+    /// rest of the pattern, accents in these first characters need to match.
+    fn get_label_text_condition(&self, column: &str, pattern: &str) -> SQLtuple {
+        if self.has_param("labels_case_sensitive") {
+            return (format!("{} LIKE ?", column), vec![pattern.to_string()]);
+        }
+        let mut ret: SQLtuple = ("".to_string(), vec![]);
+        let prefixes = Self::case_variants_of_prefix(pattern, LABEL_PREFIX_CHARS);
+        if !prefixes.is_empty() {
+            ret.0 = format!(
+                "({}) AND ",
+                vec![format!("{} LIKE ?", column); prefixes.len()].join(" OR ")
+            );
+            ret.1 = prefixes
+                .iter()
+                .map(|prefix| prefix.to_owned() + "%")
+                .collect();
+        }
+        ret.0 += &format!(
+            "CONVERT({} USING utf8mb4) COLLATE utf8mb4_general_ci LIKE ?",
+            column
+        );
+        ret.1.push(pattern.to_string());
+        ret
+    }
+
+    /// All upper/lower case combinations of the first `max_chars` characters of a LIKE pattern,
+    /// up to the first wildcard or escape character
+    fn case_variants_of_prefix(pattern: &str, max_chars: usize) -> Vec<String> {
+        let mut ret: Vec<String> = vec![];
+        for c in pattern
+            .chars()
+            .take_while(|c| !['%', '_', '\\'].contains(c))
+            .take(max_chars)
+        {
+            let mut cases = vec![c.to_lowercase().to_string(), c.to_uppercase().to_string()];
+            cases.dedup();
+            ret = match ret.is_empty() {
+                true => cases,
+                false => ret
+                    .iter()
+                    .flat_map(|prefix| cases.iter().map(move |case| prefix.to_owned() + case))
+                    .collect(),
+            };
+        }
+        ret.dedup();
+        ret
+    }
+
+    /// Entity prefix, terms table, entity ID column, and term_in_lang ID column for a Wikidata namespace
+    fn get_label_terms_table(
+        namespace_id: NamespaceID,
+    ) -> Option<(&'static str, &'static str, &'static str, &'static str)> {
+        match namespace_id {
+            0 => Some((
+                "Q",
+                "wbt_item_terms",
+                "wbit_item_id",
+                "wbit_term_in_lang_id",
+            )),
+            120 => Some((
+                "P",
+                "wbt_property_terms",
+                "wbpt_property_id",
+                "wbpt_term_in_lang_id",
+            )),
+            _ => None,
+        }
+    }
+
+    fn get_label_sql_subquery(
+        &self,
+        ret: &mut SQLtuple,
+        terms: (&str, &str, &str, &str),
+        key: &str,
+        languages: &Vec<String>,
+        s: &String,
+    ) {
+        let (_, table, id_column, term_column) = terms;
+        let has_pattern = !s.is_empty() && s != "%";
+        let has_languages = !languages.is_empty();
+        ret.0 += &format!("SELECT * FROM wbt_term_in_lang,{} t2", table);
+        if has_languages || has_pattern {
+            ret.0 += ",wbt_text_in_lang";
+        }
+        if has_pattern {
+            ret.0 += ",wbt_text";
+        }
+        ret.0 += &format!(
+            " WHERE t2.{}=t1.{} AND wbtl_id=t2.{}",
+            id_column, id_column, term_column
+        );
+        self.get_label_sql_helper(ret, key);
+        if has_languages || has_pattern {
+            let mut tmp = Self::prep_quote(&languages);
+            ret.0 += " AND wbtl_text_in_lang_id=wbxl_id";
+            if !tmp.1.is_empty() {
+                if tmp.1.len() == 1 {
+                    ret.0 += &(" AND wbxl_language=".to_owned() + &tmp.0);
+                } else {
+                    ret.0 += &(" AND wbxl_language IN (".to_owned() + &tmp.0 + ")");
+                }
+                ret.1.append(&mut tmp.1);
+            }
+            if has_pattern {
+                let mut condition = self.get_label_text_condition("wbx_text", s);
+                ret.0 += " AND wbxl_text_id=wbx_id AND ";
+                ret.0 += &condition.0;
+                ret.1.append(&mut condition.1);
+            }
+        }
+    }
+
+    /// SQL for the entities in the namespace that match the label filters, using the wbt_* term tables
+    pub fn get_label_sql(&self, namespace_id: &NamespaceID) -> Option<SQLtuple> {
+        let mut ret: SQLtuple = ("".to_string(), vec![]);
+        let yes = self.get_param_as_vec("labels_yes", "\n");
+        let any = self.get_param_as_vec("labels_any", "\n");
+        let no = self.get_param_as_vec("labels_no", "\n");
+        if yes.len() + any.len() + no.len() == 0 {
+            return None;
+        }
+
+        let langs_yes = self.get_param_as_vec("langs_labels_yes", ",");
+        let langs_any = self.get_param_as_vec("langs_labels_any", ",");
+        let langs_no = self.get_param_as_vec("langs_labels_no", ",");
+
+        let terms = Self::get_label_terms_table(*namespace_id)?;
+        let (prefix, table, id_column, _) = terms;
+        ret.0 = format!(
+            "SELECT DISTINCT CONCAT('{}',{}) AS term_full_entity_id FROM {} t1 WHERE 1=1",
+            prefix, id_column, table
+        );
+
+        yes.iter().for_each(|s| {
+            ret.0 += " AND EXISTS (";
+            self.get_label_sql_subquery(&mut ret, terms, "yes", &langs_yes, s);
+            ret.0 += ")";
+        });
+
+        if !langs_any.is_empty() {
+            ret.0 += " AND (0=1";
+            any.iter().for_each(|s| {
+                ret.0 += " OR EXISTS (";
+                self.get_label_sql_subquery(&mut ret, terms, "any", &langs_any, s);
+                ret.0 += ")";
+            });
+            ret.0 += ")";
+        }
+
+        no.iter().for_each(|s| {
+            ret.0 += " AND NOT EXISTS (";
+            self.get_label_sql_subquery(&mut ret, terms, "no", &langs_no, s);
+            ret.0 += ")";
+        });
+        Some(ret)
+    }
+
+    fn process_labels(&self, result: &PageList) -> Result<(), String> {
+        if self.get_label_sql(&0).is_none() {
+            return Ok(());
+        }
+        result.convert_to_wiki("wikidatawiki", &self)?;
+        if result.is_empty()? {
+            return Ok(());
+        }
+
+        // Batches
+        let batches: Vec<SQLtuple> = result
+            .group_by_namespace()?
+            .par_iter()
+            .filter_map(|(namespace_id, titles)| {
+                let mut sql = self.get_label_sql(namespace_id)?;
+                let (_, _, id_column, _) = Self::get_label_terms_table(*namespace_id)?;
+                sql.0 += &format!(" AND {} IN (", id_column);
+                sql.0 += &titles
+                    .par_iter()
+                    .map(|title| title[1..].to_string())
+                    .collect::<Vec<String>>()
+                    .join(",");
+                sql.0 += ")";
+                Some(sql)
+            })
+            .collect();
+
+        result.clear_entries()?;
+        result.process_batch_results(&self.state(), batches, &|row: my::Row| {
+            let term_full_entity_id = my::from_row::<String>(row);
+            self.entry_from_entity(&term_full_entity_id)
+        })?;
+        Ok(())
+    }
+
+    fn process_sitelinks(&self, result: &PageList) -> Result<(), String> {
+        if result.is_empty()? {
+            return Ok(());
+        }
+
+        let sitelinks_yes = self.get_param_as_vec("sitelinks_yes", "\n");
+        let sitelinks_any = self.get_param_as_vec("sitelinks_any", "\n");
+        let sitelinks_no = self.get_param_as_vec("sitelinks_no", "\n");
+        let sitelinks_min = self.get_param_blank("min_sitelink_count");
+        let sitelinks_max = self.get_param_blank("max_sitelink_count");
+
+        if sitelinks_yes.is_empty()
+            && sitelinks_any.is_empty()
+            && sitelinks_no.is_empty()
+            && sitelinks_min.is_empty()
+            && sitelinks_max.is_empty()
+        {
+            return Ok(());
+        }
+        let old_wiki = result.wiki()?.to_owned();
+        result.convert_to_wiki("wikidatawiki", &self)?;
+        if result.is_empty()? {
+            return Ok(());
+        }
+
+        let use_min_max = !sitelinks_min.is_empty() || !sitelinks_max.is_empty();
+
+        let mut sql: SQLtuple = ("".to_string(), vec![]);
+        sql.0 += "SELECT ";
+        if use_min_max {
+            sql.0 += "page_title,(SELECT count(*) FROM wb_items_per_site WHERE ips_item_id=substr(page_title,2)*1) AS sitelink_count" ;
+        } else {
+            sql.0 += "DISTINCT page_title,0";
+        }
+        sql.0 += " FROM page WHERE page_namespace=0";
+
+        sitelinks_yes.iter().for_each(|site|{
+            sql.0 += " AND EXISTS (SELECT * FROM wb_items_per_site WHERE ips_item_id=substr(page_title,2)*1 AND ips_site_id=? LIMIT 1)" ;
+            sql.1.push(site.to_string());
+        });
+        if !sitelinks_any.is_empty() {
+            sql.0 += " AND EXISTS (SELECT * FROM wb_items_per_site WHERE ips_item_id=substr(page_title,2)*1 AND ips_site_id IN (" ;
+            let tmp = Platform::prep_quote(&sitelinks_any);
+            Platform::append_sql(&mut sql, tmp);
+            sql.0 += ") LIMIT 1)";
+        }
+        sitelinks_no.iter().for_each(|site|{
+            sql.0 += " AND NOT EXISTS (SELECT * FROM wb_items_per_site WHERE ips_item_id=substr(page_title,2)*1 AND ips_site_id=? LIMIT 1)" ;
+            sql.1.push(site.to_string());
+        });
+        sql.0 += " AND ";
+
+        let mut having: Vec<String> = vec![];
+        match sitelinks_min.parse::<usize>() {
+            Ok(s) => having.push(format!("sitelink_count>={}", s)),
+            _ => {}
+        }
+        match sitelinks_max.parse::<usize>() {
+            Ok(s) => having.push(format!("sitelink_count<={}", s)),
+            _ => {}
+        }
+
+        let mut sql_post = "".to_string();
+        if use_min_max {
+            sql_post += " GROUP BY page_title";
+        }
+        if !having.is_empty() {
+            sql_post += " HAVING ";
+            sql_post += &having.join(" AND ");
+        }
+
+        // Batches
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .par_iter_mut()
+            .map(|sql_batch| {
+                sql_batch.0 = sql.0.to_owned() + &sql_batch.0 + &sql_post;
+                sql_batch.1.splice(..0, sql.1.to_owned());
+                sql_batch.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
+
+        result.clear_entries()?;
+        result.process_batch_results(&self.state(), batches, &|row: my::Row| {
+            let (page_title, _sitelinks_count) = my::from_row::<(String, usize)>(row);
+            Some(PageListEntry::new(Title::new(&page_title, 0)))
+        })?;
+
+        match old_wiki {
+            Some(wiki) => result.convert_to_wiki(&wiki, &self)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn filter_wikidata(&self, result: &PageList) -> Result<(), String> {
+        if result.is_empty()? {
+            return Ok(());
+        }
+        let no_statements = self.has_param("wpiu_no_statements");
+        let no_sitelinks = self.has_param("wpiu_no_sitelinks");
+        let wpiu = self.get_param_default("wpiu", "any");
+        let list = self.get_param_blank("wikidata_prop_item_use");
+        let list = list.trim();
+        let statements = self.wikidata_statements()?;
+        if list.is_empty() && statements.is_empty() && !no_statements && !no_sitelinks {
+            return Ok(());
+        }
+        let original_wiki = result.wiki()?;
+        Platform::profile(
+            "before filter_wikidata:convert_to_wiki",
+            Some(result.len()?),
+        );
+        result.convert_to_wiki("wikidatawiki", &self)?;
+        Platform::profile("after filter_wikidata:convert_to_wiki", Some(result.len()?));
+        if result.is_empty()? {
+            match original_wiki {
+                Some(wiki) => result.convert_to_wiki(&wiki, &self)?,
+                None => {}
+            }
+            return Ok(());
+        }
+        // For all/any/none
+        let parts = list
+            .split_terminator(',')
+            .filter_map(|s| match s.chars().nth(0) {
+                Some('Q') => Some((
+                    "(SELECT * FROM pagelinks WHERE pl_from=page_id AND pl_namespace=0 AND pl_title=?)".to_string(),
+                    vec![s.to_string()],
+                )),
+                Some('P') => Some((
+                    "(SELECT * FROM pagelinks WHERE pl_from=page_id AND pl_namespace=120 AND pl_title=?)".to_string(),
+                    vec![s.to_string()],
+                )),
+                _ => None,
+            })
+            .collect::<Vec<SQLtuple>>();
+
+        // Statements can not be checked in the database, so find the items that have them first
+        let items: Vec<String> = result.with_entries(|entries| {
+            entries
+                .iter()
+                .filter(|entry| entry.title().namespace_id() == 0)
+                .map(|entry| entry.title().pretty().to_string())
+                .collect()
+        })?;
+        let statement_matches = statements
+            .iter()
+            .map(|(property, value)| self.items_with_statement(&items, property, value))
+            .collect::<Result<Vec<HashSet<String>>, String>>()?;
+
+        let mut sql_post: SQLtuple = ("".to_string(), vec![]);
+        if no_statements {
+            sql_post.0 += " AND EXISTS (SELECT * FROM page_props WHERE page_id=pp_page AND pp_propname='wb-claims' AND pp_sortkey=0)" ;
+        }
+        if no_sitelinks {
+            sql_post.0 += " AND EXISTS (SELECT * FROM page_props WHERE page_id=pp_page AND pp_propname='wb-sitelinks' AND pp_sortkey=0)" ;
+        }
+
+        // The item use checks are combined into a "parts_ok" column, so they can be
+        // combined with the statement checks below
+        let (mut parts_ok, join) = match wpiu.as_str() {
+            "all" => ("(1".to_string(), " AND EXISTS "),
+            "any" => ("(0".to_string(), " OR EXISTS "),
+            "none" => ("(1".to_string(), " AND NOT EXISTS "),
+            _ => ("(1".to_string(), ""),
+        };
+        let mut parts_params: Vec<String> = vec![];
+        if !join.is_empty() {
+            parts.iter().for_each(|sql| {
+                parts_ok += &(join.to_owned() + &sql.0);
+                parts_params.append(&mut sql.1.to_owned());
+            });
+        }
+        parts_ok += ")";
+        let no_checks = parts.is_empty() && statements.is_empty();
+        let keep = |item: &String, parts_ok: bool| {
+            let mut has_statements = statement_matches.iter().map(|m| m.contains(item));
+            match wpiu.as_str() {
+                "all" => parts_ok && has_statements.all(|b| b),
+                "any" => no_checks || parts_ok || has_statements.any(|b| b),
+                "none" => parts_ok && !has_statements.any(|b| b),
+                _ => true,
+            }
+        };
+
+        // Batches
+        let batches: Vec<SQLtuple> = result
+            .to_sql_batches(PAGE_BATCH_SIZE)?
+            .iter_mut()
+            .map(|sql| {
+                sql.0 = format!(
+                    "SELECT DISTINCT page_title,{} AS parts_ok FROM page WHERE {}{}",
+                    &parts_ok, &sql.0, &sql_post.0
+                );
+                let mut params = parts_params.to_owned();
+                params.append(&mut sql.1);
+                params.append(&mut sql_post.1.to_owned());
+                sql.1 = params;
+                sql.to_owned()
+            })
+            .collect::<Vec<SQLtuple>>();
+
+        result.clear_entries()?;
+        let ret = result.process_batch_results(&self.state(), batches, &|row: my::Row| {
+            let (page_title, parts_ok) = my::from_row_opt::<(String, i64)>(row).ok()?;
+            match keep(&page_title, parts_ok != 0) {
+                true => Some(PageListEntry::new(Title::new(&page_title, 0))),
+                false => None,
+            }
+        });
+        match original_wiki {
+            Some(wiki) => result.convert_to_wiki(&wiki, &self)?,
+            None => {}
+        }
+        Ok(ret?)
+    }
+
+    /// Statements from "wikidata_statements", one per line: "P31=Q5" for a property with
+    /// a specific item value, or "P18=*" for a property with any value, as (property, value)
+    fn wikidata_statements(&self) -> Result<Vec<(String, Option<String>)>, String> {
+        lazy_static! {
+            static ref RE_STATEMENT: Regex = Regex::new(r"^(P\d+)\s*=\s*(Q\d+|\*)$")
+                .expect("Platform::wikidata_statement_parts: Regex is invalid");
+        }
+        self.get_param_blank("wikidata_statements")
+            .split('\n')
+            .map(|line| line.trim().to_uppercase())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let caps = RE_STATEMENT.captures(&line).ok_or(format!(
+                    "wikidata_statements: '{}' is not like 'P31=Q5' or 'P18=*'",
+                    line
+                ))?;
+                let value = match &caps[2] {
+                    "*" => None,
+                    value => Some(value.to_string()),
+                };
+                Ok((caps[1].to_string(), value))
+            })
+            .collect()
+    }
+
+    /// The items among `items` that have a statement (of any rank) for `property`,
+    /// with the item `value` if given. Checked with the Wikidata Query Service, in batches.
+    fn items_with_statement(
+        &self,
+        items: &[String],
+        property: &str,
+        value: &Option<String>,
+    ) -> Result<HashSet<String>, String> {
+        let api = self.state.get_api_for_wiki("wikidatawiki".to_string())?;
+        let pattern = match value {
+            Some(value) => format!("p:{}/ps:{} wd:{}", property, property, value),
+            None => format!("p:{} []", property),
+        };
+        let mut ret = HashSet::new();
+        for chunk in items.chunks(WIKIDATA_STATEMENT_BATCH_SIZE) {
+            let values = chunk
+                .iter()
+                .map(|item| format!("wd:{}", item))
+                .collect::<Vec<String>>()
+                .join(" ");
+            let sparql = format!(
+                "SELECT DISTINCT ?item {{ VALUES ?item {{ {} }} ?item {} }}",
+                values, pattern
+            );
+            let result = api
+                .sparql_query(&sparql)
+                .map_err(|e| format!("wikidata_statements: {}", e))?;
+            ret.extend(api.entities_from_sparql_result(&result, "item"));
+        }
+        Ok(ret)
+    }
+
+    /// Creates an entry for an entity ID, in the namespace registered for its prefix
+    pub fn entry_from_entity(&self, entity: &str) -> Option<PageListEntry> {
+        let namespace_id = self.state.get_entity_namespace(entity)?;
+        Some(PageListEntry::new(Title::new(
+            &entity.to_string(),
+            namespace_id,
+        )))
+    }
+
+    /// Checks if the key is one of the keys in "sortby"
+    pub fn sorts_by(&self, key: &str) -> bool {
+        PageListSort::sort_key_names(&self.get_param_blank("sortby"))
+            .iter()
+            .any(|k| k == key)
+    }
+
+    pub fn usize_option_from_param(&self, key: &str) -> Option<usize> {
+        self.get_param(key)?.parse::<usize>().ok()
+    }
+
+    /// Normalizes user-supplied page titles like MediaWiki does: underscores and runs of
+    /// whitespace become single spaces, the namespace prefix is resolved case-insensitively,
+    /// and the first letter is uppercased unless the namespace is case-sensitive.
+    pub fn normalize_title(full_title: &str, api: &Api) -> Title {
+        lazy_static! {
+            static ref RE_WHITESPACE: Regex =
+                Regex::new(r"[\s_]+").expect("Platform::normalize_title: Regex is invalid");
+        }
+        let full_title = RE_WHITESPACE.replace_all(full_title, " ");
+        let full_title = full_title.trim().trim_start_matches(':').trim();
+        // Try the part before a colon as a namespace prefix; keep the title as-is if it is not one
+        let title = match full_title.find(':') {
+            Some(pos) => {
+                let prefixed = format!(
+                    "{}:{}",
+                    Title::first_letter_uppercase(&full_title[..pos].trim().to_string()),
+                    full_title[pos + 1..].trim()
+                );
+                match Title::new_from_full(&prefixed, api) {
+                    title if title.namespace_id() != 0 => title,
+                    _ => Title::new_from_full(full_title, api),
+                }
+            }
+            None => Title::new_from_full(full_title, api),
+        };
+        let namespace_id = title.namespace_id();
+        let case_sensitive =
+            api.get_site_info_value("namespaces", &namespace_id.to_string())["case"].as_str()
+                == Some("case-sensitive");
+        match case_sensitive {
+            true => title,
+            false => Title::new(
+                &Title::first_letter_uppercase(&title.pretty().to_string()),
+                namespace_id,
+            ),
+        }
+    }
+
+    pub fn get_main_wiki(&self) -> Option<String> {
+        let language = self.get_param_default("lang", "en"); // Fallback
+        let language = self
+            .get_param_default("language", &language)
+            .replace("_", "-");
+        let project = self.get_param_default("project", "wikipedia");
+        self.get_wiki_for_language_project(&language, &project)
+    }
+
+    /// Adds the namespaces given by name in "namespaces" (comma-separated) to the namespace IDs.
+    /// Names are resolved on the main wiki and the wikis given for sources, and need to have the
+    /// same ID on all of them. Warns about namespace IDs that do not exist on these wikis.
+    /// Numeric IDs alone need no site info, so they are used as they are.
+    fn resolve_namespaces(&mut self) -> Result<(), PetScanError> {
+        let names = self.get_param_as_vec("namespaces", ",");
+        if names
+            .iter()
+            .all(|name| name.trim().parse::<usize>().is_ok())
+        {
+            names
+                .iter()
+                .filter_map(|name| name.trim().parse::<usize>().ok())
+                .for_each(|id| {
+                    self.form_parameters.ns.insert(id);
+                });
+            return Ok(());
+        }
+
+        let mut resolved: Option<(String, Vec<usize>)> = None;
+        for wiki in self.source_wikis() {
+            let api = self.state.get_api_for_wiki(wiki.to_owned())?;
+            let (namespace_ids, unknown) = Self::namespace_ids_from_names(&api, &names);
+            if !unknown.is_empty() {
+                return Err(PetScanError::BadParameter(format!(
+                    "No such namespace on {}: {}",
+                    &wiki,
+                    unknown.join(", ")
+                )));
+            }
+            match &resolved {
+                Some((first_wiki, ids)) if *ids != namespace_ids => {
+                    return Err(PetScanError::BadParameter(format!(
+                        "Namespaces '{}' have different IDs on {} and {}; use namespace IDs instead",
+                        names.join(","),
+                        first_wiki,
+                        &wiki
+                    )));
+                }
+                Some(_) => {}
+                None => resolved = Some((wiki.to_owned(), namespace_ids)),
+            }
+        }
+        let (_, namespace_ids) = match resolved {
+            Some(resolved) => resolved,
+            None => return Ok(()),
+        };
+        self.form_parameters.ns.extend(namespace_ids);
+
+        for wiki in self.source_wikis() {
+            let api = self.state.get_api_for_wiki(wiki.to_owned())?;
+            let mut missing: Vec<String> = self
+                .form_parameters
+                .ns
+                .iter()
+                .filter(|id| {
+                    api.get_site_info()["query"]["namespaces"][id.to_string()]
+                        .as_object()
+                        .is_none()
+                })
+                .map(|id| id.to_string())
+                .collect();
+            if !missing.is_empty() {
+                missing.sort();
+                self.warn(format!(
+                    "Namespace IDs that do not exist on {}: {}",
+                    &wiki,
+                    missing.join(", ")
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The main wiki, and the wikis given for sources, without duplicates
+    fn source_wikis(&self) -> Vec<String> {
+        let mut wikis: Vec<String> = vec![];
+        let source_wikis = ["manual_list_wiki", "search_wiki", "deepcat_wiki"]
+            .iter()
+            .filter_map(|param| self.get_param(param));
+        for wiki in self.get_main_wiki().into_iter().chain(source_wikis) {
+            if !wikis.contains(&wiki) {
+                wikis.push(wiki);
+            }
+        }
+        wikis
+    }
+
+    /// Resolves namespace names (canonical English or local, or numeric IDs) for the wiki of the API.
+    /// "Main" or "Article" is the main namespace. Returns the IDs, and the names that were not found.
+    pub fn namespace_ids_from_names(api: &Api, names: &[String]) -> (Vec<usize>, Vec<String>) {
+        let normalize = |s: &str| s.trim().replace('_', " ").to_lowercase();
+        let mut ids = vec![];
+        let mut unknown = vec![];
+        for name in names {
+            let wanted = normalize(name);
+            if let Ok(id) = wanted.parse::<usize>() {
+                ids.push(id);
+                continue;
+            }
+            if wanted == "main" || wanted == "article" {
+                ids.push(0);
+                continue;
+            }
+            let id = api.get_site_info()["query"]["namespaces"]
+                .as_object()
+                .and_then(|namespaces| {
+                    namespaces.values().find_map(|ns| {
+                        let names = [ns["canonical"].as_str(), ns["*"].as_str()];
+                        match names.iter().flatten().any(|n| normalize(*n) == wanted) {
+                            true => ns["id"].as_u64(),
+                            false => None,
+                        }
+                    })
+                });
+            match id {
+                Some(id) => ids.push(id as usize),
+                None => unknown.push(name.to_string()),
+            }
+        }
+        (ids, unknown)
+    }
+
+    /// Checks that the main wiki and any wikis given for sources exist in the site matrix
+    fn validate_wikis(&self) -> Result<(), PetScanError> {
+        if self.get_main_wiki().is_none() {
+            return Err(PetScanError::BadParameter(format!(
+                "No such wiki for language '{}' and project '{}'",
+                self.get_param_default("language", &self.get_param_default("lang", "en")),
+                self.get_param_default("project", "wikipedia")
+            )));
+        }
+        let wikis = self.source_wikis();
+        // Language codes use "-", but database names use "_"
+        let exists = |wiki: &String| {
+            self.state.get_server_url_for_wiki(wiki).is_ok()
+                || self
+                    .state
+                    .get_server_url_for_wiki(&wiki.replace("-", "_"))
+                    .is_ok()
+        };
+        match wikis.iter().find(|wiki| !exists(wiki)) {
+            Some(wiki) => Err(PetScanError::BadParameter(format!(
+                "No such wiki: {}",
+                wiki
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_wiki_for_language_project(
+        &self,
+        language: &String,
+        project: &String,
+    ) -> Option<String> {
+        match (language.as_str(), project.as_str()) {
+            (language, "wikipedia") => Some(language.to_owned() + "wiki"),
+            ("commons", _) => Some("commonswiki".to_string()),
+            ("wikidata", _) => Some("wikidatawiki".to_string()),
+            (_, "wikidata") => Some("wikidatawiki".to_string()),
+            (l, p) => {
+                let url = "https://".to_string() + &l + "." + &p + ".org";
+                self.state.get_wiki_for_server_url(&url)
+            }
+        }
+    }
+
+    pub fn get_response(&self) -> Result<MyResponse, String> {
+        // Shortcut: WDFIST
+        match &self.wdfist_result {
+            Some(j) => {
+                return Ok(self
+                    .state
+                    .output_json(j, self.form_parameters.params.get("callback")));
+            }
+            None => {}
+        }
+        if let Some(j) = &self.set_comparison {
+            return Ok(self
+                .state
+                .output_json(j, self.form_parameters.params.get("callback")));
+        }
+
+        let result = match &self.result {
+            Some(result) => result,
+            None => return Err(format!("Platform::get_response: No result")),
+        };
+        let wiki = match result.wiki()? {
+            Some(wiki) => wiki,
+            None => return Err(format!("Platform::get_response: No wiki in result")),
+        };
+        self.result_stats()?; // Compute before the result is drained
+
+        let mut sortby = self.get_param_blank("sortby");
+        let mut sort_order = self.get_param_blank("sortorder") == "descending".to_string();
+        if self.do_output_redlinks() && (sortby.is_empty() || sortby == "none") {
+            sortby = "redlinks".to_string();
+            sort_order = true;
+        }
+        let sorter = PageListSort::new_from_params(&sortby, sort_order);
+        if sorter.uses_random() {
+            result.assign_random_keys(self.random_seed()?)?;
+        }
+        let collator = match self.get_param("collation") {
+            Some(collation) => Some(PageList::collator_for_language(&collation)?),
+            None => None,
+        };
+        let mut pages = result.drain_into_collated_vec(sorter, collator.as_ref())?;
+        drop(result);
+        self.apply_namespace_limit(&mut pages)?;
+        self.apply_results_limit(&mut pages)?;
+        let result_count = pages.len();
+
+        let renderer: Box<dyn Render> = match self.get_param_blank("format").as_str() {
+            "wiki" => RenderWiki::new(),
+            "csv" => RenderTSV::new(","),
+            "tsv" => RenderTSV::new("\t"),
+            "json" => RenderJSON::new(),
+            "jsonl" => RenderJSONL::new(),
+            "geojson" => RenderGeoJSON::new(),
+            "kml" => RenderKML::new(),
+            "xlsx" => RenderXLSX::new(),
+            "md" => RenderMarkdown::new(),
+            "rss" => RenderRSS::new(),
+            "titles" => RenderTitles::new(),
+            "sqldump" => RenderSQLDump::new(),
+            "pagepile" => RenderPagePile::new(),
+            _ => RenderHTML::new(),
+        };
+        let mut response = renderer.response(&self, &wiki, pages)?;
+        response.filename = response
+            .content_type
+            .attachment_extension()
+            .map(|extension| format!("{}.{}", self.download_basename(), extension));
+        match self.get_param_blank("package").as_str() {
+            "" => Ok(response),
+            "zip" => self.package_zip(response, &wiki, result_count),
+            "gz" => self.package_gzip(response),
+            other => Err(format!("Unknown package '{}'", other)),
+        }
+    }
+
+    /// File name for downloads, without extension
+    fn download_basename(&self) -> String {
+        match self.psid {
+            Some(psid) => format!("petscan_{}", psid),
+            None => "petscan".to_string(),
+        }
+    }
+
+    /// File name of the rendered output inside a package
+    fn package_member_name(&self, response: &MyResponse) -> String {
+        match &response.filename {
+            Some(filename) => filename.to_string(),
+            None => format!(
+                "{}.{}",
+                self.download_basename(),
+                self.get_param_default("format", "html")
+            ),
+        }
+    }
+
+    /// Packages the rendered output as a ZIP file, with a "metadata.json" file describing the query
+    fn package_zip(
+        &self,
+        response: MyResponse,
+        wiki: &String,
+        result_count: usize,
+    ) -> Result<MyResponse, String> {
+        let truncations = self.truncations()?;
+        let metadata = json!({
+            "query": self.form_parameters().to_string(),
+            "psid": self.psid,
+            "wiki": wiki,
+            "combination": self.combination.to_string(),
+            "timestamp": Utc::now().to_rfc3339(),
+            "results": result_count,
+            "truncated": !truncations.is_empty(),
+            "truncation_reasons": truncations,
+        });
+        let body: &[u8] = match &response.binary {
+            Some(binary) => binary,
+            None => response.s.as_bytes(),
+        };
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file(self.package_member_name(&response), options)
+            .map_err(|e| format!("{:?}", e))?;
+        zip.write_all(body).map_err(|e| format!("{:?}", e))?;
+        zip.start_file("metadata.json", options)
+            .map_err(|e| format!("{:?}", e))?;
+        zip.write_all(
+            serde_json::to_string_pretty(&metadata)
+                .map_err(|e| format!("{:?}", e))?
+                .as_bytes(),
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        let bytes = zip.finish().map_err(|e| format!("{:?}", e))?.into_inner();
+        Ok(MyResponse {
+            s: "".to_string(),
+            content_type: ContentType::ZIP,
+            binary: Some(bytes),
+            filename: Some(format!("{}.zip", self.download_basename())),
+        })
+    }
+
+    /// Packages the rendered output as a gzip file
+    fn package_gzip(&self, response: MyResponse) -> Result<MyResponse, String> {
+        let body: &[u8] = match &response.binary {
+            Some(binary) => binary,
+            None => response.s.as_bytes(),
+        };
+        Ok(MyResponse {
+            s: "".to_string(),
+            content_type: ContentType::GZIP,
+            binary: Some(MyResponse::gzip(body)?),
+            filename: Some(format!("{}.gz", self.package_member_name(&response))),
+        })
+    }
+
+    pub fn get_param_as_vec(&self, param: &str, separator: &str) -> Vec<String> {
+        match self.get_param(param) {
+            Some(s) => s
+                .split(separator)
+                .map(|s| s.trim().trim_matches('\u{200E}').trim_matches('\u{200F}')) // See https://doc.rust-lang.org/reference/whitespace.html
+                .filter(|s| !s.is_empty())
+                .map(|s| Title::spaces_to_underscores(&s.to_string()))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn get_param_blank(&self, param: &str) -> String {
+        self.get_param(param).unwrap_or("".to_string())
+    }
+
+    pub fn get_param_default(&self, param: &str, default: &str) -> String {
+        let ret = self.get_param(param).unwrap_or(default.to_string());
+        if ret.is_empty() {
+            default.to_string()
+        } else {
+            ret
+        }
+    }
+
+    pub fn append_sql(sql: &mut SQLtuple, mut sub: SQLtuple) {
+        sql.0 += &sub.0;
+        sql.1.append(&mut sub.1);
+    }
+
+    /// Returns a tuple with a string containing comma-separated question marks, and the (non-empty) Vec elements
+    pub fn prep_quote(strings: &[String]) -> SQLtuple {
+        let escaped: Vec<String> = strings
+            .par_iter()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+        (Platform::get_questionmarks(escaped.len()), escaped)
+    }
+
+    pub fn full_entity_id_to_number(strings: &[String]) -> SQLtuple {
+        let escaped: Vec<String> = strings
+            .par_iter()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s[1..].to_string())
+            .collect();
+        (Platform::get_questionmarks(escaped.len()), escaped)
+    }
+
+    // Generates a string with `len` comma-separated question marks
+    pub fn get_questionmarks(len: usize) -> String {
+        let mut questionmarks: Vec<String> = Vec::new();
+        questionmarks.resize(len, "?".to_string());
+        questionmarks.join(",")
+    }
+
+    pub fn sql_tuple() -> SQLtuple {
+        ("".to_string(), vec![])
+    }
+
+    fn parse_combination_string(s: &String) -> Result<Combination, String> {
+        Self::parse_combination_string_depth(s, 0)
+    }
+
+    fn parse_combination_string_depth(s: &String, depth: usize) -> Result<Combination, String> {
+        if depth > MAX_COMBINATION_DEPTH {
+            return Err(format!(
+                "Source combination is nested too deeply (more than {} levels)",
+                MAX_COMBINATION_DEPTH
+            ));
+        }
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"\w+(?:'\w+)?|[^\w\s]")
+                .expect("Platform::parse_combination_string: Regex is invalid");
+        }
+        match s.trim().to_lowercase().as_str() {
+            "" => return Ok(Combination::None),
+            "categories" | "sparql" | "manual" | "pagepile" | "wikidata" | "search" | "deepcat"
+            | "linkshere" | "transclusions" | "petscan" | "quarry" | "contributions" => {
+                return Ok(Combination::Source(s.to_string()))
+            }
+            _ => {}
+        }
+        let mut parts: Vec<String> = RE
+            .captures_iter(s)
+            .filter_map(|cap| cap.get(0))
+            .map(|s| s.as_str().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        // Problem?
+        if parts.len() < 3 {
+            return Ok(Combination::None);
+        }
+
+        // All operators have the same precedence and are left-associative,
+        // so "a NOT b NOT c" is "(a NOT b) NOT c"
+        let mut left = match Self::take_combination_operand(&mut parts, depth)? {
+            Some(operand) => operand,
+            None => return Ok(Combination::None), // Failure to parse
+        };
+        while !parts.is_empty() {
+            let comb = parts.remove(0);
+            let right = match Self::take_combination_operand(&mut parts, depth)? {
+                Some(operand) => Box::new(operand),
+                None => return Ok(Combination::None), // Failure to parse
+            };
+            let left_box = Box::new(left);
+            left = match comb.trim().to_lowercase().as_str() {
+                "and" => Combination::Intersection((left_box, right)),
+                "or" => Combination::Union((left_box, right)),
+                "not" => Combination::Not((left_box, right)),
+                "xor" => Combination::Xor((left_box, right)),
+                _ => return Ok(Combination::None),
+            };
+        }
+        Ok(left)
+    }
+
+    /// Removes a single source, or a parenthesized group, from the front of `parts`, and parses it.
+    /// Returns `None` if parentheses are unbalanced.
+    fn take_combination_operand(
+        parts: &mut Vec<String>,
+        depth: usize,
+    ) -> Result<Option<Combination>, String> {
+        if parts.is_empty() {
+            return Ok(Some(Combination::None));
+        }
+        let first_part = parts.remove(0);
+        if first_part != "(" {
+            return Ok(Some(Self::parse_combination_string_depth(
+                &first_part,
+                depth + 1,
+            )?));
+        }
+        let mut cnt = 1;
+        let mut inner: Vec<String> = vec![];
+        loop {
+            if parts.is_empty() {
+                return Ok(None);
+            }
+            let x = parts.remove(0);
+            if x == "(" {
+                cnt += 1;
+            } else if x == ")" {
+                cnt -= 1;
+                if cnt == 0 {
+                    break;
+                }
+            }
+            inner.push(x);
+        }
+        Ok(Some(Self::parse_combination_string_depth(
+            &inner.join(" "),
+            depth + 1,
+        )?))
+    }
+
+    /// Checks is the parameter is set, and non-blank
+    pub fn has_param(&self, param: &str) -> bool {
+        match self.form_parameters().params.get(&param.to_string()) {
+            Some(s) => s != "",
+            None => false,
+        }
+    }
+
+    pub fn get_param(&self, param: &str) -> Option<String> {
+        if self.has_param(param) {
+            self.form_parameters()
+                .params
+                .get(&param.to_string())
+                .map(|s| s.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn get_combination(
+        &self,
+        available_sources: &Vec<String>,
+    ) -> Result<Combination, PetScanError> {
+        match self.get_param("source_combination") {
+            Some(combination_string) => Self::parse_combination_string(&combination_string)
+                .map_err(PetScanError::BadParameter),
+            None => {
+                let mut comb = Combination::None;
+                for source in available_sources {
+                    if comb == Combination::None {
+                        comb = Combination::Source(source.to_string());
+                    } else {
+                        comb = Combination::Intersection((
+                            Box::new(Combination::Source(source.to_string())),
+                            Box::new(comb),
+                        ));
+                    }
+                }
+                Ok(comb)
+            }
+        }
+    }
+
+    fn combine_results(
+        &self,
+        results: &mut HashMap<String, PageList>,
+        combination: &Combination,
+        depth: usize,
+    ) -> Result<PageList, String> {
+        if depth > MAX_COMBINATION_DEPTH {
+            return Err(format!(
+                "Source combination is nested too deeply (more than {} levels)",
+                MAX_COMBINATION_DEPTH
+            ));
+        }
+        match combination {
+            Combination::Source(s) => match results.remove(s) {
+                Some(r) => Ok(r),
+                None if self.timed_out_sources.contains(s) => {
+                    Err(format!("Source {} timed out", &s))
+                }
+                None => Err(format!("No result for source {}", &s)),
+            },
+            Combination::Union((a, b)) => match (a.as_ref(), b.as_ref()) {
+                (Combination::None, c) => self.combine_results(results, c, depth + 1),
+                (c, Combination::None) => self.combine_results(results, c, depth + 1),
+                (c, d) => {
+                    let r1 = self.combine_results(results, c, depth + 1)?;
+                    let r2 = self.combine_results(results, d, depth + 1)?;
+                    r1.union(&r2, Some(&self))?;
+                    Ok(r1)
+                }
+            },
+            Combination::Intersection((a, b)) => match (a.as_ref(), b.as_ref()) {
+                (Combination::None, _c) => {
+                    Err(format!("Intersection with Combination::None found"))
+                }
+                (_c, Combination::None) => {
+                    Err(format!("Intersection with Combination::None found"))
+                }
+                (c, d) => {
+                    let r1 = self.combine_results(results, c, depth + 1)?;
+                    let r2 = self.combine_results(results, d, depth + 1)?;
+                    r1.intersection(&r2, Some(&self))?;
+                    Ok(r1)
+                }
+            },
+            Combination::Not((a, b)) => match (a.as_ref(), b.as_ref()) {
+                (Combination::None, _c) => Err(format!("Not with Combination::None found")),
+                (c, Combination::None) => self.combine_results(results, c, depth + 1),
+                (c, d) => {
+                    let r1 = self.combine_results(results, c, depth + 1)?;
+                    let r2 = self.combine_results(results, d, depth + 1)?;
+                    r1.difference(&r2, Some(&self))?;
+                    Ok(r1)
+                }
+            },
+            Combination::Xor((a, b)) => match (a.as_ref(), b.as_ref()) {
+                (Combination::None, c) => self.combine_results(results, c, depth + 1),
+                (c, Combination::None) => self.combine_results(results, c, depth + 1),
+                (c, d) => {
+                    let r1 = self.combine_results(results, c, depth + 1)?;
+                    let r2 = self.combine_results(results, d, depth + 1)?;
+                    r1.symmetric_difference(&r2, Some(&self))?;
+                    Ok(r1)
+                }
+            },
+            Combination::None => Err(format!("Combination::None found")),
+        }
+    }
+
+    pub fn result(&self) -> &Option<PageList> {
+        &self.result
+    }
+
+    /// Moves the result out, e.g. to use it as the data of another query
+    pub fn take_result(&mut self) -> Option<PageList> {
+        self.result.take()
+    }
+
+    pub fn form_parameters(&self) -> &FormParameters {
+        &self.form_parameters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::AppState;
+    use crate::platform_builder::PlatformBuilder;
+    use serde_json::Value;
+    use std::env;
+    use std::fs::File;
+
+    fn get_new_state() -> Arc<AppState> {
+        let basedir = env::current_dir()
+            .expect("Can't get CWD")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let path = basedir.to_owned() + "/config.json";
+        let file = File::open(path).expect("Can not open config file");
+        let petscan_config: Value =
+            serde_json::from_reader(file).expect("Can not parse JSON from config file");
+        Arc::new(AppState::new_from_config(&petscan_config))
+    }
+
+    fn get_state() -> Arc<AppState> {
+        lazy_static! {
+            static ref STATE: Arc<AppState> = get_new_state();
+        }
+        STATE.clone()
+    }
+
+    fn run_psid_ext(psid: usize, addendum: &str) -> Result<Platform, String> {
+        let state = get_state();
+        let form_parameters = match state.get_query_from_psid(&format!("{}", &psid)) {
+            Ok(psid_query) => {
+                let query = psid_query + addendum;
+                FormParameters::outcome_from_query(&query)?
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        let mut platform = Platform::new_from_parameters(&form_parameters, state);
+        platform.run().unwrap();
+        Ok(platform)
+    }
+
+    fn run_query(query: &str) -> Platform {
+        let form_parameters = FormParameters::outcome_from_query(query).unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        platform.run().unwrap();
+        platform
+    }
+
+    fn run_psid(psid: usize) -> Platform {
+        run_psid_ext(psid, "").unwrap()
+    }
+
+    fn check_results_for_psid_ext(psid: usize, addendum: &str, wiki: &str, expected: Vec<Title>) {
+        let mut platform = run_psid_ext(psid, addendum).unwrap();
+        let s1 = platform.get_param_blank("sortby");
+        let s2 = platform.get_param_blank("sortorder");
+
+        let result = platform.result.unwrap();
+        let some_wiki = result.wiki();
+        assert_eq!(some_wiki, Ok(Some(wiki.to_string())));
+
+        // Sort/crop results
+        let mut entries = result
+            .drain_into_sorted_vec(PageListSort::new_from_params(
+                &s1,
+                s2 == "descending".to_string(),
+            ))
+            .unwrap();
+        platform.result = Some(result);
+        platform.apply_results_limit(&mut entries).unwrap();
+
+        assert_eq!(entries.len(), expected.len());
+        let titles: Vec<Title> = entries.iter().map(|e| e.title()).cloned().collect();
+        assert_eq!(titles, expected);
+    }
+
+    fn check_results_for_psid(psid: usize, wiki: &str, expected: Vec<Title>) {
+        check_results_for_psid_ext(psid, "", wiki, expected)
+    }
+
+    #[test]
+    fn test_parse_combination_string() {
+        let res =
+            Platform::parse_combination_string(&"categories NOT (sparql OR pagepile)".to_string())
+                .unwrap();
+        let expected = Combination::Not((
+            Box::new(Combination::Source("categories".to_string())),
+            Box::new(Combination::Union((
+                Box::new(Combination::Source("sparql".to_string())),
+                Box::new(Combination::Source("pagepile".to_string())),
+            ))),
+        ));
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_combination_xor() {
+        let combination =
+            Platform::parse_combination_string(&"categories XOR sparql".to_string()).unwrap();
+        assert_eq!(
+            combination,
+            Combination::Xor((
+                Box::new(Combination::Source("categories".to_string())),
+                Box::new(Combination::Source("sparql".to_string())),
+            ))
+        );
+        assert_eq!(combination.to_string(), "(categories XOR sparql)");
+
+        let page_list = |titles: Vec<&str>| {
+            let list = PageList::new_from_wiki("enwiki");
+            titles.iter().for_each(|t| {
+                list.add_entry(PageListEntry::new(Title::new(t, 0)))
+                    .unwrap()
+            });
+            list
+        };
+        let mut results: HashMap<String, PageList> = HashMap::new();
+        results.insert("categories".to_string(), page_list(vec!["A", "B", "C"]));
+        results.insert("sparql".to_string(), page_list(vec!["B", "C", "D"]));
+        let form_parameters = FormParameters::outcome_from_query("").unwrap();
+        let platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let result = platform
+            .combine_results(&mut results, &combination, 0)
+            .unwrap();
+        let mut titles: Vec<String> = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| e.title().pretty().to_string())
+            .collect();
+        titles.sort();
+        assert_eq!(titles, vec!["A", "D"]);
+    }
+
+    #[test]
+    fn test_entry_filter() {
+        let form_parameters = FormParameters::outcome_from_query(
+            "manual_list=Magnus_Manske%0AJimbo_Wales&manual_list_wiki=enwiki&doit=1",
+        )
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        platform.set_entry_filter(Box::new(|entry| {
+            // Page sizes are loaded by then
+            entry.page_bytes.is_some() && entry.title().pretty() != "Jimbo Wales"
+        }));
+        platform.run().unwrap();
+        let entries = platform.result().as_ref().unwrap().entries();
+        let titles: Vec<Title> = entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| e.title().to_owned())
+            .collect();
+        assert_eq!(titles, vec![Title::new("Magnus_Manske", 0)]);
+    }
+
+    #[test]
+    fn test_parse_combination_string_associativity() {
+        let source = |s: &str| Box::new(Combination::Source(s.to_string()));
+        let res =
+            Platform::parse_combination_string(&"categories NOT sparql NOT manual".to_string())
+                .unwrap();
+        let expected = Combination::Not((
+            Box::new(Combination::Not((source("categories"), source("sparql")))),
+            source("manual"),
+        ));
+        assert_eq!(res, expected);
+
+        let res =
+            Platform::parse_combination_string(&"categories OR sparql NOT manual".to_string())
+                .unwrap();
+        let expected = Combination::Not((
+            Box::new(Combination::Union((source("categories"), source("sparql")))),
+            source("manual"),
+        ));
+        assert_eq!(res, expected);
+        assert_eq!(res.to_string(), "((categories OR sparql) NOT manual)");
+
+        // Parentheses still group
+        let res =
+            Platform::parse_combination_string(&"categories OR (sparql NOT manual)".to_string())
+                .unwrap();
+        let expected = Combination::Union((
+            source("categories"),
+            Box::new(Combination::Not((source("sparql"), source("manual")))),
+        ));
+        assert_eq!(res, expected);
+
+        // Unbalanced parentheses fail to parse
+        let res =
+            Platform::parse_combination_string(&"categories OR (sparql NOT manual".to_string())
+                .unwrap();
+        assert_eq!(res, Combination::None);
+    }
+
+    struct StubSource {
+        name: String,
+        delay: Duration,
+        error: Option<String>,
+    }
+
+    impl DataSource for StubSource {
+        fn can_run(&self, _platform: &Platform) -> bool {
+            true
+        }
+        fn run(&mut self, _platform: &Platform) -> Result<PageList, String> {
+            thread::sleep(self.delay);
+            match &self.error {
+                Some(error) => Err(error.to_owned()),
+                None => Ok(PageList::new_from_wiki("enwiki")),
+            }
+        }
+        fn name(&self) -> String {
+            self.name.to_owned()
+        }
+    }
+
+    struct PanickingSource {}
+
+    impl DataSource for PanickingSource {
+        fn can_run(&self, _platform: &Platform) -> bool {
+            true
+        }
+        fn run(&mut self, _platform: &Platform) -> Result<PageList, String> {
+            panic!("PanickingSource");
+        }
+        fn name(&self) -> String {
+            "search".to_string()
+        }
+    }
+
+    #[test]
+    fn test_source_timeout() {
+        // Lower than the minimum
+        let form_parameters = FormParameters::outcome_from_query("source_timeout=0").unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let stub = |name: &str, seconds: u64| -> Box<dyn DataSource + Send + Sync> {
+            Box::new(StubSource {
+                name: name.to_string(),
+                delay: Duration::from_secs(seconds),
+                error: None,
+            })
+        };
+        let start = Instant::now();
+        let mut results = platform
+            .run_sources(vec![stub("manual", 0), stub("sparql", 30)])
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(MIN_SOURCE_TIMEOUT_SECONDS));
+        assert!(start.elapsed() < Duration::from_secs(15));
+        assert!(results.contains_key("manual"));
+        assert!(!results.contains_key("sparql"));
+        assert_eq!(platform.timed_out_sources, vec!["sparql".to_string()]);
+        assert_eq!(
+            platform.warnings().unwrap(),
+            vec!["Source sparql timed out after 5 seconds".to_string()]
+        );
+
+        let combination =
+            Platform::parse_combination_string(&"manual AND sparql".to_string()).unwrap();
+        let error = platform
+            .combine_results(&mut results, &combination, 0)
+            .unwrap_err();
+        assert_eq!(error, "Source sparql timed out");
+    }
+
+    #[test]
+    fn test_source_errors() {
+        let form_parameters = FormParameters::outcome_from_query("").unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let failing: Box<dyn DataSource + Send + Sync> = Box::new(StubSource {
+            name: "sparql".to_string(),
+            delay: Duration::from_secs(0),
+            error: Some("Bad query".to_string()),
+        });
+        let working: Box<dyn DataSource + Send + Sync> = Box::new(StubSource {
+            name: "manual".to_string(),
+            delay: Duration::from_secs(0),
+            error: None,
+        });
+        let results = platform.run_sources(vec![failing, working]).unwrap();
+        assert!(results.contains_key("manual"));
+        assert_eq!(
+            platform.source_errors(),
+            &vec![("sparql".to_string(), "Bad query".to_string())]
+        );
+
+        // A panicking source fails, it does not time out
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let results = platform
+            .run_sources(vec![Box::new(PanickingSource {})])
+            .unwrap();
+        assert!(results.is_empty());
+        assert!(platform.timed_out_sources.is_empty());
+        assert_eq!(
+            platform.source_errors(),
+            &vec![(
+                "search".to_string(),
+                "The source stopped unexpectedly".to_string()
+            )]
+        );
+
+        let failed = vec!["sparql".to_string()];
+        let combination =
+            Platform::parse_combination_string(&"manual AND sparql".to_string()).unwrap();
+        assert_eq!(
+            combination.without_sources(&failed),
+            Combination::Source("manual".to_string())
+        );
+        let combination =
+            Platform::parse_combination_string(&"sparql NOT manual".to_string()).unwrap();
+        assert_eq!(combination.without_sources(&failed), Combination::None);
+
+        // A real query with an invalid SPARQL query
+        let query = "manual_list=Magnus_Manske&manual_list_wiki=enwiki&sparql=NOT%20SPARQL&doit=1";
+        let form_parameters = FormParameters::outcome_from_query(query).unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        assert!(platform
+            .run()
+            .unwrap_err()
+            .to_string()
+            .starts_with("Source sparql failed: "));
+
+        let platform = run_query(&format!("{}&on_source_error=skip&format=json", query));
+        let titles: Vec<Title> = platform
+            .result()
+            .as_ref()
+            .unwrap()
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| e.title().to_owned())
+            .collect();
+        assert_eq!(titles, vec![Title::new("Magnus_Manske", 0)]);
+        let json: Value = serde_json::from_str(&platform.get_response().unwrap().s).unwrap();
+        assert_eq!(
+            json["a"]["source_errors"][0]["source"].as_str(),
+            Some("sparql")
+        );
+    }
+
+    #[test]
+    fn test_parse_combination_string_depth_limit() {
+        let nested =
+            |depth: usize| "(".repeat(depth) + "categories AND sparql" + &")".repeat(depth);
+        assert!(Platform::parse_combination_string(&nested(5)).is_ok());
+        assert!(Platform::parse_combination_string(&nested(1000))
+            .unwrap_err()
+            .contains("nested too deeply"));
+    }
+
+    #[test]
+    fn test_no_data_source_unmet_requirements() {
+        let form_parameters =
+            FormParameters::outcome_from_query("search_query=foo&search_max_results=10").unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let error = platform.run().unwrap_err().to_string();
+        assert!(error
+            .contains("search: search_query, search_max_results present but search_wiki missing"));
+    }
+
+    #[test]
+    fn test_search_namespaces_and_sort() {
+        let platform = run_query("search_wiki=enwiki&search_query=insource:%22Infobox%20person%22&search_max_results=20&search_namespaces=10&search_sort=last_edit_desc&doit=1");
+        let result = platform.result().as_ref().unwrap();
+        assert!(!result.is_empty().unwrap());
+        assert!(result
+            .with_entries(|entries| entries
+                .iter()
+                .all(|entry| entry.title().namespace_id() == 10))
+            .unwrap());
+
+        let form_parameters = FormParameters::outcome_from_query(
+            "search_wiki=enwiki&search_query=foo&search_max_results=20&search_sort=alphabetical&doit=1",
+        )
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let error = platform.run().unwrap_err().to_string();
+        assert!(error.contains("unknown sort order 'alphabetical'"));
+    }
+
+    #[test]
+    fn test_search_max_results_capped() {
+        let platform = run_query("search_wiki=enwiki&search_query=insource:%22Infobox%20person%22&search_max_results=20000&search_namespaces=10&doit=1");
+        assert_eq!(
+            platform.truncations().unwrap(),
+            vec!["search_max_results is limited to 10000".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_search_continuation() {
+        // Recorded list=search responses with srlimit=2
+        let pages = vec![
+            json!({"continue":{"sroffset":2,"continue":"-||"},"query":{"search":[{"ns":0,"title":"Alpha"},{"ns":0,"title":"Beta"}]}}),
+            json!({"continue":{"sroffset":4,"continue":"-||"},"query":{"search":[{"ns":0,"title":"Gamma"},{"ns":0,"title":"Delta"}]}}),
+            json!({"query":{"search":[{"ns":0,"title":"Epsilon"}]}}),
+        ];
+        let mut requests: Vec<Option<String>> = vec![];
+        let mut fetch = |continue_params: &HashMap<String, String>| -> Result<Value, String> {
+            requests.push(continue_params.get("sroffset").cloned());
+            Ok(pages[requests.len() - 1].clone())
+        };
+        let titles = SourceSearch::collect_results(100, &mut fetch).unwrap();
+        assert_eq!(titles.len(), 5);
+        assert_eq!(titles[4], Title::new("Epsilon", 0));
+        assert_eq!(
+            requests,
+            vec![None, Some("2".to_string()), Some("4".to_string())]
+        );
+
+        // Stops at max
+        let mut calls = 0;
+        let titles = SourceSearch::collect_results(3, &mut |_| {
+            calls += 1;
+            Ok(pages[calls - 1].clone())
+        })
+        .unwrap();
+        assert_eq!(titles.len(), 3);
+        assert_eq!(calls, 2);
+
+        // A repeating continuation does not loop forever
+        let mut calls = 0;
+        let titles = SourceSearch::collect_results(100, &mut |_| {
+            calls += 1;
+            Ok(pages[0].clone())
+        })
+        .unwrap();
+        assert_eq!(calls, 2);
+        assert_eq!(titles.len(), 4);
+    }
+
+    #[test]
+    fn test_platform_builder() {
+        let builder = PlatformBuilder::new(get_state())
+            .wiki("enwiki")
+            .categories(vec!["Chess openings".to_string()])
+            .depth(1)
+            .namespaces(vec![0])
+            .sortby(PageListSort::Multi(vec![
+                PageListSort::Size(true),
+                PageListSort::Title(false),
+            ]))
+            .output_limit(5);
+        let form_parameters = builder.form_parameters().unwrap();
+        let expected = FormParameters::outcome_from_query("language=en&project=wikipedia&categories=Chess%20openings&depth=1&ns[0]=1&sortby=size:desc,title:asc&sortorder=ascending&output_limit=5&doit=1").unwrap();
+        assert_eq!(form_parameters.params, expected.params);
+        assert_eq!(form_parameters.ns, expected.ns);
+
+        let mut platform = builder.build().unwrap();
+        platform.run().unwrap();
+        let titles = |platform: &Platform| {
+            let mut titles: Vec<Title> = platform
+                .result()
+                .as_ref()
+                .unwrap()
+                .entries()
+                .read()
+                .unwrap()
+                .iter()
+                .map(|e| e.title().to_owned())
+                .collect();
+            titles.sort();
+            titles
+        };
+        let expected_platform = run_query(&expected.to_string());
+        assert!(!titles(&platform).is_empty());
+        assert_eq!(titles(&platform), titles(&expected_platform));
+
+        // Sorters survive the round trip through parameters
+        for sorter in vec![
+            PageListSort::Title(true),
+            PageListSort::WikidataProperty("P569".to_string(), false),
+            PageListSort::Multi(vec![PageListSort::Size(true), PageListSort::Title(false)]),
+        ] {
+            let (sortby, descending) = sorter.to_params();
+            assert_eq!(PageListSort::new_from_params(&sortby, descending), sorter);
+        }
+
+        assert!(PlatformBuilder::new(get_state())
+            .wiki("nosuchwiki")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let run_error = |query: &str| {
+            let form_parameters = FormParameters::outcome_from_query(query).unwrap();
+            let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+            platform.run().unwrap_err()
+        };
+        match run_error("doit=1") {
+            PetScanError::NoDataSource(_) => {}
+            other => panic!("Expected NoDataSource, got {:?}", other),
+        }
+        match run_error("manual_list=Magnus_Manske&manual_list_wiki=enwiki&regexp_filter=(&doit=1")
+        {
+            PetScanError::BadParameter(message) => {
+                assert!(message.starts_with("Invalid regexp_filter '('"))
+            }
+            other => panic!("Expected BadParameter, got {:?}", other),
+        }
+        match run_error(
+            "manual_list=Magnus_Manske&manual_list_wiki=enwiki&namespaces=Nosuchnamespace&doit=1",
+        ) {
+            PetScanError::BadParameter(_) => {}
+            other => panic!("Expected BadParameter, got {:?}", other),
+        }
+        let nested = format!("{}manual{}", "(".repeat(40), ")".repeat(40));
+        match run_error(&format!(
+            "manual_list=Magnus_Manske&manual_list_wiki=enwiki&source_combination={}&doit=1",
+            nested
+        )) {
+            PetScanError::BadParameter(_) => {}
+            other => panic!("Expected BadParameter, got {:?}", other),
+        }
+        match get_state().get_query_from_psid(&"x".to_string()) {
+            Err(PetScanError::BadParameter(_)) => {}
+            other => panic!("Expected BadParameter, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_source_linkshere() {
+        let platform = run_query(
+            "linkshere_wiki=enwiki&linkshere_title=Count_von_Count&linkshere_namespaces=0&doit=1",
+        );
+        let result = platform.result().as_ref().unwrap();
+        assert!(result
+            .with_entries(
+                |entries| entries.contains(&PageListEntry::new(Title::new("Sesame Street", 0)))
+            )
+            .unwrap());
+        assert!(result
+            .with_entries(|entries| entries
+                .iter()
+                .all(|entry| entry.title().namespace_id() == 0))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_source_transclusions() {
+        let platform = run_query("transclusions_wiki=enwiki&transclusions_title=Infobox_chess_opening&transclusions_namespaces=0&doit=1");
+        assert_eq!(
+            platform.wiki_by_source().get("transclusions"),
+            Some(&"enwiki".to_string())
+        );
+        let result = platform.result().as_ref().unwrap();
+        assert!(result
+            .with_entries(
+                |entries| entries.contains(&PageListEntry::new(Title::new("Sicilian Defence", 0)))
+            )
+            .unwrap());
+        assert!(result
+            .with_entries(|entries| entries
+                .iter()
+                .all(|entry| entry.title().namespace_id() == 0))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_source_petscan() {
+        // PSID 10138979 is a manual list of [[Q12345]] on Wikidata
+        let platform = run_query("petscan_psid=10138979&manual_list=Q12345%0AQ42&manual_list_wiki=wikidatawiki&source_combination=petscan%20AND%20manual&doit=1");
+        assert_eq!(
+            platform.wiki_by_source().get("petscan"),
+            Some(&"wikidatawiki".to_string())
+        );
+        let result = platform.result().as_ref().unwrap();
+        assert_eq!(result.len().unwrap(), 1);
+        assert!(result
+            .with_entries(|entries| entries.contains(&PageListEntry::new(Title::new("Q12345", 0))))
+            .unwrap());
+
+        let form_parameters = FormParameters::outcome_from_query(&format!(
+            "petscan_psid=10138979&petscan_depth={}&doit=1",
+            MAX_PETSCAN_DEPTH
+        ))
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let error = platform.run().unwrap_err().to_string();
+        assert!(error.contains("nested too deeply"));
+    }
+
+    #[test]
+    fn test_source_quarry_result() {
+        // Hand-written, in the format of https://quarry.wmcloud.org/query/<id>/result/latest/0/json
+        let result = json!({"headers":["page_id","page_namespace","page_title"],"rows":[[4283,0,"Count_von_Count"],[35128,0,"Sesame_Street"],[1542957,14,"Sesame_Street_characters"]]});
+        let list = SourceQuarry::page_list_from_result("enwiki", &result, "page_title", None, 100)
+            .unwrap();
+        assert_eq!(list.wiki().unwrap(), Some("enwiki".to_string()));
+        assert_eq!(list.len().unwrap(), 3);
+        assert!(list
+            .with_entries(|entries| entries.contains(&PageListEntry::new(Title::new(
+                "Sesame Street characters",
+                14
+            ))))
+            .unwrap());
+
+        // Only the first rows are used
+        let list =
+            SourceQuarry::page_list_from_result("enwiki", &result, "page_title", None, 2).unwrap();
+        assert_eq!(list.len().unwrap(), 2);
+
+        let error =
+            SourceQuarry::page_list_from_result("enwiki", &result, "title", None, 100).unwrap_err();
+        assert!(error.contains("no column 'title'"));
+        let error =
+            SourceQuarry::page_list_from_result("enwiki", &result, "page_title", Some("ns"), 100)
+                .unwrap_err();
+        assert!(error.contains("no column 'ns'"));
+    }
+
+    #[test]
+    fn test_source_contributions() {
+        let platform = run_query("contribs_wiki=enwiki&contribs_user=ClueBot_NG&contribs_after=2020-01-01&contribs_before=2020-01-01%2001:00&contribs_namespaces=0&doit=1");
+        let result = platform.result().as_ref().unwrap();
+        assert!(!result.is_empty().unwrap());
+        assert!(result
+            .with_entries(|entries| entries
+                .iter()
+                .all(|entry| entry.title().namespace_id() == 0))
+            .unwrap());
+
+        assert!(platform.truncations().unwrap().is_empty());
+
+        // Outside of the window
+        let platform = run_query("contribs_wiki=enwiki&contribs_user=ClueBot_NG&contribs_after=2001-01-01&contribs_before=2001-01-02&doit=1");
+        assert!(platform.result().as_ref().unwrap().is_empty().unwrap());
+
+        // Without a window, the pages of a prolific user are capped
+        let platform = run_query("contribs_wiki=enwiki&contribs_user=ClueBot_NG&doit=1");
+        assert_eq!(
+            platform.result().as_ref().unwrap().len(),
+            Ok(CONTRIBS_MAX_PAGES)
+        );
+        assert!(platform.truncations().unwrap()[0].contains("contribs_after"));
+    }
+
+    #[test]
+    fn test_deepcat() {
+        let form_parameters =
+            FormParameters::outcome_from_query("deepcat_query=deepcat:Chess_openings").unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let error = platform.run().unwrap_err().to_string();
+        assert!(error.contains("deepcat: deepcat_query present but deepcat_wiki missing"));
+
+        let platform = run_query(
+            "deepcat_wiki=enwiki&deepcat_query=deepcat:Chess_openings%20intitle:Sicilian&ns[0]=1&doit=1",
+        );
+        let titles: Vec<String> = platform
+            .result()
+            .as_ref()
+            .unwrap()
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| e.title().pretty().to_string())
+            .collect();
+        assert!(titles.contains(&"Sicilian Defence".to_string()));
+    }
+
+    #[test]
+    fn test_namespace_ids_from_names() {
+        let state = get_state();
+        let names = vec![
+            "Category".to_string(),
+            "User_talk".to_string(),
+            "Main".to_string(),
+            "4".to_string(),
+            "No such namespace".to_string(),
+        ];
+        for wiki in &["enwiki", "dewiki"] {
+            let api = state.get_api_for_wiki(wiki.to_string()).unwrap();
+            let (ids, unknown) = Platform::namespace_ids_from_names(&api, &names);
+            assert_eq!(ids, vec![14, 3, 0, 4]);
+            assert_eq!(unknown, vec!["No such namespace".to_string()]);
+        }
+        // Local names work as well
+        let api = state.get_api_for_wiki("dewiki".to_string()).unwrap();
+        let (ids, _) = Platform::namespace_ids_from_names(&api, &["Kategorie".to_string()]);
+        assert_eq!(ids, vec![14]);
+
+        let platform = run_query("manual_list=Magnus_Manske&manual_list_wiki=enwiki&namespaces=Main,Category&ns[1234]=1&doit=1");
+        assert!(platform.form_parameters().ns.contains(&14));
+        assert!(platform
+            .warnings()
+            .unwrap()
+            .contains(&"Namespace IDs that do not exist on enwiki: 1234".to_string()));
+
+        // Names are resolved on the wikis of the sources as well
+        let platform = run_query("language=de&manual_list=Magnus_Manske&manual_list_wiki=dewiki&namespaces=Kategorie&doit=1");
+        assert!(platform.form_parameters().ns.contains(&14));
+        let form_parameters = FormParameters::outcome_from_query(
+            "manual_list=Magnus_Manske&manual_list_wiki=dewiki&namespaces=Kategorie&doit=1",
+        )
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        assert_eq!(
+            platform.run().unwrap_err(),
+            PetScanError::BadParameter("No such namespace on enwiki: Kategorie".to_string())
+        );
+
+        // Numeric IDs alone do not need the site info
+        let platform =
+            run_query("manual_list=Magnus_Manske&manual_list_wiki=enwiki&namespaces=14&doit=1");
+        assert!(platform.form_parameters().ns.contains(&14));
+        assert!(platform.warnings().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_wikis() {
+        let run_error = |query: &str| {
+            let form_parameters = FormParameters::outcome_from_query(query).unwrap();
+            let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+            platform.run().unwrap_err().to_string()
+        };
+        assert_eq!(
+            run_error("language=xx&project=nosuchproject&categories=Foo&doit=1"),
+            "No such wiki for language 'xx' and project 'nosuchproject'"
+        );
+        assert_eq!(
+            run_error("language=qqq&project=wikipedia&categories=Foo&doit=1"),
+            "No such wiki: qqqwiki"
+        );
+        assert_eq!(
+            run_error("manual_list=Foo&manual_list_wiki=nosuchwiki&doit=1"),
+            "No such wiki: nosuchwiki"
+        );
+    }
+
+    #[test]
+    fn test_title_label_match() {
+        check_results_for_psid_ext(
+            10087995,
+            "&title_label_match=matches&title_label_language=en",
+            "enwiki",
+            vec![Title::new("Magnus_Manske", 0)],
+        );
+        check_results_for_psid_ext(
+            10087995,
+            "&title_label_match=differs&title_label_language=en",
+            "enwiki",
+            vec![],
+        );
+        let platform = run_psid_ext(10087995, "&title_label_match=matches").unwrap();
+        let entries = platform.result().as_ref().unwrap().entries();
+        let entries = entries.read().unwrap();
+        assert!(entries
+            .iter()
+            .all(|e| e.get_extra("item_label") == Some("Magnus Manske".to_string())));
+
+        // [[Q1]] has the lower-case English label "universe"
+        let platform = run_query("manual_list=Universe&manual_list_wiki=enwiki&title_label_match=matches&title_label_language=en&doit=1");
+        let entries = platform.result().as_ref().unwrap().entries();
+        let entries = entries.read().unwrap();
+        assert!(entries.contains(&PageListEntry::new(Title::new("Universe", 0))));
+    }
+
+    #[test]
+    fn test_unlinked_with_langlinks() {
+        // [[Magnus Manske]] has a Wikidata item, so it is not reported
+        check_results_for_psid_ext(10087995, "&unlinked_with_langlinks=1", "enwiki", vec![]);
+    }
+
+    #[test]
+    fn test_editor_count() {
+        let platform = run_query(
+            "manual_list=Barack_Obama%0AMagnus_Manske&manual_list_wiki=enwiki&min_editors=1000&doit=1",
+        );
+        let entries = platform.result().as_ref().unwrap().entries();
+        let entries = entries.read().unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.iter().next().unwrap();
+        assert_eq!(entry.title(), &Title::new("Barack_Obama", 0));
+        assert!(entry.editor_count.unwrap() >= 1000);
+
+        check_results_for_psid_ext(10087995, "&max_editors=1", "enwiki", vec![]);
+    }
+
+    #[test]
+    fn test_manual_list_wikidata_redirects() {
+        // Find any item that was merged into another one
+        let state = get_state();
+        let (redirect, target) = {
+            let db_user_pass = state.get_db_mutex().lock().unwrap();
+            let mut conn = state
+                .get_wiki_db_connection(&db_user_pass, &"wikidatawiki".to_string())
+                .unwrap();
+            let row = conn
+                .prep_exec("SELECT page_title,rd_title FROM page,redirect WHERE rd_from=page_id AND page_namespace=0 AND rd_namespace=0 LIMIT 1", ())
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            my::from_row::<(String, String)>(row)
+        };
+        let titles_for = |addendum: &str| {
+            let platform = run_query(&format!(
+                "manual_list=Q42%0A{}%0AQ0&manual_list_wiki=wikidatawiki{}&doit=1",
+                redirect, addendum
+            ));
+            let entries = platform.result().as_ref().unwrap().entries();
+            let mut titles: Vec<Title> = entries
+                .read()
+                .unwrap()
+                .iter()
+                .map(|e| e.title().to_owned())
+                .collect();
+            titles.sort_by(|a, b| a.pretty().cmp(b.pretty()));
+            (titles, platform.warnings().unwrap())
+        };
+
+        let (titles, warnings) = titles_for("");
+        assert_eq!(titles, vec![Title::new("Q42", 0)]);
+        assert!(warnings.contains(&"1 entities in the manual list do not exist".to_string()));
+        assert!(
+            warnings.contains(&"1 redirected entities in the manual list were removed".to_string())
+        );
+
+        let (titles, _) = titles_for("&wd_resolve_redirects=1");
+        let mut expected = vec![Title::new("Q42", 0), Title::new(&target, 0)];
+        expected.sort_by(|a, b| a.pretty().cmp(b.pretty()));
+        expected.dedup();
+        assert_eq!(titles, expected);
+    }
+
+    #[test]
+    fn test_latest_edit_revert() {
+        let platform = run_query(
+            "language=en&project=wikipedia&categories=Biology&depth=1&latest_edit_revert=yes&doit=1",
+        );
+        let entries = platform.result().as_ref().unwrap().entries();
+        let entries = entries.read().unwrap();
+        assert!(entries.iter().all(|e| {
+            e.get_extra("revert_tags")
+                .unwrap()
+                .split('|')
+                .all(|tag| REVERT_CHANGE_TAGS.contains(&tag))
+        }));
+
+        let platform = run_query(
+            "language=en&project=wikipedia&categories=Biology&depth=1&latest_edit_revert=no&doit=1",
+        );
+        let entries = platform.result().as_ref().unwrap().entries();
+        let entries = entries.read().unwrap();
+        assert!(!entries.is_empty());
+        assert!(entries.iter().all(|e| e.get_extra("revert_tags").is_none()));
+    }
+
+    #[test]
+    fn test_langlink_count() {
+        let platform = run_query(
+            "manual_list=Berlin%0AMagnus_Manske&manual_list_wiki=enwiki&min_langlinks=100&doit=1",
+        );
+        let entries = platform.result().as_ref().unwrap().entries();
+        let entries = entries.read().unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.iter().next().unwrap();
+        assert_eq!(entry.title(), &Title::new("Berlin", 0));
+        assert!(entry.langlink_count.unwrap() >= 100);
+
+        check_results_for_psid_ext(10087995, "&min_langlinks=1000", "enwiki", vec![]);
+    }
+
+    #[test]
+    fn test_created_before_after() {
+        assert_eq!(
+            Platform::mediawiki_timestamp_from_param("created_before", "2020-01-31"),
+            Ok("20200131000000".to_string())
+        );
+        assert!(Platform::mediawiki_timestamp_from_param("created_before", "Jan").is_err());
+        // [[Magnus Manske]] was created in 2005, [[Berlin]] in 2001
+        let query = "manual_list=Berlin%0AMagnus_Manske&manual_list_wiki=enwiki&doit=1";
+        let titles_for = |addendum: &str| {
+            let platform = run_query(&format!("{}{}", query, addendum));
+            let entries = platform.result().as_ref().unwrap().entries();
+            let mut titles: Vec<Title> = entries
+                .read()
+                .unwrap()
+                .iter()
+                .map(|e| e.title().to_owned())
+                .collect();
+            titles.sort_by(|a, b| a.pretty().cmp(b.pretty()));
+            titles
+        };
+        assert_eq!(
+            titles_for("&created_before=2003-01-01"),
+            vec![Title::new("Berlin", 0)]
+        );
+        assert_eq!(
+            titles_for("&created_after=2003-01-01"),
+            vec![Title::new("Magnus_Manske", 0)]
+        );
+        assert_eq!(titles_for("&created_before=2001"), vec![]);
+    }
+
+    #[test]
+    fn test_last_editor() {
+        // Find any article last edited by a bot
+        let state = get_state();
+        let (page, bot) = {
+            let db_user_pass = state.get_db_mutex().lock().unwrap();
+            let mut conn = state
+                .get_wiki_db_connection(&db_user_pass, &"enwiki".to_string())
+                .unwrap();
+            let row = conn
+                .prep_exec("SELECT page_title,actor_name FROM page,revision,actor,user_groups WHERE rev_id=page_latest AND actor_id=rev_actor AND ug_user=actor_user AND ug_group='bot' AND page_namespace=0 AND page_is_redirect=0 LIMIT 1", ())
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            my::from_row::<(String, String)>(row)
+        };
+        let query = format!(
+            "manual_list={}%0AMagnus_Manske&manual_list_wiki=enwiki&doit=1",
+            FormParameters::percent_encode(&page)
+        );
+        let entries_for = |addendum: &str| {
+            let platform = run_query(&format!("{}{}", query, addendum));
+            let entries = platform.result().as_ref().unwrap().entries();
+            let entries = entries.read().unwrap();
+            entries.iter().cloned().collect::<Vec<PageListEntry>>()
+        };
+
+        let entries = entries_for(&format!(
+            "&last_editor={}",
+            FormParameters::percent_encode(&bot)
+        ));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].title(), &Title::new(&page, 0));
+        assert_eq!(entries[0].get_last_editor(), Some(bot.to_owned()));
+
+        let entries = entries_for(&format!(
+            "&last_editor_not={}",
+            FormParameters::percent_encode(&bot)
+        ));
+        assert!(!entries.iter().any(|e| e.title() == &Title::new(&page, 0)));
+
+        assert_eq!(
+            Platform::normalize_user_name("2001:db8::1"),
+            "2001:DB8::1".to_string()
+        );
+    }
+
+    #[test]
+    fn test_manual_list_enwiki_use_props() {
+        check_results_for_psid(10087995, "enwiki", vec![Title::new("Magnus_Manske", 0)]);
+    }
+
+    #[test]
+    fn test_manual_list_enwiki_sitelinks() {
+        // This assumes [[en:Count von Count]] has no lvwiki article
+        check_results_for_psid(10123257, "wikidatawiki", vec![Title::new("Q13520818", 0)]);
+    }
+
+    #[test]
+    fn test_manual_list_enwiki_min_max_sitelinks() {
+        // [[Count von Count]] vs. [[Magnus Manske]]
+        check_results_for_psid(10123897, "wikidatawiki", vec![Title::new("Q13520818", 0)]); // Min 15
+        check_results_for_psid(10124667, "wikidatawiki", vec![Title::new("Q12345", 0)]);
+        // Max 15
+    }
+
+    #[test]
+    fn test_manual_list_enwiki_label_filter() {
+        // [[Count von Count]] vs. [[Magnus Manske]]
+        check_results_for_psid(10125089, "wikidatawiki", vec![Title::new("Q12345", 0)]);
+        // Label "Count%" in en
+    }
+
+    #[test]
+    fn test_label_sql_wbt_tables() {
+        let form_parameters = FormParameters::outcome_from_query(
+            "labels_yes=Count%25&langs_labels_yes=en&cb_labels_yes_l=1&labels_no=Magnus%25&langs_labels_no=en",
+        )
+        .unwrap();
+        let platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let sql = platform.get_label_sql(&0).unwrap();
+        assert!(!sql.0.contains("wb_terms"));
+        assert!(sql
+            .0
+            .starts_with("SELECT DISTINCT CONCAT('Q',wbit_item_id)"));
+        assert!(sql.0.contains(" AND EXISTS ("));
+        assert!(sql.0.contains(" AND NOT EXISTS ("));
+        assert!(sql.0.contains("wbtl_type_id=1"));
+        assert_eq!(sql.1, vec!["en", "Count%", "en", "Magnus%"]);
+        let sql = platform.get_label_sql(&120).unwrap();
+        assert!(sql
+            .0
+            .starts_with("SELECT DISTINCT CONCAT('P',wbpt_property_id) AS term_full_entity_id FROM wbt_property_terms"));
+        assert!(sql.0.contains("t2.wbpt_property_id=t1.wbpt_property_id"));
+        assert!(platform.get_label_sql(&146).is_none());
+    }
+
+    #[test]
+    fn test_wikidata_statements() {
+        // [[Q42]] (Douglas Adams) is a human with a date of birth, [[Q12345]] (Count von Count) is not.
+        // [[Q15632617]] (fictional human) links to P31 and Q5, but is not an instance of Q5
+        let titles_for = |addendum: &str| {
+            let platform = run_query(&format!(
+                "manual_list=Q42%0AQ12345%0AQ15632617&manual_list_wiki=wikidatawiki{}&doit=1",
+                addendum
+            ));
+            let mut titles: Vec<String> = platform
+                .result()
+                .as_ref()
+                .unwrap()
+                .with_entries(|entries| {
+                    entries
+                        .iter()
+                        .map(|entry| entry.title().pretty().to_string())
+                        .collect()
+                })
+                .unwrap();
+            titles.sort();
+            titles
+        };
+        assert_eq!(titles_for("&wikidata_statements=P31%3DQ5"), vec!["Q42"]);
+        assert_eq!(titles_for("&wikidata_statements=P569%3D*"), vec!["Q42"]);
+        assert_eq!(
+            titles_for("&wikidata_statements=P31%3DQ5&wpiu=none"),
+            vec!["Q12345", "Q15632617"]
+        );
+
+        let form_parameters = FormParameters::outcome_from_query(
+            "manual_list=Q42&manual_list_wiki=wikidatawiki&wikidata_statements=P31%3Dhuman&doit=1",
+        )
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let error = platform.run().unwrap_err().to_string();
+        assert!(error.contains("is not like 'P31=Q5'"));
+    }
+
+    #[test]
+    fn test_manual_list_wikidata_label_filter_no() {
+        // Manual list [[Q12345]] (Count von Count) and [[Q13520818]] (Magnus Manske), excluding "Count%" labels in en
+        let platform = run_query("manual_list=Q12345%0AQ13520818&manual_list_wiki=wikidatawiki&labels_no=Count%25&langs_labels_no=en&doit=1");
+        let entries = platform.result().as_ref().unwrap().entries();
+        let titles: Vec<Title> = entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| e.title().to_owned())
+            .collect();
+        assert_eq!(titles, vec![Title::new("Q13520818", 0)]);
+    }
+
+    #[test]
+    fn test_manual_list_enwiki_neg_cat_filter() {
+        // [[Count von Count]] vs. [[Magnus Manske]]
+        // Manual list on enwiki, minus [[Category:Fictional vampires]]
+        check_results_for_psid(10126217, "enwiki", vec![Title::new("Magnus Manske", 0)]);
+    }
+
+    #[test]
+    fn test_source_labels() {
+        check_results_for_psid(
+            10225056,
+            "wikidatawiki",
+            vec![Title::new("Q13520818", 0), Title::new("Q10995651", 0)],
+        );
+    }
+
+    #[test]
+    fn test_uploader_is() {
+        // Manual list [[File:KingsCollegeChapelWest.jpg]] on commons
+        check_results_for_psid_ext(
+            10137125,
+            "&uploader_is=Someone_else,solipsist~commonswiki",
+            "commonswiki",
+            vec![Title::new("KingsCollegeChapelWest.jpg", 6)],
+        );
+        check_results_for_psid_ext(10137125, "&uploader_is=Someone_else", "commonswiki", vec![]);
+        check_results_for_psid_ext(10137125, "&uploader_anon=1", "commonswiki", vec![]);
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        // [[File:KingsCollegeChapelWest.jpg]] is 1025x768 pixels
+        let file = vec![Title::new("KingsCollegeChapelWest.jpg", 6)];
+        check_results_for_psid_ext(10137125, "&min_width=1000", "commonswiki", file.clone());
+        check_results_for_psid_ext(10137125, "&min_width=1100", "commonswiki", vec![]);
+        check_results_for_psid_ext(
+            10137125,
+            "&min_height=700&max_height=800",
+            "commonswiki",
+            file.clone(),
+        );
+        check_results_for_psid_ext(10137125, "&min_megapixels=0.5", "commonswiki", file);
+        check_results_for_psid_ext(10137125, "&min_megapixels=1", "commonswiki", vec![]);
+        // Non-file pages are removed
+        check_results_for_psid_ext(10087995, "&min_width=1", "enwiki", vec![]);
+    }
+
+    #[test]
+    fn test_manual_list_commons_file_info() {
+        // Manual list [[File:KingsCollegeChapelWest.jpg]] on commons
+        let platform = run_psid(10137125);
+        let result = platform.result.unwrap();
+        let entries = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<PageListEntry>>();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.get(0).unwrap();
+        assert_eq!(entry.page_id, Some(1340715));
+        let fi = entry.get_file_info();
+        assert!(fi.is_some());
+        let fi = fi.unwrap();
+        assert!(fi.file_usage.len() > 10);
+        assert_eq!(fi.img_size, Some(223131));
+        assert_eq!(fi.img_width, Some(1025));
+        assert_eq!(fi.img_height, Some(768));
+        assert_eq!(fi.img_user_text, Some("Solipsist~commonswiki".to_string()));
+        assert_eq!(
+            fi.img_sha1,
+            Some("sypcaey3hmlhjky46x0nhiwhiivx6yj".to_string())
+        );
+    }
+
+    #[test]
+    fn test_manual_list_enwiki_page_info() {
+        // Manual list [[Cambridge]] on enwiki
+        let platform = run_psid(10136716);
+        let result = platform.result.unwrap();
+        let entries = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<PageListEntry>>();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.get(0).unwrap();
+        assert_eq!(entry.page_id, Some(36995));
+        assert!(entry.page_bytes.is_some());
+        assert!(entry.get_page_timestamp().is_some());
+        assert_eq!(
+            entry.get_page_image(),
+            Some("KingsCollegeChapelWest.jpg".to_string())
+        );
+        assert_eq!(entry.disambiguation, TriState::No);
+        assert!(entry.incoming_links.is_some());
+        assert!(entry.incoming_links.unwrap() > 7500);
+        assert!(entry.get_coordinates().is_some());
+    }
+
+    #[test]
+    fn test_manual_list_enwiki_annotate_wikidata_item() {
+        // Manual list [[Count von Count]] on enwiki
+        let platform = run_psid(10137767);
+        let result = platform.result.unwrap();
+        let entries = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<PageListEntry>>();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.get(0).unwrap();
+        assert_eq!(entry.page_id, Some(239794));
+        assert_eq!(entry.get_wikidata_item(), Some("Q12345".to_string()));
+    }
+
+    #[test]
+    fn test_manual_list_enwiki_subpages() {
+        // Manual list [[User:Magnus Manske]] on enwiki, subpages, not "root page"
+        let platform = run_psid(10138030);
+        let result = platform.result.unwrap();
+        let entries = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<PageListEntry>>();
+        assert!(entries.len() > 100);
+        // Try to find pages with no '/'
+        assert!(!entries
+            .iter()
+            .any(|entry| { entry.title().pretty().find('/').is_none() }));
+    }
+
+    #[test]
+    fn test_manual_list_wikidata_labels() {
+        // Manual list [[Q12345]], nl label/desc
+        let platform = run_psid(10138979);
+        let result = platform.result.unwrap();
+        let entries = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<PageListEntry>>();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.get(0).unwrap();
+        assert_eq!(entry.page_id, Some(13925));
+        assert_eq!(entry.get_wikidata_label(), Some("Graaf Tel".to_string()));
+        assert_eq!(
+            entry.get_wikidata_description(),
+            Some("figuur van Sesamstraat".to_string())
+        );
+    }
+
+    #[test]
+    fn test_wikidata_label_languages() {
+        // Manual list [[Q12345]], en/de/fr labels in one pass
+        let platform = run_query("manual_list=Q12345&manual_list_wiki=wikidatawiki&wikidata_label_languages=en,de,fr&doit=1");
+        assert_eq!(
+            platform.wikidata_label_languages(),
+            vec!["en".to_string(), "de".to_string(), "fr".to_string()]
+        );
+        let result = platform.result.unwrap();
+        let entries = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<PageListEntry>>();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.get(0).unwrap();
+        assert_eq!(
+            entry.get_wikidata_label(),
+            Some("Count von Count".to_string())
+        );
+        assert_eq!(
+            entry.get_wikidata_label_in("en"),
+            Some("Count von Count".to_string())
+        );
+        assert_eq!(
+            entry.get_wikidata_label_in("de"),
+            Some("Graf Zahl".to_string())
+        );
+        assert!(entry.get_wikidata_label_in("fr").is_some());
+        assert!(entry.get_wikidata_label_in("nl").is_none());
+    }
+
+    #[test]
+    fn test_manual_list_wikidata_regexp() {
+        check_results_for_psid_ext(
+            10140344,
+            "&regexp_filter=.*Manske",
+            "wikidatawiki",
+            vec![Title::new("Q13520818", 0)],
+        );
+        check_results_for_psid_ext(
+            10140344,
+            "&regexp_filter=Graaf.*",
+            "wikidatawiki",
+            vec![Title::new("Q12345", 0)],
+        );
+        check_results_for_psid_ext(
+            10140616,
+            "&regexp_filter=&regexp_filter=Jimbo.*",
+            "enwiki",
+            vec![Title::new("Jimbo Wales", 0)],
+        );
+        check_results_for_psid_ext(
+            10140616,
+            "&regexp_filter=&regexp_filter=.*Sanger",
+            "enwiki",
+            vec![Title::new("Larry Sanger", 0)],
+        );
+    }
+
+    #[test]
+    fn test_manual_list_wikidata_regexp_invert() {
+        check_results_for_psid_ext(
+            10140344,
+            "&regexp_filter=.*Manske&regexp_filter_invert=1",
+            "wikidatawiki",
+            vec![Title::new("Q12345", 0)],
+        );
+        check_results_for_psid_ext(
+            10140344,
+            "&regexp_filter=Graaf.*&regexp_filter_invert=1",
+            "wikidatawiki",
+            vec![Title::new("Q13520818", 0)],
+        );
+    }
+
+    #[test]
+    fn test_en_categories_sparql_common_wiki_other() {
+        check_results_for_psid(15960820, "frwiki", vec![Title::new("Magnus Manske", 0)]);
+    }
 
-        let mut sql_post: SQLtuple = ("".to_string(), vec![]);
-        if no_statements {
-            sql_post.0 += " AND EXISTS (SELECT * FROM page_props WHERE page_id=pp_page AND pp_propname='wb-claims' AND pp_sortkey=0)" ;
-        }
-        if no_sitelinks {
-            sql_post.0 += " AND EXISTS (SELECT * FROM page_props WHERE page_id=pp_page AND pp_propname='wb-sitelinks' AND pp_sortkey=0)" ;
-        }
-        if !parts.is_empty() {
-            match wpiu.as_str() {
-                "all" => {
-                    parts.iter().for_each(|sql| {
-                        sql_post.0 += &(" AND EXISTS ".to_owned() + &sql.0);
-                        sql_post.1.append(&mut sql.1.to_owned());
-                    });
-                }
-                "any" => {
-                    sql_post.0 += " AND (0";
-                    parts.iter().for_each(|sql| {
-                        sql_post.0 += &(" OR EXISTS ".to_owned() + &sql.0);
-                        sql_post.1.append(&mut sql.1.to_owned());
-                    });
-                    sql_post.0 += ")";
-                }
-                "none" => {
-                    parts.iter().for_each(|sql| {
-                        sql_post.0 += &(" AND NOT EXISTS ".to_owned() + &sql.0);
-                        sql_post.1.append(&mut sql.1.to_owned());
-                    });
-                }
-                _ => {}
-            }
+    #[test]
+    fn test_trim_extended_whitespace() {
+        let platform = run_psid(15015735); // The categories contain a left-to-right mark
+        let result = platform.result.unwrap();
+        let entries = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<PageListEntry>>();
+        assert!(entries.len() > 20);
+    }
+
+    #[test]
+    fn test_template_talk_pages() {
+        let platform = run_psid(15059382);
+        let result = platform.result.unwrap();
+        let entries = result
+            .entries()
+            .read()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<PageListEntry>>();
+        assert!(entries.len() > 0);
+        for entry in entries {
+            assert_eq!(entry.title().namespace_id(), 0);
         }
+    }
 
-        // Batches
-        let batches: Vec<SQLtuple> = result
-            .to_sql_batches(PAGE_BATCH_SIZE)?
-            .iter_mut()
-            .map(|sql| {
-                sql.0 = "SELECT DISTINCT page_title FROM page WHERE ".to_owned()
-                    + &sql.0
-                    + &sql_post.0.to_owned();
-                sql.1.append(&mut sql_post.1.to_owned());
-                sql.to_owned()
-            })
-            .collect::<Vec<SQLtuple>>();
+    #[test]
+    fn test_manual_list_wikidata_entity_type() {
+        let query =
+            "manual_list=Q42%0AProperty:P31%0ALexeme:L7&manual_list_wiki=wikidatawiki&doit=1";
+        let entity_type = |t: &str| -> Vec<Title> {
+            let platform = run_query(&format!("{}&wikidata_entity_type={}", query, t));
+            let result = platform.result.unwrap();
+            let mut titles = result
+                .entries()
+                .read()
+                .unwrap()
+                .iter()
+                .map(|e| e.title())
+                .cloned()
+                .collect::<Vec<Title>>();
+            titles.sort_by_key(|t| t.namespace_id());
+            titles
+        };
+        assert_eq!(entity_type("item"), vec![Title::new("Q42", 0)]);
+        assert_eq!(entity_type("property"), vec![Title::new("P31", 120)]);
+        assert_eq!(entity_type("lexeme"), vec![Title::new("L7", 146)]);
+        assert_eq!(entity_type("any").len(), 3);
+
+        let form_parameters =
+            FormParameters::outcome_from_query(&format!("{}&wikidata_entity_type=items", query))
+                .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let error = platform.run().unwrap_err().to_string();
+        assert!(error.contains("Unknown wikidata_entity_type 'items'"));
+    }
 
-        result.clear_entries()?;
-        let ret = result.process_batch_results(&self.state(), batches, &|row: my::Row| {
-            let pp_value: String = my::from_row(row);
-            Some(PageListEntry::new(Title::new(&pp_value, 0)))
-        });
-        match original_wiki {
-            Some(wiki) => result.convert_to_wiki(&wiki, &self)?,
-            None => {}
-        }
-        ret
+    #[test]
+    fn test_load_page_contents() {
+        let platform = run_psid(10087995);
+        let result = platform.result().as_ref().unwrap();
+        platform.load_page_contents(result).unwrap();
+        let content = platform
+            .get_page_content("enwiki", &Title::new("Magnus_Manske", 0))
+            .unwrap();
+        assert!(content.contains("Manske"));
     }
 
-    pub fn entry_from_entity(entity: &str) -> Option<PageListEntry> {
-        // TODO media-info?
-        match entity.chars().next() {
-            Some('Q') => Some(PageListEntry::new(Title::new(&entity.to_string(), 0))),
-            Some('P') => Some(PageListEntry::new(Title::new(&entity.to_string(), 120))),
-            Some('L') => Some(PageListEntry::new(Title::new(&entity.to_string(), 146))),
-            _ => None,
-        }
+    #[test]
+    fn test_content_regexp() {
+        check_results_for_psid_ext(
+            10087995,
+            "&content_regexp=MANSKE&content_regexp_case_insensitive=1",
+            "enwiki",
+            vec![Title::new("Magnus_Manske", 0)],
+        );
+        check_results_for_psid_ext(
+            10087995,
+            "&content_regexp=XyzzyNotOnThePage",
+            "enwiki",
+            vec![],
+        );
+        let form_parameters = FormParameters::outcome_from_query("content_regexp=(").unwrap();
+        let platform = Platform::new_from_parameters(&form_parameters, get_state());
+        let result = PageList::new_from_wiki("enwiki");
+        assert!(platform.process_content_regexp(&result).is_err());
     }
 
-    pub fn usize_option_from_param(&self, key: &str) -> Option<usize> {
-        self.get_param(key)?.parse::<usize>().ok()
+    #[test]
+    fn test_wiki_by_source() {
+        let platform = run_psid(10087995);
+        assert_eq!(
+            platform.wiki_by_source().get("manual"),
+            Some(&"enwiki".to_string())
+        );
     }
 
-    pub fn get_main_wiki(&self) -> Option<String> {
-        let language = self.get_param_default("lang", "en"); // Fallback
-        let language = self
-            .get_param_default("language", &language)
-            .replace("_", "-");
-        let project = self.get_param_default("project", "wikipedia");
-        self.get_wiki_for_language_project(&language, &project)
+    #[test]
+    fn test_template_like_pattern() {
+        assert_eq!(Platform::template_like_pattern("*-stub"), "%-stub");
+        assert_eq!(
+            Platform::template_like_pattern(" Bio stub_x "),
+            "Bio\\_stub\\_x"
+        );
+        assert_eq!(Platform::template_like_pattern("50%*"), "50\\%%");
     }
 
-    pub fn get_wiki_for_language_project(
-        &self,
-        language: &String,
-        project: &String,
-    ) -> Option<String> {
-        match (language.as_str(), project.as_str()) {
-            (language, "wikipedia") => Some(language.to_owned() + "wiki"),
-            ("commons", _) => Some("commonswiki".to_string()),
-            ("wikidata", _) => Some("wikidatawiki".to_string()),
-            (_, "wikidata") => Some("wikidatawiki".to_string()),
-            (l, p) => {
-                let url = "https://".to_string() + &l + "." + &p + ".org";
-                self.state.get_wiki_for_server_url(&url)
-            }
-        }
+    #[test]
+    fn test_stubs_only() {
+        check_results_for_psid_ext(
+            10087995,
+            "&stubs_only=1&stubs_max_bytes=100000000",
+            "enwiki",
+            vec![Title::new("Magnus_Manske", 0)],
+        );
+        check_results_for_psid_ext(
+            10087995,
+            "&stubs_only=1&stubs_max_bytes=1&stubs_template=No_such_template_*",
+            "enwiki",
+            vec![],
+        );
+
+        // Pages in a stub category carry a "*-stub" template, whatever their size
+        let platform = run_query("language=en&project=wikipedia&categories=Biology_stubs&depth=0&ns[0]=1&stubs_only=1&stubs_max_bytes=1&doit=1");
+        let result = platform.result().as_ref().unwrap();
+        assert!(!result.is_empty().unwrap());
+        assert!(result
+            .with_entries(|entries| entries.iter().all(|entry| entry.page_bytes > Some(1)))
+            .unwrap());
     }
 
-    pub fn get_response(&self) -> Result<MyResponse, String> {
-        // Shortcut: WDFIST
-        match &self.wdfist_result {
-            Some(j) => {
-                return Ok(self
-                    .state
-                    .output_json(j, self.form_parameters.params.get("callback")));
-            }
-            None => {}
-        }
+    #[test]
+    fn test_sort_by_wikidata_property() {
+        let platform = run_query(
+            "manual_list=Q937%0AQ42%0AQ2%0AQ1339&manual_list_wiki=wikidatawiki&sortby=wd:P569&doit=1",
+        );
+        let entries = platform
+            .result
+            .as_ref()
+            .unwrap()
+            .drain_into_sorted_vec(PageListSort::new_from_params(&"wd:P569".to_string(), false))
+            .unwrap();
+        let titles: Vec<Title> = entries.iter().map(|e| e.title()).cloned().collect();
+        // Q2 (Earth) has no date of birth and sorts last
+        assert_eq!(
+            titles,
+            vec![
+                Title::new("Q1339", 0),
+                Title::new("Q937", 0),
+                Title::new("Q42", 0),
+                Title::new("Q2", 0)
+            ]
+        );
+    }
 
-        let result = match &self.result {
-            Some(result) => result,
-            None => return Err(format!("Platform::get_response: No result")),
-        };
-        let wiki = match result.wiki()? {
-            Some(wiki) => wiki,
-            None => return Err(format!("Platform::get_response: No wiki in result")),
-        };
+    #[test]
+    fn test_cascade_protected() {
+        // Articles are not transcluded on cascade-protected pages
+        check_results_for_psid_ext(10087995, "&cascade_protected=yes", "enwiki", vec![]);
+        check_results_for_psid_ext(
+            10087995,
+            "&cascade_protected=no",
+            "enwiki",
+            vec![Title::new("Magnus_Manske", 0)],
+        );
 
-        let mut sortby = self.get_param_blank("sortby");
-        let mut sort_order = self.get_param_blank("sortorder") == "descending".to_string();
-        if self.do_output_redlinks() && (sortby.is_empty() || sortby == "none") {
-            sortby = "redlinks".to_string();
-            sort_order = true;
-        }
-        let mut pages =
-            result.drain_into_sorted_vec(PageListSort::new_from_params(&sortby, sort_order))?;
-        drop(result);
-        self.apply_results_limit(&mut pages);
+        // Template:In_the_news is transcluded on the cascade-protected Main Page
+        let platform = run_query("manual_list=Template:In_the_news%0AMagnus_Manske&manual_list_wiki=enwiki&cascade_protected=yes&doit=1");
+        let entries = platform.result().as_ref().unwrap().entries();
+        let entries = entries.read().unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = entries
+            .get(&PageListEntry::new(Title::new("In_the_news", 10)))
+            .unwrap();
+        assert_eq!(entry.cascade_protected, TriState::Yes);
+    }
 
-        let renderer: Box<dyn Render> = match self.get_param_blank("format").as_str() {
-            "wiki" => RenderWiki::new(),
-            "csv" => RenderTSV::new(","),
-            "tsv" => RenderTSV::new("\t"),
-            "json" => RenderJSON::new(),
-            "pagepile" => RenderPagePile::new(),
-            _ => RenderHTML::new(),
+    #[test]
+    fn test_protection() {
+        let titles_for = |protection: &str| {
+            let platform = run_query(&format!(
+                "manual_list=Main_Page%0AMagnus_Manske&manual_list_wiki=enwiki&protection={}&doit=1",
+                protection
+            ));
+            let entries = platform.result().as_ref().unwrap().entries();
+            let mut titles: Vec<String> = entries
+                .read()
+                .unwrap()
+                .iter()
+                .map(|e| e.title().pretty().to_string())
+                .collect();
+            titles.sort();
+            titles
         };
-        renderer.response(&self, &wiki, pages)
+        // Move-protected only
+        assert_eq!(titles_for("move%3Dsysop"), vec!["Main Page"]);
+        // Unprotected only
+        assert_eq!(titles_for("none"), vec!["Magnus Manske"]);
     }
 
-    pub fn get_param_as_vec(&self, param: &str, separator: &str) -> Vec<String> {
-        match self.get_param(param) {
-            Some(s) => s
-                .split(separator)
-                .map(|s| s.trim().trim_matches('\u{200E}').trim_matches('\u{200F}')) // See https://doc.rust-lang.org/reference/whitespace.html
-                .filter(|s| !s.is_empty())
-                .map(|s| Title::spaces_to_underscores(&s.to_string()))
-                .collect(),
-            None => vec![],
-        }
+    #[test]
+    fn test_content_model() {
+        let platform = run_query(
+            "manual_list=Module:Citation/CS1%0ATemplate:Citation%0AMagnus_Manske&manual_list_wiki=enwiki&content_model=Scribunto&doit=1",
+        );
+        let entries = platform.result().as_ref().unwrap().entries();
+        let titles: Vec<Title> = entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| e.title().to_owned())
+            .collect();
+        assert_eq!(titles, vec![Title::new("Citation/CS1", 828)]);
     }
 
-    pub fn get_param_blank(&self, param: &str) -> String {
-        self.get_param(param).unwrap_or("".to_string())
+    #[test]
+    fn test_links_to_page() {
+        check_results_for_psid_ext(
+            10087995,
+            "&links_to_page=MediaWiki",
+            "enwiki",
+            vec![Title::new("Magnus_Manske", 0)],
+        );
+        check_results_for_psid_ext(
+            10087995,
+            "&links_to_page=MediaWiki&links_to_page_mode=none",
+            "enwiki",
+            vec![],
+        );
+        check_results_for_psid_ext(
+            10087995,
+            "&links_to_page=No_such_page_for_PetScan%0AMediaWiki",
+            "enwiki",
+            vec![Title::new("Magnus_Manske", 0)],
+        );
+        check_results_for_psid_ext(
+            10087995,
+            "&links_to_page=No_such_page_for_PetScan%0AMediaWiki&links_to_page_mode=all",
+            "enwiki",
+            vec![],
+        );
     }
 
-    pub fn get_param_default(&self, param: &str, default: &str) -> String {
-        let ret = self.get_param(param).unwrap_or(default.to_string());
-        if ret.is_empty() {
-            default.to_string()
-        } else {
-            ret
-        }
-    }
+    #[test]
+    fn test_output_limit_truncation() {
+        let query = "manual_list=Magnus_Manske%0AJimbo_Wales&manual_list_wiki=enwiki&doit=1";
+        let json_for = |addendum: &str| -> Value {
+            let platform = run_query(&format!("{}{}", query, addendum));
+            serde_json::from_str(&platform.get_response().unwrap().s).unwrap()
+        };
 
-    pub fn append_sql(sql: &mut SQLtuple, mut sub: SQLtuple) {
-        sql.0 += &sub.0;
-        sql.1.append(&mut sub.1);
+        let json = json_for("&format=json&output_limit=1");
+        assert_eq!(json["a"]["truncated"].as_bool(), Some(true));
+        assert_eq!(
+            json["a"]["truncation_reasons"][0].as_str(),
+            Some("output_limit: showing 1 of 2 results")
+        );
+
+        let json = json_for("&format=json&output_compatability=quick-intersection&output_limit=1");
+        assert_eq!(json["truncated"].as_bool(), Some(true));
+
+        // Not truncated if the limit is not reached
+        let json = json_for("&format=json&output_limit=2");
+        assert!(json["a"]["truncated"].is_null());
+
+        let platform = run_query(&format!("{}&format=html&output_limit=1", query));
+        assert!(platform.get_response().unwrap().s.contains(
+            "<span tt='warn_results_truncated'></span> output_limit: showing 1 of 2 results"
+        ));
     }
 
-    /// Returns a tuple with a string containing comma-separated question marks, and the (non-empty) Vec elements
-    pub fn prep_quote(strings: &[String]) -> SQLtuple {
-        let escaped: Vec<String> = strings
-            .par_iter()
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .map(|s| s.to_string())
+    #[test]
+    fn test_limit_per_namespace() {
+        let platform = run_query(
+            "manual_list=Magnus_Manske&manual_list_wiki=enwiki&limit_per_namespace=2&doit=1",
+        );
+        let titles = vec![
+            Title::new("A", 0),
+            Title::new("A", 2),
+            Title::new("B", 0),
+            Title::new("A", 14),
+            Title::new("C", 0),
+            Title::new("B", 2),
+            Title::new("D", 0),
+        ];
+        let mut entries: Vec<PageListEntry> = titles
+            .iter()
+            .map(|title| PageListEntry::new(title.to_owned()))
             .collect();
-        (Platform::get_questionmarks(escaped.len()), escaped)
+        platform.apply_namespace_limit(&mut entries).unwrap();
+        let titles: Vec<Title> = entries.iter().map(|e| e.title()).cloned().collect();
+        assert_eq!(
+            titles,
+            vec![
+                Title::new("A", 0),
+                Title::new("A", 2),
+                Title::new("B", 0),
+                Title::new("A", 14),
+                Title::new("B", 2),
+            ]
+        );
+        assert_eq!(
+            platform.truncations().unwrap(),
+            vec!["limit_per_namespace: showing 2 of 4 results in namespace 0".to_string()]
+        );
     }
 
-    pub fn full_entity_id_to_number(strings: &[String]) -> SQLtuple {
-        let escaped: Vec<String> = strings
-            .par_iter()
-            .map(|s| s.trim())
-            .filter(|s| !s.is_empty())
-            .map(|s| s[1..].to_string())
-            .collect();
-        (Platform::get_questionmarks(escaped.len()), escaped)
+    #[test]
+    fn test_output_limit_above_result_count() {
+        let platform = run_query(
+            "manual_list=Magnus_Manske%0AJimbo_Wales&manual_list_wiki=enwiki&output_limit=10&doit=1",
+        );
+        let mut entries = platform
+            .result()
+            .as_ref()
+            .unwrap()
+            .drain_into_sorted_vec(PageListSort::Default(false))
+            .unwrap();
+        platform.apply_results_limit(&mut entries).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries.iter().any(|e| e.title().pretty().is_empty()));
+        assert!(platform.truncations().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_render_warning() {
+        assert_eq!(
+            RenderHTML::render_warning("<span tt='warn_sparql'></span>"),
+            "<span tt='warn_sparql'></span>"
+        );
+        assert_eq!(
+            RenderHTML::render_warning("<span tt='warn_blacklist_removed' num='2'></span>"),
+            "<span tt='warn_blacklist_removed' num='2'></span>"
+        );
+        assert_eq!(
+            RenderHTML::render_warning("Source manual failed and was skipped: <script>x</script>"),
+            "Source manual failed and was skipped: &lt;script&gt;x&lt;/script&gt;"
+        );
+        assert_eq!(
+            RenderHTML::render_warning("<span tt='warn_sparql' onmouseover='x'></span>"),
+            "&lt;span tt=&#x27;warn_sparql&#x27; onmouseover=&#x27;x&#x27;&gt;&lt;/span&gt;"
+        );
     }
 
-    // Generates a string with `len` comma-separated question marks
-    pub fn get_questionmarks(len: usize) -> String {
-        let mut questionmarks: Vec<String> = Vec::new();
-        questionmarks.resize(len, "?".to_string());
-        questionmarks.join(",")
+    #[test]
+    fn test_pageviews_from_response() {
+        let ok = r#"{"items":[{"views":3},{"views":4}]}"#;
+        assert_eq!(
+            Platform::pageviews_from_response(reqwest::StatusCode::OK, ok),
+            Some(7)
+        );
+        assert_eq!(
+            Platform::pageviews_from_response(reqwest::StatusCode::NOT_FOUND, "{}"),
+            Some(0)
+        );
+        assert_eq!(
+            Platform::pageviews_from_response(reqwest::StatusCode::INTERNAL_SERVER_ERROR, ok),
+            None
+        );
+        assert_eq!(
+            Platform::pageviews_from_response(reqwest::StatusCode::OK, "not json"),
+            None
+        );
     }
 
-    pub fn sql_tuple() -> SQLtuple {
-        ("".to_string(), vec![])
+    #[test]
+    fn test_html_locale() {
+        let de = HtmlLocale::new("de");
+        assert_eq!(de.format_number("1234567"), "1.234.567");
+        assert_eq!(de.format_number("-1234"), "-1.234");
+        assert_eq!(de.format_number("123"), "123");
+        assert_eq!(de.format_number("12.5"), "12.5");
+        assert_eq!(de.format_timestamp("20190102030405"), "02.01.2019 03:04:05");
+        assert_eq!(de.format_timestamp("2019-01-02"), "2019-01-02");
+        assert_eq!(HtmlLocale::new("en-gb").format_number("1234"), "1,234");
+        assert_eq!(HtmlLocale::new("fr").format_number("1234"), "1\u{a0}234");
+
+        // Unknown locales use a neutral format
+        let neutral = HtmlLocale::new("xx");
+        assert_eq!(neutral.format_number("1234567"), "1234567");
+        assert_eq!(
+            neutral.format_timestamp("20190102030405"),
+            "2019-01-02 03:04:05"
+        );
     }
 
-    fn get_label_sql_helper(&self, ret: &mut SQLtuple, part1: &str, part2: &str) {
-        let mut types: Vec<String> = vec![];
-        if self.has_param(&("cb_labels_".to_owned() + part1 + "_l")) {
-            types.push("label".to_string());
-        }
-        if self.has_param(&("cb_labels_".to_owned() + part1 + "_a")) {
-            types.push("alias".to_string());
-        }
-        if self.has_param(&("cb_labels_".to_owned() + part1 + "_d")) {
-            types.push("description".to_string());
-        }
-        if !types.is_empty() {
-            let mut tmp = Self::prep_quote(&types);
-            ret.0 += &(" AND ".to_owned() + part2 + &" IN (".to_owned() + &tmp.0 + ")");
-            ret.1.append(&mut tmp.1);
-        }
+    #[test]
+    fn test_timestamp_format() {
+        let timestamp = "20190102030405";
+        assert_eq!(
+            TimestampFormat::new_from_param("")
+                .unwrap()
+                .format(timestamp),
+            "20190102030405"
+        );
+        assert_eq!(
+            TimestampFormat::new_from_param("mw")
+                .unwrap()
+                .format(timestamp),
+            "20190102030405"
+        );
+        assert_eq!(
+            TimestampFormat::new_from_param("iso8601")
+                .unwrap()
+                .format(timestamp),
+            "2019-01-02T03:04:05Z"
+        );
+        assert_eq!(
+            TimestampFormat::new_from_param("unix")
+                .unwrap()
+                .format(timestamp),
+            "1546398245"
+        );
+        // Not a MediaWiki timestamp
+        assert_eq!(TimestampFormat::Unix.format("yesterday"), "yesterday");
+        assert!(TimestampFormat::new_from_param("rfc2822").is_err());
     }
 
-    pub fn get_label_sql(&self) -> SQLtuple {
-        lazy_static! {
-            static ref RE1: Regex =
-                Regex::new(r#"[^a-z,]"#).expect("Platform::get_label_sql Regex is invalid");
-        }
-        let mut ret: SQLtuple = ("".to_string(), vec![]);
-        let yes = self.get_param_as_vec("labels_yes", "\n");
-        let any = self.get_param_as_vec("labels_any", "\n");
-        let no = self.get_param_as_vec("labels_no", "\n");
-        if yes.len() + any.len() + no.len() == 0 {
-            return ret;
-        }
-
-        let langs_yes = self.get_param_as_vec("langs_labels_yes", ",");
-        let langs_any = self.get_param_as_vec("langs_labels_any", ",");
-        let langs_no = self.get_param_as_vec("langs_labels_no", ",");
-
-        ret.0 = "SELECT DISTINCT concat('Q',wbit_item_id) AS term_full_entity_id 
-            FROM wbt_text,wbt_item_terms wbt_item_terms1,wbt_type,wbt_term_in_lang,wbt_text_in_lang 
-            WHERE wbit_term_in_lang_id = wbtl_id 
-            AND wbtl_type_id = wby_id 
-            AND wbtl_text_in_lang_id = wbxl_id 
-            AND wbxl_text_id = wbx_id"
-            .to_string();
+    #[test]
+    fn test_json_omit_empty() {
+        let addendum = "&format=json";
+        let full = run_psid_ext(10087995, addendum).unwrap();
+        let full = full.get_response().unwrap().s;
+        let sparse = run_psid_ext(10087995, &format!("{}&omit_empty=1", addendum)).unwrap();
+        let sparse = sparse.get_response().unwrap().s;
+        // Main namespace pages have an empty "nstext"
+        assert!(full.contains("\"nstext\":\"\""));
+        assert!(!sparse.contains("\"nstext\""));
+        assert!(sparse.len() < full.len());
+    }
 
-        yes.iter().for_each(|s| {
-            if s != "%" {
-                ret.0 += &(" AND wbx_text LIKE ?".to_owned());
-                ret.1.push(s.to_string());
-            }
-            if !langs_yes.is_empty() {
-                let mut tmp = Self::prep_quote(&langs_yes);
-                ret.0 += &(" AND wbxl_language IN (".to_owned() + &tmp.0 + ")");
-                ret.1.append(&mut tmp.1);
-                self.get_label_sql_helper(&mut ret, "yes", "wby_name");
-            }
-        });
+    #[test]
+    fn test_maintenance_tagged() {
+        check_results_for_psid_ext(
+            10087995,
+            "&maintenance_tagged=1&maintenance_templates=No_such_template_*",
+            "enwiki",
+            vec![],
+        );
+        // Biographies use some template starting with "I", e.g. "Infobox"
+        check_results_for_psid_ext(
+            10087995,
+            "&maintenance_tagged=1&maintenance_templates=I*",
+            "enwiki",
+            vec![Title::new("Magnus_Manske", 0)],
+        );
+    }
 
-        if !langs_any.is_empty() {
-            ret.0 += " AND (";
-            let mut first = true;
-            any.iter().for_each(|s| {
-                if first {
-                    first = false;
-                } else {
-                    ret.0 += " OR "
-                }
-                if s != "%" {
-                    ret.0 += &(" ( wbx_text LIKE ?".to_owned());
-                    ret.1.push(s.to_string());
-                }
-                if !langs_any.is_empty() {
-                    let mut tmp = Self::prep_quote(&langs_any);
-                    ret.0 += &(" AND wbxl_language IN (".to_owned() + &tmp.0 + ")");
-                    ret.1.append(&mut tmp.1);
-                    self.get_label_sql_helper(&mut ret, "any", "wby_name");
-                }
-                ret.0 += ")";
-            });
-            ret.0 += ")";
-        }
+    #[test]
+    fn test_sparql_extra_vars() {
+        let platform = run_query("sparql=SELECT%20%3Fq%20%3Fx%20%7B%20VALUES%20(%3Fq%20%3Fx)%20%7B%20(wd%3AQ42%20%2242%22)%20(wd%3AQ2%20UNDEF)%20%7D%20%7D&sparql_extra_vars=1&doit=1");
+        assert_eq!(platform.sparql_extra_vars().unwrap(), vec!["x".to_string()]);
+        let result = platform.result.unwrap();
+        let entries = result.entries().read().unwrap();
+        let value = |q: &str| {
+            entries
+                .get(&PageListEntry::new(Title::new(q, 0)))
+                .and_then(|e| e.get_extra("x"))
+        };
+        assert_eq!(value("Q42"), Some("42".to_string()));
+        assert_eq!(value("Q2"), None);
+    }
 
-        no.iter().for_each(|s| {
-            ret.0 += " AND NOT EXISTS (
-                SELECT * FROM 
-                wbt_text wbt_text2,
-                wbt_item_terms wbt_item_terms2,
-                wbt_type wbt_type2,
-                wbt_term_in_lang wbt_term_in_lang2,
-                wbt_text_in_lang wbt_text_in_lang2
-                WHERE wbt_item_terms2.wbit_term_in_lang_id = wbt_term_in_lang2.wbtl_id 
-                AND wbt_term_in_lang2.wbtl_type_id = wbt_type2.wby_id 
-                AND wbt_term_in_lang2.wbtl_text_in_lang_id = wbt_text_in_lang2.wbxl_id 
-                AND wbt_text_in_lang2.wbxl_text_id = wbt_text2.wbx_id
-                AND wbt_item_terms1.wbit_item_id=wbt_item_terms2.wbit_item_id 
-                AND wbt_type2.wby_name='item'";
-            if s != "%" {
-                ret.0 += &(" AND wbt_text2.wbx_text LIKE ?".to_owned());
-                ret.1.push(s.to_string());
-            }
-            if !langs_no.is_empty() {
-                let mut tmp = Self::prep_quote(&langs_no);
-                ret.0 += &(" AND wbt_type2.wbxl_language IN (".to_owned() + &tmp.0 + ")");
-                ret.1.append(&mut tmp.1);
-                self.get_label_sql_helper(&mut ret, "no", "wbt_type2.wby_name");
-            }
-            ret.0 += ")";
-        });
-        println!("{:?}", &ret);
-        ret
+    #[test]
+    fn test_sparql_mediainfo_entity() {
+        let platform = run_query("sparql=SELECT%20%3Fm%20%7B%20VALUES%20%3Fm%20%7B%20%3Chttps%3A%2F%2Fcommons.wikimedia.org%2Fentity%2FM12345%3E%20wd%3AQ42%20%7D%20%7D&doit=1");
+        let result = platform.result.as_ref().unwrap();
+        // Can not be mixed with Wikidata items
+        assert_eq!(result.wiki().unwrap(), Some("wikidatawiki".to_string()));
+        let entries = result.entries().read().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries.contains(&PageListEntry::new(Title::new("Q42", 0))));
+        assert!(platform.warnings().unwrap()[0].contains("MediaInfo"));
+
+        // [[commons:M12345]] is the MediaInfo entity of the file with page ID 12345
+        let platform = run_query("sparql=SELECT%20%3Fm%20%7B%20VALUES%20%3Fm%20%7B%20%3Chttps%3A%2F%2Fcommons.wikimedia.org%2Fentity%2FM12345%3E%20%7D%20%7D&doit=1");
+        let result = platform.result.as_ref().unwrap();
+        assert_eq!(result.wiki().unwrap(), Some("commonswiki".to_string()));
+        let entries = result.entries().read().unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = entries.iter().next().unwrap();
+        assert_eq!(entry.title().namespace_id(), 6);
+        assert_eq!(entry.page_id, Some(12345));
+        assert!(!entry.title().pretty().starts_with("M12345"));
     }
 
-    fn parse_combination_string(s: &String) -> Combination {
-        lazy_static! {
-            static ref RE: Regex = Regex::new(r"\w+(?:'\w+)?|[^\w\s]")
-                .expect("Platform::parse_combination_string: Regex is invalid");
-        }
-        match s.trim().to_lowercase().as_str() {
-            "" => return Combination::None,
-            "categories" | "sparql" | "manual" | "pagepile" | "wikidata" | "search" => {
-                return Combination::Source(s.to_string())
-            }
-            _ => {}
-        }
-        let mut parts: Vec<String> = RE
-            .captures_iter(s)
-            .filter_map(|cap| cap.get(0))
-            .map(|s| s.as_str().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
-        // Problem?
-        if parts.len() < 3 {
-            return Combination::None;
-        }
+    #[test]
+    fn test_normalize_title() {
+        let state = get_state();
+        let api = state.get_api_for_wiki("enwiki".to_string()).unwrap();
+        assert_eq!(
+            Platform::normalize_title(" category:foo__bar  baz", &api),
+            Title::new("Foo bar baz", 14)
+        );
+        assert_eq!(
+            Platform::normalize_title("talk : magnus_manske", &api),
+            Title::new("Magnus Manske", 1)
+        );
+        assert_eq!(
+            Platform::normalize_title(":magnus manske", &api),
+            Title::new("Magnus Manske", 0)
+        );
+        assert_eq!(
+            Platform::normalize_title("star Wars: A New Hope", &api),
+            Title::new("Star Wars: A New Hope", 0)
+        );
+        let api = state.get_api_for_wiki("enwiktionary".to_string()).unwrap();
+        assert_eq!(
+            Platform::normalize_title("foo_bar", &api),
+            Title::new("foo bar", 0)
+        );
+    }
 
-        let first_part = match parts.get(0) {
-            Some(part) => part.to_owned(),
-            None => "".to_string(),
-        };
-        let left = if first_part == "(" {
-            let mut cnt = 0;
-            let mut new_left: Vec<String> = vec![];
-            loop {
-                if parts.is_empty() {
-                    return Combination::None; // Failure to parse
-                }
-                let x = parts.remove(0);
-                if x == "(" {
-                    if cnt > 0 {
-                        new_left.push(x.to_string());
-                    }
-                    cnt += 1;
-                } else if x == ")" {
-                    cnt -= 1;
-                    if cnt == 0 {
-                        break;
-                    } else {
-                        new_left.push(x.to_string());
-                    }
-                } else {
-                    new_left.push(x.to_string());
-                }
-            }
-            new_left.join(" ")
-        } else {
-            parts.remove(0)
+    #[test]
+    fn test_seeded_random_sample() {
+        let items: Vec<String> = (1..=40).map(|q| format!("Q{}", q)).collect();
+        let query = format!(
+            "manual_list={}&manual_list_wiki=wikidatawiki&format=tsv&sortby=random&output_limit=10&doit=1",
+            items.join("%0A")
+        );
+        let sample = |seed: u64| {
+            run_query(&format!("{}&random_seed={}", query, seed))
+                .get_response()
+                .unwrap()
+                .s
         };
-        if parts.is_empty() {
-            return Self::parse_combination_string(&left);
-        }
-        let comb = parts.remove(0);
-        let left = Box::new(Self::parse_combination_string(&left));
-        let rest = Box::new(Self::parse_combination_string(&parts.join(" ")));
-        match comb.trim().to_lowercase().as_str() {
-            "and" => Combination::Intersection((left, rest)),
-            "or" => Combination::Union((left, rest)),
-            "not" => Combination::Not((left, rest)),
-            _ => Combination::None,
-        }
+        assert_eq!(sample(42), sample(42));
+        assert_ne!(sample(42), sample(43));
     }
 
-    /// Checks is the parameter is set, and non-blank
-    pub fn has_param(&self, param: &str) -> bool {
-        match self.form_parameters().params.get(&param.to_string()) {
-            Some(s) => s != "",
-            None => false,
-        }
+    #[test]
+    fn test_geojson() {
+        let platform = run_query("manual_list=Berlin%0AMagnus_Manske&manual_list_wiki=enwiki&add_coordinates=1&format=geojson&doit=1");
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.content_type, ContentType::GeoJSON);
+        let json: Value = serde_json::from_str(&response.s).unwrap();
+        assert_eq!(json["type"].as_str(), Some("FeatureCollection"));
+        let features = json["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["properties"]["title"].as_str(), Some("Berlin"));
+        let lon = features[0]["geometry"]["coordinates"][0].as_f64().unwrap();
+        let lat = features[0]["geometry"]["coordinates"][1].as_f64().unwrap();
+        assert!(lat > 52.0 && lat < 53.0);
+        assert!(lon > 13.0 && lon < 14.0);
     }
 
-    pub fn get_param(&self, param: &str) -> Option<String> {
-        if self.has_param(param) {
-            self.form_parameters()
-                .params
-                .get(&param.to_string())
-                .map(|s| s.to_string())
-        } else {
-            None
-        }
+    #[test]
+    fn test_kml() {
+        let platform = run_query("manual_list=Berlin%0AMagnus_Manske&manual_list_wiki=enwiki&add_coordinates=1&format=kml&doit=1");
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.content_type, ContentType::KML);
+        assert_eq!(response.s.matches("<Placemark>").count(), 1);
+        assert!(response.s.contains("<name>Berlin</name>"));
+        assert!(response.s.contains("https://en.wikipedia.org/wiki/Berlin"));
+        assert!(response.s.contains("<Point><coordinates>13."));
     }
 
-    fn get_combination(&self, available_sources: &Vec<String>) -> Combination {
-        match self.get_param("source_combination") {
-            Some(combination_string) => Self::parse_combination_string(&combination_string),
-            None => {
-                let mut comb = Combination::None;
-                for source in available_sources {
-                    if comb == Combination::None {
-                        comb = Combination::Source(source.to_string());
-                    } else {
-                        comb = Combination::Intersection((
-                            Box::new(Combination::Source(source.to_string())),
-                            Box::new(comb),
-                        ));
-                    }
-                }
-                comb
-            }
+    #[test]
+    fn test_xlsx() {
+        use calamine::{DataType, Reader, Xlsx};
+        use std::io::Cursor;
+        let platform = run_psid_ext(10087995, "&format=xlsx").unwrap();
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.content_type, ContentType::XLSX);
+        let bytes = response.binary.unwrap();
+        let mut workbook = Xlsx::new(Cursor::new(bytes)).unwrap();
+        let range = workbook.worksheet_range("PetScan").unwrap().unwrap();
+        let mut rows = range.rows();
+        let header: Vec<DataType> = rows.next().unwrap().to_vec();
+        assert_eq!(header[1], DataType::String("title".to_string()));
+        assert_eq!(header[2], DataType::String("pageid".to_string()));
+        let row: Vec<DataType> = rows.next().unwrap().to_vec();
+        assert_eq!(row[1], DataType::String("Magnus_Manske".to_string()));
+        // Page IDs are written as numbers, not text
+        match row[2] {
+            DataType::Float(page_id) => assert!(page_id > 0.0),
+            _ => panic!("page_id is not a numeric cell: {:?}", row[2]),
         }
     }
 
-    fn combine_results(
-        &self,
-        results: &mut HashMap<String, PageList>,
-        combination: &Combination,
-    ) -> Result<PageList, String> {
-        match combination {
-            Combination::Source(s) => match results.remove(s) {
-                Some(r) => Ok(r),
-                None => Err(format!("No result for source {}", &s)),
-            },
-            Combination::Union((a, b)) => match (a.as_ref(), b.as_ref()) {
-                (Combination::None, c) => self.combine_results(results, c),
-                (c, Combination::None) => self.combine_results(results, c),
-                (c, d) => {
-                    let r1 = self.combine_results(results, c)?;
-                    let r2 = self.combine_results(results, d)?;
-                    r1.union(&r2, Some(&self))?;
-                    Ok(r1)
-                }
-            },
-            Combination::Intersection((a, b)) => match (a.as_ref(), b.as_ref()) {
-                (Combination::None, _c) => {
-                    Err(format!("Intersection with Combination::None found"))
-                }
-                (_c, Combination::None) => {
-                    Err(format!("Intersection with Combination::None found"))
-                }
-                (c, d) => {
-                    let r1 = self.combine_results(results, c)?;
-                    let r2 = self.combine_results(results, d)?;
-                    r1.intersection(&r2, Some(&self))?;
-                    Ok(r1)
-                }
-            },
-            Combination::Not((a, b)) => match (a.as_ref(), b.as_ref()) {
-                (Combination::None, _c) => Err(format!("Not with Combination::None found")),
-                (c, Combination::None) => self.combine_results(results, c),
-                (c, d) => {
-                    let r1 = self.combine_results(results, c)?;
-                    let r2 = self.combine_results(results, d)?;
-                    r1.difference(&r2, Some(&self))?;
-                    Ok(r1)
-                }
-            },
-            Combination::None => Err(format!("Combination::None found")),
-        }
+    #[test]
+    fn test_markdown() {
+        assert_eq!(RenderMarkdown::escape_cell("A|B"), "A\\|B");
+        assert_eq!(RenderMarkdown::escape_cell("A\\|B"), "A\\\\\\|B");
+        let platform = run_psid_ext(10087995, "&format=md").unwrap();
+        let md = platform.get_response().unwrap().s;
+        let lines: Vec<&str> = md.split('\n').collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("| title |"));
+        assert!(lines[1].starts_with("| --- |"));
+        assert!(lines[2]
+            .starts_with("| [Magnus Manske](https://en.wikipedia.org/wiki/Magnus_Manske) |"));
     }
 
-    pub fn result(&self) -> &Option<PageList> {
-        &self.result
+    #[test]
+    fn test_rss() {
+        let platform = run_psid_ext(10087995, "&format=rss&output_limit=5").unwrap();
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.content_type, ContentType::RSS);
+        let rss = response.s;
+        assert!(rss.starts_with("<?xml"));
+        assert_eq!(rss.matches("<item>").count(), 1);
+        assert_eq!(rss.matches("</item>").count(), 1);
+        let first_link = rss
+            .split("<item>")
+            .nth(1)
+            .and_then(|item| item.split("<link>").nth(1))
+            .and_then(|link| link.split("</link>").next());
+        assert_eq!(
+            first_link,
+            Some("https://en.wikipedia.org/wiki/Magnus_Manske")
+        );
     }
 
-    pub fn form_parameters(&self) -> &FormParameters {
-        &self.form_parameters
+    #[test]
+    fn test_jsonl() {
+        let platform = run_query("manual_list=Magnus_Manske%0AJimbo_Wales&manual_list_wiki=enwiki&format=jsonl&sortby=title&doit=1");
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.content_type, ContentType::JSONL);
+        let lines: Vec<Value> = response
+            .s
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["title"].as_str(), Some("Jimbo_Wales"));
+        assert_eq!(lines[1]["title"].as_str(), Some("Magnus_Manske"));
+        assert_eq!(lines[1]["namespace"].as_i64(), Some(0));
+        assert!(lines[1]["page_id"].as_u64().is_some());
+        // Unpopulated annotations are left out
+        assert!(lines[1].get("coordinates").is_none());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::app_state::AppState;
-    use serde_json::Value;
-    use std::env;
-    use std::fs::File;
+    #[test]
+    fn test_convert_label_fallback() {
+        let platform = run_query("manual_list=Q42&manual_list_wiki=wikidatawiki&common_wiki=other&common_wiki_other=enwiki&convert_label_fallback=1&doit=1");
+        let result = platform.result.unwrap();
+        assert_eq!(result.wiki(), Ok(Some("enwiki".to_string())));
+        let entries = result.entries().read().unwrap();
+        let entry = entries
+            .get(&PageListEntry::new(Title::new("Douglas_Adams", 0)))
+            .unwrap();
+        // Converted via sitelink, not via the label heuristic
+        assert_eq!(entry.get_extra("label_match_item"), None);
 
-    fn get_new_state() -> Arc<AppState> {
-        let basedir = env::current_dir()
-            .expect("Can't get CWD")
-            .to_str()
-            .unwrap()
-            .to_string();
-        let path = basedir.to_owned() + "/config.json";
-        let file = File::open(path).expect("Can not open config file");
-        let petscan_config: Value =
-            serde_json::from_reader(file).expect("Can not parse JSON from config file");
-        Arc::new(AppState::new_from_config(&petscan_config))
+        // Items can not have sitelinks to Wiktionary entries, but the label "dog" of Q144 is one
+        let platform = run_query("manual_list=Q144&manual_list_wiki=wikidatawiki&common_wiki=other&common_wiki_other=enwiktionary&convert_label_fallback=1&doit=1");
+        let result = platform.result.unwrap();
+        assert_eq!(result.wiki(), Ok(Some("enwiktionary".to_string())));
+        let entries = result.entries().read().unwrap();
+        let entry = entries
+            .get(&PageListEntry::new(Title::new("dog", 0)))
+            .unwrap();
+        assert_eq!(
+            entry.get_extra("label_match_item"),
+            Some("Q144".to_string())
+        );
     }
 
-    fn get_state() -> Arc<AppState> {
-        lazy_static! {
-            static ref STATE: Arc<AppState> = get_new_state();
-        }
-        STATE.clone()
+    #[test]
+    fn test_label_language_fallback() {
+        // Q63810120 is a scientific paper with an English, but no German, label
+        let platform = run_query("manual_list=Q63810120&manual_list_wiki=wikidatawiki&wikidata_label_language=de&label_fallback=en&doit=1");
+        let result = platform.result.unwrap();
+        let entries = result.entries().read().unwrap();
+        let entry = entries
+            .get(&PageListEntry::new(Title::new("Q63810120", 0)))
+            .unwrap();
+        assert_eq!(entry.get_label_language_used(), Some("en".to_string()));
+        assert!(entry.get_wikidata_label().is_some());
+    }
+
+    #[test]
+    fn test_titles() {
+        let query = "manual_list=Magnus_Manske%0ATalk:Berlin%0AJimbo_Wales&manual_list_wiki=enwiki&format=titles&sortby=ns_title&doit=1";
+        let platform = run_query(query);
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.content_type, ContentType::Plain);
+        assert_eq!(response.s, "Jimbo Wales\nMagnus Manske\nTalk:Berlin");
+        let platform = run_query(&format!("{}&titles_underscores=1", query));
+        assert_eq!(
+            platform.get_response().unwrap().s,
+            "Jimbo_Wales\nMagnus_Manske\nTalk:Berlin"
+        );
+        let platform = run_query("manual_list=Q42%0AProperty:P31&manual_list_wiki=wikidatawiki&format=titles&sortby=ns_title&doit=1");
+        assert_eq!(platform.get_response().unwrap().s, "Q42\nP31");
     }
 
-    fn run_psid_ext(psid: usize, addendum: &str) -> Result<Platform, String> {
-        let state = get_state();
-        let form_parameters = match state.get_query_from_psid(&format!("{}", &psid)) {
-            Ok(psid_query) => {
-                let query = psid_query + addendum;
-                FormParameters::outcome_from_query(&query)?
-            }
-            Err(e) => return Err(e),
+    #[test]
+    fn test_json_large_synthetic_list() {
+        let entries: Vec<PageListEntry> = (0..5000)
+            .map(|i| {
+                let mut entry = PageListEntry::new(Title::new(&format!("Page_{}", i), 0));
+                entry.page_id = Some(i + 1);
+                entry
+            })
+            .collect();
+        let wiki = "enwiki".to_string();
+        let entries_of = |addendum: &str| -> Value {
+            let platform = run_psid_ext(10087995, addendum).unwrap();
+            let s = RenderJSON::new()
+                .response(&platform, &wiki, entries.clone())
+                .unwrap()
+                .s;
+            let json: Value = serde_json::from_str(&s).unwrap();
+            assert!(json["a"]["querytime_sec"].is_number());
+            json["*"][0]["a"]["*"].to_owned()
         };
-        let mut platform = Platform::new_from_parameters(&form_parameters, state);
-        platform.run().unwrap();
-        Ok(platform)
+        // Incrementally written output matches the output built as one JSON value
+        let chunked = entries_of("&format=json");
+        assert_eq!(chunked.as_array().unwrap().len(), 5000);
+        assert_eq!(chunked[4999]["title"].as_str(), Some("Page_4999"));
+        assert_eq!(chunked, entries_of("&format=json&json-pretty=1"));
     }
 
-    fn run_psid(psid: usize) -> Platform {
-        run_psid_ext(psid, "").unwrap()
+    #[test]
+    fn test_sqldump() {
+        assert_eq!(RenderSQLDump::quote("Ender's_Game"), "'Ender\\'s_Game'");
+        assert_eq!(RenderSQLDump::quote("a\\b"), "'a\\\\b'");
+        let platform = run_query("manual_list=Ender%27s_Game&manual_list_wiki=enwiki&format=sqldump&sqldump_table=my_pages&doit=1");
+        let sql = platform.get_response().unwrap().s;
+        let lines: Vec<&str> = sql.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("CREATE TABLE IF NOT EXISTS `my_pages`"));
+        assert!(lines[1].starts_with(
+            "INSERT INTO `my_pages` (`wiki`,`namespace`,`title`,`page_id`,`wikidata_item`) VALUES ('enwiki',0,'Ender\\'s_Game',"
+        ));
+        let platform = run_query("manual_list=Ender%27s_Game&manual_list_wiki=enwiki&format=sqldump&sqldump_table=x;DROP&doit=1");
+        assert!(platform.get_response().is_err());
     }
 
-    fn check_results_for_psid_ext(psid: usize, addendum: &str, wiki: &str, expected: Vec<Title>) {
-        let mut platform = run_psid_ext(psid, addendum).unwrap();
-        let s1 = platform.get_param_blank("sortby");
-        let s2 = platform.get_param_blank("sortorder");
+    #[test]
+    fn test_csv_bom() {
+        let bom: &[u8] = &[0xEF, 0xBB, 0xBF];
+        let query = "manual_list=Magnus_Manske&manual_list_wiki=enwiki&doit=1";
+        let platform = run_query(&format!("{}&format=csv&csv_bom=1", query));
+        let s = platform.get_response().unwrap().s;
+        assert!(s.as_bytes().starts_with(bom));
+        let platform = run_query(&format!("{}&format=csv", query));
+        let s = platform.get_response().unwrap().s;
+        assert!(!s.as_bytes().starts_with(bom));
+        let platform = run_query(&format!("{}&format=tsv&csv_bom=1", query));
+        let s = platform.get_response().unwrap().s;
+        assert!(!s.as_bytes().starts_with(bom));
+    }
 
-        let result = platform.result.unwrap();
-        let some_wiki = result.wiki();
-        assert_eq!(some_wiki, Ok(Some(wiki.to_string())));
+    #[test]
+    fn test_add_category_sortkey() {
+        let platform = run_query(
+            "language=en&project=wikipedia&categories=Biology&depth=0&ns[0]=1&add_category_sortkey=1&doit=1",
+        );
+        let entries = platform.result().as_ref().unwrap().entries();
+        let entries = entries.read().unwrap();
+        assert!(!entries.is_empty());
+        // Members without an explicit sort key have an empty prefix
+        assert!(entries
+            .iter()
+            .all(|e| e.get_extra("category_sortkey").is_some()));
 
-        // Sort/crop results
-        let mut entries = result
-            .drain_into_sorted_vec(PageListSort::new_from_params(
-                &s1,
-                s2 == "descending".to_string(),
-            ))
+        let platform = run_query(
+            "language=en&project=wikipedia&categories=Biology%0AChemistry&combination=union&depth=0&ns[0]=1&add_category_sortkey=1&doit=1",
+        );
+        assert!(platform
+            .warnings()
+            .unwrap()
+            .iter()
+            .any(|w| w.contains("add_category_sortkey requires exactly one positive category")));
+    }
+
+    #[test]
+    fn test_gzip() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+        let original = "PetScan ".repeat(1000);
+        let compressed = MyResponse::gzip(original.as_bytes()).unwrap();
+        assert!(compressed.len() < original.len());
+        let mut decompressed = String::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_string(&mut decompressed)
             .unwrap();
-        platform.result = Some(result);
-        platform.apply_results_limit(&mut entries);
+        assert_eq!(decompressed, original);
 
-        assert_eq!(entries.len(), expected.len());
-        let titles: Vec<Title> = entries.iter().map(|e| e.title()).cloned().collect();
-        assert_eq!(titles, expected);
+        assert!(MyResponse::accepts_gzip(Some("gzip, deflate, br")));
+        assert!(MyResponse::accepts_gzip(Some("br;q=1.0, GZIP;q=0.5")));
+        assert!(!MyResponse::accepts_gzip(Some("gzip;q=0")));
+        assert!(!MyResponse::accepts_gzip(Some("deflate")));
+        assert!(!MyResponse::accepts_gzip(None));
     }
 
-    fn check_results_for_psid(psid: usize, wiki: &str, expected: Vec<Title>) {
-        check_results_for_psid_ext(psid, "", wiki, expected)
+    #[test]
+    fn test_case_variants_of_prefix() {
+        assert_eq!(
+            Platform::case_variants_of_prefix("ab%", 3),
+            vec!["ab", "aB", "Ab", "AB"]
+        );
+        assert_eq!(Platform::case_variants_of_prefix("1a", 3), vec!["1a", "1A"]);
+        assert!(Platform::case_variants_of_prefix("%ab", 3).is_empty());
+        assert_eq!(Platform::case_variants_of_prefix("abcd", 1), vec!["a", "A"]);
     }
 
     #[test]
-    fn test_parse_combination_string() {
-        let res =
-            Platform::parse_combination_string(&"categories NOT (sparql OR pagepile)".to_string());
-        let expected = Combination::Not((
-            Box::new(Combination::Source("categories".to_string())),
-            Box::new(Combination::Union((
-                Box::new(Combination::Source("sparql".to_string())),
-                Box::new(Combination::Source("pagepile".to_string())),
-            ))),
+    fn test_labels_case_sensitive() {
+        let sql_for = |query: &str| {
+            let form_parameters = FormParameters::outcome_from_query(query).unwrap();
+            let platform = Platform::new_from_parameters(&form_parameters, get_state());
+            platform.get_label_sql(&0).unwrap()
+        };
+        let sql = sql_for("labels_yes=Douglas%20Adams&langs_labels_yes=en");
+        assert!(sql.0.contains(
+            "(wbx_text LIKE ? OR wbx_text LIKE ? OR wbx_text LIKE ? OR wbx_text LIKE ? OR wbx_text LIKE ? OR wbx_text LIKE ? OR wbx_text LIKE ? OR wbx_text LIKE ?) AND CONVERT(wbx_text USING utf8mb4) COLLATE utf8mb4_general_ci LIKE ?"
         ));
-        assert_eq!(res, expected);
+        assert!(sql.1.contains(&"dOu%".to_string()));
+        assert!(sql.1.contains(&"Douglas Adams".to_string()));
+        // Without a literal start, there is nothing to narrow by
+        let sql = sql_for("labels_yes=%25Adams&langs_labels_yes=en");
+        assert!(sql
+            .0
+            .contains("wbxl_text_id=wbx_id AND CONVERT(wbx_text USING utf8mb4)"));
+        let sql = sql_for("labels_yes=Douglas%20Adams&langs_labels_yes=en&labels_case_sensitive=1");
+        assert!(!sql.0.contains("CONVERT("));
+        assert!(sql.0.contains("wbx_text LIKE ?"));
     }
 
     #[test]
-    fn test_manual_list_enwiki_use_props() {
-        check_results_for_psid(10087995, "enwiki", vec![Title::new("Magnus_Manske", 0)]);
+    fn test_content_disposition() {
+        let query = "manual_list=Magnus_Manske&manual_list_wiki=enwiki&doit=1";
+        let platform = run_query(&format!("{}&format=csv", query));
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.filename, Some("petscan.csv".to_string()));
+        let http_response = response.respond(false).unwrap();
+        assert_eq!(
+            http_response.headers().get("Content-Disposition").unwrap(),
+            "attachment; filename=\"petscan.csv\""
+        );
+
+        let platform = run_query(&format!("{}&format=html", query));
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.filename, None);
+        let http_response = response.respond(false).unwrap();
+        assert!(http_response.headers().get("Content-Disposition").is_none());
+
+        let mut platform = run_query(&format!("{}&format=tsv", query));
+        platform.psid = Some(12345);
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.filename, Some("petscan_12345.tsv".to_string()));
     }
 
     #[test]
-    fn test_manual_list_enwiki_sitelinks() {
-        // This assumes [[en:Count von Count]] has no lvwiki article
-        check_results_for_psid(10123257, "wikidatawiki", vec![Title::new("Q13520818", 0)]);
+    fn test_package() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+        let plain = run_psid_ext(10087995, "&format=csv").unwrap();
+        let plain = plain.get_response().unwrap().s;
+
+        let platform = run_psid_ext(10087995, "&format=csv&package=zip").unwrap();
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.content_type, ContentType::ZIP);
+        assert_eq!(response.filename, Some("petscan.zip".to_string()));
+        let mut archive =
+            zip::ZipArchive::new(std::io::Cursor::new(response.binary.unwrap())).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, vec!["metadata.json", "petscan.csv"]);
+        let mut csv = String::new();
+        archive
+            .by_name("petscan.csv")
+            .unwrap()
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert_eq!(csv, plain);
+        let metadata: Value =
+            serde_json::from_reader(archive.by_name("metadata.json").unwrap()).unwrap();
+        assert_eq!(metadata["results"].as_u64(), Some(1));
+        assert!(metadata["combination"].is_string());
+        assert!(metadata["timestamp"].is_string());
+
+        let platform = run_psid_ext(10087995, "&format=csv&package=gz").unwrap();
+        let response = platform.get_response().unwrap();
+        assert_eq!(response.content_type, ContentType::GZIP);
+        assert_eq!(response.filename, Some("petscan.csv.gz".to_string()));
+        let mut csv = String::new();
+        GzDecoder::new(&response.binary.unwrap()[..])
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert_eq!(csv, plain);
     }
 
     #[test]
-    fn test_manual_list_enwiki_min_max_sitelinks() {
-        // [[Count von Count]] vs. [[Magnus Manske]]
-        check_results_for_psid(10123897, "wikidatawiki", vec![Title::new("Q13520818", 0)]); // Min 15
-        check_results_for_psid(10124667, "wikidatawiki", vec![Title::new("Q12345", 0)]);
-        // Max 15
+    fn test_set_compare() {
+        // manual: Q42, Q1; sparql: Q42, Q2
+        let platform = run_query("manual_list=Q42%0AQ1&manual_list_wiki=wikidatawiki&sparql=SELECT%20%3Fq%20%7B%20VALUES%20%3Fq%20%7B%20wd%3AQ42%20wd%3AQ2%20%7D%20%7D&set_compare=1&doit=1");
+        let response = platform.get_response().unwrap();
+        let json: Value = serde_json::from_str(&response.s).unwrap();
+        assert_eq!(json["sources"]["a"].as_str(), Some("manual"));
+        assert_eq!(json["sources"]["b"].as_str(), Some("sparql"));
+        assert_eq!(json["counts"]["a"].as_u64(), Some(2));
+        assert_eq!(json["counts"]["b"].as_u64(), Some(2));
+        assert_eq!(json["counts"]["intersection"].as_u64(), Some(1));
+        assert_eq!(json["counts"]["a_minus_b"].as_u64(), Some(1));
+        assert_eq!(json["counts"]["b_minus_a"].as_u64(), Some(1));
+        assert_eq!(json["counts"]["union"].as_u64(), Some(3));
+
+        let form_parameters = FormParameters::outcome_from_query(
+            "manual_list=Q42&manual_list_wiki=wikidatawiki&set_compare=1&doit=1",
+        )
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        assert!(platform.run().is_err());
     }
 
     #[test]
-    fn test_manual_list_enwiki_label_filter() {
-        // [[Count von Count]] vs. [[Magnus Manske]]
-        check_results_for_psid(10125089, "wikidatawiki", vec![Title::new("Q12345", 0)]);
-        // Label "Count%" in en
+    fn test_result_stats() {
+        let platform = run_psid(10087995);
+        let stats = platform.result_stats().unwrap();
+        assert_eq!(stats.total, 1);
+        assert_eq!(stats.by_namespace.get(&0), Some(&1));
+        assert_eq!(stats.with_wikidata_item, 0);
+        assert_eq!(stats.with_coordinates, 0);
+        assert_eq!(stats.dropped_in_conversion, 0);
+        // Still available after the result has been drained for output
+        platform.get_response().unwrap();
+        assert_eq!(platform.result_stats().unwrap(), stats);
     }
 
     #[test]
-    fn test_manual_list_enwiki_neg_cat_filter() {
-        // [[Count von Count]] vs. [[Magnus Manske]]
-        // Manual list on enwiki, minus [[Category:Fictional vampires]]
-        check_results_for_psid(10126217, "enwiki", vec![Title::new("Magnus Manske", 0)]);
+    fn test_min_page_age() {
+        let cutoff = Platform::timestamp_days_ago(30);
+        assert!("20010115192712".to_string() <= cutoff);
+        let created_now = Utc::now().format("%Y%m%d%H%M%S").to_string();
+        assert!(created_now > cutoff);
+        check_results_for_psid_ext(
+            10087995,
+            "&min_page_age=30",
+            "enwiki",
+            vec![Title::new("Magnus_Manske", 0)],
+        );
     }
 
     #[test]
-    fn test_source_labels() {
-        check_results_for_psid(
-            10225056,
-            "wikidatawiki",
-            vec![Title::new("Q13520818", 0), Title::new("Q10995651", 0)],
+    fn test_entityschema_unavailable() {
+        let mut state = (*get_state()).clone();
+        state.config["entityschema_validator"] = json!("");
+        let form_parameters = FormParameters::outcome_from_query(
+            "manual_list=Magnus_Manske&manual_list_wiki=enwiki&validate_entityschema=E10&doit=1",
+        )
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, Arc::new(state));
+        platform.run().unwrap();
+        assert_eq!(platform.result.as_ref().unwrap().len(), Ok(1));
+        assert_eq!(
+            platform.warnings().unwrap(),
+            vec!["<span tt='warn_entityschema_unavailable'></span>".to_string()]
         );
     }
 
     #[test]
-    fn test_manual_list_commons_file_info() {
-        // Manual list [[File:KingsCollegeChapelWest.jpg]] on commons
-        let platform = run_psid(10137125);
-        let result = platform.result.unwrap();
-        let entries = result
-            .entries()
-            .read()
-            .unwrap()
-            .iter()
-            .cloned()
-            .collect::<Vec<PageListEntry>>();
-        assert_eq!(entries.len(), 1);
-        let entry = entries.get(0).unwrap();
-        assert_eq!(entry.page_id, Some(1340715));
-        let fi = entry.get_file_info();
-        assert!(fi.is_some());
-        let fi = fi.unwrap();
-        assert!(fi.file_usage.len() > 10);
-        assert_eq!(fi.img_size, Some(223131));
-        assert_eq!(fi.img_width, Some(1025));
-        assert_eq!(fi.img_height, Some(768));
-        assert_eq!(fi.img_user_text, Some("Solipsist~commonswiki".to_string()));
+    fn test_entityschema_partial_failure() {
+        // A validator that only knows Q42, and fails for everything else
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            use std::io::{Read, Write};
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut buffer = [0; 4096];
+                let size = stream.read(&mut buffer).unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..size]).to_string();
+                let response = match request.contains("entity=Q42") {
+                    true => "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 14\r\nConnection: close\r\n\r\n{\"valid\":true}",
+                    false => "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        let mut state = (*get_state()).clone();
+        state.config["entityschema_validator"] = json!(url);
+        let form_parameters = FormParameters::outcome_from_query(
+            "manual_list=Q42%0AQ12345&manual_list_wiki=wikidatawiki&validate_entityschema=E999999&doit=1",
+        )
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, Arc::new(state));
+        platform.run().unwrap();
+        let result = platform.result.as_ref().unwrap();
+        let entries = result.entries().read().unwrap();
+        assert_eq!(entries.len(), 2);
+        let conforms = |q: &str| {
+            entries
+                .get(&PageListEntry::new(Title::new(q, 0)))
+                .unwrap()
+                .entityschema_conforms
+                .to_owned()
+        };
+        assert_eq!(conforms("Q42"), TriState::Yes);
+        assert_eq!(conforms("Q12345"), TriState::Unknown);
         assert_eq!(
-            fi.img_sha1,
-            Some("sypcaey3hmlhjky46x0nhiwhiivx6yj".to_string())
+            platform.warnings().unwrap(),
+            vec!["<span tt='warn_entityschema_failed' num='1'></span>".to_string()]
         );
     }
 
     #[test]
-    fn test_manual_list_enwiki_page_info() {
-        // Manual list [[Cambridge]] on enwiki
-        let platform = run_psid(10136716);
-        let result = platform.result.unwrap();
-        let entries = result
-            .entries()
-            .read()
-            .unwrap()
-            .iter()
-            .cloned()
-            .collect::<Vec<PageListEntry>>();
-        assert_eq!(entries.len(), 1);
-        let entry = entries.get(0).unwrap();
-        assert_eq!(entry.page_id, Some(36995));
-        assert!(entry.page_bytes.is_some());
-        assert!(entry.get_page_timestamp().is_some());
-        assert_eq!(
-            entry.get_page_image(),
-            Some("KingsCollegeChapelWest.jpg".to_string())
+    fn test_manual_list_enwiki_with_talk() {
+        check_results_for_psid_ext(
+            10087995,
+            "&with_talk=1&sortby=ns_title",
+            "enwiki",
+            vec![
+                Title::new("Magnus_Manske", 0),
+                Title::new("Magnus_Manske", 1),
+            ],
         );
-        assert_eq!(entry.disambiguation, TriState::No);
-        assert!(entry.incoming_links.is_some());
-        assert!(entry.incoming_links.unwrap() > 7500);
-        assert!(entry.get_coordinates().is_some());
     }
 
     #[test]
-    fn test_manual_list_enwiki_annotate_wikidata_item() {
-        // Manual list [[Count von Count]] on enwiki
-        let platform = run_psid(10137767);
-        let result = platform.result.unwrap();
-        let entries = result
+    fn test_manual_list_enwiki_blacklist() {
+        let platform = run_query("manual_list=Magnus_Manske%0AJimbo_Wales%0ALarry_Sanger&manual_list_wiki=enwiki&blacklist=Jimbo_Wales%0A%0A+Larry+Sanger+%0AJimbo+Wales&doit=1");
+        let result = platform.result.as_ref().unwrap();
+        let titles: Vec<Title> = result
             .entries()
             .read()
             .unwrap()
             .iter()
+            .map(|e| e.title())
             .cloned()
-            .collect::<Vec<PageListEntry>>();
-        assert_eq!(entries.len(), 1);
-        let entry = entries.get(0).unwrap();
-        assert_eq!(entry.page_id, Some(239794));
-        assert_eq!(entry.get_wikidata_item(), Some("Q12345".to_string()));
-    }
+            .collect();
+        assert_eq!(titles, vec![Title::new("Magnus_Manske", 0)]);
+        assert_eq!(
+            platform.warnings().unwrap(),
+            vec!["<span tt='warn_blacklist_removed' num='2'></span>".to_string()]
+        );
 
-    #[test]
-    fn test_manual_list_enwiki_subpages() {
-        // Manual list [[User:Magnus Manske]] on enwiki, subpages, not "root page"
-        let platform = run_psid(10138030);
-        let result = platform.result.unwrap();
-        let entries = result
+        // A number is a title, not a page ID
+        let platform = run_query(
+            "manual_list=1984%0AMagnus_Manske&manual_list_wiki=enwiki&blacklist=1984&doit=1",
+        );
+        let result = platform.result.as_ref().unwrap();
+        assert_eq!(result.len(), Ok(1));
+        assert!(result
             .entries()
             .read()
             .unwrap()
-            .iter()
-            .cloned()
-            .collect::<Vec<PageListEntry>>();
-        assert!(entries.len() > 100);
-        // Try to find pages with no '/'
-        assert!(!entries
-            .iter()
-            .any(|entry| { entry.title().pretty().find('/').is_none() }));
+            .contains(&PageListEntry::new(Title::new("Magnus_Manske", 0))));
+
+        let form_parameters = FormParameters::outcome_from_query(
+            "manual_list=Magnus_Manske&manual_list_wiki=enwiki&blacklist=%23x&doit=1",
+        )
+        .unwrap();
+        let mut platform = Platform::new_from_parameters(&form_parameters, get_state());
+        assert!(platform.run().is_err());
     }
 
     #[test]
-    fn test_manual_list_wikidata_labels() {
-        // Manual list [[Q12345]], nl label/desc
-        let platform = run_psid(10138979);
-        let result = platform.result.unwrap();
-        let entries = result
+    fn test_manual_list_enwiki_new_redirects() {
+        let query = "manual_list=UK%0AMagnus_Manske&manual_list_wiki=enwiki&doit=1";
+        let platform = run_query(&format!("{}&new_redirects_days=100000", query));
+        let entries: Vec<PageListEntry> = platform
+            .result
+            .unwrap()
             .entries()
             .read()
             .unwrap()
             .iter()
             .cloned()
-            .collect::<Vec<PageListEntry>>();
+            .collect();
         assert_eq!(entries.len(), 1);
-        let entry = entries.get(0).unwrap();
-        assert_eq!(entry.page_id, Some(13925));
-        assert_eq!(entry.get_wikidata_label(), Some("Graaf Tel".to_string()));
+        assert_eq!(*entries[0].title(), Title::new("UK", 0));
         assert_eq!(
-            entry.get_wikidata_description(),
-            Some("figuur van Sesamstraat".to_string())
-        );
-    }
-
-    #[test]
-    fn test_manual_list_wikidata_regexp() {
-        check_results_for_psid_ext(
-            10140344,
-            "&regexp_filter=.*Manske",
-            "wikidatawiki",
-            vec![Title::new("Q13520818", 0)],
-        );
-        check_results_for_psid_ext(
-            10140344,
-            "&regexp_filter=Graaf.*",
-            "wikidatawiki",
-            vec![Title::new("Q12345", 0)],
-        );
-        check_results_for_psid_ext(
-            10140616,
-            "&regexp_filter=&regexp_filter=Jimbo.*",
-            "enwiki",
-            vec![Title::new("Jimbo Wales", 0)],
-        );
-        check_results_for_psid_ext(
-            10140616,
-            "&regexp_filter=&regexp_filter=.*Sanger",
-            "enwiki",
-            vec![Title::new("Larry Sanger", 0)],
+            entries[0].get_redirect_target(),
+            Some(Title::new("United_Kingdom", 0))
         );
-    }
+        assert!(entries[0].get_page_created().is_some());
+        assert!(entries[0].get_page_creator().is_some());
 
-    #[test]
-    fn test_en_categories_sparql_common_wiki_other() {
-        check_results_for_psid(15960820, "frwiki", vec![Title::new("Magnus Manske", 0)]);
+        let platform = run_query(&format!("{}&new_redirects_days=0", query));
+        assert_eq!(platform.result.unwrap().len(), Ok(0));
     }
 
     #[test]
-    fn test_trim_extended_whitespace() {
-        let platform = run_psid(15015735); // The categories contain a left-to-right mark
-        let result = platform.result.unwrap();
-        let entries = result
-            .entries()
-            .read()
+    fn test_explain() {
+        let platform = run_query("manual_list=Magnus_Manske%0AJimbo_Wales&manual_list_wiki=enwiki&blacklist=Jimbo_Wales&explain=1&doit=1");
+        let explain = platform.explain().unwrap();
+        let blacklist_step = explain["steps"]
+            .as_array()
             .unwrap()
             .iter()
-            .cloned()
-            .collect::<Vec<PageListEntry>>();
-        assert!(entries.len() > 20);
-    }
-
-    #[test]
-    fn test_template_talk_pages() {
-        let platform = run_psid(15059382);
-        let result = platform.result.unwrap();
-        let entries = result
-            .entries()
-            .read()
+            .find(|step| step["step"] == "process_blacklist")
             .unwrap()
-            .iter()
-            .cloned()
-            .collect::<Vec<PageListEntry>>();
-        assert!(entries.len() > 0);
-        for entry in entries {
-            assert_eq!(entry.title().namespace_id(), 0);
-        }
+            .to_owned();
+        assert_eq!(blacklist_step["dropped"], json!(1));
+        assert_eq!(blacklist_step["dropped_sample"], json!(["0:Jimbo_Wales"]));
+        assert_eq!(explain["kept_sample"][0]["title"], json!("0:Magnus_Manske"));
+        assert!(explain["kept_sample"][0]["passed"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("process_blacklist")));
     }
 }
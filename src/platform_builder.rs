@@ -0,0 +1,130 @@
+use crate::app_state::AppState;
+use crate::form_parameters::FormParameters;
+use crate::pagelist::PageListSort;
+use crate::platform::{Combination, Platform};
+use std::collections::HashMap;
+use std::sync::Arc;
+use wikibase::mediawiki::api::NamespaceID;
+
+/// Builds a `Platform` from typed settings, for use as a library.
+/// The settings are turned into the same parameters as a web query, so a built
+/// `Platform` behaves exactly like one created from the equivalent query string.
+#[derive(Debug, Clone)]
+pub struct PlatformBuilder {
+    state: Arc<AppState>,
+    wiki: Option<String>,
+    params: HashMap<String, String>,
+}
+
+impl PlatformBuilder {
+    pub fn new(state: Arc<AppState>) -> Self {
+        let mut params = HashMap::new();
+        params.insert("doit".to_string(), "1".to_string());
+        Self {
+            state,
+            wiki: None,
+            params,
+        }
+    }
+
+    /// The main wiki, e.g. "enwiki" or "dewiktionary"
+    pub fn wiki(mut self, wiki: &str) -> Self {
+        self.wiki = Some(wiki.to_string());
+        self
+    }
+
+    pub fn categories(self, categories: Vec<String>) -> Self {
+        self.param("categories", &categories.join("\n"))
+    }
+
+    pub fn negative_categories(self, categories: Vec<String>) -> Self {
+        self.param("negcats", &categories.join("\n"))
+    }
+
+    pub fn depth(self, depth: u16) -> Self {
+        self.param("depth", &depth.to_string())
+    }
+
+    pub fn namespaces(mut self, namespace_ids: Vec<NamespaceID>) -> Self {
+        for namespace_id in namespace_ids {
+            self = self.param(&format!("ns[{}]", namespace_id), "1");
+        }
+        self
+    }
+
+    pub fn manual_list(self, wiki: &str, titles: Vec<String>) -> Self {
+        self.param("manual_list_wiki", wiki)
+            .param("manual_list", &titles.join("\n"))
+    }
+
+    pub fn sparql(self, query: &str) -> Self {
+        self.param("sparql", query)
+    }
+
+    pub fn combination(self, combination: Combination) -> Self {
+        match combination {
+            Combination::None => self,
+            combination => self.param("source_combination", &combination.to_string()),
+        }
+    }
+
+    pub fn sortby(self, sorter: PageListSort) -> Self {
+        let (sortby, descending) = sorter.to_params();
+        let sortorder = if descending {
+            "descending"
+        } else {
+            "ascending"
+        };
+        self.param("sortby", &sortby).param("sortorder", sortorder)
+    }
+
+    pub fn output_limit(self, limit: usize) -> Self {
+        self.param("output_limit", &limit.to_string())
+    }
+
+    pub fn format(self, format: &str) -> Self {
+        self.param("format", format)
+    }
+
+    /// Sets any other parameter, as it would appear in a query string
+    pub fn param(mut self, key: &str, value: &str) -> Self {
+        self.params.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn form_parameters(&self) -> Result<FormParameters, String> {
+        let mut params = self.params.clone();
+        if let Some(wiki) = &self.wiki {
+            let (language, project) = self.language_and_project(wiki)?;
+            params.insert("language".to_string(), language);
+            params.insert("project".to_string(), project);
+        }
+        Ok(FormParameters::new_from_params(params))
+    }
+
+    pub fn build(&self) -> Result<Platform, String> {
+        Ok(Platform::new_from_parameters(
+            &self.form_parameters()?,
+            self.state.clone(),
+        ))
+    }
+
+    /// Splits the server name of a wiki, e.g. "https://de.wiktionary.org" into ("de","wiktionary")
+    fn language_and_project(&self, wiki: &str) -> Result<(String, String), String> {
+        let url = self
+            .state
+            .get_server_url_for_wiki(&wiki.to_string())
+            .map_err(|_| format!("No such wiki: {}", wiki))?;
+        let host = url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://");
+        let parts: Vec<&str> = host.split('.').collect();
+        match parts.as_slice() {
+            [language, project, _] => Ok((language.to_string(), project.to_string())),
+            _ => Err(format!(
+                "Can not determine language and project of {}",
+                wiki
+            )),
+        }
+    }
+}
@@ -4,7 +4,9 @@ use crate::pagelist::{LinkCount, PageListEntry};
 use crate::platform::*;
 use chrono::prelude::*;
 use htmlescape::encode_minimal;
+use regex::Regex;
 use serde_json::Value;
+use simple_excel_writer::{Row, Workbook};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -12,11 +14,108 @@ use wikibase::mediawiki::api::Api;
 use wikibase::mediawiki::title::Title;
 
 static MAX_HTML_RESULTS: usize = 10000;
+static JSON_ENTRIES_PLACEHOLDER: &str = "\u{0}petscan_entries\u{0}";
 static AUTOLIST_WIKIDATA: &str = "www.wikidata.org";
 static AUTOLIST_COMMONS: &str = "commons.wikimedia.org";
 
 //________________________________________________________________________________________________________________________
 
+/// Output format for timestamps, from the "timestamp_format" parameter
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimestampFormat {
+    MediaWiki,
+    Iso8601,
+    Unix,
+}
+
+impl TimestampFormat {
+    pub fn new_from_param(s: &str) -> Result<Self, String> {
+        match s {
+            "" | "mw" => Ok(Self::MediaWiki),
+            "iso8601" => Ok(Self::Iso8601),
+            "unix" => Ok(Self::Unix),
+            other => Err(format!("Unknown timestamp_format '{}'", other)),
+        }
+    }
+
+    /// Converts a MediaWiki (YYYYMMDDHHMMSS) timestamp; anything else is returned unchanged
+    pub fn format(&self, timestamp: &str) -> String {
+        if *self == Self::MediaWiki {
+            return timestamp.to_string();
+        }
+        match Utc.datetime_from_str(timestamp, "%Y%m%d%H%M%S") {
+            Ok(date) => match self {
+                Self::Iso8601 => date.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                Self::Unix => date.timestamp().to_string(),
+                Self::MediaWiki => timestamp.to_string(),
+            },
+            Err(_) => timestamp.to_string(),
+        }
+    }
+}
+
+/// Number and date formatting for the HTML interface, based on `interface_language`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlLocale {
+    digit_group_separator: &'static str,
+    date_format: &'static str,
+}
+
+impl HtmlLocale {
+    /// Unknown languages get ungrouped numbers and ISO-style dates
+    pub fn new(interface_language: &str) -> Self {
+        let language = interface_language
+            .split(|c| c == '-' || c == '_')
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        let (digit_group_separator, date_format) = match language.as_str() {
+            "en" => (",", "%Y-%m-%d %H:%M:%S"),
+            "de" | "da" | "id" | "tr" => (".", "%d.%m.%Y %H:%M:%S"),
+            "es" | "it" | "pt" => (".", "%d/%m/%Y %H:%M:%S"),
+            "nl" => (".", "%d-%m-%Y %H:%M:%S"),
+            "fr" => ("\u{a0}", "%d/%m/%Y %H:%M:%S"),
+            "ru" | "uk" | "pl" | "cs" | "fi" | "nb" => ("\u{a0}", "%d.%m.%Y %H:%M:%S"),
+            "sv" => ("\u{a0}", "%Y-%m-%d %H:%M:%S"),
+            "ja" | "zh" | "ko" => (",", "%Y/%m/%d %H:%M:%S"),
+            _ => ("", "%Y-%m-%d %H:%M:%S"),
+        };
+        Self {
+            digit_group_separator,
+            date_format,
+        }
+    }
+
+    /// Groups the digits of an integer; anything else is returned unchanged
+    pub fn format_number(&self, number: &str) -> String {
+        let digits = number.strip_prefix('-').unwrap_or(number);
+        if self.digit_group_separator.is_empty()
+            || digits.is_empty()
+            || !digits.chars().all(|c| c.is_ascii_digit())
+        {
+            return number.to_string();
+        }
+        let mut ret = number[..number.len() - digits.len()].to_string();
+        for (pos, c) in digits.chars().enumerate() {
+            if pos > 0 && (digits.len() - pos) % 3 == 0 {
+                ret += self.digit_group_separator;
+            }
+            ret.push(c);
+        }
+        ret
+    }
+
+    /// Converts a MediaWiki (YYYYMMDDHHMMSS) timestamp; anything else is returned unchanged
+    pub fn format_timestamp(&self, timestamp: &str) -> String {
+        match Utc.datetime_from_str(timestamp, "%Y%m%d%H%M%S") {
+            Ok(date) => date.format(self.date_format).to_string(),
+            Err(_) => timestamp.to_string(),
+        }
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
 #[derive(Debug, Clone)]
 pub struct RenderParams {
     wiki: String,
@@ -31,6 +130,24 @@ pub struct RenderParams {
     add_defaultsort: bool,
     add_disambiguation: bool,
     add_incoming_links: bool,
+    add_pageviews: bool,
+    add_langlink_count: bool,
+    add_editor_count: bool,
+    show_provenance: bool,
+    show_entityschema: bool,
+    show_cascade_protected: bool,
+    show_label_match_item: bool,
+    show_label_language_used: bool,
+    show_category_sortkey: bool,
+    show_item_label: bool,
+    show_revert_tags: bool,
+    add_entity_uri: bool,
+    show_maintenance_templates: bool,
+    extra_columns: Vec<String>,
+    label_columns: Vec<String>,
+    show_new_redirects: bool,
+    show_created: bool,
+    show_last_editor: bool,
     do_output_redlinks: bool,
     use_autolist: bool,
     autolist_creator_mode: bool,
@@ -41,8 +158,11 @@ pub struct RenderParams {
     json_output_compatability: String,
     json_callback: String,
     json_sparse: bool,
+    json_omit_empty: bool,
     json_pretty: bool,
     giu: bool,
+    timestamp_format: TimestampFormat,
+    html_locale: HtmlLocale,
 }
 
 impl RenderParams {
@@ -58,7 +178,35 @@ impl RenderParams {
             add_image: platform.has_param("add_image"),
             add_defaultsort: platform.has_param("add_defaultsort"),
             add_disambiguation: platform.has_param("add_disambiguation"),
-            add_incoming_links: platform.get_param_blank("sortby") == "incoming_links".to_string(),
+            add_incoming_links: platform.sorts_by("incoming_links"),
+            add_pageviews: platform.sorts_by("pageviews") || platform.has_param("add_pageviews"),
+            add_langlink_count: platform.has_param("unlinked_with_langlinks")
+                || platform.has_param("min_langlinks")
+                || platform.has_param("max_langlinks"),
+            add_editor_count: platform.has_param("min_editors")
+                || platform.has_param("max_editors"),
+            show_provenance: platform.has_param("show_provenance"),
+            show_entityschema: platform.has_param("validate_entityschema"),
+            show_cascade_protected: platform.has_param("cascade_protected"),
+            show_label_match_item: platform.has_param("convert_label_fallback"),
+            show_label_language_used: platform.has_param("label_fallback"),
+            show_category_sortkey: platform.has_param("add_category_sortkey"),
+            show_item_label: platform.has_param("title_label_match"),
+            show_revert_tags: platform.has_param("latest_edit_revert"),
+            add_entity_uri: platform.has_param("add_entity_uri"),
+            show_maintenance_templates: platform.has_param("maintenance_tagged"),
+            extra_columns: platform
+                .sparql_extra_vars()?
+                .iter()
+                .map(|var| format!("sparql:{}", var))
+                .collect(),
+            label_columns: Self::label_columns(platform, wiki),
+            show_new_redirects: platform.has_param("new_redirects_days"),
+            show_created: platform.has_param("created_before")
+                || platform.has_param("created_after"),
+            show_last_editor: platform.has_param("last_editor")
+                || platform.has_param("last_editor_not")
+                || platform.sorts_by("last_editor"),
             show_wikidata_item: false,
             is_wikidata: wiki == "wikidatawiki",
             do_output_redlinks: platform.do_output_redlinks(),
@@ -72,12 +220,40 @@ impl RenderParams {
                 .get_param_default("output_compatability", "catscan"), // Default; "quick-intersection" ?
             json_callback: platform.get_param_blank("callback"),
             json_sparse: platform.has_param("sparse"),
+            json_omit_empty: platform.has_param("omit_empty"),
             json_pretty: platform.has_param("json-pretty"),
             giu: platform.has_param("giu"),
+            timestamp_format: TimestampFormat::new_from_param(
+                &platform.get_param_blank("timestamp_format"),
+            )?,
+            html_locale: HtmlLocale::new(&platform.get_param_default("interface_language", "en")),
         };
         ret.show_wikidata_item = ret.wdi == "any" || ret.wdi == "with";
         Ok(ret)
     }
+
+    /// One label and one description column per language, if labels in several languages were requested
+    fn label_columns(platform: &Platform, wiki: &String) -> Vec<String> {
+        let languages = platform.wikidata_label_languages();
+        if wiki != "wikidatawiki" || languages.len() < 2 {
+            return vec![];
+        }
+        languages
+            .iter()
+            .map(|language| format!("label:{}", language))
+            .chain(
+                languages
+                    .iter()
+                    .map(|language| format!("description:{}", language)),
+            )
+            .collect()
+    }
+
+    fn format_timestamp(&self, timestamp: &Option<String>) -> Option<String> {
+        timestamp
+            .as_ref()
+            .map(|timestamp| self.timestamp_format.format(timestamp))
+    }
 }
 
 //________________________________________________________________________________________________________________________
@@ -126,6 +302,9 @@ pub trait Render {
         if params.show_wikidata_item {
             columns.push("wikidata_item");
         }
+        if params.add_entity_uri {
+            columns.push("entity_uri");
+        }
         if params.add_coordinates {
             columns.push("coordinates");
         }
@@ -138,15 +317,73 @@ pub trait Render {
         if params.add_incoming_links {
             columns.push("incoming_links");
         }
+        if params.add_pageviews {
+            columns.push("pageviews");
+        }
+        if params.add_langlink_count {
+            columns.push("langlink_count");
+        }
+        if params.add_editor_count {
+            columns.push("editor_count");
+        }
         if params.file_data {
             self.file_data_keys().iter().for_each(|k| columns.push(*k));
         }
         if params.file_usage {
             columns.push("fileusage");
         }
+        if params.show_provenance {
+            columns.push("provenance");
+        }
+        if params.show_entityschema {
+            columns.push("entityschema");
+        }
+        if params.show_cascade_protected {
+            columns.push("cascade_protected");
+        }
+        if params.show_maintenance_templates {
+            columns.push("maintenance_templates");
+        }
+        if params.show_label_match_item {
+            columns.push("label_match_item");
+        }
+        if params.show_label_language_used {
+            columns.push("label_language_used");
+        }
+        if params.show_category_sortkey {
+            columns.push("category_sortkey");
+        }
+        if params.show_item_label {
+            columns.push("item_label");
+        }
+        if params.show_revert_tags {
+            columns.push("revert_tags");
+        }
+        params
+            .extra_columns
+            .iter()
+            .for_each(|col| columns.push(col.as_str()));
+        params
+            .label_columns
+            .iter()
+            .for_each(|col| columns.push(col.as_str()));
+        if params.show_new_redirects {
+            columns.push("redirect_target");
+            columns.push("created");
+            columns.push("creator");
+        } else if params.show_created {
+            columns.push("created");
+        }
+        if params.show_last_editor {
+            columns.push("last_editor");
+        }
         columns
     }
 
+    /// Renders free text, such as SPARQL values, safely for the output format
+    fn render_cell_text(&self, text: &String) -> String {
+        text.to_string()
+    }
     fn render_cell_title(&self, _entry: &PageListEntry, _params: &RenderParams) -> String;
     fn render_cell_wikidata_item(&self, _entry: &PageListEntry, _params: &RenderParams) -> String;
     fn render_user_name(&self, _user: &String, _params: &RenderParams) -> String;
@@ -230,13 +467,22 @@ pub trait Render {
                 "page_id" => self.opt_u32(&entry.page_id),
                 "namespace" => self.render_cell_namespace(entry, params),
                 "size" => self.opt_u32(&entry.page_bytes),
-                "timestamp" => self.opt_string(&entry.get_page_timestamp()),
+                "timestamp" => {
+                    self.opt_string(&params.format_timestamp(&entry.get_page_timestamp()))
+                }
                 "wikidata_item" => self.render_cell_wikidata_item(entry, params),
+                "entity_uri" => self.opt_string(&entry.entity_uri(&params.wiki)),
                 "image" => self.render_cell_image(&entry.get_page_image(), params),
                 "number" => params.row_number.to_string(),
                 "defaultsort" => self.opt_string(&entry.get_defaultsort()),
                 "disambiguation" => self.opt_bool(&entry.disambiguation.as_option_bool()),
                 "incoming_links" => self.opt_linkcount(&entry.incoming_links),
+                "langlink_count" => self.opt_linkcount(&entry.langlink_count),
+                "editor_count" => self.opt_u32(&entry.editor_count),
+                "pageviews" => entry
+                    .pageviews
+                    .map(|x| x.to_string())
+                    .unwrap_or("".to_string()),
 
                 "img_size" => match &entry.get_file_info() {
                     Some(fi) => self.opt_usize(&fi.img_size),
@@ -267,7 +513,7 @@ pub trait Render {
                     None => "".to_string(),
                 },
                 "img_timestamp" => match &entry.get_file_info() {
-                    Some(fi) => self.opt_string(&fi.img_timestamp),
+                    Some(fi) => self.opt_string(&params.format_timestamp(&fi.img_timestamp)),
                     None => "".to_string(),
                 },
                 "img_sha1" => match &entry.get_file_info() {
@@ -286,6 +532,50 @@ pub trait Render {
                 },
                 "coordinates" => self.render_coordinates(entry, params),
                 "fileusage" => self.render_cell_fileusage(&entry, &params),
+                "provenance" => entry.get_provenance().unwrap_or(vec![]).join("|"),
+                "entityschema" => self.opt_bool(&entry.entityschema_conforms.as_option_bool()),
+                "cascade_protected" => self.opt_bool(&entry.cascade_protected.as_option_bool()),
+                "label_match_item" => self.opt_string(&entry.get_extra("label_match_item")),
+                "label_language_used" => self.opt_string(&entry.get_label_language_used()),
+                "category_sortkey" => self.opt_string(&entry.get_extra("category_sortkey")),
+                "item_label" => self.opt_string(&entry.get_extra("item_label")),
+                "revert_tags" => self.opt_string(&entry.get_extra("revert_tags")),
+                "redirect_target" => match entry.get_redirect_target() {
+                    Some(target) => target
+                        .full_pretty(&params.api)
+                        .unwrap_or(target.pretty().to_string()),
+                    None => "".to_string(),
+                },
+                "created" => self.opt_string(&params.format_timestamp(&entry.get_page_created())),
+                "creator" => match entry.get_page_creator() {
+                    Some(user) => self.render_user_name(&user, params),
+                    None => "".to_string(),
+                },
+                "last_editor" => match entry.get_last_editor() {
+                    Some(user) => self.render_user_name(&user, params),
+                    None => "".to_string(),
+                },
+                "maintenance_templates" => self.render_cell_text(
+                    &entry
+                        .get_maintenance_templates()
+                        .unwrap_or(vec![])
+                        .join("|"),
+                ),
+                other if other.starts_with("sparql:") => self.render_cell_text(
+                    &entry
+                        .get_extra(&other["sparql:".len()..])
+                        .unwrap_or("".to_string()),
+                ),
+                other if other.starts_with("label:") => self.render_cell_text(
+                    &entry
+                        .get_wikidata_label_in(&other["label:".len()..])
+                        .unwrap_or("".to_string()),
+                ),
+                other if other.starts_with("description:") => self.render_cell_text(
+                    &entry
+                        .get_wikidata_description_in(&other["description:".len()..])
+                        .unwrap_or("".to_string()),
+                ),
 
                 _ => "<".to_string() + k + ">",
             };
@@ -373,6 +663,8 @@ impl Render for RenderWiki {
         Ok(MyResponse {
             s: rows.join("\n"),
             content_type: ContentType::Plain,
+            binary: None,
+            filename: None,
         })
     }
 
@@ -496,13 +788,20 @@ impl Render for RenderTSV {
             rows.push(row);
         }
 
+        let mut s = rows.join("\n");
+        // Excel needs a byte-order mark to recognize UTF-8 in CSV files
+        if self.separator == "," && platform.has_param("csv_bom") {
+            s.insert(0, '\u{FEFF}');
+        }
         Ok(MyResponse {
-            s: rows.join("\n"),
+            s,
             content_type: match self.separator.as_str() {
                 "," => ContentType::CSV,
                 "\t" => ContentType::TSV,
                 _ => ContentType::Plain, // Fallback
             },
+            binary: None,
+            filename: None,
         })
     }
 
@@ -625,7 +924,14 @@ impl Render for RenderHTML {
         for warning in platform.warnings()? {
             rows.push(format!(
                 "<div class='alert alert-warning' style='clear:both'>{}</div>",
-                warning
+                Self::render_warning(&warning)
+            ));
+        }
+
+        for truncation in platform.truncations()? {
+            rows.push(format!(
+                "<div class='alert alert-warning' style='clear:both'><span tt='warn_results_truncated'></span> {}</div>",
+                encode_minimal(&truncation)
             ));
         }
 
@@ -645,6 +951,7 @@ impl Render for RenderHTML {
             if params.row_number < MAX_HTML_RESULTS {
                 params.row_number += 1;
                 let row = self.row_from_entry(&entry, &header, &params, &platform);
+                let row = self.localize_row(row, &header, &params);
                 let row = self.render_html_row(&row, &header);
                 output += &row;
             }
@@ -688,6 +995,8 @@ impl Render for RenderHTML {
         Ok(MyResponse {
             s: html,
             content_type: ContentType::HTML,
+            binary: None,
+            filename: None,
         })
     }
 
@@ -716,6 +1025,9 @@ impl Render for RenderHTML {
             None => "".to_string(),
         }
     }
+    fn render_cell_text(&self, text: &String) -> String {
+        encode_minimal(text)
+    }
     fn render_user_name(&self, user: &String, params: &RenderParams) -> String {
         let title = Title::new(user, 2);
         self.render_wikilink(&title, &params.wiki, &None, params, false, &None, false)
@@ -854,6 +1166,20 @@ impl RenderHTML {
         Box::new(Self {})
     }
 
+    /// Warnings can echo user input, so only a bare translation span (with an optional number
+    /// for "$1") is passed through as HTML
+    pub fn render_warning(warning: &str) -> String {
+        lazy_static! {
+            static ref RE_TRANSLATION_SPAN: Regex =
+                Regex::new(r"^<span tt='[a-z0-9_]+'( num='\d+')?></span>$")
+                    .expect("RenderHTML::render_warning: Regex is invalid");
+        }
+        match RE_TRANSLATION_SPAN.is_match(warning) {
+            true => warning.to_string(),
+            false => encode_minimal(warning),
+        }
+    }
+
     fn escape_attribute(&self, s: &String) -> String {
         FormParameters::percent_encode(s)
             .replace('<', "&lt;")
@@ -912,6 +1238,29 @@ impl RenderHTML {
         ret
     }
 
+    /// Formats numbers and dates for the interface language; machine formats keep raw values
+    fn localize_row(
+        &self,
+        row: Vec<String>,
+        header: &Vec<(String, String)>,
+        params: &RenderParams,
+    ) -> Vec<String> {
+        row.into_iter()
+            .zip(header.iter())
+            .map(|(cell, (key, _))| match key.as_str() {
+                "number" | "size" | "incoming_links" | "pageviews" | "langlink_count"
+                | "editor_count" | "linknumber" | "redlink_count" | "img_size" | "img_width"
+                | "img_height" => params.html_locale.format_number(&cell),
+                "timestamp" | "img_timestamp" | "created"
+                    if params.timestamp_format == TimestampFormat::MediaWiki =>
+                {
+                    params.html_locale.format_timestamp(&cell)
+                }
+                _ => cell,
+            })
+            .collect()
+    }
+
     fn render_html_row(&self, row: &Vec<String>, header: &Vec<(String, String)>) -> String {
         let mut ret = "<tr>".to_string();
         for col_num in 0..row.len() {
@@ -960,10 +1309,31 @@ impl RenderHTML {
                 "defaultsort" => "<th tt='h_defaultsort'></th>".to_string(),
                 "disambiguation" => "<th tt='h_disambiguation'></th>".to_string(),
                 "incoming_links" => "<th tt='h_incoming_links'></th>".to_string(),
+                "pageviews" => "<th tt='h_pageviews'></th>".to_string(),
+                "langlink_count" => "<th tt='h_langlink_count'></th>".to_string(),
+                "editor_count" => "<th tt='h_editor_count'></th>".to_string(),
                 "fileusage" => "<th tt='file_usage_data'></th>".to_string(),
+                "provenance" => "<th tt='h_provenance'></th>".to_string(),
+                "entityschema" => "<th tt='h_entityschema'></th>".to_string(),
+                "cascade_protected" => "<th tt='h_cascade_protected'></th>".to_string(),
+                "entity_uri" => "<th tt='h_entity_uri'></th>".to_string(),
+                "label_match_item" => "<th tt='h_label_match_item'></th>".to_string(),
+                "label_language_used" => "<th tt='h_label_language_used'></th>".to_string(),
+                "category_sortkey" => "<th tt='h_category_sortkey'></th>".to_string(),
+                "item_label" => "<th tt='h_item_label'></th>".to_string(),
+                "revert_tags" => "<th tt='h_revert_tags'></th>".to_string(),
+                "maintenance_templates" => "<th tt='h_maintenance_templates'></th>".to_string(),
+                "redirect_target" => "<th tt='h_redirect_target'></th>".to_string(),
+                "created" => "<th tt='h_created'></th>".to_string(),
+                "creator" => "<th tt='h_creator'></th>".to_string(),
+                "last_editor" => "<th tt='h_last_editor'></th>".to_string(),
                 other => {
                     // File data etc.
-                    if fdk.contains(&other) {
+                    if other.starts_with("sparql:") {
+                        format!("<th>{}</th>", encode_minimal(&other["sparql:".len()..]))
+                    } else if other.starts_with("label:") || other.starts_with("description:") {
+                        format!("<th>{}</th>", encode_minimal(other))
+                    } else if fdk.contains(&other) {
                         format!("<th tt='h_{}'></th>", &other)
                     } else {
                         format!("<th>UNKNOWN:'{}'</th>", &other)
@@ -1034,26 +1404,31 @@ impl Render for RenderJSON {
                 .for_each(|k| header.push((k.to_string(), k.to_string())));
         }
 
-        let value: Value = match params.json_output_compatability.as_str() {
-            "quick-intersection" => self.quick_intersection(platform, entries, &params, &header),
-            _ => self.cat_scan(platform, entries, &params, &header), // Default
-        };
-
         let mut out: String = "".to_string();
         if !params.json_callback.is_empty() {
             out += &params.json_callback;
             out += "(";
         }
 
-        let output = if params.json_pretty {
-            ::serde_json::to_string_pretty(&value)
+        if params.json_pretty || params.json_output_compatability == "quick-intersection" {
+            let value: Value = match params.json_output_compatability.as_str() {
+                "quick-intersection" => {
+                    self.quick_intersection(platform, entries, &params, &header)
+                }
+                _ => self.cat_scan(platform, entries, &params, &header), // Default
+            };
+            let output = if params.json_pretty {
+                ::serde_json::to_string_pretty(&value)
+            } else {
+                ::serde_json::to_string(&value)
+            };
+            match output {
+                Ok(o) => out += &o,
+                Err(e) => return Err(format!("JSON encoding failed: {:?}", e)),
+            };
         } else {
-            ::serde_json::to_string(&value)
-        };
-        match output {
-            Ok(o) => out += &o,
-            Err(e) => return Err(format!("JSON encoding failed: {:?}", e)),
-        };
+            out += &self.cat_scan_chunked(platform, entries, &params, &header)?;
+        }
 
         if !params.json_callback.is_empty() {
             out += ")";
@@ -1062,6 +1437,8 @@ impl Render for RenderJSON {
         Ok(MyResponse {
             s: out.to_string(),
             content_type: content_type,
+            binary: None,
+            filename: None,
         })
     }
 
@@ -1087,6 +1464,14 @@ impl RenderJSON {
         Box::new(Self {})
     }
 
+    fn source_errors_json(&self, platform: &Platform) -> Value {
+        platform
+            .source_errors()
+            .iter()
+            .map(|(source, error)| json!({"source":source,"error":error}))
+            .collect()
+    }
+
     fn get_query_string(&self, platform: &Platform) -> String {
         "https://petscan.wmflabs.org/?".to_string() + &platform.form_parameters().to_string()
     }
@@ -1098,52 +1483,116 @@ impl RenderJSON {
         params: &RenderParams,
         header: &Vec<(String, String)>,
     ) -> Value {
-        let entry_data: Vec<Value> = if params.json_sparse {
-            entries
-                .iter()
-                .filter_map(|entry| {
-                    Some(json!(entry.title().full_with_underscores(&params.api)?))
-                })
-                .collect()
-        } else {
-            entries.iter().filter_map(|entry| {
-                let mut o = json!({
-                    "n":"page",
-                    "title":entry.title().with_underscores(),
-                    "id":entry.page_id.unwrap_or(0),
-                    "namespace":entry.title().namespace_id(),
-                    "len":entry.page_bytes.unwrap_or(0),
-                    "touched":entry.get_page_timestamp().unwrap_or("".to_string()),
-                    "nstext":params.api.get_canonical_namespace_name(entry.title().namespace_id()).unwrap_or("")
-                });
-                match entry.get_wikidata_item() {
-                    Some(q) => {
-                        o["q"] = json!(q);
-                        o["metadata"]["wikidata"] = json!(q);
-                    }
-                    None => {}
-                }
-                self.add_metadata(&mut o, &entry, header);
-                if params.file_data {
-                    match &o["metadata"].get("fileusage") {
-                        Some(_) => o["gil"] = o["metadata"]["fileusage"].to_owned(),
-                        None => {}
-                    }
-                    self.file_data_keys().iter().for_each(|k|{
-                        match &o["metadata"].get(k) {
-                            Some(_) => o[k] = o["metadata"][k].to_owned(),
-                            None => {}
-                        }
-                    });
-                }
-                Some(o)
-            }).collect()
-        };
+        let entry_data: Vec<Value> = entries
+            .iter()
+            .filter_map(|entry| self.cat_scan_entry(entry, params, header))
+            .collect();
         let seconds: f32 = match platform.query_time() {
             Some(duration) => (duration.as_millis() as f32) / (1000 as f32),
             None => 0.0,
         };
-        json!({"n":"result","a":{"query":self.get_query_string(platform),"querytime_sec":seconds},"*":[{"n":"combination","a":{"type":platform.get_param_default("combination","subset"),"*":entry_data}}]})
+        let mut ret = json!({"n":"result","a":{"query":self.get_query_string(platform),"querytime_sec":seconds},"*":[{"n":"combination","a":{"type":platform.get_param_default("combination","subset"),"*":entry_data}}]});
+        ret["a"]["wiki_by_source"] = json!(platform.wiki_by_source());
+        let truncations = platform.truncations().unwrap_or(vec![]);
+        if !truncations.is_empty() {
+            ret["a"]["truncated"] = json!(true);
+            ret["a"]["truncation_reasons"] = json!(truncations);
+        }
+        if !platform.source_errors().is_empty() {
+            ret["a"]["source_errors"] = self.source_errors_json(platform);
+        }
+        let pruned_subcats = platform.pruned_subcats().unwrap_or(vec![]);
+        if !pruned_subcats.is_empty() {
+            ret["a"]["pruned_subcats"] = json!(pruned_subcats);
+        }
+        match platform.explain() {
+            Some(explain) => ret["a"]["explain"] = explain,
+            None => {}
+        }
+        ret
+    }
+
+    fn cat_scan_entry(
+        &self,
+        entry: &PageListEntry,
+        params: &RenderParams,
+        header: &Vec<(String, String)>,
+    ) -> Option<Value> {
+        if params.json_sparse {
+            return Some(json!(entry.title().full_with_underscores(&params.api)?));
+        }
+        let mut o = json!({
+            "n":"page",
+            "title":entry.title().with_underscores(),
+            "id":entry.page_id.unwrap_or(0),
+            "namespace":entry.title().namespace_id(),
+            "len":entry.page_bytes.unwrap_or(0),
+            "touched":params.format_timestamp(&entry.get_page_timestamp()).unwrap_or("".to_string()),
+            "nstext":params.api.get_canonical_namespace_name(entry.title().namespace_id()).unwrap_or("")
+        });
+        match entry.get_wikidata_item() {
+            Some(q) => {
+                o["q"] = json!(q);
+                o["metadata"]["wikidata"] = json!(q);
+            }
+            None => {}
+        }
+        self.add_metadata(&mut o, &entry, header, params);
+        if params.json_omit_empty {
+            Self::omit_empty_fields(&mut o);
+        }
+        if params.file_data {
+            match &o["metadata"].get("fileusage") {
+                Some(_) => o["gil"] = o["metadata"]["fileusage"].to_owned(),
+                None => {}
+            }
+            self.file_data_keys()
+                .iter()
+                .for_each(|k| match &o["metadata"].get(k) {
+                    Some(_) => o[k] = o["metadata"][k].to_owned(),
+                    None => {}
+                });
+        }
+        Some(o)
+    }
+
+    /// Like cat_scan, but serializes the entries one by one into the output, so the JSON values
+    /// for all entries never have to be held in memory at the same time.
+    fn cat_scan_chunked(
+        &self,
+        platform: &Platform,
+        entries: Vec<PageListEntry>,
+        params: &RenderParams,
+        header: &Vec<(String, String)>,
+    ) -> Result<String, String> {
+        let mut ret = self.cat_scan(platform, vec![], params, header);
+        ret["*"][0]["a"]["*"] = json!(JSON_ENTRIES_PLACEHOLDER);
+        let placeholder = ::serde_json::to_string(&json!(JSON_ENTRIES_PLACEHOLDER))
+            .map_err(|e| format!("JSON encoding failed: {:?}", e))?;
+        let metadata =
+            ::serde_json::to_string(&ret).map_err(|e| format!("JSON encoding failed: {:?}", e))?;
+        let pos = metadata
+            .find(&placeholder)
+            .ok_or(format!("JSON encoding failed: entries placeholder missing"))?;
+
+        let mut out = String::with_capacity(metadata.len() + entries.len() * 128);
+        out += &metadata[..pos];
+        out.push('[');
+        let mut first = true;
+        for value in entries
+            .iter()
+            .filter_map(|entry| self.cat_scan_entry(entry, params, header))
+        {
+            if !first {
+                out.push(',');
+            }
+            first = false;
+            out += &::serde_json::to_string(&value)
+                .map_err(|e| format!("JSON encoding failed: {:?}", e))?;
+        }
+        out.push(']');
+        out += &metadata[pos + placeholder.len()..];
+        Ok(out)
     }
 
     fn quick_intersection(
@@ -1168,6 +1617,23 @@ impl RenderJSON {
             }
             None => {}
         }
+        ret["wiki_by_source"] = json!(platform.wiki_by_source());
+        let truncations = platform.truncations().unwrap_or(vec![]);
+        if !truncations.is_empty() {
+            ret["truncated"] = json!(true);
+            ret["truncation_reasons"] = json!(truncations);
+        }
+        if !platform.source_errors().is_empty() {
+            ret["source_errors"] = self.source_errors_json(platform);
+        }
+        let pruned_subcats = platform.pruned_subcats().unwrap_or(vec![]);
+        if !pruned_subcats.is_empty() {
+            ret["pruned_subcats"] = json!(pruned_subcats);
+        }
+        match platform.explain() {
+            Some(explain) => ret["explain"] = explain,
+            None => {}
+        }
 
         // Namespaces
         match params.api.get_site_info()["query"]["namespaces"].as_object() {
@@ -1198,7 +1664,7 @@ impl RenderJSON {
                         "page_id" : entry.page_id.unwrap_or(0),
                         "page_namespace" : entry.title().namespace_id(),
                         "page_title" : entry.title().with_underscores(),
-                        "page_latest" : entry.get_page_timestamp().unwrap_or("".to_string()),
+                        "page_latest" : params.format_timestamp(&entry.get_page_timestamp()).unwrap_or("".to_string()),
                         "page_len" : entry.page_bytes.unwrap_or(0),
                         //"meta" : {}
                     });
@@ -1208,7 +1674,10 @@ impl RenderJSON {
                             None => {}
                         }
                     }
-                    self.add_metadata(&mut o, &entry, header);
+                    self.add_metadata(&mut o, &entry, header, params);
+                    if params.json_omit_empty {
+                        Self::omit_empty_fields(&mut o);
+                    }
                     Some(o)
                 })
                 .collect();
@@ -1217,7 +1686,12 @@ impl RenderJSON {
         ret
     }
 
-    fn get_file_info_value(&self, entry: &PageListEntry, key: &str) -> Option<Value> {
+    fn get_file_info_value(
+        &self,
+        entry: &PageListEntry,
+        key: &str,
+        params: &RenderParams,
+    ) -> Option<Value> {
         match &entry.get_file_info() {
             Some(fi) => match key {
                 "img_size" => fi.img_size.as_ref().map(|s| json!(s)),
@@ -1227,7 +1701,7 @@ impl RenderJSON {
                 "img_major_mime" => fi.img_major_mime.as_ref().map(|s| json!(s)),
                 "img_minor_mime" => fi.img_minor_mime.as_ref().map(|s| json!(s)),
                 "img_user_text" => fi.img_user_text.as_ref().map(|s| json!(s)),
-                "img_timestamp" => fi.img_timestamp.as_ref().map(|s| json!(s)),
+                "img_timestamp" => params.format_timestamp(&fi.img_timestamp).map(|s| json!(s)),
                 "img_sha1" => fi.img_sha1.as_ref().map(|s| json!(s)),
                 other => {
                     println!("KEY NOT FOUND:{}", &other);
@@ -1282,7 +1756,40 @@ impl RenderJSON {
         }
     }
 
-    fn add_metadata(&self, o: &mut Value, entry: &PageListEntry, header: &Vec<(String, String)>) {
+    /// For "omit_empty": removes nulls, empty strings and objects, and unknown (0) page IDs
+    /// and sizes from an entry, so pages lacking most metadata take less space
+    fn omit_empty_fields(o: &mut Value) {
+        let map = match o.as_object_mut() {
+            Some(map) => map,
+            None => return,
+        };
+        map.values_mut().for_each(Self::omit_empty_fields);
+        let empty_keys: Vec<String> = map
+            .iter()
+            .filter(|(key, value)| match value {
+                Value::Null => true,
+                Value::String(s) => s.is_empty(),
+                Value::Object(o) => o.is_empty(),
+                Value::Number(n) => {
+                    ["id", "len", "page_id", "page_len"].contains(&key.as_str())
+                        && n.as_u64() == Some(0)
+                }
+                _ => false,
+            })
+            .map(|(key, _)| key.to_string())
+            .collect();
+        empty_keys.iter().for_each(|key| {
+            map.remove(key);
+        });
+    }
+
+    fn add_metadata(
+        &self,
+        o: &mut Value,
+        entry: &PageListEntry,
+        header: &Vec<(String, String)>,
+        params: &RenderParams,
+    ) {
         header.iter().for_each(|(head, _)| {
             let value = match head.to_string().as_str() {
                 "checkbox" | "number" | "page_id" | "title" | "namespace" | "size"
@@ -1290,15 +1797,45 @@ impl RenderJSON {
                 "image" => entry.get_page_image().map(|s| json!(s)),
                 "linknumber" => entry.link_count.as_ref().map(|s| json!(s)),
                 "wikidata" => entry.get_wikidata_item().map(|s| json!(s)),
+                "entity_uri" => entry.entity_uri(&params.wiki).map(|s| json!(s)),
                 "defaultsort" => entry.get_defaultsort().map(|s| json!(s)),
                 "disambiguation" => Some(entry.disambiguation.as_json()),
                 "incoming_links" => entry.incoming_links.as_ref().map(|s| json!(s)),
+                "pageviews" => entry.pageviews.map(|s| json!(s)),
+                "langlink_count" => entry.langlink_count.map(|s| json!(s)),
+                "editor_count" => entry.editor_count.map(|s| json!(s)),
                 "coordinates" => match &entry.get_coordinates() {
                     Some(coord) => Some(json!(format!("{}/{}", coord.lat, coord.lon))),
                     None => None,
                 },
                 "fileusage" => self.get_file_usage_as_string(entry),
-                other => self.get_file_info_value(entry, other),
+                "provenance" => entry.get_provenance().map(|s| json!(s)),
+                "entityschema" => Some(entry.entityschema_conforms.as_json()),
+                "cascade_protected" => Some(entry.cascade_protected.as_json()),
+                "label_match_item" => entry.get_extra("label_match_item").map(|s| json!(s)),
+                "label_language_used" => entry.get_label_language_used().map(|s| json!(s)),
+                "category_sortkey" => entry.get_extra("category_sortkey").map(|s| json!(s)),
+                "item_label" => entry.get_extra("item_label").map(|s| json!(s)),
+                "revert_tags" => entry.get_extra("revert_tags").map(|s| json!(s)),
+                "maintenance_templates" => entry.get_maintenance_templates().map(|s| json!(s)),
+                other if other.starts_with("sparql:") => {
+                    entry.get_extra(&other["sparql:".len()..]).map(|s| json!(s))
+                }
+                other if other.starts_with("label:") => entry
+                    .get_wikidata_label_in(&other["label:".len()..])
+                    .map(|s| json!(s)),
+                other if other.starts_with("description:") => entry
+                    .get_wikidata_description_in(&other["description:".len()..])
+                    .map(|s| json!(s)),
+                "redirect_target" => entry.get_redirect_target().map(|target| {
+                    json!({"namespace":target.namespace_id(),"title":target.with_underscores()})
+                }),
+                "created" => params
+                    .format_timestamp(&entry.get_page_created())
+                    .map(|s| json!(s)),
+                "creator" => entry.get_page_creator().map(|s| json!(s)),
+                "last_editor" => entry.get_last_editor().map(|s| json!(s)),
+                other => self.get_file_info_value(entry, other, params),
             };
             //println!("{}:{:?}", &head, &value);
             match value {
@@ -1311,6 +1848,697 @@ impl RenderJSON {
 
 //________________________________________________________________________________________________________________________
 
+/// Renders SQL INSERT statements for a simple `pages` table
+pub struct RenderSQLDump {}
+
+impl Render for RenderSQLDump {
+    fn response(
+        &self,
+        platform: &Platform,
+        wiki: &String,
+        entries: Vec<PageListEntry>,
+    ) -> Result<MyResponse, String> {
+        lazy_static! {
+            static ref RE_TABLE_NAME: Regex =
+                Regex::new(r"^[A-Za-z0-9_]+$").expect("RenderSQLDump: Regex is invalid");
+        }
+        let table = platform.get_param_default("sqldump_table", "pages");
+        if !RE_TABLE_NAME.is_match(&table) {
+            return Err(format!("Invalid sqldump_table '{}'", &table));
+        }
+        let wiki_quoted = Self::quote(wiki);
+        let mut rows: Vec<String> = vec![format!(
+            "CREATE TABLE IF NOT EXISTS `{}` (`wiki` VARCHAR(64) NOT NULL, `namespace` INT NOT NULL, `title` VARBINARY(255) NOT NULL, `page_id` INT UNSIGNED NULL, `wikidata_item` VARCHAR(32) NULL);",
+            &table
+        )];
+        entries.iter().for_each(|entry| {
+            rows.push(format!(
+                "INSERT INTO `{}` (`wiki`,`namespace`,`title`,`page_id`,`wikidata_item`) VALUES ({},{},{},{},{});",
+                &table,
+                &wiki_quoted,
+                entry.title().namespace_id(),
+                Self::quote(&entry.title().with_underscores()),
+                entry
+                    .page_id
+                    .map(|id| id.to_string())
+                    .unwrap_or("NULL".to_string()),
+                entry
+                    .get_wikidata_item()
+                    .map(|q| Self::quote(&q))
+                    .unwrap_or("NULL".to_string())
+            ))
+        });
+        Ok(MyResponse {
+            s: rows.join("\n") + "\n",
+            content_type: ContentType::Plain,
+            binary: None,
+            filename: None,
+        })
+    }
+
+    fn render_cell_title(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_wikidata_item(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_user_name(&self, _user: &String, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_image(&self, _image: &Option<String>, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_namespace(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+}
+
+impl RenderSQLDump {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+
+    /// Quotes a string as a MySQL/MariaDB string literal
+    pub fn quote(s: &str) -> String {
+        let mut ret = String::with_capacity(s.len() + 2);
+        ret.push('\'');
+        s.chars().for_each(|c| match c {
+            '\\' => ret += "\\\\",
+            '\'' => ret += "\\'",
+            '"' => ret += "\\\"",
+            '\0' => ret += "\\0",
+            '\n' => ret += "\\n",
+            '\r' => ret += "\\r",
+            '\x1a' => ret += "\\Z",
+            c => ret.push(c),
+        });
+        ret.push('\'');
+        ret
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Renders plain page titles, one per line
+pub struct RenderTitles {}
+
+impl Render for RenderTitles {
+    fn response(
+        &self,
+        platform: &Platform,
+        wiki: &String,
+        entries: Vec<PageListEntry>,
+    ) -> Result<MyResponse, String> {
+        let params = RenderParams::new(platform, wiki)?;
+        let underscores = platform.has_param("titles_underscores");
+        let rows: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                let title = entry.title();
+                if params.is_wikidata {
+                    // Q/P/L IDs
+                    title.pretty().to_string()
+                } else if underscores {
+                    title
+                        .full_with_underscores(&params.api)
+                        .unwrap_or(title.with_underscores().to_string())
+                } else {
+                    title
+                        .full_pretty(&params.api)
+                        .unwrap_or(title.pretty().to_string())
+                }
+            })
+            .collect();
+        Ok(MyResponse {
+            s: rows.join("\n"),
+            content_type: ContentType::Plain,
+            binary: None,
+            filename: None,
+        })
+    }
+
+    fn render_cell_title(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_wikidata_item(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_user_name(&self, _user: &String, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_image(&self, _image: &Option<String>, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_namespace(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+}
+
+impl RenderTitles {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Renders newline-delimited JSON, one compact object per page
+pub struct RenderJSONL {}
+
+impl Render for RenderJSONL {
+    fn response(
+        &self,
+        platform: &Platform,
+        wiki: &String,
+        entries: Vec<PageListEntry>,
+    ) -> Result<MyResponse, String> {
+        let params = RenderParams::new(platform, wiki)?;
+        let header: Vec<(String, String)> = self
+            .get_initial_columns(&params)
+            .iter()
+            .filter(|col| !["checkbox", "number", "title", "page_id", "namespace"].contains(col))
+            .map(|col| (col.to_string(), col.to_string()))
+            .collect();
+        let json_renderer = RenderJSON {};
+        let mut out: Vec<u8> = Vec::with_capacity(entries.len() * 64);
+        for entry in entries {
+            let mut o = json!({
+                "title":entry.title().with_underscores(),
+                "namespace":entry.title().namespace_id(),
+                "page_id":entry.page_id,
+                "size":entry.page_bytes,
+                "timestamp":params.format_timestamp(&entry.get_page_timestamp()),
+            });
+            json_renderer.add_metadata(&mut o, &entry, &header, &params);
+            match o.as_object_mut().and_then(|map| map.remove("metadata")) {
+                Some(Value::Object(metadata)) => {
+                    metadata.into_iter().for_each(|(k, v)| o[k] = v);
+                }
+                _ => {}
+            }
+            RenderJSON::omit_empty_fields(&mut o);
+            ::serde_json::to_writer(&mut out, &o)
+                .map_err(|e| format!("JSONL encoding failed: {:?}", e))?;
+            out.push(b'\n');
+        }
+        Ok(MyResponse {
+            s: String::from_utf8(out).map_err(|e| format!("JSONL encoding failed: {:?}", e))?,
+            content_type: ContentType::JSONL,
+            binary: None,
+            filename: None,
+        })
+    }
+
+    fn render_cell_title(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_wikidata_item(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_user_name(&self, _user: &String, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_image(&self, _image: &Option<String>, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_namespace(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+}
+
+impl RenderJSONL {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Renders GeoJSON
+pub struct RenderGeoJSON {}
+
+impl Render for RenderGeoJSON {
+    fn response(
+        &self,
+        platform: &Platform,
+        wiki: &String,
+        entries: Vec<PageListEntry>,
+    ) -> Result<MyResponse, String> {
+        let params = RenderParams::new(platform, wiki)?;
+        let include_null_geometry = platform.has_param("geojson_null_geometry");
+        let features: Vec<Value> = entries
+            .iter()
+            .filter_map(|entry| {
+                let geometry = match entry.get_coordinates() {
+                    Some(coord) => json!({"type":"Point","coordinates":[coord.lon,coord.lat]}),
+                    None if include_null_geometry => Value::Null,
+                    None => return None,
+                };
+                Some(json!({
+                    "type":"Feature",
+                    "geometry":geometry,
+                    "properties":{
+                        "title":entry.title().with_underscores(),
+                        "namespace":entry.title().namespace_id(),
+                        "page_id":entry.page_id,
+                        "wikidata_item":entry.get_wikidata_item(),
+                    }
+                }))
+            })
+            .collect();
+        let value = json!({"type":"FeatureCollection","features":features});
+
+        let output = if params.json_pretty {
+            ::serde_json::to_string_pretty(&value)
+        } else {
+            ::serde_json::to_string(&value)
+        };
+        match output {
+            Ok(s) => Ok(MyResponse {
+                s: s,
+                content_type: ContentType::GeoJSON,
+                binary: None,
+                filename: None,
+            }),
+            Err(e) => Err(format!("GeoJSON encoding failed: {:?}", e)),
+        }
+    }
+
+    fn render_cell_title(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_wikidata_item(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_user_name(&self, _user: &String, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_image(&self, _image: &Option<String>, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_namespace(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+}
+
+impl RenderGeoJSON {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Renders KML
+pub struct RenderKML {}
+
+impl Render for RenderKML {
+    fn response(
+        &self,
+        platform: &Platform,
+        wiki: &String,
+        entries: Vec<PageListEntry>,
+    ) -> Result<MyResponse, String> {
+        let params = RenderParams::new(platform, wiki)?;
+        let server = params.state.get_server_url_for_wiki(wiki)?;
+        let mut kml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_string();
+        kml += "<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n";
+        kml += &format!("<name>PetScan {}</name>\n", encode_minimal(wiki));
+        entries.iter().for_each(|entry| {
+            let coord = match entry.get_coordinates() {
+                Some(coord) => coord,
+                None => return,
+            };
+            let name = entry
+                .title()
+                .full_pretty(&params.api)
+                .unwrap_or(entry.title().pretty().to_string());
+            let full_title = entry
+                .title()
+                .full_with_underscores(&params.api)
+                .unwrap_or(entry.title().with_underscores().to_string());
+            let url = format!(
+                "{}/wiki/{}",
+                &server,
+                FormParameters::percent_encode(&full_title)
+            );
+            kml += "<Placemark>\n";
+            kml += &format!("<name>{}</name>\n", encode_minimal(&name));
+            kml += &format!(
+                "<description>{}</description>\n",
+                encode_minimal(&format!("<a href=\"{}\">{}</a>", &url, &url))
+            );
+            kml += &format!(
+                "<Point><coordinates>{},{},0</coordinates></Point>\n",
+                coord.lon, coord.lat
+            );
+            kml += "</Placemark>\n";
+        });
+        kml += "</Document>\n</kml>\n";
+        Ok(MyResponse {
+            s: kml,
+            content_type: ContentType::KML,
+            binary: None,
+            filename: None,
+        })
+    }
+
+    fn render_cell_title(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_wikidata_item(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_user_name(&self, _user: &String, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_image(&self, _image: &Option<String>, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_namespace(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+}
+
+impl RenderKML {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Renders an RSS 2.0 feed
+pub struct RenderRSS {}
+
+impl Render for RenderRSS {
+    fn response(
+        &self,
+        platform: &Platform,
+        wiki: &String,
+        entries: Vec<PageListEntry>,
+    ) -> Result<MyResponse, String> {
+        let params = RenderParams::new(platform, wiki)?;
+        let server = params.state.get_server_url_for_wiki(wiki)?;
+        let (title, link) = match platform.psid {
+            Some(psid) => (
+                format!("PetScan query {}", psid),
+                format!("https://petscan.wmflabs.org/?psid={}", psid),
+            ),
+            None => (
+                "PetScan query".to_string(),
+                "https://petscan.wmflabs.org/?".to_string()
+                    + &platform.form_parameters().to_string(),
+            ),
+        };
+        let mut rss = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_string();
+        rss += "<rss version=\"2.0\">\n<channel>\n";
+        rss += &format!("<title>{}</title>\n", encode_minimal(&title));
+        rss += &format!("<link>{}</link>\n", encode_minimal(&link));
+        rss += &format!(
+            "<description>{} on {}</description>\n",
+            encode_minimal(&platform.combination().to_string()),
+            encode_minimal(wiki)
+        );
+        entries.iter().for_each(|entry| {
+            let name = entry
+                .title()
+                .full_pretty(&params.api)
+                .unwrap_or(entry.title().pretty().to_string());
+            let full_title = entry
+                .title()
+                .full_with_underscores(&params.api)
+                .unwrap_or(entry.title().with_underscores().to_string());
+            let url = format!(
+                "{}/wiki/{}",
+                &server,
+                FormParameters::percent_encode(&full_title)
+            );
+            rss += "<item>\n";
+            rss += &format!("<title>{}</title>\n", encode_minimal(&name));
+            rss += &format!("<link>{}</link>\n", encode_minimal(&url));
+            rss += &format!("<guid>{}</guid>\n", encode_minimal(&url));
+            match entry
+                .get_page_timestamp()
+                .and_then(|ts| Utc.datetime_from_str(&ts, "%Y%m%d%H%M%S").ok())
+            {
+                Some(date) => rss += &format!("<pubDate>{}</pubDate>\n", date.to_rfc2822()),
+                None => {}
+            }
+            rss += "</item>\n";
+        });
+        rss += "</channel>\n</rss>\n";
+        Ok(MyResponse {
+            s: rss,
+            content_type: ContentType::RSS,
+            binary: None,
+            filename: None,
+        })
+    }
+
+    fn render_cell_title(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_wikidata_item(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_user_name(&self, _user: &String, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_image(&self, _image: &Option<String>, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+    fn render_cell_namespace(&self, _entry: &PageListEntry, _params: &RenderParams) -> String {
+        "".to_string()
+    }
+}
+
+impl RenderRSS {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Renders a GitHub-flavored Markdown table
+pub struct RenderMarkdown {}
+
+impl Render for RenderMarkdown {
+    fn response(
+        &self,
+        platform: &Platform,
+        wiki: &String,
+        entries: Vec<PageListEntry>,
+    ) -> Result<MyResponse, String> {
+        let mut params = RenderParams::new(platform, wiki)?;
+        let header: Vec<(String, String)> = self
+            .get_initial_columns(&params)
+            .iter()
+            .filter(|col| **col != "number" && **col != "checkbox")
+            .map(|col| (col.to_string(), col.to_string()))
+            .collect();
+
+        let mut rows: Vec<String> = vec![];
+        rows.push(self.markdown_row(header.iter().map(|(_, v)| v.to_string()).collect()));
+        rows.push(self.markdown_row(header.iter().map(|_| "---".to_string()).collect()));
+        for entry in entries {
+            params.row_number += 1;
+            let row = self
+                .row_from_entry(&entry, &header, &params, &platform)
+                .iter()
+                .zip(header.iter())
+                .map(|(cell, (k, _))| match k.as_str() {
+                    "title" => cell.to_string(), // Already escaped
+                    _ => Self::escape_cell(cell),
+                })
+                .collect();
+            rows.push(self.markdown_row(row));
+        }
+
+        Ok(MyResponse {
+            s: rows.join("\n"),
+            content_type: ContentType::Plain,
+            binary: None,
+            filename: None,
+        })
+    }
+
+    fn render_cell_title(&self, entry: &PageListEntry, params: &RenderParams) -> String {
+        let full_title = entry
+            .title()
+            .full_with_underscores(&params.api)
+            .unwrap_or(entry.title().with_underscores().to_string());
+        let label = entry
+            .title()
+            .full_pretty(&params.api)
+            .unwrap_or(entry.title().pretty().to_string());
+        let server = params
+            .state
+            .get_server_url_for_wiki(&params.wiki)
+            .unwrap_or("".to_string());
+        format!(
+            "[{}]({}/wiki/{})",
+            Self::escape_cell(&label),
+            server,
+            FormParameters::percent_encode(&full_title)
+        )
+    }
+    fn render_cell_wikidata_item(&self, entry: &PageListEntry, _params: &RenderParams) -> String {
+        entry.get_wikidata_item().unwrap_or("".to_string())
+    }
+    fn render_user_name(&self, user: &String, _params: &RenderParams) -> String {
+        user.to_string()
+    }
+    fn render_cell_image(&self, image: &Option<String>, _params: &RenderParams) -> String {
+        image.to_owned().unwrap_or("".to_string())
+    }
+    fn render_cell_namespace(&self, entry: &PageListEntry, params: &RenderParams) -> String {
+        entry
+            .title()
+            .namespace_name(&params.api)
+            .unwrap_or(&"".to_string())
+            .to_string()
+    }
+}
+
+impl RenderMarkdown {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+
+    /// Escapes table cell contents; pipes would otherwise start a new cell
+    pub fn escape_cell(s: &str) -> String {
+        s.replace('\\', "\\\\")
+            .replace('|', "\\|")
+            .replace('\n', " ")
+    }
+
+    fn markdown_row(&self, cells: Vec<String>) -> String {
+        format!("| {} |", cells.join(" | "))
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
+/// Renders an Excel workbook
+pub struct RenderXLSX {}
+
+impl Render for RenderXLSX {
+    fn response(
+        &self,
+        platform: &Platform,
+        wiki: &String,
+        entries: Vec<PageListEntry>,
+    ) -> Result<MyResponse, String> {
+        let mut params = RenderParams::new(platform, wiki)?;
+        let mut header: Vec<(&str, &str)> = vec![
+            ("number", "number"),
+            ("title", "title"),
+            ("page_id", "pageid"),
+            ("namespace", "namespace"),
+            ("size", "length"),
+            ("timestamp", "touched"),
+        ];
+        if params.show_wikidata_item {
+            header.push(("wikidata_item", "Wikidata"));
+        }
+        if params.file_data {
+            self.file_data_keys()
+                .iter()
+                .for_each(|k| header.push((k, k)));
+        }
+        let mut header: Vec<(String, String)> = header
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        for col in self.get_initial_columns(&params) {
+            if !header.iter().any(|(k, _)| col == k) && col != "number" {
+                header.push((col.to_string(), col.to_string()));
+            }
+        }
+
+        let mut rows: Vec<Row> = vec![];
+        let mut row = Row::new();
+        header.iter().for_each(|(_, v)| row.add_cell(v.to_string()));
+        rows.push(row);
+        for entry in entries {
+            params.row_number += 1;
+            let cells = self.row_from_entry(&entry, &header, &params, &platform);
+            let mut row = Row::new();
+            header
+                .iter()
+                .zip(cells.into_iter())
+                .for_each(|((k, _), cell)| {
+                    match (Self::is_numeric_column(k), cell.parse::<f64>()) {
+                        (true, Ok(number)) => row.add_cell(number),
+                        _ => row.add_cell(cell),
+                    }
+                });
+            rows.push(row);
+        }
+
+        let mut workbook = Workbook::create_in_memory();
+        let mut sheet = workbook.create_sheet("PetScan");
+        workbook
+            .write_sheet(&mut sheet, |sheet_writer| {
+                for row in rows {
+                    sheet_writer.append_row(row)?;
+                }
+                Ok(())
+            })
+            .map_err(|e| format!("XLSX generation failed: {:?}", e))?;
+        let bytes = workbook
+            .close()
+            .map_err(|e| format!("XLSX generation failed: {:?}", e))?
+            .ok_or(format!("XLSX generation failed: no data"))?;
+        Ok(MyResponse {
+            s: "".to_string(),
+            content_type: ContentType::XLSX,
+            binary: Some(bytes),
+            filename: None,
+        })
+    }
+
+    fn render_cell_title(&self, entry: &PageListEntry, _params: &RenderParams) -> String {
+        entry.title().with_underscores()
+    }
+    fn render_cell_wikidata_item(&self, entry: &PageListEntry, _params: &RenderParams) -> String {
+        entry.get_wikidata_item().unwrap_or("".to_string())
+    }
+    fn render_user_name(&self, user: &String, _params: &RenderParams) -> String {
+        user.to_string()
+    }
+    fn render_cell_image(&self, image: &Option<String>, _params: &RenderParams) -> String {
+        image.to_owned().unwrap_or("".to_string())
+    }
+    fn render_cell_namespace(&self, entry: &PageListEntry, params: &RenderParams) -> String {
+        entry
+            .title()
+            .namespace_name(&params.api)
+            .unwrap_or(&"UNKNOWN_NAMESPACE".to_string())
+            .to_string()
+    }
+}
+
+impl RenderXLSX {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {})
+    }
+
+    /// Columns written as numeric cells, so spreadsheets can sort and sum them
+    fn is_numeric_column(key: &str) -> bool {
+        match key {
+            "number" | "page_id" | "size" | "incoming_links" | "pageviews" | "langlink_count"
+            | "editor_count" | "linknumber" | "redlink_count" | "img_size" | "img_width"
+            | "img_height" => true,
+            _ => false,
+        }
+    }
+}
+
+//________________________________________________________________________________________________________________________
+
 /// Renders PagePile
 pub struct RenderPagePile {}
 
@@ -1365,6 +2593,8 @@ impl Render for RenderPagePile {
         Ok(MyResponse {
             s: html,
             content_type: ContentType::HTML,
+            binary: None,
+            filename: None,
         })
     }
 
@@ -185,7 +185,7 @@ impl WDfist {
             });
 
             // Run batches
-            let rows = PageList::new_from_wiki("commonswiki").run_batch_queries(&self.state, batches).map_err(|e|format!("{:?}",e))?;
+            let rows = PageList::new_from_wiki("commonswiki").run_batch_queries(&self.state, batches).map_err(|e|format!("{}",e))?;
 
             // Collect pages and items, per wiki
             let page_file: Vec<(String, String)> = rows